@@ -0,0 +1,21 @@
+use std::io;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Ac3Error {
+    #[error("Not enough data: {0}")]
+    NotEnoughData(&'static str),
+
+    #[error("Unsupported bitstream id {0}")]
+    UnsupportedBsid(u8),
+
+    #[error("Reserved sample rate code {0}")]
+    ReservedSampleRate(u8),
+
+    #[error("Reserved frame size code {0}")]
+    ReservedFrameSize(u8),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
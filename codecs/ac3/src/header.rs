@@ -0,0 +1,211 @@
+use bytes::Bytes;
+use bytesio::bit_reader::BitReader;
+
+use crate::{error::Ac3Error, SYNC_WORD};
+
+const SAMPLE_RATES: [u32; 3] = [48000, 44100, 32000];
+/// E-AC-3 reduced sample rates, signalled through fscod2
+const REDUCED_SAMPLE_RATES: [u32; 3] = [24000, 22050, 16000];
+/// Nominal bit rates in kbit/s, indexed by frmsizecod / 2
+const BIT_RATES: [u32; 19] = [
+    32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384, 448, 512, 576, 640,
+];
+/// Full bandwidth channels for each audio coding mode
+const ACMOD_CHANNELS: [u8; 8] = [2, 1, 2, 3, 3, 4, 4, 5];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The syncinfo and the start of the bsi of a syncframe, both for AC-3
+/// (ATSC A/52 - 5.3) and E-AC-3 (ATSC A/52 - E.1.2).
+pub struct SyncFrameHeader {
+    pub bsid: u8,
+    /// Sample rate code, 3 for the E-AC-3 reduced rates
+    pub fscod: u8,
+    pub sample_rate: u32,
+    /// Size of the whole syncframe in bytes
+    pub frame_size: usize,
+    /// Number of 256 sample audio blocks, always 6 for AC-3
+    pub blocks: u8,
+    /// AC-3 only, E-AC-3 carries the bit rate implicitly in the frame size
+    pub frmsizecod: u8,
+    pub bsmod: u8,
+    pub acmod: u8,
+    pub lfeon: bool,
+    /// E-AC-3 dependent substream, extending the independent one before it
+    pub dependent: bool,
+    pub substream_id: u8,
+    /// E-AC-3 custom channel map of a dependent substream
+    pub channel_map: Option<u16>,
+}
+
+impl SyncFrameHeader {
+    pub fn parse(data: &[u8]) -> Result<Self, Ac3Error> {
+        if data.len() < 8 {
+            return Err(Ac3Error::NotEnoughData("syncframe header"));
+        }
+
+        // bsid sits at the same position in both syncframe formats
+        let bsid = data[5] >> 3;
+        match bsid {
+            0..=8 => Self::parse_ac3(data),
+            11..=16 => Self::parse_eac3(data),
+            _ => Err(Ac3Error::UnsupportedBsid(bsid)),
+        }
+    }
+
+    fn parse_ac3(data: &[u8]) -> Result<Self, Ac3Error> {
+        let mut bit_reader = BitReader::from(Bytes::copy_from_slice(&data[..8]));
+
+        let _syncword = bit_reader.read_bits(16)?;
+        let _crc1 = bit_reader.read_bits(16)?;
+        let fscod = bit_reader.read_bits(2)? as u8;
+        let frmsizecod = bit_reader.read_bits(6)? as u8;
+        let bsid = bit_reader.read_bits(5)? as u8;
+        let bsmod = bit_reader.read_bits(3)? as u8;
+        let acmod = bit_reader.read_bits(3)? as u8;
+
+        if acmod & 0x01 != 0 && acmod != 0x01 {
+            let _cmixlev = bit_reader.read_bits(2)?;
+        }
+        if acmod & 0x04 != 0 {
+            let _surmixlev = bit_reader.read_bits(2)?;
+        }
+        if acmod == 0x02 {
+            let _dsurmod = bit_reader.read_bits(2)?;
+        }
+        let lfeon = bit_reader.read_bit()?;
+
+        let sample_rate = *SAMPLE_RATES
+            .get(fscod as usize)
+            .ok_or(Ac3Error::ReservedSampleRate(fscod))?;
+        let bit_rate = *BIT_RATES
+            .get(frmsizecod as usize / 2)
+            .ok_or(Ac3Error::ReservedFrameSize(frmsizecod))?;
+
+        // ATSC A/52 - Table 5.18, 44.1 kHz frames alternate in size to keep the bit rate
+        let words = match sample_rate {
+            48000 => bit_rate * 2,
+            44100 => bit_rate * 96000 / 44100 + (frmsizecod & 0x01) as u32,
+            _ => bit_rate * 3,
+        };
+
+        Ok(Self {
+            bsid,
+            fscod,
+            sample_rate,
+            frame_size: words as usize * 2,
+            blocks: 6,
+            frmsizecod,
+            bsmod,
+            acmod,
+            lfeon,
+            dependent: false,
+            substream_id: 0,
+            channel_map: None,
+        })
+    }
+
+    fn parse_eac3(data: &[u8]) -> Result<Self, Ac3Error> {
+        let mut bit_reader = BitReader::from(Bytes::copy_from_slice(&data[..data.len().min(16)]));
+
+        let _syncword = bit_reader.read_bits(16)?;
+        let strmtyp = bit_reader.read_bits(2)? as u8;
+        let substream_id = bit_reader.read_bits(3)? as u8;
+        let frmsiz = bit_reader.read_bits(11)? as usize;
+        let fscod = bit_reader.read_bits(2)? as u8;
+
+        let (sample_rate, blocks) = if fscod == 0x03 {
+            let fscod2 = bit_reader.read_bits(2)? as u8;
+            let sample_rate = *REDUCED_SAMPLE_RATES
+                .get(fscod2 as usize)
+                .ok_or(Ac3Error::ReservedSampleRate(fscod2))?;
+            (sample_rate, 6)
+        } else {
+            let numblkscod = bit_reader.read_bits(2)? as usize;
+            (SAMPLE_RATES[fscod as usize], [1, 2, 3, 6][numblkscod])
+        };
+
+        let acmod = bit_reader.read_bits(3)? as u8;
+        let lfeon = bit_reader.read_bit()?;
+        let bsid = bit_reader.read_bits(5)? as u8;
+        let _dialnorm = bit_reader.read_bits(5)?;
+        if bit_reader.read_bit()? {
+            let _compr = bit_reader.read_bits(8)?;
+        }
+
+        // dual mono carries a second set of these for the right channel
+        if acmod == 0x00 {
+            let _dialnorm2 = bit_reader.read_bits(5)?;
+            if bit_reader.read_bit()? {
+                let _compr2 = bit_reader.read_bits(8)?;
+            }
+        }
+
+        let dependent = strmtyp == 0x01;
+        let channel_map = if dependent && bit_reader.read_bit()? {
+            Some(bit_reader.read_bits(16)? as u16)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            bsid,
+            fscod,
+            sample_rate,
+            frame_size: (frmsiz + 1) * 2,
+            blocks,
+            frmsizecod: 0,
+            // only carried in the informational metadata further into the bsi
+            bsmod: 0,
+            acmod,
+            lfeon,
+            dependent,
+            substream_id,
+            channel_map,
+        })
+    }
+
+    pub fn is_eac3(&self) -> bool {
+        self.bsid > 10
+    }
+
+    /// Samples per channel in the syncframe
+    pub fn samples(&self) -> u32 {
+        self.blocks as u32 * 256
+    }
+
+    /// Bit rate in kbit/s
+    pub fn bit_rate(&self) -> u32 {
+        if self.is_eac3() {
+            (self.frame_size as u64 * 8 * self.sample_rate as u64 / self.samples() as u64 / 1000) as u32
+        } else {
+            BIT_RATES[self.frmsizecod as usize / 2]
+        }
+    }
+
+    pub fn channels(&self) -> u8 {
+        ACMOD_CHANNELS[self.acmod as usize] + self.lfeon as u8
+    }
+
+    /// chan_loc of the dec3 box, the channels a dependent substream adds
+    /// on top of its independent substream.
+    /// ETSI TS 102 366 - Table F.6.1 and ATSC A/52 - Table E.1.4
+    pub fn chan_loc(&self) -> u16 {
+        let channel_map = match self.channel_map {
+            Some(channel_map) => channel_map,
+            None => return 0,
+        };
+
+        // chanmap bits counted from the most significant, Lc/Rc pair to LFE2
+        const LOCATIONS: [u8; 9] = [5, 6, 7, 8, 9, 10, 11, 12, 14];
+
+        LOCATIONS
+            .iter()
+            .enumerate()
+            .filter(|(_, bit)| channel_map & (0x8000 >> **bit) != 0)
+            .fold(0, |chan_loc, (location, _)| chan_loc | (1 << location))
+    }
+
+    pub(crate) fn is_sync_word(data: &[u8]) -> bool {
+        data.len() >= 2 && u16::from_be_bytes([data[0], data[1]]) == SYNC_WORD
+    }
+}
@@ -0,0 +1,15 @@
+pub mod error;
+pub mod header;
+pub mod reader;
+
+pub use self::{
+    error::Ac3Error,
+    header::SyncFrameHeader,
+    reader::{SyncFrame, SyncFrameReader},
+};
+
+/// Every syncframe, AC-3 or E-AC-3, starts with this word
+pub const SYNC_WORD: u16 = 0x0B77;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,81 @@
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::{error::Ac3Error, header::SyncFrameHeader};
+
+#[derive(Debug, Clone)]
+pub struct SyncFrame {
+    pub header: SyncFrameHeader,
+    pub data: Bytes,
+}
+
+/// Splits PES payloads into syncframes. A syncframe is allowed to span
+/// PES packets, so the tail of one payload is kept for the next.
+#[derive(Debug, Default)]
+pub struct SyncFrameReader {
+    buffer: BytesMut,
+}
+
+impl SyncFrameReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<SyncFrame>, Ac3Error> {
+        self.buffer.extend_from_slice(data);
+
+        let mut frames = Vec::new();
+
+        loop {
+            if !self.resync() {
+                break;
+            }
+
+            if self.buffer.len() < 8 {
+                break;
+            }
+
+            let header = match SyncFrameHeader::parse(&self.buffer) {
+                Ok(header) => header,
+                Err(Ac3Error::UnsupportedBsid(bsid)) => {
+                    log::warn!("Skipping syncframe with unsupported bsid {}", bsid);
+                    self.buffer.advance(2);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            if self.buffer.len() < header.frame_size {
+                break;
+            }
+
+            let data = self.buffer.split_to(header.frame_size).freeze();
+            frames.push(SyncFrame { header, data });
+        }
+
+        Ok(frames)
+    }
+
+    /// Drops anything in front of the next sync word, returns whether one was found.
+    fn resync(&mut self) -> bool {
+        let position = self
+            .buffer
+            .windows(2)
+            .position(SyncFrameHeader::is_sync_word);
+
+        match position {
+            Some(position) => {
+                if position != 0 {
+                    log::debug!("Dropping {} bytes in front of the AC-3 sync word", position);
+                }
+                self.buffer.advance(position);
+                true
+            }
+            None => {
+                // the last byte could be the first half of a sync word
+                let keep = self.buffer.len().min(1);
+                self.buffer.advance(self.buffer.len() - keep);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{SyncFrameHeader, SyncFrameReader};
+
+/// 48 kHz, 384 kbit/s, 3/2 with LFE
+const AC3_HEADER: [u8; 8] = [0x0B, 0x77, 0x00, 0x00, 0x1C, 0x40, 0xE1, 0x00];
+/// independent substream, 48 kHz, 6 blocks, 3/2 with LFE
+const EAC3_HEADER: [u8; 8] = [0x0B, 0x77, 0x02, 0xFF, 0x3F, 0x80, 0x00, 0x00];
+
+fn frame(header: &[u8], frame_size: usize) -> Vec<u8> {
+    let mut frame = header.to_vec();
+    frame.resize(frame_size, 0xAA);
+    frame
+}
+
+#[test]
+fn test_parse_ac3_header() {
+    let header = SyncFrameHeader::parse(&AC3_HEADER).unwrap();
+
+    assert!(!header.is_eac3());
+    assert_eq!(header.bsid, 8);
+    assert_eq!(header.sample_rate, 48000);
+    assert_eq!(header.frame_size, 1536);
+    assert_eq!(header.bit_rate(), 384);
+    assert_eq!(header.samples(), 1536);
+    assert_eq!(header.acmod, 7);
+    assert!(header.lfeon);
+    assert_eq!(header.channels(), 6);
+}
+
+#[test]
+fn test_parse_ac3_header_44_1khz() {
+    let header = SyncFrameHeader::parse(&[0x0B, 0x77, 0x00, 0x00, 0x5D, 0x40, 0x40, 0x00]).unwrap();
+
+    assert_eq!(header.sample_rate, 44100);
+    // odd frame size codes carry the extra word
+    assert_eq!(header.frame_size, 1672);
+    assert_eq!(header.channels(), 2);
+}
+
+#[test]
+fn test_parse_eac3_header() {
+    let header = SyncFrameHeader::parse(&EAC3_HEADER).unwrap();
+
+    assert!(header.is_eac3());
+    assert!(!header.dependent);
+    assert_eq!(header.bsid, 16);
+    assert_eq!(header.frame_size, 1536);
+    assert_eq!(header.blocks, 6);
+    assert_eq!(header.bit_rate(), 384);
+    assert_eq!(header.channels(), 6);
+    assert_eq!(header.chan_loc(), 0);
+
+    let dependent = SyncFrameHeader::parse(&[0x0B, 0x77, 0x40, 0xFF, 0x34, 0x80, 0x10, 0x20, 0x00]).unwrap();
+
+    assert!(dependent.dependent);
+    assert_eq!(dependent.frame_size, 512);
+    assert_eq!(dependent.channel_map, Some(0x0200));
+    // Lrs/Rrs pair
+    assert_eq!(dependent.chan_loc(), 0x02);
+}
+
+#[test]
+fn test_reader_across_payloads() {
+    let mut data = vec![0x00, 0x42];
+    data.extend(frame(&AC3_HEADER, 1536));
+    data.extend(frame(&AC3_HEADER, 1536));
+
+    let mut reader = SyncFrameReader::new();
+
+    let frames = reader.push(&data[..2000]).unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].data.len(), 1536);
+
+    let frames = reader.push(&data[2000..]).unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(&frames[0].data[..8], &AC3_HEADER);
+}
@@ -3,6 +3,8 @@ use std::io;
 use bytes::Bytes;
 use bytesio::{bit_reader::BitReader, bit_writer::BitWriter, bytes_reader::BytesCursor};
 
+use crate::{seq::SequenceHeaderObu, ObuHeader, ObuType};
+
 #[derive(Debug, Clone, PartialEq)]
 /// AV1 Codec Configuration Record
 /// https://aomediacodec.github.io/av1-isobmff/#av1codecconfigurationbox-syntax
@@ -23,6 +25,40 @@ pub struct AV1CodecConfigurationRecord {
 }
 
 impl AV1CodecConfigurationRecord {
+    /// Builds the record from a sequence header OBU, which has to carry its
+    /// obu_size field.
+    pub fn from_sequence_header(config_obu: Bytes) -> io::Result<Self> {
+        let (header, data) = ObuHeader::parse(&mut BitReader::from(config_obu.clone()))?;
+
+        if header.obu_type != ObuType::SequenceHeader {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "OBU is not a sequence header",
+            ));
+        }
+
+        let seq = SequenceHeaderObu::parse(header, data)?;
+        let op_point = seq.operating_points.first().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "sequence header has no operating point")
+        })?;
+
+        Ok(AV1CodecConfigurationRecord {
+            marker: true,
+            version: 1,
+            seq_profile: seq.seq_profile,
+            seq_level_idx_0: op_point.seq_level_idx,
+            seq_tier_0: op_point.seq_tier,
+            high_bitdepth: seq.color_config.bit_depth > 8,
+            twelve_bit: seq.color_config.bit_depth == 12,
+            monochrome: seq.color_config.mono_chrome,
+            chroma_subsampling_x: seq.color_config.subsampling_x,
+            chroma_subsampling_y: seq.color_config.subsampling_y,
+            chroma_sample_position: seq.color_config.chroma_sample_position,
+            initial_presentation_delay_minus_one: None,
+            config_obu,
+        })
+    }
+
     pub fn demux(reader: &mut io::Cursor<Bytes>) -> io::Result<Self> {
         let mut bit_reader = BitReader::new(reader);
 
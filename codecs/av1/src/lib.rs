@@ -1,8 +1,10 @@
 mod config;
 mod obu;
+mod temporal_unit;
 
 pub use config::AV1CodecConfigurationRecord;
 pub use obu::{seq, ObuHeader, ObuType};
+pub use temporal_unit::TemporalUnit;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,187 @@
+use std::io;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use bytesio::bit_reader::BitReader;
+
+use crate::{seq::SequenceHeaderObu, ObuHeader, ObuType};
+
+#[derive(Debug, Clone, PartialEq)]
+/// A temporal unit converted from the start code based format used in
+/// MPEG-2 TS to the low overhead bitstream format used in ISOBMFF samples.
+/// AOM AV1 in MPEG-2 TS - 3.2 and AV1-ISOBMFF - 2.4
+pub struct TemporalUnit {
+    /// OBUs with their obu_size field, temporal delimiters and padding removed
+    pub data: Bytes,
+    /// The sequence header OBU, if the temporal unit carries one
+    pub sequence_header: Option<Bytes>,
+    /// The first frame is a shown key frame, making this a sync sample
+    pub key_frame: bool,
+}
+
+impl TemporalUnit {
+    pub fn from_start_codes(data: &[u8]) -> io::Result<Self> {
+        let mut out = BytesMut::new();
+        let mut sequence_header = None;
+        let mut reduced_still_picture_header = false;
+        let mut key_frame = None;
+
+        for obu in split_start_codes(data) {
+            let obu = remove_emulation_prevention(obu);
+            if obu.is_empty() {
+                continue;
+            }
+
+            let obu_type = ObuType::from((obu[0] >> 3) & 0x0f);
+            let extension_flag = obu[0] & 0x04 != 0;
+            let has_size_field = obu[0] & 0x02 != 0;
+            let header_size = if extension_flag { 2 } else { 1 };
+
+            if obu.len() < header_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "OBU is shorter than its header",
+                ));
+            }
+
+            let payload = if has_size_field {
+                let (size, leb128_size) = read_leb128(&obu[header_size..])?;
+                let begin = header_size + leb128_size;
+                obu.get(begin..begin + size as usize).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "OBU is shorter than its obu_size")
+                })?
+            } else {
+                // trailing zero bytes belong to a four byte start code or are
+                // trailing_zero_8bits, neither is part of the OBU
+                let end = obu.iter().rposition(|&byte| byte != 0x00).map_or(header_size, |pos| pos + 1);
+                &obu[header_size..end.max(header_size)]
+            };
+
+            match obu_type {
+                ObuType::TemporalDelimiter | ObuType::Padding => continue,
+                ObuType::SequenceHeader => {
+                    let header = ObuHeader {
+                        obu_type: ObuType::SequenceHeader,
+                        extension_flag,
+                        has_size_field: true,
+                        extension_header: None,
+                    };
+                    let seq = SequenceHeaderObu::parse(header, Bytes::copy_from_slice(payload))?;
+                    reduced_still_picture_header = seq.reduced_still_picture_header;
+                }
+                ObuType::Frame | ObuType::FrameHeader if key_frame.is_none() => {
+                    key_frame = Some(is_shown_key_frame(payload, reduced_still_picture_header)?);
+                }
+                _ => {}
+            }
+
+            let begin = out.len();
+
+            // ISOBMFF samples need the obu_size field on every OBU
+            out.put_u8(obu[0] | 0x02);
+            out.extend_from_slice(&obu[1..header_size]);
+            write_leb128(&mut out, payload.len() as u64);
+            out.extend_from_slice(payload);
+
+            if obu_type == ObuType::SequenceHeader {
+                sequence_header = Some(Bytes::copy_from_slice(&out[begin..]));
+            }
+        }
+
+        // a sync sample has to carry the sequence header ahead of its key frame
+        let key_frame = sequence_header.is_some() && key_frame.unwrap_or(false);
+
+        Ok(Self {
+            data: out.freeze(),
+            sequence_header,
+            key_frame,
+        })
+    }
+}
+
+/// Splits the payload on 0x000001 start codes.
+fn split_start_codes(data: &[u8]) -> Vec<&[u8]> {
+    let mut obus = Vec::new();
+    let mut begin = None;
+    let mut pos = 0;
+
+    while pos + 2 < data.len() {
+        if data[pos] == 0x00 && data[pos + 1] == 0x00 && data[pos + 2] == 0x01 {
+            if let Some(begin) = begin {
+                obus.push(&data[begin..pos]);
+            }
+            pos += 3;
+            begin = Some(pos);
+        } else {
+            pos += 1;
+        }
+    }
+
+    if let Some(begin) = begin {
+        obus.push(&data[begin..]);
+    }
+
+    obus
+}
+
+/// Drops the 0x03 inserted after two zero bytes to prevent start code emulation.
+fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+
+    for &byte in data {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+
+        zeros = if byte == 0x00 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+
+    out
+}
+
+/// Reads the start of the uncompressed_header.
+/// AV1-Spec-2 - 5.9.2
+fn is_shown_key_frame(payload: &[u8], reduced_still_picture_header: bool) -> io::Result<bool> {
+    if reduced_still_picture_header {
+        return Ok(true);
+    }
+
+    let mut bit_reader = BitReader::from(Bytes::copy_from_slice(payload));
+
+    let show_existing_frame = bit_reader.read_bit()?;
+    if show_existing_frame {
+        return Ok(false);
+    }
+
+    let frame_type = bit_reader.read_bits(2)?;
+    let show_frame = bit_reader.read_bit()?;
+
+    Ok(frame_type == 0 && show_frame) // KEY_FRAME
+}
+
+/// AV1-Spec-2 - 4.10.5
+fn read_leb128(data: &[u8]) -> io::Result<(u64, usize)> {
+    let mut value = 0;
+    for (i, byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7f) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "invalid leb128"))
+}
+
+fn write_leb128(out: &mut BytesMut, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.put_u8(byte);
+            break;
+        }
+        out.put_u8(byte | 0x80);
+    }
+}
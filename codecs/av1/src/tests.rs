@@ -5,7 +5,7 @@ use bytesio::bit_reader::BitReader;
 use crate::{
     config::AV1CodecConfigurationRecord,
     seq::{ColorConfig, OperatingPoint, SequenceHeaderObu},
-    ObuHeader, ObuType,
+    ObuHeader, ObuType, TemporalUnit,
 };
 
 #[test]
@@ -104,3 +104,37 @@ fn test_config_mux() {
 
     assert_eq!(buf, data);
 }
+
+#[test]
+fn test_config_from_sequence_header() {
+    let data = b"\x81\r\x0c\0\n\x0f\0\0\0j\xef\xbf\xe1\xbc\x02\x19\x90\x10\x10\x10@".to_vec();
+
+    let config = AV1CodecConfigurationRecord::demux(&mut io::Cursor::new(data.into())).unwrap();
+
+    assert_eq!(
+        AV1CodecConfigurationRecord::from_sequence_header(config.config_obu.clone()).unwrap(),
+        config
+    );
+}
+
+#[test]
+fn test_temporal_unit_from_start_codes() {
+    // temporal delimiter, the sequence header above without obu_size and with
+    // an emulation prevention byte, then a shown key frame
+    let data = b"\0\0\x01\x12\0\0\0\0\x01\x08\0\0\x03\0j\xef\xbf\xe1\xbc\x02\x19\x90\x10\x10\x10@\0\0\x01\x30\x10\xab";
+
+    let tu = TemporalUnit::from_start_codes(data).unwrap();
+
+    let sequence_header = b"\n\x0f\0\0\0j\xef\xbf\xe1\xbc\x02\x19\x90\x10\x10\x10@";
+    assert_eq!(tu.sequence_header.as_deref(), Some(&sequence_header[..]));
+    assert!(tu.key_frame);
+
+    let mut expected = sequence_header.to_vec();
+    expected.extend_from_slice(b"\x32\x02\x10\xab");
+    assert_eq!(&tu.data[..], &expected[..]);
+
+    // an inter frame without a sequence header is not a sync sample
+    let tu = TemporalUnit::from_start_codes(b"\0\0\x01\x12\0\0\0\x01\x30\x30\xab").unwrap();
+    assert!(!tu.key_frame);
+    assert_eq!(&tu.data[..], b"\x32\x02\x30\xab");
+}
@@ -12,6 +12,9 @@ use exp_golomb::{read_exp_golomb, read_signed_exp_golomb};
 
 use crate::{error::HevcError, nal::{self, NaluType}};
 
+/// Upper bound of num_short_term_ref_pic_sets, 7.4.3.2.1
+const MAX_SHORT_TERM_REF_PIC_SETS: usize = 64;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HEVCDecoderConfigurationRecord {
     pub configuration_version: u8,
@@ -40,6 +43,39 @@ pub struct HEVCDecoderConfigurationRecord {
     pub vps: Vec<nal::Unit>,
     pub sps: Vec<nal::Unit>,
     pub pps: Vec<nal::Unit>,
+    /// The arrays hold every parameter set of the stream and none are
+    /// carried in the samples, as `hvc1` requires
+    pub array_completeness: bool,
+
+    //SPS
+    pub width: u32,
+    pub height: u32,
+    pub sar_width: u32,
+    pub sar_height: u32,
+    pub color_config: Option<ColorConfig>,
+    pub timing_info: Option<TimingInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Color config for SPS
+pub struct ColorConfig {
+    pub full_range: bool,
+    pub color_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// VUI timing info, one frame lasts `num_units_in_tick / time_scale` seconds
+pub struct TimingInfo {
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+}
+
+impl TimingInfo {
+    pub fn frame_rate(&self) -> f64 {
+        self.time_scale as f64 / self.num_units_in_tick as f64
+    }
 }
 
 impl Default for HEVCDecoderConfigurationRecord {
@@ -74,6 +110,15 @@ impl Default for HEVCDecoderConfigurationRecord {
             vps: Default::default(),
             sps: Default::default(),
             pps: Default::default(),
+            array_completeness: false,
+
+            //SPS
+            width: Default::default(),
+            height: Default::default(),
+            sar_width: 1,
+            sar_height: 1,
+            color_config: None,
+            timing_info: None,
         }
     }
 }
@@ -94,7 +139,8 @@ impl TryFrom<&[u8]> for HEVCDecoderConfigurationRecord {
 
         buf.advance(22);
 
-        if buf.get_u8() & 0x3f != NaluType::NaluTypeVps as u8 {
+        let vps_array = buf.get_u8();
+        if vps_array & 0x3f != NaluType::NaluTypeVps as u8 {
             return Err(HevcError::NotEnoughData("DCR Vps length"));
         }
 
@@ -164,6 +210,7 @@ impl TryFrom<&[u8]> for HEVCDecoderConfigurationRecord {
         c.vps = vps;
         c.sps = sps;
         c.pps = pps;
+        c.array_completeness = vps_array & 0x80 != 0;
 
         Ok(c)
     }
@@ -196,22 +243,24 @@ impl HEVCDecoderConfigurationRecord {
         Ok(())
     }
 
+    // ISO/IEC 23008-2:2020 - 7.3.2.2
     fn parse_sps(&mut self) -> Result<(), HevcError> {
         let sps = &self.sps[0].data;
         let buffer = HEVCDecoderConfigurationRecord::ebsp_to_rbsp(sps);
         let mut bit_reader = BitReader::from(buffer);
 
-        let video_paramter_set_id = bit_reader.read_bits(4)?;
+        let _video_paramter_set_id = bit_reader.read_bits(4)?;
         let max_sub_layers_minus1 = bit_reader.read_bits(3)?;
-        let temporal_id_nesting_flag = bit_reader.read_bit()?;
+        let _temporal_id_nesting_flag = bit_reader.read_bit()?;
 
+        // profile_tier_level
         self.general_profile_space = bit_reader.read_bits(2)? as u8;
         self.general_tier_flag = bit_reader.read_bit()?;
         self.general_profile_idc =  bit_reader.read_bits(5)? as u8;
         self.general_profile_compatibility_flags = bit_reader.read_u32::<BigEndian>()?;
         self.general_constraint_indicator_flags = bit_reader.read_u48::<BigEndian>()?;
-
         self.general_level_idc = bit_reader.read_u8()?;
+
         let mut sub_layer_profile_present_flag: Vec<bool> = Vec::new();
         let mut sub_layer_level_present_flag: Vec<bool> = Vec::new();
 
@@ -221,58 +270,254 @@ impl HEVCDecoderConfigurationRecord {
         }
 
         if max_sub_layers_minus1 > 0 {
-            for _ in max_sub_layers_minus1..8{
-                bit_reader.read_bits(2)?;
-            } 
+            for _ in max_sub_layers_minus1..8 {
+                bit_reader.seek_bits(2)?; // reserved_zero_2bits
+            }
         }
 
-        for i in 0..max_sub_layers_minus1 {
-            if sub_layer_profile_present_flag[i as usize] {
-                bit_reader.read_u8()?;             // sub_layer_profile_space, sub_layer_tier_flag, sub_layer_profile_idc
-                bit_reader.read_bits(4)?;   // sub_layer_profile_compatibility_flag
-                bit_reader.read_bits(6)?;
+        for i in 0..max_sub_layers_minus1 as usize {
+            if sub_layer_profile_present_flag[i] {
+                // sub_layer_profile_space .. sub_layer_reserved_zero_bit
+                bit_reader.seek_bits(88)?;
             }
 
-            if sub_layer_level_present_flag[i as usize] {
-                bit_reader.read_u8()?;
+            if sub_layer_level_present_flag[i] {
+                bit_reader.seek_bits(8)?; // sub_layer_level_idc
             }
         }
 
         let _seq_parameter_set_id = read_exp_golomb(&mut bit_reader)?;
         self.chroma_format_idc = read_exp_golomb(&mut bit_reader)? as u8;
 
-        if self.chroma_format_idc == 3{
-            bit_reader.read_u8()?;
+        let mut separate_colour_plane_flag = false;
+        if self.chroma_format_idc == 3 {
+            separate_colour_plane_flag = bit_reader.read_bit()?;
         }
 
         let pic_width_in_luma_samples = read_exp_golomb(&mut bit_reader)?;
         let pic_height_in_luma_samples = read_exp_golomb(&mut bit_reader)?;
 
-        let conformance_window_flag = bit_reader.read_bit()?;
-
         let mut left_offset = 0;
         let mut right_offset = 0;
         let mut top_offset = 0;
         let mut bottom_offset = 0;
 
-        if conformance_window_flag {
-            left_offset += read_exp_golomb(&mut bit_reader)?;
-            right_offset += read_exp_golomb(&mut bit_reader)?;
-            top_offset += read_exp_golomb(&mut bit_reader)?;
-            bottom_offset += read_exp_golomb(&mut bit_reader)?;
+        // conformance_window_flag
+        if bit_reader.read_bit()? {
+            left_offset = read_exp_golomb(&mut bit_reader)?;
+            right_offset = read_exp_golomb(&mut bit_reader)?;
+            top_offset = read_exp_golomb(&mut bit_reader)?;
+            bottom_offset = read_exp_golomb(&mut bit_reader)?;
         }
 
+        // the offsets are in chroma samples, Table 6-1
+        let (sub_width_c, sub_height_c) = match (self.chroma_format_idc, separate_colour_plane_flag) {
+            (1, _) => (2, 2),
+            (2, _) => (2, 1),
+            _ => (1, 1),
+        };
+
+        self.width = Self::cropped_size(pic_width_in_luma_samples, sub_width_c, left_offset, right_offset)?;
+        self.height = Self::cropped_size(pic_height_in_luma_samples, sub_height_c, top_offset, bottom_offset)?;
+
         self.bit_depth_luma_minus8 = read_exp_golomb(&mut bit_reader)? as u8;
         self.bit_depth_chroma_minus8 = read_exp_golomb(&mut bit_reader)? as u8;
 
-        let width = pic_width_in_luma_samples - (left_offset + right_offset);
-        let height = pic_height_in_luma_samples - (top_offset + bottom_offset);
+        let log2_max_pic_order_cnt_lsb_minus4 = read_exp_golomb(&mut bit_reader)?;
+
+        // sps_sub_layer_ordering_info_present_flag
+        let first_sub_layer = if bit_reader.read_bit()? { 0 } else { max_sub_layers_minus1 };
+        for _ in first_sub_layer..=max_sub_layers_minus1 {
+            read_exp_golomb(&mut bit_reader)?; // sps_max_dec_pic_buffering_minus1
+            read_exp_golomb(&mut bit_reader)?; // sps_max_num_reorder_pics
+            read_exp_golomb(&mut bit_reader)?; // sps_max_latency_increase_plus1
+        }
+
+        read_exp_golomb(&mut bit_reader)?; // log2_min_luma_coding_block_size_minus3
+        read_exp_golomb(&mut bit_reader)?; // log2_diff_max_min_luma_coding_block_size
+        read_exp_golomb(&mut bit_reader)?; // log2_min_luma_transform_block_size_minus2
+        read_exp_golomb(&mut bit_reader)?; // log2_diff_max_min_luma_transform_block_size
+        read_exp_golomb(&mut bit_reader)?; // max_transform_hierarchy_depth_inter
+        read_exp_golomb(&mut bit_reader)?; // max_transform_hierarchy_depth_intra
+
+        // scaling_list_enabled_flag, sps_scaling_list_data_present_flag
+        if bit_reader.read_bit()? && bit_reader.read_bit()? {
+            // scaling_list_data, 7.3.4
+            for size_id in 0..4 {
+                let mut matrix_id = 0;
+                while matrix_id < 6 {
+                    // scaling_list_pred_mode_flag
+                    if !bit_reader.read_bit()? {
+                        read_exp_golomb(&mut bit_reader)?; // scaling_list_pred_matrix_id_delta
+                    } else {
+                        let coef_num = 64.min(1 << (4 + (size_id << 1)));
+                        if size_id > 1 {
+                            read_signed_exp_golomb(&mut bit_reader)?; // scaling_list_dc_coef_minus8
+                        }
+                        for _ in 0..coef_num {
+                            read_signed_exp_golomb(&mut bit_reader)?; // scaling_list_delta_coef
+                        }
+                    }
+                    matrix_id += if size_id == 3 { 3 } else { 1 };
+                }
+            }
+        }
+
+        bit_reader.seek_bits(1)?; // amp_enabled_flag
+        bit_reader.seek_bits(1)?; // sample_adaptive_offset_enabled_flag
+
+        // pcm_enabled_flag
+        if bit_reader.read_bit()? {
+            bit_reader.seek_bits(4)?; // pcm_sample_bit_depth_luma_minus1
+            bit_reader.seek_bits(4)?; // pcm_sample_bit_depth_chroma_minus1
+            read_exp_golomb(&mut bit_reader)?; // log2_min_pcm_luma_coding_block_size_minus3
+            read_exp_golomb(&mut bit_reader)?; // log2_diff_max_min_pcm_luma_coding_block_size
+            bit_reader.seek_bits(1)?; // pcm_loop_filter_disabled_flag
+        }
+
+        // st_ref_pic_set, 7.3.7
+        let num_short_term_ref_pic_sets = read_exp_golomb(&mut bit_reader)? as usize;
+        if num_short_term_ref_pic_sets > MAX_SHORT_TERM_REF_PIC_SETS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "num_short_term_ref_pic_sets above 64").into());
+        }
+
+        let mut num_delta_pocs: Vec<u64> = Vec::with_capacity(num_short_term_ref_pic_sets);
+        for st_rps_idx in 0..num_short_term_ref_pic_sets {
+            // inter_ref_pic_set_prediction_flag
+            if st_rps_idx != 0 && bit_reader.read_bit()? {
+                bit_reader.seek_bits(1)?; // delta_rps_sign
+                read_exp_golomb(&mut bit_reader)?; // abs_delta_rps_minus1
+
+                // delta_idx_minus1 is only present in slice headers, so the
+                // reference set is always the previous one
+                let mut num_deltas = 0;
+                for _ in 0..=num_delta_pocs[st_rps_idx - 1] {
+                    let used_by_curr_pic_flag = bit_reader.read_bit()?;
+                    let use_delta_flag = used_by_curr_pic_flag || bit_reader.read_bit()?;
+                    if use_delta_flag {
+                        num_deltas += 1;
+                    }
+                }
+                num_delta_pocs.push(num_deltas);
+            } else {
+                let num_negative_pics = read_exp_golomb(&mut bit_reader)?;
+                let num_positive_pics = read_exp_golomb(&mut bit_reader)?;
+                for _ in 0..num_negative_pics + num_positive_pics {
+                    read_exp_golomb(&mut bit_reader)?; // delta_poc_sX_minus1
+                    bit_reader.seek_bits(1)?; // used_by_curr_pic_sX_flag
+                }
+                num_delta_pocs.push(num_negative_pics + num_positive_pics);
+            }
+        }
+
+        // long_term_ref_pics_present_flag
+        if bit_reader.read_bit()? {
+            let num_long_term_ref_pics_sps = read_exp_golomb(&mut bit_reader)?;
+            let lsb_bits = log2_max_pic_order_cnt_lsb_minus4 as i64 + 4;
+            for _ in 0..num_long_term_ref_pics_sps {
+                bit_reader.seek_bits(lsb_bits)?; // lt_ref_pic_poc_lsb_sps
+                bit_reader.seek_bits(1)?; // used_by_curr_pic_lt_sps_flag
+            }
+        }
+
+        bit_reader.seek_bits(1)?; // sps_temporal_mvp_enabled_flag
+        bit_reader.seek_bits(1)?; // strong_intra_smoothing_enabled_flag
+
+        // vui_parameters_present_flag
+        if bit_reader.read_bit()? {
+            self.parse_vui(&mut bit_reader)?;
+        }
 
-        println!("width {}, height {}", width, height);
-            
         Ok(())
     }
 
+    /// Picture size inside the conformance window, whose offsets are in chroma samples.
+    fn cropped_size(size: u64, sub: u64, offset: u64, other_offset: u64) -> Result<u32, HevcError> {
+        offset
+            .checked_add(other_offset)
+            .and_then(|offsets| offsets.checked_mul(sub))
+            .and_then(|crop| size.checked_sub(crop))
+            .and_then(|size| u32::try_from(size).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "conformance window larger than the picture").into())
+    }
+
+    // ISO/IEC 23008-2:2020 - E.2.1, everything after the timing info is
+    // left unread
+    fn parse_vui(&mut self, bit_reader: &mut BitReader) -> Result<(), HevcError> {
+        // aspect_ratio_info_present_flag
+        if bit_reader.read_bit()? {
+            let aspect_ratio_idc = bit_reader.read_u8()? as usize;
+            let sar_w_table = [1, 12, 10, 16, 40, 24, 20, 32, 80, 18, 15, 64, 160, 4, 3, 2];
+            let sar_h_table = [1, 11, 11, 11, 33, 11, 11, 11, 33, 11, 11, 33,  99, 3, 2, 1];
+
+            if 0 < aspect_ratio_idc && aspect_ratio_idc <= 16 {
+                self.sar_width = sar_w_table[aspect_ratio_idc - 1];
+                self.sar_height = sar_h_table[aspect_ratio_idc - 1];
+            } else if aspect_ratio_idc == 255 {
+                self.sar_width = bit_reader.read_bits(16)? as u32;
+                self.sar_height = bit_reader.read_bits(16)? as u32;
+            }
+        }
+
+        // overscan_info_present_flag
+        if bit_reader.read_bit()? {
+            bit_reader.seek_bits(1)?; // overscan_appropriate_flag
+        }
+
+        // video_signal_type_present_flag
+        if bit_reader.read_bit()? {
+            bit_reader.seek_bits(3)?; // video_format
+            let full_range = bit_reader.read_bit()?;
+
+            // colour_description_present_flag
+            let (color_primaries, transfer_characteristics, matrix_coefficients) = if bit_reader.read_bit()? {
+                (bit_reader.read_u8()?, bit_reader.read_u8()?, bit_reader.read_u8()?)
+            } else {
+                (2, 2, 2) // unspecified
+            };
+
+            self.color_config = Some(ColorConfig {
+                full_range,
+                color_primaries,
+                transfer_characteristics,
+                matrix_coefficients,
+            });
+        }
+
+        // chroma_loc_info_present_flag
+        if bit_reader.read_bit()? {
+            read_exp_golomb(bit_reader)?; // chroma_sample_loc_type_top_field
+            read_exp_golomb(bit_reader)?; // chroma_sample_loc_type_bottom_field
+        }
+
+        bit_reader.seek_bits(1)?; // neutral_chroma_indication_flag
+        bit_reader.seek_bits(1)?; // field_seq_flag
+        bit_reader.seek_bits(1)?; // frame_field_info_present_flag
+
+        // default_display_window_flag
+        if bit_reader.read_bit()? {
+            read_exp_golomb(bit_reader)?; // def_disp_win_left_offset
+            read_exp_golomb(bit_reader)?; // def_disp_win_right_offset
+            read_exp_golomb(bit_reader)?; // def_disp_win_top_offset
+            read_exp_golomb(bit_reader)?; // def_disp_win_bottom_offset
+        }
+
+        // vui_timing_info_present_flag
+        if bit_reader.read_bit()? {
+            let timing_info = TimingInfo {
+                num_units_in_tick: bit_reader.read_u32::<BigEndian>()?,
+                time_scale: bit_reader.read_u32::<BigEndian>()?,
+            };
+
+            if timing_info.num_units_in_tick > 0 {
+                // hvcC carries the frame rate in frames per 256 seconds
+                self.avg_frame_rate = (timing_info.frame_rate() * 256.0).min(u16::MAX as f64) as u16;
+                self.timing_info = Some(timing_info);
+            }
+        }
+
+        Ok(())
+    }
 
     fn parse_pps(&mut self) -> Result<(), HevcError> {
         let pps = &self.pps[0].data;
@@ -378,21 +623,21 @@ impl HEVCDecoderConfigurationRecord {
         buf.put_u8(0x03);
 
         //vps
-        buf.put_u8(32u8);
+        buf.put_u8(self.array_header(32u8));
         buf.put_u16(1);
         let temp: Vec<u8> = (&self.vps[0]).into();
         buf.put_u16(temp.len() as u16);
         buf.extend_from_slice(temp.as_slice());
 
         //sps
-        buf.put_u8(33u8);
+        buf.put_u8(self.array_header(33u8));
         buf.put_u16(1);
         let temp: Vec<u8> = (&self.sps[0]).into();
         buf.put_u16(temp.len() as u16);
         buf.extend_from_slice(temp.as_slice());
 
         //pps
-        buf.put_u8(34u8);
+        buf.put_u8(self.array_header(34u8));
         buf.put_u16(1);
         let temp: Vec<u8> = (&self.pps[0]).into();
         buf.put_u16(temp.len() as u16);
@@ -442,21 +687,21 @@ impl HEVCDecoderConfigurationRecord {
         bit_writer.write_u8(0x03)?;
 
         //vps
-        bit_writer.write_u8(32u8)?;
+        bit_writer.write_u8(self.array_header(32u8))?;
         bit_writer.write_u16::<BigEndian>(1)?;
         let temp: Vec<u8> = (&self.vps[0]).into();
         bit_writer.write_u16::<BigEndian>(temp.len() as u16)?;
         bit_writer.write_all(temp.as_slice())?;
 
         //sps
-        bit_writer.write_u8(33u8)?;
+        bit_writer.write_u8(self.array_header(33u8))?;
         bit_writer.write_u16::<BigEndian>(1)?;
         let temp: Vec<u8> = (&self.sps[0]).into();
         bit_writer.write_u16::<BigEndian>(temp.len() as u16)?;
         bit_writer.write_all(temp.as_slice())?;
 
         //pps
-        bit_writer.write_u8(34u8)?;
+        bit_writer.write_u8(self.array_header(34u8))?;
         bit_writer.write_u16::<BigEndian>(1)?;
         let temp: Vec<u8> = (&self.pps[0]).into();
         bit_writer.write_u16::<BigEndian>(temp.len() as u16)?;
@@ -467,6 +712,11 @@ impl HEVCDecoderConfigurationRecord {
         Ok(())
     }
 
+    /// array_completeness, reserved and NAL_unit_type of a parameter set array.
+    fn array_header(&self, nal_unit_type: u8) -> u8 {
+        (self.array_completeness as u8) << 7 | nal_unit_type
+    }
+
     pub fn size(&self) -> u64 {
         1 // configuration_version
         + 1 // general_profile_space, general_tier_flag, general_profile_idc
@@ -500,3 +750,151 @@ impl HEVCDecoderConfigurationRecord {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sps_dimensions_colour_and_timing() {
+        let data = b"B\x01\x01\x01@\0\0\x03\0\x90\0\0\x03\0\0\x03\0\x99\xa0\x01@ \x05\xa1e\x95R\x90\x84d_\xf8\xc0Z\x80\x80\x80\x82\0\0\x03\0\x02\0\0\x03\x01 \xc0\x0b\xbc\xa2\0\x02bX\0\x011-\x08";
+
+        let mut dcr = HEVCDecoderConfigurationRecord {
+            sps: vec![nal::Unit::try_from(&data[..]).unwrap()],
+            ..Default::default()
+        };
+        dcr.parse_sps().unwrap();
+
+        assert_eq!(dcr.general_profile_idc, 1);
+        assert_eq!(dcr.general_level_idc, 153);
+        assert_eq!(dcr.chroma_format_idc, 1);
+        assert_eq!((dcr.width, dcr.height), (2560, 1440));
+        assert_eq!((dcr.sar_width, dcr.sar_height), (1, 1));
+        assert_eq!(
+            dcr.color_config,
+            Some(ColorConfig {
+                full_range: false,
+                color_primaries: 1,
+                transfer_characteristics: 1,
+                matrix_coefficients: 1,
+            })
+        );
+        assert_eq!(dcr.timing_info.as_ref().map(TimingInfo::frame_rate), Some(144.0));
+        assert_eq!(dcr.avg_frame_rate, 144 * 256);
+    }
+
+    /// A 4:2:0 1920x1088 main profile SPS without VUI.
+    fn sps(conformance_window: Option<[u64; 4]>, num_short_term_ref_pic_sets: u64) -> nal::Unit {
+        use exp_golomb::write_exp_golomb;
+
+        let mut writer = BitWriter::default();
+        writer.write_bits(0x4201, 16).unwrap(); // nal_unit_header
+        writer.write_bits(0, 4).unwrap(); // sps_video_parameter_set_id
+        writer.write_bits(0, 3).unwrap(); // sps_max_sub_layers_minus1
+        writer.write_bit(true).unwrap(); // sps_temporal_id_nesting_flag
+        writer.write_bits(1, 8).unwrap(); // general_profile_space, tier and idc
+        writer.write_bits(0x6000_0000, 32).unwrap();
+        writer.write_bits(0x9000_0000_0000, 48).unwrap();
+        writer.write_bits(93, 8).unwrap(); // general_level_idc
+
+        for value in [0, 1, 1920, 1088] {
+            write_exp_golomb(&mut writer, value).unwrap();
+        }
+
+        writer.write_bit(conformance_window.is_some()).unwrap();
+        for offset in conformance_window.into_iter().flatten() {
+            write_exp_golomb(&mut writer, offset).unwrap();
+        }
+
+        // bit depths, log2_max_pic_order_cnt_lsb_minus4
+        for value in [0, 0, 4] {
+            write_exp_golomb(&mut writer, value).unwrap();
+        }
+
+        writer.write_bit(true).unwrap(); // sps_sub_layer_ordering_info_present_flag
+        for value in [4, 0, 0, 0, 3, 0, 3, 0, 0] {
+            write_exp_golomb(&mut writer, value).unwrap();
+        }
+
+        writer.write_bit(false).unwrap(); // scaling_list_enabled_flag
+        writer.write_bit(false).unwrap(); // amp_enabled_flag
+        writer.write_bit(true).unwrap(); // sample_adaptive_offset_enabled_flag
+        writer.write_bit(false).unwrap(); // pcm_enabled_flag
+
+        write_exp_golomb(&mut writer, num_short_term_ref_pic_sets).unwrap();
+        for index in 0..num_short_term_ref_pic_sets {
+            if index != 0 {
+                writer.write_bit(false).unwrap(); // inter_ref_pic_set_prediction_flag
+            }
+            write_exp_golomb(&mut writer, 1).unwrap(); // num_negative_pics
+            write_exp_golomb(&mut writer, 0).unwrap(); // num_positive_pics
+            write_exp_golomb(&mut writer, 0).unwrap(); // delta_poc_s0_minus1
+            writer.write_bit(true).unwrap(); // used_by_curr_pic_s0_flag
+        }
+
+        writer.write_bit(false).unwrap(); // long_term_ref_pics_present_flag
+        writer.write_bit(true).unwrap(); // sps_temporal_mvp_enabled_flag
+        writer.write_bit(true).unwrap(); // strong_intra_smoothing_enabled_flag
+        writer.write_bit(false).unwrap(); // vui_parameters_present_flag
+        writer.write_bit(true).unwrap(); // rbsp_stop_one_bit
+        writer.align().unwrap();
+
+        nal::Unit::try_from(&writer.into_inner()[..]).unwrap()
+    }
+
+    fn parse(unit: nal::Unit) -> Result<HEVCDecoderConfigurationRecord, HevcError> {
+        let mut dcr = HEVCDecoderConfigurationRecord {
+            sps: vec![unit],
+            ..Default::default()
+        };
+        dcr.parse_sps()?;
+        Ok(dcr)
+    }
+
+    #[test]
+    fn crops_to_conformance_window() {
+        let dcr = parse(sps(Some([0, 0, 0, 4]), 1)).unwrap();
+        assert_eq!((dcr.width, dcr.height), (1920, 1080));
+
+        let dcr = parse(sps(None, 1)).unwrap();
+        assert_eq!((dcr.width, dcr.height), (1920, 1088));
+    }
+
+    #[test]
+    fn rejects_conformance_window_larger_than_picture() {
+        let err = parse(sps(Some([500, 500, 0, 0]), 1)).unwrap_err();
+        assert!(matches!(err, HevcError::IOError(err) if err.kind() == io::ErrorKind::InvalidData));
+
+        assert!(parse(sps(Some([0, 0, u64::MAX / 2, u64::MAX / 2]), 1)).is_err());
+    }
+
+    #[test]
+    fn limits_short_term_ref_pic_sets() {
+        assert!(parse(sps(None, 64)).is_ok());
+
+        let err = parse(sps(None, 65)).unwrap_err();
+        assert!(matches!(err, HevcError::IOError(err) if err.kind() == io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn writes_array_completeness() {
+        let mut dcr = HEVCDecoderConfigurationRecord {
+            vps: vec![nal::Unit::try_from(&[0x40, 0x01, 0x0C][..]).unwrap()],
+            sps: vec![sps(None, 1)],
+            pps: vec![nal::Unit::try_from(&[0x44, 0x01, 0xC1][..]).unwrap()],
+            ..Default::default()
+        };
+
+        for array_completeness in [false, true] {
+            dcr.array_completeness = array_completeness;
+
+            let mut data = Vec::new();
+            dcr.mux(&mut data).unwrap();
+            assert_eq!(data[23], (array_completeness as u8) << 7 | 32);
+
+            let parsed = HEVCDecoderConfigurationRecord::try_from(&data[..]).unwrap();
+            assert_eq!(parsed.array_completeness, array_completeness);
+            assert_eq!((parsed.vps, parsed.sps, parsed.pps), (dcr.vps.clone(), dcr.sps.clone(), dcr.pps.clone()));
+        }
+    }
+}
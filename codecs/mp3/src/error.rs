@@ -0,0 +1,22 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Mp3Error {
+    #[error("Not enough data: {0}")]
+    NotEnoughData(&'static str),
+
+    #[error("Frame sync not found")]
+    SyncNotFound,
+
+    #[error("Reserved MPEG audio version")]
+    ReservedVersion,
+
+    #[error("Unsupported layer {0}, only Layer III is supported")]
+    UnsupportedLayer(u8),
+
+    #[error("Free format or reserved bit rate index {0}")]
+    UnsupportedBitRate(u8),
+
+    #[error("Reserved sample rate index {0}")]
+    ReservedSampleRate(u8),
+}
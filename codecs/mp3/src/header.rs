@@ -0,0 +1,113 @@
+use crate::error::Mp3Error;
+
+/// Bit rates in kbit/s of Layer III, indexed by bitrate_index
+const MPEG1_BIT_RATES: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+const MPEG2_BIT_RATES: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+const MPEG1_SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpegVersion {
+    Mpeg1,
+    Mpeg2,
+    /// Unofficial extension of MPEG-2 to lower sample rates
+    Mpeg25,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// MPEG audio frame header, restricted to Layer III.
+/// ISO/IEC 11172-3 - 2.4.1.3 and ISO/IEC 13818-3 - 2.4.1.3
+pub struct FrameHeader {
+    pub version: MpegVersion,
+    /// CRC follows the header
+    pub protected: bool,
+    /// kbit/s
+    pub bit_rate: u32,
+    pub sample_rate: u32,
+    pub padding: bool,
+    pub channel_mode: u8,
+}
+
+impl FrameHeader {
+    pub const SIZE: usize = 4;
+
+    pub fn parse(data: &[u8]) -> Result<Self, Mp3Error> {
+        if data.len() < Self::SIZE {
+            return Err(Mp3Error::NotEnoughData("frame header"));
+        }
+
+        if !Self::is_sync(data) {
+            return Err(Mp3Error::SyncNotFound);
+        }
+
+        let version = match (data[1] >> 3) & 0x03 {
+            0b00 => MpegVersion::Mpeg25,
+            0b10 => MpegVersion::Mpeg2,
+            0b11 => MpegVersion::Mpeg1,
+            _ => return Err(Mp3Error::ReservedVersion),
+        };
+
+        // layer is coded as 4 - layer, 0 is reserved
+        let layer = 4 - ((data[1] >> 1) & 0x03);
+        if layer != 3 {
+            return Err(Mp3Error::UnsupportedLayer(layer));
+        }
+
+        let protected = data[1] & 0x01 == 0;
+
+        let bit_rate_index = data[2] >> 4;
+        let bit_rates = match version {
+            MpegVersion::Mpeg1 => &MPEG1_BIT_RATES,
+            _ => &MPEG2_BIT_RATES,
+        };
+        let bit_rate = match bit_rates.get(bit_rate_index as usize) {
+            Some(&bit_rate) if bit_rate != 0 => bit_rate,
+            _ => return Err(Mp3Error::UnsupportedBitRate(bit_rate_index)),
+        };
+
+        let sample_rate_index = (data[2] >> 2) & 0x03;
+        let sample_rate = *MPEG1_SAMPLE_RATES
+            .get(sample_rate_index as usize)
+            .ok_or(Mp3Error::ReservedSampleRate(sample_rate_index))?;
+        let sample_rate = match version {
+            MpegVersion::Mpeg1 => sample_rate,
+            MpegVersion::Mpeg2 => sample_rate / 2,
+            MpegVersion::Mpeg25 => sample_rate / 4,
+        };
+
+        Ok(Self {
+            version,
+            protected,
+            bit_rate,
+            sample_rate,
+            padding: data[2] & 0x02 != 0,
+            channel_mode: data[3] >> 6,
+        })
+    }
+
+    /// Samples per channel in the frame
+    pub fn samples(&self) -> u32 {
+        match self.version {
+            MpegVersion::Mpeg1 => 1152,
+            _ => 576,
+        }
+    }
+
+    /// Size of the whole frame in bytes, header included
+    pub fn frame_size(&self) -> usize {
+        (self.samples() / 8 * self.bit_rate * 1000 / self.sample_rate) as usize + self.padding as usize
+    }
+
+    pub fn channels(&self) -> u8 {
+        // channel_mode 3 is single channel
+        if self.channel_mode == 0x03 {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub(crate) fn is_sync(data: &[u8]) -> bool {
+        data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0
+    }
+}
@@ -0,0 +1,12 @@
+pub mod error;
+pub mod header;
+pub mod reader;
+
+pub use self::{
+    error::Mp3Error,
+    header::{FrameHeader, MpegVersion},
+    reader::{Frame, FrameReader},
+};
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,75 @@
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::{error::Mp3Error, header::FrameHeader};
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub header: FrameHeader,
+    pub data: Bytes,
+}
+
+/// Splits PES payloads into frames. A frame is allowed to span PES
+/// packets, so the tail of one payload is kept for the next.
+#[derive(Debug, Default)]
+pub struct FrameReader {
+    buffer: BytesMut,
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<Frame>, Mp3Error> {
+        self.buffer.extend_from_slice(data);
+
+        let mut frames = Vec::new();
+
+        loop {
+            if !self.resync() || self.buffer.len() < FrameHeader::SIZE {
+                break;
+            }
+
+            let header = match FrameHeader::parse(&self.buffer) {
+                Ok(header) => header,
+                Err(Mp3Error::NotEnoughData(_)) => break,
+                Err(err) => {
+                    // an emulated sync in the middle of a frame looks the same
+                    log::debug!("Skipping invalid MPEG audio frame header: {}", err);
+                    self.buffer.advance(1);
+                    continue;
+                }
+            };
+
+            if self.buffer.len() < header.frame_size() {
+                break;
+            }
+
+            let data = self.buffer.split_to(header.frame_size()).freeze();
+            frames.push(Frame { header, data });
+        }
+
+        Ok(frames)
+    }
+
+    /// Drops anything in front of the next frame sync, returns whether one was found.
+    fn resync(&mut self) -> bool {
+        let position = self.buffer.windows(2).position(FrameHeader::is_sync);
+
+        match position {
+            Some(position) => {
+                if position != 0 {
+                    log::debug!("Dropping {} bytes in front of the MPEG audio frame sync", position);
+                }
+                self.buffer.advance(position);
+                true
+            }
+            None => {
+                // the last byte could be the first half of a sync
+                let keep = self.buffer.len().min(1);
+                self.buffer.advance(self.buffer.len() - keep);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{FrameHeader, FrameReader, MpegVersion};
+
+/// MPEG-1 Layer III, 128 kbit/s, 44.1 kHz, joint stereo
+const MPEG1_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x40];
+
+fn frame(header: &[u8], frame_size: usize) -> Vec<u8> {
+    let mut frame = header.to_vec();
+    frame.resize(frame_size, 0x55);
+    frame
+}
+
+#[test]
+fn test_parse_mpeg1_header() {
+    let header = FrameHeader::parse(&MPEG1_HEADER).unwrap();
+
+    assert_eq!(header.version, MpegVersion::Mpeg1);
+    assert!(!header.protected);
+    assert_eq!(header.bit_rate, 128);
+    assert_eq!(header.sample_rate, 44100);
+    assert_eq!(header.samples(), 1152);
+    assert_eq!(header.frame_size(), 417);
+    assert_eq!(header.channels(), 2);
+
+    let padded = FrameHeader::parse(&[0xFF, 0xFB, 0x92, 0x40]).unwrap();
+    assert_eq!(padded.frame_size(), 418);
+}
+
+#[test]
+fn test_parse_mpeg2_header() {
+    let header = FrameHeader::parse(&[0xFF, 0xF3, 0x80, 0xC0]).unwrap();
+
+    assert_eq!(header.version, MpegVersion::Mpeg2);
+    assert_eq!(header.bit_rate, 64);
+    assert_eq!(header.sample_rate, 22050);
+    assert_eq!(header.samples(), 576);
+    assert_eq!(header.frame_size(), 208);
+    assert_eq!(header.channels(), 1);
+}
+
+#[test]
+fn test_rejects_other_layers() {
+    // MPEG-1 Layer II
+    assert!(FrameHeader::parse(&[0xFF, 0xFD, 0x90, 0x40]).is_err());
+    // free format
+    assert!(FrameHeader::parse(&[0xFF, 0xFB, 0x00, 0x40]).is_err());
+}
+
+#[test]
+fn test_reader_across_payloads() {
+    let mut data = vec![0x00, 0x01, 0x02];
+    data.extend(frame(&MPEG1_HEADER, 417));
+    data.extend(frame(&MPEG1_HEADER, 417));
+
+    let mut reader = FrameReader::new();
+
+    let frames = reader.push(&data[..500]).unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].data.len(), 417);
+
+    let frames = reader.push(&data[500..]).unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(&frames[0].data[..4], &MPEG1_HEADER);
+}
@@ -0,0 +1,66 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Channel layout of an Opus stream, as carried in the OpusHead and dOps.
+/// RFC 7845 - 5.1.1
+pub struct ChannelConfig {
+    pub channels: u8,
+    pub mapping_family: u8,
+    pub stream_count: u8,
+    pub coupled_count: u8,
+    pub mapping: Vec<u8>,
+}
+
+/// Vorbis channel order for mapping family 1, indexed by channel count - 1
+const VORBIS_CHANNEL_MAPPING: [&[u8]; 8] = [
+    &[0],
+    &[0, 1],
+    &[0, 2, 1],
+    &[0, 1, 2, 3],
+    &[0, 4, 1, 2, 3],
+    &[0, 4, 1, 2, 3, 5],
+    &[0, 4, 1, 2, 3, 5, 6],
+    &[0, 6, 1, 2, 3, 4, 5, 7],
+];
+
+/// Stream and coupled stream counts for 3 to 8 channels
+const STREAM_COUNTS: [(u8, u8); 6] = [(2, 1), (2, 2), (3, 2), (4, 2), (4, 3), (5, 3)];
+
+impl ChannelConfig {
+    /// Reads the channel_config_code of the Opus audio extension descriptor.
+    /// Codes 0x81 and above carry an explicit mapping and are not supported.
+    pub fn from_code(channel_config_code: u8) -> Option<Self> {
+        match channel_config_code {
+            // dual mono
+            0x00 | 0x80 => Some(Self {
+                channels: 2,
+                mapping_family: 255,
+                stream_count: 2,
+                coupled_count: 0,
+                mapping: vec![0, 1],
+            }),
+            0x01 | 0x02 => Some(Self {
+                channels: channel_config_code,
+                mapping_family: 0,
+                stream_count: 1,
+                coupled_count: channel_config_code - 1,
+                mapping: VORBIS_CHANNEL_MAPPING[channel_config_code as usize - 1].to_vec(),
+            }),
+            0x03..=0x08 => {
+                let (stream_count, coupled_count) = STREAM_COUNTS[channel_config_code as usize - 3];
+                Some(Self {
+                    channels: channel_config_code,
+                    mapping_family: 1,
+                    stream_count,
+                    coupled_count,
+                    mapping: VORBIS_CHANNEL_MAPPING[channel_config_code as usize - 1].to_vec(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self::from_code(0x02).expect("stereo is always supported")
+    }
+}
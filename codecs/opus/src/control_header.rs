@@ -0,0 +1,89 @@
+use std::io;
+
+use bytes::{Buf, Bytes};
+
+const CONTROL_HEADER_PREFIX: u16 = 0x3ff;
+
+#[derive(Debug, Clone, PartialEq)]
+/// An Opus packet taken out of a PES payload, with the number of samples
+/// at 48 kHz to drop from its start and end.
+pub struct AccessUnit {
+    pub data: Bytes,
+    pub start_trim: u16,
+    pub end_trim: u16,
+}
+
+/// Splits a PES payload into the access units behind each opus_control_header.
+/// ETSI TS 102 366 - Opus in MPEG-2 TS, opus_access_unit
+pub fn read_access_units(data: &[u8]) -> io::Result<Vec<AccessUnit>> {
+    let mut reader = io::Cursor::new(Bytes::copy_from_slice(data));
+    let mut access_units = Vec::new();
+
+    while reader.has_remaining() {
+        if reader.remaining() < 3 {
+            return Err(invalid_data("opus_control_header is truncated"));
+        }
+
+        let flags = reader.get_u16();
+        if flags >> 5 != CONTROL_HEADER_PREFIX {
+            return Err(invalid_data("opus_control_header prefix not found"));
+        }
+
+        let start_trim_flag = flags & 0x10 != 0;
+        let end_trim_flag = flags & 0x08 != 0;
+        let control_extension_flag = flags & 0x04 != 0;
+
+        let mut au_size = 0;
+        loop {
+            if !reader.has_remaining() {
+                return Err(invalid_data("au_size is truncated"));
+            }
+
+            let byte = reader.get_u8();
+            au_size += byte as usize;
+            if byte != 0xff {
+                break;
+            }
+        }
+
+        let start_trim = if start_trim_flag { read_trim(&mut reader)? } else { 0 };
+        let end_trim = if end_trim_flag { read_trim(&mut reader)? } else { 0 };
+
+        if control_extension_flag {
+            if !reader.has_remaining() {
+                return Err(invalid_data("control_extension_length is missing"));
+            }
+
+            let control_extension_length = reader.get_u8() as usize;
+            if reader.remaining() < control_extension_length {
+                return Err(invalid_data("control extension is truncated"));
+            }
+            reader.advance(control_extension_length);
+        }
+
+        if reader.remaining() < au_size {
+            return Err(invalid_data("access unit is shorter than au_size"));
+        }
+
+        access_units.push(AccessUnit {
+            data: reader.copy_to_bytes(au_size),
+            start_trim,
+            end_trim,
+        });
+    }
+
+    Ok(access_units)
+}
+
+fn read_trim(reader: &mut io::Cursor<Bytes>) -> io::Result<u16> {
+    if reader.remaining() < 2 {
+        return Err(invalid_data("trim is truncated"));
+    }
+
+    // 3 reserved bits
+    Ok(reader.get_u16() & 0x1fff)
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,13 @@
+mod config;
+mod control_header;
+mod packet;
+
+pub use config::ChannelConfig;
+pub use control_header::{read_access_units, AccessUnit};
+pub use packet::packet_duration;
+
+/// Opus always decodes at 48 kHz
+pub const SAMPLE_RATE: u32 = 48000;
+
+#[cfg(test)]
+mod tests;
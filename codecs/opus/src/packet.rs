@@ -0,0 +1,23 @@
+/// Number of samples at 48 kHz an Opus packet decodes to.
+/// RFC 6716 - 3.1
+pub fn packet_duration(packet: &[u8]) -> Option<u32> {
+    let toc = *packet.first()?;
+    let config = toc >> 3;
+
+    let frame_size = match config {
+        // SILK only, 10, 20, 40 and 60 ms
+        0..=11 => [480, 960, 1920, 2880][config as usize % 4],
+        // hybrid, 10 and 20 ms
+        12..=15 => [480, 960][config as usize % 2],
+        // CELT only, 2.5, 5, 10 and 20 ms
+        _ => [120, 240, 480, 960][config as usize % 4],
+    };
+
+    let frame_count = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => (*packet.get(1)? & 0x3f) as u32,
+    };
+
+    Some(frame_size * frame_count)
+}
@@ -0,0 +1,61 @@
+use crate::{packet_duration, read_access_units, AccessUnit, ChannelConfig};
+
+#[test]
+fn test_read_access_units() {
+    let mut data = vec![0x7f, 0xe0, 0x03, 0xfc, 0xff, 0xfe];
+    // start trim of 312 samples and a 300 byte access unit
+    data.extend_from_slice(&[0x7f, 0xf0, 0xff, 0x2d, 0x01, 0x38]);
+    data.extend_from_slice(&[0x78; 300]);
+
+    let access_units = read_access_units(&data).unwrap();
+
+    assert_eq!(access_units.len(), 2);
+    assert_eq!(
+        access_units[0],
+        AccessUnit {
+            data: vec![0xfc, 0xff, 0xfe].into(),
+            start_trim: 0,
+            end_trim: 0,
+        }
+    );
+    assert_eq!(access_units[1].start_trim, 312);
+    assert_eq!(access_units[1].end_trim, 0);
+    assert_eq!(access_units[1].data.len(), 300);
+}
+
+#[test]
+fn test_read_access_units_skips_control_extension() {
+    let data = [0x7f, 0xec, 0x01, 0x00, 0x05, 0x02, 0xaa, 0xbb, 0xfc];
+
+    let access_units = read_access_units(&data).unwrap();
+
+    assert_eq!(access_units.len(), 1);
+    assert_eq!(access_units[0].end_trim, 5);
+    assert_eq!(&access_units[0].data[..], &[0xfc]);
+
+    assert!(read_access_units(&[0x0b, 0x77, 0x01, 0x00]).is_err());
+}
+
+#[test]
+fn test_packet_duration() {
+    // CELT 20 ms, one frame
+    assert_eq!(packet_duration(&[0xfc]), Some(960));
+    // SILK 60 ms, two frames
+    assert_eq!(packet_duration(&[0x19]), Some(5760));
+    // CELT 2.5 ms, signalled frame count
+    assert_eq!(packet_duration(&[0x83, 0x04]), Some(480));
+    assert_eq!(packet_duration(&[]), None);
+}
+
+#[test]
+fn test_channel_config_from_code() {
+    assert_eq!(ChannelConfig::default().channels, 2);
+    assert_eq!(ChannelConfig::default().mapping_family, 0);
+
+    let surround = ChannelConfig::from_code(0x06).unwrap();
+    assert_eq!(surround.mapping_family, 1);
+    assert_eq!((surround.stream_count, surround.coupled_count), (4, 2));
+    assert_eq!(surround.mapping, vec![0, 4, 1, 2, 3, 5]);
+
+    assert_eq!(ChannelConfig::from_code(0x82), None);
+}
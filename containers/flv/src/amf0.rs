@@ -0,0 +1,227 @@
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::FlvError;
+
+const NUMBER: u8 = 0x00;
+const BOOLEAN: u8 = 0x01;
+const STRING: u8 = 0x02;
+const OBJECT: u8 = 0x03;
+const NULL: u8 = 0x05;
+const UNDEFINED: u8 = 0x06;
+const ECMA_ARRAY: u8 = 0x08;
+const OBJECT_END: u8 = 0x09;
+const STRICT_ARRAY: u8 = 0x0A;
+const DATE: u8 = 0x0B;
+const LONG_STRING: u8 = 0x0C;
+
+/// Deepest nesting of objects and arrays accepted while decoding, the
+/// decoder recurses once per level.
+pub const MAX_DEPTH: usize = 32;
+
+/// AMF0 values as used by RTMP commands and FLV script data. Object
+/// properties keep their order, which some clients rely on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Amf0Value {
+    Number(f64),
+    Boolean(bool),
+    String(String),
+    Object(Vec<(String, Amf0Value)>),
+    Null,
+    Undefined,
+    EcmaArray(Vec<(String, Amf0Value)>),
+    StrictArray(Vec<Amf0Value>),
+    /// Milliseconds since the epoch, the time zone is ignored
+    Date(f64),
+}
+
+impl Amf0Value {
+    /// Decodes every value in `data`, e.g. a command name followed by its arguments.
+    pub fn decode_all(data: &[u8]) -> Result<Vec<Self>, FlvError> {
+        let mut data = Bytes::copy_from_slice(data);
+        let mut values = Vec::new();
+
+        while data.has_remaining() {
+            values.push(Self::decode(&mut data)?);
+        }
+
+        Ok(values)
+    }
+
+    pub fn decode(data: &mut Bytes) -> Result<Self, FlvError> {
+        Self::decode_nested(data, 0)
+    }
+
+    fn decode_nested(data: &mut Bytes, depth: usize) -> Result<Self, FlvError> {
+        if depth > MAX_DEPTH {
+            return Err(FlvError::Amf0TooDeep(MAX_DEPTH));
+        }
+
+        ensure(data, 1, "AMF0 type marker")?;
+
+        Ok(match data.get_u8() {
+            NUMBER => {
+                ensure(data, 8, "AMF0 number")?;
+                Self::Number(data.get_f64())
+            },
+            BOOLEAN => {
+                ensure(data, 1, "AMF0 boolean")?;
+                Self::Boolean(data.get_u8() != 0)
+            },
+            STRING => Self::String(read_string(data)?),
+            LONG_STRING => {
+                ensure(data, 4, "AMF0 long string length")?;
+                let length = data.get_u32() as usize;
+                Self::String(read_utf8(data, length)?)
+            },
+            OBJECT => Self::Object(read_properties(data, depth + 1)?),
+            NULL => Self::Null,
+            UNDEFINED => Self::Undefined,
+            ECMA_ARRAY => {
+                // the count is only a hint, the array is terminated like an object
+                ensure(data, 4, "AMF0 ECMA array count")?;
+                data.advance(4);
+                Self::EcmaArray(read_properties(data, depth + 1)?)
+            },
+            STRICT_ARRAY => {
+                ensure(data, 4, "AMF0 strict array count")?;
+                let count = data.get_u32();
+                let mut values = Vec::new();
+                for _ in 0..count {
+                    values.push(Self::decode_nested(data, depth + 1)?);
+                }
+                Self::StrictArray(values)
+            },
+            DATE => {
+                ensure(data, 10, "AMF0 date")?;
+                let date = data.get_f64();
+                data.advance(2);
+                Self::Date(date)
+            },
+            marker => return Err(FlvError::UnknownAmf0Marker(marker)),
+        })
+    }
+
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        match self {
+            Self::Number(value) => {
+                buf.put_u8(NUMBER);
+                buf.put_f64(*value);
+            },
+            Self::Boolean(value) => {
+                buf.put_u8(BOOLEAN);
+                buf.put_u8(*value as u8);
+            },
+            Self::String(value) => {
+                if value.len() > u16::MAX as usize {
+                    buf.put_u8(LONG_STRING);
+                    buf.put_u32(value.len() as u32);
+                    buf.put_slice(value.as_bytes());
+                } else {
+                    buf.put_u8(STRING);
+                    write_string(buf, value);
+                }
+            },
+            Self::Object(properties) => {
+                buf.put_u8(OBJECT);
+                write_properties(buf, properties);
+            },
+            Self::Null => buf.put_u8(NULL),
+            Self::Undefined => buf.put_u8(UNDEFINED),
+            Self::EcmaArray(properties) => {
+                buf.put_u8(ECMA_ARRAY);
+                buf.put_u32(properties.len() as u32);
+                write_properties(buf, properties);
+            },
+            Self::StrictArray(values) => {
+                buf.put_u8(STRICT_ARRAY);
+                buf.put_u32(values.len() as u32);
+                for value in values {
+                    value.encode(buf);
+                }
+            },
+            Self::Date(value) => {
+                buf.put_u8(DATE);
+                buf.put_f64(*value);
+                buf.put_u16(0);
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Looks a property up on an object or ECMA array.
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        match self {
+            Self::Object(properties) | Self::EcmaArray(properties) => properties
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+fn ensure(data: &Bytes, length: usize, what: &'static str) -> Result<(), FlvError> {
+    if data.remaining() < length {
+        return Err(FlvError::NotEnoughData(what));
+    }
+
+    Ok(())
+}
+
+fn read_utf8(data: &mut Bytes, length: usize) -> Result<String, FlvError> {
+    ensure(data, length, "AMF0 string")?;
+    let bytes = data.split_to(length);
+    String::from_utf8(bytes.to_vec()).map_err(|_| FlvError::InvalidString)
+}
+
+fn read_string(data: &mut Bytes) -> Result<String, FlvError> {
+    ensure(data, 2, "AMF0 string length")?;
+    let length = data.get_u16() as usize;
+    read_utf8(data, length)
+}
+
+fn read_properties(data: &mut Bytes, depth: usize) -> Result<Vec<(String, Amf0Value)>, FlvError> {
+    let mut properties = Vec::new();
+
+    loop {
+        let name = read_string(data)?;
+
+        if name.is_empty() {
+            ensure(data, 1, "AMF0 object end")?;
+            if data[0] == OBJECT_END {
+                data.advance(1);
+                return Ok(properties);
+            }
+        }
+
+        properties.push((name, Amf0Value::decode_nested(data, depth)?));
+    }
+}
+
+fn write_string<B: BufMut>(buf: &mut B, value: &str) {
+    buf.put_u16(value.len() as u16);
+    buf.put_slice(value.as_bytes());
+}
+
+fn write_properties<B: BufMut>(buf: &mut B, properties: &[(String, Amf0Value)]) {
+    for (name, value) in properties {
+        write_string(buf, name);
+        value.encode(buf);
+    }
+
+    write_string(buf, "");
+    buf.put_u8(OBJECT_END);
+}
@@ -0,0 +1,40 @@
+use bytes::{Buf, Bytes};
+
+use crate::FlvError;
+
+const SOUND_FORMAT_AAC: u8 = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioPacket {
+    /// AudioSpecificConfig
+    SequenceHeader(Bytes),
+    /// A single raw AAC frame
+    Raw(Bytes),
+}
+
+/// An FLV AUDIODATA body. Only AAC is supported, the sound rate, size and
+/// type fields are fixed for AAC and the real values live in the AudioSpecificConfig.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioTag {
+    pub packet: AudioPacket,
+}
+
+impl AudioTag {
+    pub fn parse(mut data: Bytes) -> Result<Self, FlvError> {
+        if data.remaining() < 2 {
+            return Err(FlvError::NotEnoughData("audio tag header"));
+        }
+
+        let sound_format = data.get_u8() >> 4;
+        if sound_format != SOUND_FORMAT_AAC {
+            return Err(FlvError::UnsupportedSoundFormat(sound_format));
+        }
+
+        let packet = match data.get_u8() {
+            0 => AudioPacket::SequenceHeader(data),
+            _ => AudioPacket::Raw(data),
+        };
+
+        Ok(Self { packet })
+    }
+}
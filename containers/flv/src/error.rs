@@ -0,0 +1,28 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FlvError {
+    #[error("Not enough data: {0}")]
+    NotEnoughData(&'static str),
+
+    #[error("Unsupported video codec id {0}")]
+    UnsupportedVideoCodec(u8),
+
+    #[error("Unsupported video FourCC {0:?}")]
+    UnsupportedFourCc([u8; 4]),
+
+    #[error("Unsupported video packet type {0}")]
+    UnsupportedVideoPacketType(u8),
+
+    #[error("Unsupported sound format {0}")]
+    UnsupportedSoundFormat(u8),
+
+    #[error("Unknown AMF0 type marker {0:#04x}")]
+    UnknownAmf0Marker(u8),
+
+    #[error("AMF0 values nested deeper than {0} levels")]
+    Amf0TooDeep(usize),
+
+    #[error("Invalid UTF-8 in AMF0 string")]
+    InvalidString,
+}
@@ -0,0 +1,14 @@
+pub mod amf0;
+pub mod audio;
+pub mod error;
+pub mod video;
+
+pub use self::{
+    amf0::Amf0Value,
+    audio::{AudioPacket, AudioTag},
+    error::FlvError,
+    video::{VideoCodec, VideoPacket, VideoTag},
+};
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,122 @@
+use bytes::Bytes;
+
+use crate::{amf0::MAX_DEPTH, Amf0Value, AudioPacket, AudioTag, FlvError, VideoCodec, VideoPacket, VideoTag};
+
+#[test]
+fn test_parse_legacy_avc() {
+    let header = VideoTag::parse(Bytes::from_static(&[0x17, 0x00, 0x00, 0x00, 0x00, 0x01, 0x64, 0x00, 0x1F])).unwrap();
+
+    assert_eq!(header.codec, VideoCodec::Avc);
+    assert!(header.key_frame);
+    assert_eq!(header.packet, VideoPacket::SequenceStart(Bytes::from_static(&[0x01, 0x64, 0x00, 0x1F])));
+
+    // inter frame with a composition time of -40ms
+    let frame = VideoTag::parse(Bytes::from_static(&[0x27, 0x01, 0xFF, 0xFF, 0xD8, 0x00, 0x00, 0x00, 0x01, 0x41])).unwrap();
+
+    assert!(!frame.key_frame);
+    assert_eq!(frame.packet, VideoPacket::CodedFrames {
+        composition_time: -40,
+        data: Bytes::from_static(&[0x00, 0x00, 0x00, 0x01, 0x41]),
+    });
+}
+
+#[test]
+fn test_parse_enhanced_hevc() {
+    let header = VideoTag::parse(Bytes::from_static(&[0x90, b'h', b'v', b'c', b'1', 0x01, 0x01])).unwrap();
+
+    assert_eq!(header.codec, VideoCodec::Hevc);
+    assert!(header.key_frame);
+    assert_eq!(header.packet, VideoPacket::SequenceStart(Bytes::from_static(&[0x01, 0x01])));
+
+    let frame = VideoTag::parse(Bytes::from_static(&[0xA1, b'h', b'v', b'c', b'1', 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x01, 0x02])).unwrap();
+
+    assert!(!frame.key_frame);
+    assert_eq!(frame.packet, VideoPacket::CodedFrames {
+        composition_time: 80,
+        data: Bytes::from_static(&[0x00, 0x00, 0x00, 0x01, 0x02]),
+    });
+
+    // CodedFramesX has no composition time
+    let frame = VideoTag::parse(Bytes::from_static(&[0x93, b'h', b'v', b'c', b'1', 0x00, 0x00, 0x00, 0x01, 0x26])).unwrap();
+
+    assert!(frame.key_frame);
+    assert_eq!(frame.packet, VideoPacket::CodedFrames {
+        composition_time: 0,
+        data: Bytes::from_static(&[0x00, 0x00, 0x00, 0x01, 0x26]),
+    });
+
+    assert!(matches!(
+        VideoTag::parse(Bytes::from_static(&[0x90, b'a', b'v', b'0', b'1'])),
+        Err(FlvError::UnsupportedFourCc(_))
+    ));
+}
+
+#[test]
+fn test_parse_aac_audio() {
+    let header = AudioTag::parse(Bytes::from_static(&[0xAF, 0x00, 0x11, 0x90])).unwrap();
+    assert_eq!(header.packet, AudioPacket::SequenceHeader(Bytes::from_static(&[0x11, 0x90])));
+
+    let frame = AudioTag::parse(Bytes::from_static(&[0xAF, 0x01, 0x21, 0x10])).unwrap();
+    assert_eq!(frame.packet, AudioPacket::Raw(Bytes::from_static(&[0x21, 0x10])));
+
+    // MP3
+    assert!(matches!(
+        AudioTag::parse(Bytes::from_static(&[0x2F, 0xFF, 0xFB])),
+        Err(FlvError::UnsupportedSoundFormat(2))
+    ));
+}
+
+#[test]
+fn test_amf0_round_trip() {
+    let command = vec![
+        Amf0Value::String("connect".into()),
+        Amf0Value::Number(1.0),
+        Amf0Value::Object(vec![
+            ("app".into(), Amf0Value::String("live".into())),
+            ("fpad".into(), Amf0Value::Boolean(false)),
+            ("audioCodecs".into(), Amf0Value::Number(3575.0)),
+        ]),
+        Amf0Value::Null,
+        Amf0Value::EcmaArray(vec![("videocodecid".into(), Amf0Value::Number(7.0))]),
+        Amf0Value::StrictArray(vec![Amf0Value::Undefined, Amf0Value::Date(1.0)]),
+    ];
+
+    let mut buf = Vec::new();
+    for value in &command {
+        value.encode(&mut buf);
+    }
+
+    let decoded = Amf0Value::decode_all(&buf).unwrap();
+    assert_eq!(decoded, command);
+
+    assert_eq!(decoded[2].get("app").and_then(Amf0Value::as_str), Some("live"));
+    assert_eq!(decoded[4].get("videocodecid").and_then(Amf0Value::as_number), Some(7.0));
+    assert_eq!(decoded[4].get("audiocodecid"), None);
+
+    assert!(matches!(Amf0Value::decode_all(&[0x02, 0x00, 0x05, b'a']), Err(FlvError::NotEnoughData(_))));
+}
+
+#[test]
+fn test_amf0_nesting_limit() {
+    let nested = |depth: usize| {
+        let mut buf = Vec::new();
+        for _ in 0..depth {
+            buf.extend_from_slice(&[0x03, 0x00, 0x00]);
+        }
+        buf.push(0x05);
+        for _ in 0..depth {
+            buf.extend_from_slice(&[0x00, 0x00, 0x09]);
+        }
+        buf
+    };
+
+    assert!(Amf0Value::decode_all(&nested(MAX_DEPTH)).is_ok());
+    assert!(matches!(Amf0Value::decode_all(&nested(MAX_DEPTH + 1)), Err(FlvError::Amf0TooDeep(_))));
+
+    // deep enough to overflow the stack without the limit
+    let bomb = [0x03, 0x00, 0x00].repeat(200_000);
+    assert!(matches!(Amf0Value::decode_all(&bomb), Err(FlvError::Amf0TooDeep(_))));
+
+    let arrays = [0x0A, 0x00, 0x00, 0x00, 0x01].repeat(200_000);
+    assert!(matches!(Amf0Value::decode_all(&arrays), Err(FlvError::Amf0TooDeep(_))));
+}
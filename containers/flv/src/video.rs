@@ -0,0 +1,142 @@
+use bytes::{Buf, Bytes};
+
+use crate::FlvError;
+
+const CODEC_ID_AVC: u8 = 7;
+/// Not part of the FLV spec, but what the Chinese CDN extension and most
+/// encoders predating enhanced RTMP use for HEVC.
+const CODEC_ID_HEVC: u8 = 12;
+
+const FRAME_TYPE_KEY: u8 = 1;
+const FRAME_TYPE_COMMAND: u8 = 5;
+
+const IS_EX_HEADER: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    Avc,
+    Hevc,
+}
+
+impl VideoCodec {
+    fn from_codec_id(codec_id: u8) -> Result<Self, FlvError> {
+        match codec_id {
+            CODEC_ID_AVC => Ok(Self::Avc),
+            CODEC_ID_HEVC => Ok(Self::Hevc),
+            _ => Err(FlvError::UnsupportedVideoCodec(codec_id)),
+        }
+    }
+
+    fn from_fourcc(fourcc: [u8; 4]) -> Result<Self, FlvError> {
+        match &fourcc {
+            b"avc1" => Ok(Self::Avc),
+            b"hvc1" => Ok(Self::Hevc),
+            _ => Err(FlvError::UnsupportedFourCc(fourcc)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VideoPacket {
+    /// AVCDecoderConfigurationRecord or HEVCDecoderConfigurationRecord
+    SequenceStart(Bytes),
+    /// Length prefixed NAL units, `composition_time` is the pts - dts offset in milliseconds
+    CodedFrames {
+        composition_time: i32,
+        data: Bytes,
+    },
+    SequenceEnd,
+    /// Video info/command frames carry no picture
+    Command,
+}
+
+/// An FLV VIDEODATA body, either the legacy layout or the enhanced RTMP
+/// one signalled through IsExHeader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoTag {
+    pub codec: VideoCodec,
+    pub key_frame: bool,
+    pub packet: VideoPacket,
+}
+
+impl VideoTag {
+    pub fn parse(mut data: Bytes) -> Result<Self, FlvError> {
+        if data.is_empty() {
+            return Err(FlvError::NotEnoughData("video tag header"));
+        }
+
+        let header = data.get_u8();
+
+        if header & IS_EX_HEADER != 0 {
+            Self::parse_ex(header, data)
+        } else {
+            Self::parse_legacy(header, data)
+        }
+    }
+
+    fn parse_legacy(header: u8, mut data: Bytes) -> Result<Self, FlvError> {
+        let frame_type = header >> 4;
+        let codec = VideoCodec::from_codec_id(header & 0x0F)?;
+
+        if frame_type == FRAME_TYPE_COMMAND {
+            return Ok(Self { codec, key_frame: false, packet: VideoPacket::Command });
+        }
+
+        if data.remaining() < 4 {
+            return Err(FlvError::NotEnoughData("AVC packet header"));
+        }
+
+        let packet_type = data.get_u8();
+        let composition_time = read_si24(&mut data);
+
+        let packet = match packet_type {
+            0 => VideoPacket::SequenceStart(data),
+            1 => VideoPacket::CodedFrames { composition_time, data },
+            2 => VideoPacket::SequenceEnd,
+            _ => return Err(FlvError::UnsupportedVideoPacketType(packet_type)),
+        };
+
+        Ok(Self { codec, key_frame: frame_type == FRAME_TYPE_KEY, packet })
+    }
+
+    fn parse_ex(header: u8, mut data: Bytes) -> Result<Self, FlvError> {
+        let frame_type = (header >> 4) & 0x07;
+        let packet_type = header & 0x0F;
+
+        if data.remaining() < 4 {
+            return Err(FlvError::NotEnoughData("video FourCC"));
+        }
+
+        let mut fourcc = [0; 4];
+        data.copy_to_slice(&mut fourcc);
+        let codec = VideoCodec::from_fourcc(fourcc)?;
+
+        if frame_type == FRAME_TYPE_COMMAND {
+            return Ok(Self { codec, key_frame: false, packet: VideoPacket::Command });
+        }
+
+        let packet = match packet_type {
+            0 => VideoPacket::SequenceStart(data),
+            1 => {
+                if data.remaining() < 3 {
+                    return Err(FlvError::NotEnoughData("composition time"));
+                }
+
+                let composition_time = read_si24(&mut data);
+                VideoPacket::CodedFrames { composition_time, data }
+            },
+            2 => VideoPacket::SequenceEnd,
+            // CodedFramesX, composition time is implied to be zero
+            3 => VideoPacket::CodedFrames { composition_time: 0, data },
+            _ => return Err(FlvError::UnsupportedVideoPacketType(packet_type)),
+        };
+
+        Ok(Self { codec, key_frame: frame_type == FRAME_TYPE_KEY, packet })
+    }
+}
+
+fn read_si24(data: &mut Bytes) -> i32 {
+    let value = data.get_uint(3) as i32;
+    // sign extend from 24 bits
+    (value << 8) >> 8
+}
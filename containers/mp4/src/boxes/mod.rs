@@ -15,9 +15,9 @@ use header::BoxHeader;
 pub use traits::BoxType;
 
 use crate::boxes::types::{
-    av01::Av01, av1c::Av1C, avc1::Avc1, avcc::AvcC, btrt::Btrt, clap::Clap, co64::Co64, colr::Colr,
-    ctts::Ctts, dinf::Dinf, dref::Dref, edts::Edts, elst::Elst, esds::Esds, ftyp::Ftyp, hdlr::Hdlr,
-    hev1::Hev1, hmhd::Hmhd, hvcc::HvcC, mdat::Mdat, mdhd::Mdhd, mdia::Mdia, mehd::Mehd, mfhd::Mfhd,
+    ac3::Ac3, av01::Av01, av1c::Av1C, avc1::Avc1, avcc::AvcC, btrt::Btrt, clap::Clap, co64::Co64, colr::Colr,
+    ctts::Ctts, dac3::Dac3, dec3::Dec3, dinf::Dinf, dops::Dops, dref::Dref, ec3::Ec3, edts::Edts, elst::Elst, esds::Esds, ftyp::Ftyp, hdlr::Hdlr,
+    hev1::Hev1, hmhd::Hmhd, hvc1::Hvc1, hvcc::HvcC, mdat::Mdat, mdhd::Mdhd, mdia::Mdia, mehd::Mehd, mfhd::Mfhd,
     minf::Minf, moof::Moof, moov::Moov, mp4a::Mp4a, mvex::Mvex, mvhd::Mvhd, nmhd::Nmhd, opus::Opus,
     padb::Padb, pasp::Pasp, sbgp::Sbgp, sdtp::Sdtp, smhd::Smhd, stbl::Stbl, stco::Stco, stdp::Stdp,
     stsc::Stsc, stsd::Stsd, stsh::Stsh, stss::Stss, stsz::Stsz, stts::Stts, stz2::Stz2, subs::Subs,
@@ -36,5 +36,6 @@ impl_box!(
     Url, Avc1, Clap, Pasp, AvcC, Btrt,
     Mp4a, Esds, Moof, Mfhd, Traf, Tfhd,
     Tfdt, Trun, Mdat, Av01, Av1C, Colr,
-    Hev1, HvcC, Opus,
+    Hev1, Hvc1, HvcC, Opus, Dops, Ac3,
+    Dac3, Ec3, Dec3,
 );
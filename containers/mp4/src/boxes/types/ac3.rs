@@ -0,0 +1,100 @@
+use std::io;
+
+use bytes::{Buf, Bytes};
+
+use crate::{
+    boxes::{header::BoxHeader, traits::BoxType, DynBox},
+    codec::AudioCodec,
+};
+
+use super::{
+    btrt::Btrt,
+    dac3::Dac3,
+    stsd::{AudioSampleEntry, SampleEntry},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+/// AC-3 Audio Sample Entry
+/// ETSI TS 102 366 - F.3
+pub struct Ac3 {
+    pub header: BoxHeader,
+    pub audio_sample_entry: SampleEntry<AudioSampleEntry>,
+    pub dac3: Dac3,
+    pub btrt: Option<Btrt>,
+    pub unknown: Vec<DynBox>,
+}
+
+impl Ac3 {
+    pub fn new(audio_sample_entry: SampleEntry<AudioSampleEntry>, dac3: Dac3, btrt: Option<Btrt>) -> Self {
+        Self {
+            header: BoxHeader::new(Self::NAME),
+            audio_sample_entry,
+            dac3,
+            btrt,
+            unknown: Vec::new(),
+        }
+    }
+
+    pub fn codec(&self) -> io::Result<AudioCodec> {
+        Ok(AudioCodec::Ac3)
+    }
+}
+
+impl BoxType for Ac3 {
+    const NAME: [u8; 4] = *b"ac-3";
+
+    fn demux(header: BoxHeader, data: Bytes) -> io::Result<Self> {
+        let mut reader = io::Cursor::new(data);
+
+        let audio_sample_entry = SampleEntry::<AudioSampleEntry>::demux(&mut reader)?;
+        let mut dac3 = None;
+        let mut btrt = None;
+        let mut unknown = Vec::new();
+
+        while reader.has_remaining() {
+            let dyn_box = DynBox::demux(&mut reader)?;
+            match dyn_box {
+                DynBox::Dac3(dac3_box) => {
+                    dac3 = Some(dac3_box);
+                }
+                DynBox::Btrt(btrt_box) => {
+                    btrt = Some(btrt_box);
+                }
+                _ => {
+                    unknown.push(dyn_box);
+                }
+            }
+        }
+
+        let dac3 = dac3.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "ac-3 box is missing dOps box")
+        })?;
+
+        Ok(Self {
+            header,
+            audio_sample_entry,
+            dac3,
+            btrt,
+            unknown,
+        })
+    }
+
+    fn primitive_size(&self) -> u64 {
+        self.audio_sample_entry.size()
+            + self.dac3.size()
+            + self.btrt.as_ref().map(|b| b.size()).unwrap_or(0)
+            + self.unknown.iter().map(|b| b.size()).sum::<u64>()
+    }
+
+    fn primitive_mux<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.audio_sample_entry.mux(writer)?;
+        self.dac3.mux(writer)?;
+        if let Some(btrt) = &self.btrt {
+            btrt.mux(writer)?;
+        }
+        for unknown in &self.unknown {
+            unknown.mux(writer)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use std::io;
+
+use bytes::Bytes;
+use bytesio::{bit_reader::BitReader, bit_writer::BitWriter};
+
+use crate::boxes::{header::BoxHeader, traits::BoxType};
+
+#[derive(Debug, Clone, PartialEq)]
+/// AC-3 Specific Box
+/// ETSI TS 102 366 - F.4
+pub struct Dac3 {
+    pub header: BoxHeader,
+    pub fscod: u8,
+    pub bsid: u8,
+    pub bsmod: u8,
+    pub acmod: u8,
+    pub lfeon: bool,
+    pub bit_rate_code: u8,
+}
+
+impl Dac3 {
+    pub fn new(fscod: u8, bsid: u8, bsmod: u8, acmod: u8, lfeon: bool, bit_rate_code: u8) -> Self {
+        Self {
+            header: BoxHeader::new(Self::NAME),
+            fscod,
+            bsid,
+            bsmod,
+            acmod,
+            lfeon,
+            bit_rate_code,
+        }
+    }
+}
+
+impl BoxType for Dac3 {
+    const NAME: [u8; 4] = *b"dac3";
+
+    fn demux(header: BoxHeader, data: Bytes) -> io::Result<Self> {
+        let mut bit_reader = BitReader::from(data);
+
+        Ok(Self {
+            header,
+            fscod: bit_reader.read_bits(2)? as u8,
+            bsid: bit_reader.read_bits(5)? as u8,
+            bsmod: bit_reader.read_bits(3)? as u8,
+            acmod: bit_reader.read_bits(3)? as u8,
+            lfeon: bit_reader.read_bit()?,
+            bit_rate_code: bit_reader.read_bits(5)? as u8,
+        })
+    }
+
+    fn primitive_size(&self) -> u64 {
+        3
+    }
+
+    fn primitive_mux<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        let mut bit_writer = BitWriter::default();
+
+        bit_writer.write_bits(self.fscod as u64, 2)?;
+        bit_writer.write_bits(self.bsid as u64, 5)?;
+        bit_writer.write_bits(self.bsmod as u64, 3)?;
+        bit_writer.write_bits(self.acmod as u64, 3)?;
+        bit_writer.write_bit(self.lfeon)?;
+        bit_writer.write_bits(self.bit_rate_code as u64, 5)?;
+        bit_writer.write_bits(0, 5)?; // reserved
+
+        writer.write_all(&bit_writer.into_inner())
+    }
+}
@@ -0,0 +1,120 @@
+use std::io;
+
+use bytes::Bytes;
+use bytesio::{bit_reader::BitReader, bit_writer::BitWriter};
+
+use crate::boxes::{header::BoxHeader, traits::BoxType};
+
+#[derive(Debug, Clone, PartialEq)]
+/// EC-3 Specific Box
+/// ETSI TS 102 366 - F.6
+pub struct Dec3 {
+    pub header: BoxHeader,
+    /// kbit/s
+    pub data_rate: u16,
+    pub independent_substreams: Vec<IndependentSubstream>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndependentSubstream {
+    pub fscod: u8,
+    pub bsid: u8,
+    pub asvc: bool,
+    pub bsmod: u8,
+    pub acmod: u8,
+    pub lfeon: bool,
+    pub num_dep_sub: u8,
+    /// Only present with dependent substreams
+    pub chan_loc: u16,
+}
+
+impl Dec3 {
+    pub fn new(data_rate: u16, independent_substreams: Vec<IndependentSubstream>) -> Self {
+        Self {
+            header: BoxHeader::new(Self::NAME),
+            data_rate,
+            independent_substreams,
+        }
+    }
+}
+
+impl BoxType for Dec3 {
+    const NAME: [u8; 4] = *b"dec3";
+
+    fn demux(header: BoxHeader, data: Bytes) -> io::Result<Self> {
+        let mut bit_reader = BitReader::from(data);
+
+        let data_rate = bit_reader.read_bits(13)? as u16;
+        let num_ind_sub = bit_reader.read_bits(3)? as usize + 1;
+
+        let mut independent_substreams = Vec::with_capacity(num_ind_sub);
+        for _ in 0..num_ind_sub {
+            let fscod = bit_reader.read_bits(2)? as u8;
+            let bsid = bit_reader.read_bits(5)? as u8;
+            let _reserved = bit_reader.read_bit()?;
+            let asvc = bit_reader.read_bit()?;
+            let bsmod = bit_reader.read_bits(3)? as u8;
+            let acmod = bit_reader.read_bits(3)? as u8;
+            let lfeon = bit_reader.read_bit()?;
+            let _reserved = bit_reader.read_bits(3)?;
+            let num_dep_sub = bit_reader.read_bits(4)? as u8;
+
+            let chan_loc = if num_dep_sub > 0 {
+                bit_reader.read_bits(9)? as u16
+            } else {
+                let _reserved = bit_reader.read_bit()?;
+                0
+            };
+
+            independent_substreams.push(IndependentSubstream {
+                fscod,
+                bsid,
+                asvc,
+                bsmod,
+                acmod,
+                lfeon,
+                num_dep_sub,
+                chan_loc,
+            });
+        }
+
+        Ok(Self {
+            header,
+            data_rate,
+            independent_substreams,
+        })
+    }
+
+    fn primitive_size(&self) -> u64 {
+        2 // data_rate, num_ind_sub
+        + self.independent_substreams.len() as u64 * 3
+        + self.independent_substreams.iter().filter(|substream| substream.num_dep_sub > 0).count() as u64
+    }
+
+    fn primitive_mux<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        let mut bit_writer = BitWriter::default();
+
+        bit_writer.write_bits(self.data_rate as u64, 13)?;
+        bit_writer.write_bits(self.independent_substreams.len().saturating_sub(1) as u64, 3)?;
+
+        for substream in &self.independent_substreams {
+            bit_writer.write_bits(substream.fscod as u64, 2)?;
+            bit_writer.write_bits(substream.bsid as u64, 5)?;
+            bit_writer.write_bit(false)?; // reserved
+            bit_writer.write_bit(substream.asvc)?;
+            bit_writer.write_bits(substream.bsmod as u64, 3)?;
+            bit_writer.write_bits(substream.acmod as u64, 3)?;
+            bit_writer.write_bit(substream.lfeon)?;
+            bit_writer.write_bits(0, 3)?; // reserved
+            bit_writer.write_bits(substream.num_dep_sub as u64, 4)?;
+
+            if substream.num_dep_sub > 0 {
+                bit_writer.write_bits(substream.chan_loc as u64, 9)?;
+            } else {
+                bit_writer.write_bit(false)?; // reserved
+            }
+        }
+
+        writer.write_all(&bit_writer.into_inner())
+    }
+}
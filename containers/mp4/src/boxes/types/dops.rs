@@ -0,0 +1,129 @@
+use std::io;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use bytes::{Buf, Bytes};
+
+use crate::boxes::{header::BoxHeader, traits::BoxType};
+
+#[derive(Debug, Clone, PartialEq)]
+/// Opus Specific Box
+/// Encapsulation of Opus in ISO Base Media File Format - Version 0.8.1 - 4.3.2
+pub struct Dops {
+    pub header: BoxHeader,
+    pub version: u8,
+    pub output_channel_count: u8,
+    pub pre_skip: u16,
+    pub input_sample_rate: u32,
+    pub output_gain: i16,
+    pub channel_mapping_family: u8,
+    pub channel_mapping_table: Option<ChannelMappingTable>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Present for every channel mapping family but 0
+pub struct ChannelMappingTable {
+    pub stream_count: u8,
+    pub coupled_count: u8,
+    pub channel_mapping: Vec<u8>,
+}
+
+impl Dops {
+    pub fn new(
+        output_channel_count: u8,
+        pre_skip: u16,
+        input_sample_rate: u32,
+        channel_mapping_family: u8,
+        channel_mapping_table: Option<ChannelMappingTable>,
+    ) -> Self {
+        Self {
+            header: BoxHeader::new(Self::NAME),
+            version: 0,
+            output_channel_count,
+            pre_skip,
+            input_sample_rate,
+            output_gain: 0,
+            channel_mapping_family,
+            channel_mapping_table,
+        }
+    }
+}
+
+impl BoxType for Dops {
+    const NAME: [u8; 4] = *b"dOps";
+
+    fn demux(header: BoxHeader, data: Bytes) -> io::Result<Self> {
+        let mut reader = io::Cursor::new(data);
+
+        let version = reader.read_u8()?;
+        let output_channel_count = reader.read_u8()?;
+        let pre_skip = reader.read_u16::<BigEndian>()?;
+        let input_sample_rate = reader.read_u32::<BigEndian>()?;
+        let output_gain = reader.read_i16::<BigEndian>()?;
+        let channel_mapping_family = reader.read_u8()?;
+
+        let channel_mapping_table = if channel_mapping_family != 0 {
+            let stream_count = reader.read_u8()?;
+            let coupled_count = reader.read_u8()?;
+
+            if reader.remaining() < output_channel_count as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "dOps channel mapping is shorter than the channel count",
+                ));
+            }
+
+            let mut channel_mapping = vec![0; output_channel_count as usize];
+            reader.copy_to_slice(&mut channel_mapping);
+
+            Some(ChannelMappingTable {
+                stream_count,
+                coupled_count,
+                channel_mapping,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            header,
+            version,
+            output_channel_count,
+            pre_skip,
+            input_sample_rate,
+            output_gain,
+            channel_mapping_family,
+            channel_mapping_table,
+        })
+    }
+
+    fn primitive_size(&self) -> u64 {
+        1 // version
+        + 1 // output_channel_count
+        + 2 // pre_skip
+        + 4 // input_sample_rate
+        + 2 // output_gain
+        + 1 // channel_mapping_family
+        + self.channel_mapping_table.as_ref().map(|table| {
+            1 // stream_count
+            + 1 // coupled_count
+            + table.channel_mapping.len() as u64
+        }).unwrap_or(0)
+    }
+
+    fn primitive_mux<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        writer.write_u8(self.version)?;
+        writer.write_u8(self.output_channel_count)?;
+        writer.write_u16::<BigEndian>(self.pre_skip)?;
+        writer.write_u32::<BigEndian>(self.input_sample_rate)?;
+        writer.write_i16::<BigEndian>(self.output_gain)?;
+        writer.write_u8(self.channel_mapping_family)?;
+
+        if let Some(table) = &self.channel_mapping_table {
+            writer.write_u8(table.stream_count)?;
+            writer.write_u8(table.coupled_count)?;
+            writer.write_all(&table.channel_mapping)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use std::io;
+
+use bytes::{Buf, Bytes};
+
+use crate::{
+    boxes::{header::BoxHeader, traits::BoxType, DynBox},
+    codec::AudioCodec,
+};
+
+use super::{
+    btrt::Btrt,
+    dec3::Dec3,
+    stsd::{AudioSampleEntry, SampleEntry},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+/// Enhanced AC-3 Audio Sample Entry
+/// ETSI TS 102 366 - F.5
+pub struct Ec3 {
+    pub header: BoxHeader,
+    pub audio_sample_entry: SampleEntry<AudioSampleEntry>,
+    pub dec3: Dec3,
+    pub btrt: Option<Btrt>,
+    pub unknown: Vec<DynBox>,
+}
+
+impl Ec3 {
+    pub fn new(audio_sample_entry: SampleEntry<AudioSampleEntry>, dec3: Dec3, btrt: Option<Btrt>) -> Self {
+        Self {
+            header: BoxHeader::new(Self::NAME),
+            audio_sample_entry,
+            dec3,
+            btrt,
+            unknown: Vec::new(),
+        }
+    }
+
+    pub fn codec(&self) -> io::Result<AudioCodec> {
+        Ok(AudioCodec::Eac3)
+    }
+}
+
+impl BoxType for Ec3 {
+    const NAME: [u8; 4] = *b"ec-3";
+
+    fn demux(header: BoxHeader, data: Bytes) -> io::Result<Self> {
+        let mut reader = io::Cursor::new(data);
+
+        let audio_sample_entry = SampleEntry::<AudioSampleEntry>::demux(&mut reader)?;
+        let mut dec3 = None;
+        let mut btrt = None;
+        let mut unknown = Vec::new();
+
+        while reader.has_remaining() {
+            let dyn_box = DynBox::demux(&mut reader)?;
+            match dyn_box {
+                DynBox::Dec3(dec3_box) => {
+                    dec3 = Some(dec3_box);
+                }
+                DynBox::Btrt(btrt_box) => {
+                    btrt = Some(btrt_box);
+                }
+                _ => {
+                    unknown.push(dyn_box);
+                }
+            }
+        }
+
+        let dec3 = dec3.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "ec-3 box is missing dOps box")
+        })?;
+
+        Ok(Self {
+            header,
+            audio_sample_entry,
+            dec3,
+            btrt,
+            unknown,
+        })
+    }
+
+    fn primitive_size(&self) -> u64 {
+        self.audio_sample_entry.size()
+            + self.dec3.size()
+            + self.btrt.as_ref().map(|b| b.size()).unwrap_or(0)
+            + self.unknown.iter().map(|b| b.size()).sum::<u64>()
+    }
+
+    fn primitive_mux<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.audio_sample_entry.mux(writer)?;
+        self.dec3.mux(writer)?;
+        if let Some(btrt) = &self.btrt {
+            btrt.mux(writer)?;
+        }
+        for unknown in &self.unknown {
+            unknown.mux(writer)?;
+        }
+        Ok(())
+    }
+}
@@ -13,10 +13,20 @@ use super::{
     stsd::{SampleEntry, VisualSampleEntry},
 };
 
+/// `hev1`, parameter sets may also be carried in the samples
+pub const HEV1: u32 = u32::from_be_bytes(*b"hev1");
+
+/// `hvc1`, parameter sets are only carried in the hvcC
+pub const HVC1: u32 = u32::from_be_bytes(*b"hvc1");
+
+/// HEVC (H.265) Codec Box with parameter sets in band
+/// ISO/IEC 14496-15:2022 - 8.4
+pub type Hev1 = HevcSampleEntry<HEV1>;
+
 #[derive(Debug, Clone, PartialEq)]
-/// HEVC (H.265) Codec Box
+/// HEVC (H.265) Codec Box, `hev1` or `hvc1` depending on the sample entry type
 /// ISO/IEC 14496-15:2022 - 8.4
-pub struct Hev1 {
+pub struct HevcSampleEntry<const TYPE: u32> {
     pub header: BoxHeader,
     pub visual_sample_entry: SampleEntry<VisualSampleEntry>,
     pub hvcc: HvcC,
@@ -24,7 +34,7 @@ pub struct Hev1 {
     pub unknown: Vec<DynBox>,
 }
 
-impl Hev1 {
+impl<const TYPE: u32> HevcSampleEntry<TYPE> {
     pub fn new(
         visual_sample_entry: SampleEntry<VisualSampleEntry>,
         hvcc: HvcC,
@@ -41,6 +51,7 @@ impl Hev1 {
 
     pub fn codec(&self) -> io::Result<VideoCodec> {
         Ok(VideoCodec::Hevc {
+            sample_entry: Self::NAME,
             constraint_indicator: self.hvcc.hevc_config.general_constraint_indicator_flags,
             level: self.hvcc.hevc_config.general_level_idc,
             profile: self.hvcc.hevc_config.general_profile_idc,
@@ -51,8 +62,8 @@ impl Hev1 {
     }
 }
 
-impl BoxType for Hev1 {
-    const NAME: [u8; 4] = *b"hev1";
+impl<const TYPE: u32> BoxType for HevcSampleEntry<TYPE> {
+    const NAME: [u8; 4] = TYPE.to_be_bytes();
 
     fn demux(header: BoxHeader, data: Bytes) -> io::Result<Self> {
         let mut reader = io::Cursor::new(data);
@@ -88,7 +99,10 @@ impl BoxType for Hev1 {
         }
 
         let hvcc = hvcc.ok_or_else(|| {
-            io::Error::new(io::ErrorKind::InvalidData, "trak box is missing tkhd box")
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} box is missing hvcC box", Self::NAME.escape_ascii()),
+            )
         })?;
 
         Ok(Self {
@@ -119,3 +133,63 @@ impl BoxType for Hev1 {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use bytes::Bytes;
+    use h265::{config::HEVCDecoderConfigurationRecord, nal};
+
+    use crate::{
+        boxes::{traits::BoxType, DynBox},
+        codec::VideoCodec,
+        types::{
+            hvc1::Hvc1,
+            hvcc::HvcC,
+            stsd::{SampleEntry, VisualSampleEntry},
+        },
+    };
+
+    fn hvcc(array_completeness: bool) -> HvcC {
+        HvcC::new(HEVCDecoderConfigurationRecord {
+            vps: vec![nal::Unit::try_from(&[0x40, 0x01, 0x0C, 0x01][..]).unwrap()],
+            sps: vec![nal::Unit::try_from(&[0x42, 0x01, 0x01, 0x01][..]).unwrap()],
+            pps: vec![nal::Unit::try_from(&[0x44, 0x01, 0xC1, 0x72][..]).unwrap()],
+            array_completeness,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn hvc1_round_trips() {
+        let hvc1 = Hvc1::new(
+            SampleEntry::new(VisualSampleEntry::new(1920, 1080, None)),
+            hvcc(true),
+            None,
+        );
+
+        let mut data = Vec::new();
+        hvc1.mux(&mut data).unwrap();
+        assert_eq!(data.len() as u64, hvc1.size());
+        assert_eq!(&data[4..8], b"hvc1");
+
+        let parsed = DynBox::demux(&mut io::Cursor::new(Bytes::from(data))).unwrap();
+        let parsed = parsed.as_hvc1().expect("parsed as hvc1");
+        assert_eq!(*parsed, hvc1);
+        assert!(parsed.hvcc.hevc_config.array_completeness);
+
+        let codec = parsed.codec().unwrap();
+        assert!(matches!(codec, VideoCodec::Hevc { sample_entry, .. } if sample_entry == *b"hvc1"));
+        assert!(codec.to_string().starts_with("hvc1."));
+    }
+
+    #[test]
+    fn rejects_sample_entry_without_hvcc() {
+        let mut data = Vec::new();
+        SampleEntry::new(VisualSampleEntry::new(1920, 1080, None)).mux(&mut data).unwrap();
+
+        let err = Hvc1::demux(crate::boxes::header::BoxHeader::new(*b"hvc1"), Bytes::from(data)).unwrap_err();
+        assert_eq!(err.to_string(), "hvc1 box is missing hvcC box");
+    }
+}
@@ -0,0 +1,8 @@
+pub use super::hev1::HVC1;
+
+use super::hev1::HevcSampleEntry;
+
+/// HEVC (H.265) Codec Box, parameter sets are only carried in the hvcC
+/// and never in the samples
+/// ISO/IEC 14496-15:2022 - 8.4
+pub type Hvc1 = HevcSampleEntry<HVC1>;
@@ -1,3 +1,4 @@
+pub mod ac3;
 pub mod av01;
 pub mod av1c;
 pub mod avc1;
@@ -7,15 +8,20 @@ pub mod clap;
 pub mod co64;
 pub mod colr;
 pub mod ctts;
+pub mod dac3;
+pub mod dec3;
 pub mod dinf;
+pub mod dops;
 pub mod dref;
 pub mod edts;
+pub mod ec3;
 pub mod elst;
 pub mod esds;
 pub mod ftyp;
 pub mod hdlr;
 pub mod hev1;
 pub mod hmhd;
+pub mod hvc1;
 pub mod hvcc;
 pub mod mdat;
 pub mod mdhd;
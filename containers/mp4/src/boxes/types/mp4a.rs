@@ -40,16 +40,20 @@ impl Mp4a {
     }
 
     pub fn codec(&self) -> io::Result<AudioCodec> {
-        let info = self
-            .esds
-            .es_descriptor
-            .decoder_config
-            .as_ref()
+        let decoder_config = self.esds.es_descriptor.decoder_config.as_ref();
+        if decoder_config.map(|c| c.object_type_indication) == Some(0x6B) {
+            return Ok(AudioCodec::Mp3);
+        }
+
+        let info = decoder_config
             .and_then(|c| c.decoder_specific_info.as_ref().map(|c| c.data.clone()))
             .ok_or_else(|| {
                 io::Error::new(io::ErrorKind::InvalidData, "Missing decoder specific info")
             })?;
-        let aac_config = aac::config::AudioObjectType::try_from(info[0] as u16).unwrap();
+        // audioObjectType is the top 5 bits of the AudioSpecificConfig
+        let object_type = info.first().map(|byte| (byte >> 3) as u16).unwrap_or_default();
+        let aac_config = aac::config::AudioObjectType::try_from(object_type)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
 
         Ok(AudioCodec::Aac {
             object_type: aac_config,
@@ -9,6 +9,7 @@ use crate::{
 
 use super::{
     btrt::Btrt,
+    dops::Dops,
     stsd::{AudioSampleEntry, SampleEntry},
 };
 
@@ -18,15 +19,17 @@ use super::{
 pub struct Opus {
     pub header: BoxHeader,
     pub audio_sample_entry: SampleEntry<AudioSampleEntry>,
+    pub dops: Dops,
     pub btrt: Option<Btrt>,
     pub unknown: Vec<DynBox>,
 }
 
 impl Opus {
-    pub fn new(audio_sample_entry: SampleEntry<AudioSampleEntry>, btrt: Option<Btrt>) -> Self {
+    pub fn new(audio_sample_entry: SampleEntry<AudioSampleEntry>, dops: Dops, btrt: Option<Btrt>) -> Self {
         Self {
             header: BoxHeader::new(Self::NAME),
             audio_sample_entry,
+            dops,
             btrt,
             unknown: Vec::new(),
         }
@@ -44,12 +47,16 @@ impl BoxType for Opus {
         let mut reader = io::Cursor::new(data);
 
         let audio_sample_entry = SampleEntry::<AudioSampleEntry>::demux(&mut reader)?;
+        let mut dops = None;
         let mut btrt = None;
         let mut unknown = Vec::new();
 
         while reader.has_remaining() {
             let dyn_box = DynBox::demux(&mut reader)?;
             match dyn_box {
+                DynBox::Dops(dops_box) => {
+                    dops = Some(dops_box);
+                }
                 DynBox::Btrt(btrt_box) => {
                     btrt = Some(btrt_box);
                 }
@@ -59,9 +66,14 @@ impl BoxType for Opus {
             }
         }
 
+        let dops = dops.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Opus box is missing dOps box")
+        })?;
+
         Ok(Self {
             header,
             audio_sample_entry,
+            dops,
             btrt,
             unknown,
         })
@@ -69,12 +81,14 @@ impl BoxType for Opus {
 
     fn primitive_size(&self) -> u64 {
         self.audio_sample_entry.size()
+            + self.dops.size()
             + self.btrt.as_ref().map(|b| b.size()).unwrap_or(0)
             + self.unknown.iter().map(|b| b.size()).sum::<u64>()
     }
 
     fn primitive_mux<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
         self.audio_sample_entry.mux(writer)?;
+        self.dops.mux(writer)?;
         if let Some(btrt) = &self.btrt {
             btrt.mux(writer)?;
         }
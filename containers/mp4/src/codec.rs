@@ -13,6 +13,8 @@ pub enum VideoCodec {
     /// There is barely any documentation on this.
     /// http://hevcvideo.xp3.biz/html5_video.html
     Hevc {
+        /// `hev1` or `hvc1`, which the codec string starts with
+        sample_entry: [u8; 4],
         general_profile_space: u8,
         profile_compatibility: u32,
         profile: u8,
@@ -45,6 +47,7 @@ impl fmt::Display for VideoCodec {
                 level,
             } => write!(f, "avc1.{:02x}{:02x}{:02x}", profile, constraint_set, level),
             VideoCodec::Hevc {
+                sample_entry,
                 general_profile_space,
                 profile,
                 level,
@@ -53,7 +56,8 @@ impl fmt::Display for VideoCodec {
                 constraint_indicator,
             } => write!(
                 f,
-                "hev1.{}{:x}.{:x}.{}{:x}.{:x}",
+                "{}.{}{:x}.{:x}.{}{:x}.{:x}",
+                sample_entry.escape_ascii(),
                 match general_profile_space {
                     1 => "A",
                     2 => "B",
@@ -130,7 +134,7 @@ impl FromStr for VideoCodec {
                     level,
                 })
             }
-            "hev1" => {
+            "hev1" | "hvc1" => {
                 if splits.len() < 6 {
                     return Err("invalid codec, missing profile".into());
                 }
@@ -173,7 +177,11 @@ impl FromStr for VideoCodec {
                     )
                 })?;
 
+                let mut sample_entry = [0; 4];
+                sample_entry.copy_from_slice(splits[0].as_bytes());
+
                 Ok(VideoCodec::Hevc {
+                    sample_entry,
                     general_profile_space,
                     profile,
                     level,
@@ -282,6 +290,10 @@ impl FromStr for VideoCodec {
 pub enum AudioCodec {
     Aac { object_type: AudioObjectType },
     Opus,
+    Ac3,
+    Eac3,
+    /// MPEG-1 audio, object type indication 0x6B
+    Mp3,
 }
 
 impl fmt::Display for AudioCodec {
@@ -289,6 +301,9 @@ impl fmt::Display for AudioCodec {
         match self {
             AudioCodec::Aac { object_type } => write!(f, "mp4a.40.{}", u16::from(*object_type as u8)),
             AudioCodec::Opus => write!(f, "opus"),
+            AudioCodec::Ac3 => write!(f, "ac-3"),
+            AudioCodec::Eac3 => write!(f, "ec-3"),
+            AudioCodec::Mp3 => write!(f, "mp4a.6B"),
         }
     }
 }
@@ -303,6 +318,7 @@ impl FromStr for AudioCodec {
         }
 
         match splits[0] {
+            "mp4a" if splits.get(1).map_or(false, |oti| oti.eq_ignore_ascii_case("6b")) => Ok(AudioCodec::Mp3),
             "mp4a" => {
                 if splits.len() < 3 {
                     return Err("invalid codec, missing object type".into());
@@ -317,6 +333,8 @@ impl FromStr for AudioCodec {
                 })
             }
             "opus" => Ok(AudioCodec::Opus),
+            "ac-3" => Ok(AudioCodec::Ac3),
+            "ec-3" => Ok(AudioCodec::Eac3),
             r => Err(format!("invalid codec, unknown type: {}", r)),
         }
     }
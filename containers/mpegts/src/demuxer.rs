@@ -1,5 +1,5 @@
-use std::{collections::HashMap, io::{self, Seek}};
-use crate::{pid::Pid, section::{pat::PAT, pmt::PMT, pes_header::{PesHeader, StreamId}}, error::DemuxError, packet_header::PacketHeader, DemuxerEvent};
+use std::{collections::HashMap, io};
+use crate::{pid::Pid, crc, section::{assembler::SectionAssembler, pat::PAT, pmt::PMT, pes_header::{PesHeader, StreamId}, scte35::{SpliceInfoSection, SPLICE_INFO_TABLE_ID}}, error::DemuxError, packet_header::PacketHeader, stream_type::StreamType, sync::{PacketSync, SyncEvent}, stats::DemuxerStats, DemuxerEvent};
 use byteorder::ReadBytesExt;
 use anyhow::{Result, bail};
 use bytes::{BytesMut, BufMut, Bytes};
@@ -8,21 +8,45 @@ use bytesio::bytes_reader::BytesCursor;
 pub const SYNC_BYTE: u8 = 0x47;
 pub const SIZE: usize = 188;
 
+const PAT_TABLE_ID: u8 = 0x00;
+const PMT_TABLE_ID: u8 = 0x02;
+
 #[derive(Clone, Debug)]
 struct Packet {
     buffer: BytesMut,
     stream_id: StreamId,
     pts: Option<u64>,
     dts: Option<u64>,
+    expected_size: Option<usize>,
 }
 
-pub struct Demuxer {
-    pmt_pid: Option<Pid>,
-    pcr_pid: Option<Pid>,
+/// Which programs of a (possibly multi-program) transport stream to demux.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgramSelection {
+    /// The first program listed in the PAT
+    First,
+    /// A single program by its program_number
+    Number(u16),
+    /// Every program listed in the PAT
+    All,
+}
+
+#[derive(Clone, Debug)]
+struct Program {
+    pmt_pid: Pid,
     pmt: Option<PMT>,
+}
+
+pub struct Demuxer {
+    selection: ProgramSelection,
+    programs: HashMap<u16, Program>,
+    pat_program_count: usize,
+    pat_version: Option<u8>,
+    sections: SectionAssembler,
+    sync: PacketSync,
+    stats: DemuxerStats,
 
     return_queue: Vec<DemuxerEvent>,
-    continutiy_check: HashMap<Pid, u8>,
     packets: HashMap<Pid, Packet>,
 }
 
@@ -32,23 +56,213 @@ pub static ANNEXB_NALUSTART_CODE: Bytes = Bytes::from_static(&[0x00, 0x00, 0x00,
 impl Demuxer {
 
     pub fn new() -> Demuxer {
-        Demuxer {   
-            pmt_pid: None,
-            pcr_pid: None,
+        Self::with_program_selection(ProgramSelection::First)
+    }
 
-            pmt: None,
+    pub fn with_program_selection(selection: ProgramSelection) -> Demuxer {
+        Demuxer {
+            selection,
+            programs: HashMap::new(),
+            pat_program_count: 0,
+            pat_version: None,
+            sections: SectionAssembler::new(),
+            sync: PacketSync::new(),
+            stats: DemuxerStats::new(),
 
-            continutiy_check: HashMap::new(),
             packets: HashMap::new(),
             return_queue: Vec::new(),
         }
     }
 
+    /// Number of programs selected from the latest PAT.
+    pub fn program_count(&self) -> usize {
+        self.programs.len()
+    }
+
+    /// Number of programs listed in the latest PAT, selected or not.
+    pub fn pat_program_count(&self) -> usize {
+        self.pat_program_count
+    }
+
+    pub fn pmt(&self, program_number: u16) -> Option<&PMT> {
+        self.programs
+            .get(&program_number)
+            .and_then(|program| program.pmt.as_ref())
+    }
+
+    pub fn pcr_pid(&self, program_number: u16) -> Option<Pid> {
+        self.programs
+            .get(&program_number)
+            .and_then(|program| program.pmt.as_ref())
+            .map(|pmt| pmt.pcr_pid)
+    }
+
+    /// Transport stream error counters since the demuxer was created.
+    pub fn stats(&self) -> &DemuxerStats {
+        &self.stats
+    }
+
     pub fn has_stream(&self, pid: &Pid) -> bool {
-        if let Some(pmt) = &self.pmt {
-            return pmt.streams.contains_key(pid);
+        self.program_for_stream(pid).is_some()
+    }
+
+    fn program_for_stream(&self, pid: &Pid) -> Option<(u16, StreamType)> {
+        self.programs.iter().find_map(|(program_number, program)| {
+            program.pmt
+                .as_ref()
+                .and_then(|pmt| pmt.streams.get(pid))
+                .map(|stream_type| (*program_number, stream_type.clone()))
+        })
+    }
+
+    fn is_pmt_pid(&self, pid: &Pid) -> bool {
+        self.programs.values().any(|program| program.pmt_pid == *pid)
+    }
+
+    fn handle_section(&mut self, pid: Pid, section: Bytes) -> Result<()> {
+        let table_id = section[0];
+        // only long form sections have their CRC checked by the assembler
+        let section_syntax_indicator = section.get(1).is_some_and(|byte| byte & 0x80 != 0);
+        let mut reader = io::Cursor::new(section);
+
+        if pid == Pid::PAT && !section_syntax_indicator {
+            log::debug!("Dropping PAT without section_syntax_indicator");
+            self.stats.pat_error();
+        } else if pid == Pid::PAT && table_id != PAT_TABLE_ID {
+            log::debug!("Unexpected table {:#04x} on the PAT pid", table_id);
+            self.stats.pat_error();
+        } else if pid == Pid::PAT {
+            let pat = PAT::try_new(&mut reader)?;
+            self.stats.pat();
+
+            if !pat.current_next || self.pat_version == Some(pat.version) {
+                return Ok(());
+            }
+
+            self.pat_version = Some(pat.version);
+
+            // program_number 0 points at the network PID rather than a PMT
+            let candidates = pat.programs
+                .iter()
+                .filter(|program| program.program_number != 0);
+
+            self.pat_program_count = candidates.clone().count();
+
+            let selected: Vec<_> = match self.selection {
+                ProgramSelection::First => candidates.take(1).collect(),
+                ProgramSelection::Number(number) => candidates.filter(|program| program.program_number == number).collect(),
+                ProgramSelection::All => candidates.collect(),
+            };
+
+            if selected.is_empty() {
+                log::warn!("No program matching {:?} in PAT", self.selection);
+            }
+
+            let mut programs = HashMap::new();
+
+            for program in selected {
+                let entry = match self.programs.remove(&program.program_number) {
+                    Some(existing) if existing.pmt_pid == program.program_pid => existing,
+                    _ => {
+                        log::debug!("Found PMT pid:{:?} for program {}", program.program_pid, program.program_number);
+                        Program {
+                            pmt_pid: program.program_pid,
+                            pmt: None,
+                        }
+                    }
+                };
+
+                programs.insert(program.program_number, entry);
+            }
+
+            self.stats.pmt_pids(programs.values().map(|program| program.pmt_pid));
+            self.programs = programs;
+        } else if table_id == PMT_TABLE_ID && !section_syntax_indicator {
+            log::debug!("Dropping PMT without section_syntax_indicator on {:?}", pid);
+            self.stats.crc_error(pid);
+        } else if table_id == PMT_TABLE_ID {
+            let pmt = PMT::try_new(&mut reader)?;
+            self.stats.pmt(pid);
+
+            if !pmt.current_next {
+                return Ok(());
+            }
+
+            let program = match self.programs.get_mut(&pmt.program_number) {
+                Some(program) if program.pmt_pid == pid => program,
+                _ => return Ok(()),
+            };
+
+            if let Some(current) = &program.pmt {
+                if current.version == pmt.version {
+                    return Ok(());
+                }
+
+                log::debug!("PMT for program {} updated to version {}", pmt.program_number, pmt.version);
+            }
+
+            let event = DemuxerEvent::StreamDetails(pmt.program_number, pmt.streams.clone());
+            program.pmt = Some(pmt);
+            self.emit(event);
+        }
+
+        Ok(())
+    }
+
+    fn handle_splice_section(&mut self, program_number: u16, pid: Pid, section: Bytes) -> Result<()> {
+        if section[0] != SPLICE_INFO_TABLE_ID {
+            return Ok(());
         }
-        false
+
+        // splice_info_section is a short form section but still carries a CRC
+        if crc::sum32(&section) != 0 {
+            log::warn!("Dropping splice_info_section with invalid CRC32 on {:?}", pid);
+            self.stats.crc_error(pid);
+            return Ok(());
+        }
+
+        let splice = SpliceInfoSection::try_new(section)?;
+        log::debug!("SCTE-35 {:?} on program {}", splice.command, program_number);
+
+        self.emit(DemuxerEvent::Splice(program_number, splice));
+        Ok(())
+    }
+
+    fn emit_packet(&mut self, program_number: u16, pid: Pid, stream_type: StreamType, packet: Packet) {
+        if packet.buffer.is_empty() {
+            return;
+        }
+
+        // Opus and AV1 are carried in private_stream_1, only their PMT stream type says what they are
+        let stream_id = match packet.stream_id {
+            StreamId::Unknown(_) if stream_type.is_audio() => StreamId::Audio(0),
+            StreamId::Unknown(_) if stream_type.is_video() => StreamId::Video(0),
+            stream_id => stream_id,
+        };
+
+        match stream_id {
+            StreamId::Audio(_) => {
+                self.emit(DemuxerEvent::Audio(program_number, pid, stream_type, packet.buffer.freeze(), packet.pts));
+            }
+            StreamId::Video(_) => {
+                self.emit(DemuxerEvent::Video(program_number, pid, stream_type, packet.buffer.freeze(), packet.pts, packet.dts));
+            },
+            _ => (),
+        }
+    }
+
+    /// Emits any PES packets still being buffered, for use at the end of
+    /// a stream where no further start indicator will arrive.
+    pub fn flush(&mut self) -> Vec<DemuxerEvent> {
+        let pids: Vec<Pid> = self.packets.keys().cloned().collect();
+
+        for pid in pids {
+            if let (Some(packet), Some((program_number, stream_type))) = (self.packets.remove(&pid), self.program_for_stream(&pid)) {
+                self.emit_packet(program_number, pid, stream_type, packet);
+            }
+        }
+
+        self.return_queue.drain(..).collect()
     }
 
     fn emit(&mut self, event: DemuxerEvent) {
@@ -56,120 +270,419 @@ impl Demuxer {
     }
 
     pub fn check_continutiy(&mut self, packet_header: &PacketHeader) {
-        if self.continutiy_check.contains_key(&packet_header.pid) {
+        self.stats.packet(packet_header);
+    }
 
-            let last_counter = self.continutiy_check.get_mut(&packet_header.pid).unwrap();
+    /// Feeds a packet's payload to the section assembler, counting any
+    /// sections it had to drop against the pid.
+    fn push_sections(&mut self, header: &PacketHeader, payload: Bytes) -> Vec<Bytes> {
+        let crc_errors = self.sections.crc_errors();
+        let sections = self.sections.push(header.pid, header.pusi, payload);
 
-            let mut expected_count = 0;
+        for _ in crc_errors..self.sections.crc_errors() {
+            self.stats.crc_error(header.pid);
+        }
 
-            if *last_counter < 0x0f {
-                expected_count = *last_counter + 1;
-            }
+        sections
+    }
 
-            if packet_header.continuity_counter != expected_count {
-                log::debug!("An out-of-order packet was received.(PID : {:?} Expected : {}, Received : {}", packet_header.pid, expected_count, packet_header.continuity_counter);
+    /// Feeds raw bytes from the transport. The input does not need to be
+    /// packet aligned, partial packets are held until the next call. Packets
+    /// that fail to parse are counted in the stats and skipped.
+    pub fn push(&mut self, buf: &[u8]) ->Result<Vec<DemuxerEvent>> {
+        for event in self.sync.push(buf) {
+            match event {
+                SyncEvent::Packet(packet) => {
+                    if let Err(err) = self.push_packet(packet) {
+                        log::debug!("Dropping malformed packet: {}", err);
+                        self.stats.malformed_packet();
+                    }
+                },
+                SyncEvent::Acquired(packet_size) => self.emit(DemuxerEvent::SyncAcquired(packet_size)),
+                SyncEvent::Lost => {
+                    self.stats.sync_lost();
+                    self.emit(DemuxerEvent::SyncLost);
+                },
             }
-
-            *last_counter = expected_count;
-        } else {
-            self.continutiy_check.insert(packet_header.pid, packet_header.continuity_counter);
         }
+
+        Ok(self.return_queue.drain(..).collect())
     }
 
-    pub fn push(&mut self, buf: &[u8]) ->Result<Vec<DemuxerEvent>> {
-        let mut itr = buf
-            .chunks_exact(SIZE);
+    fn push_packet(&mut self, packet: Bytes) -> Result<()> {
+        let mut reader  = io::Cursor::new(packet);
+        let sync_byte = reader.read_u8()?;
 
-            while let Some(packet) = itr.next() {
-                let mut reader  = io::Cursor::new(bytes::Bytes::copy_from_slice(packet));
-                let sync_byte = reader.read_u8()?;
+        if sync_byte != SYNC_BYTE {
+            bail!(DemuxError::InvalidSyncByte{ expected: SYNC_BYTE, found: sync_byte});
+        }
 
-                if sync_byte != SYNC_BYTE {
-                    bail!(DemuxError::InvalidSyncByte{ expected: SYNC_BYTE, found: sync_byte});
-                }
+        let header = PacketHeader::try_new(&mut reader)?;
+        self.check_continutiy(&header);
+
+        if let Some(system_clock) = header.system_clock() {
+            self.stats.pcr(header.pid, system_clock, header.discontinuity);
+        }
 
-                let header = PacketHeader::try_new(&mut reader)?;
+        if header.pcr.is_some() || header.discontinuity {
+            // a PCR pid may be shared between programs
+            let programs: Vec<u16> = self.programs
+                .iter()
+                .filter(|(_, program)| program.pmt.as_ref().map(|pmt| pmt.pcr_pid) == Some(header.pid))
+                .map(|(program_number, _)| *program_number)
+                .collect();
+
+            for program_number in programs {
+                if header.discontinuity {
+                    self.emit(DemuxerEvent::Discontinuity(program_number));
+                }
 
                 if let Some(pcr) = header.pcr {
-                    self.emit(DemuxerEvent::ClockRef(pcr));
+                    self.emit(DemuxerEvent::ClockRef(program_number, pcr));
                 }
+            }
+        }
 
-                if header.pid == Pid::PAT {
-                    if header.adaptation_control.has_payload() {
-                        // skip pointer field
-                        reader.seek(io::SeekFrom::Current(1))?;
-                    }
+        if (header.pid == Pid::PAT || self.is_pmt_pid(&header.pid)) && header.adaptation_control.has_payload() {
+            let payload = reader.get_remaining();
 
-                    let pat = PAT::try_new(&mut reader)?;
+            for section in self.push_sections(&header, payload) {
+                self.handle_section(header.pid, section)?;
+            }
+        }
 
-                    //We assume a single program
-                    if self.pmt_pid == None {
-                        let pid: Pid = pat.programs[0].program_pid;
-                        log::debug!("Found PMT pid:{:?}", pid);
-                        self.pmt_pid = Some(pid);
-                    }
+        if let Some((program_number, StreamType::SCTE35)) = self.program_for_stream(&header.pid) {
+            if header.adaptation_control.has_payload() {
+                let payload = reader.get_remaining();
+
+                for section in self.push_sections(&header, payload) {
+                    self.handle_splice_section(program_number, header.pid, section)?;
                 }
+            }
 
-                if let Some(pid) = self.pmt_pid {
-                    if pid == header.pid {
-                        if header.adaptation_control.has_payload() {
-                            // skip pointer field
-                            reader.seek(io::SeekFrom::Current(1))?;
-                        }
-                        
-                        let pmt = PMT::try_new(&mut reader)?;
-                        self.pcr_pid = Some(pmt.pcr_pid);
+            return Ok(());
+        }
 
-                        if self.pmt.is_none() {
-                            self.emit(DemuxerEvent::StreamDetails(pmt.streams.clone()));
-                            self.pmt = Some(pmt);
-                        }
+        if let Some((program_number, stream_type)) = self.program_for_stream(&header.pid) {
+            if header.adaptation_control.has_payload() {
+
+                if header.pusi {
+                    // a PES without a declared length ends at the next start
+                    if let Some(packet) = self.packets.remove(&header.pid) {
+                        self.emit_packet(program_number, header.pid, stream_type.clone(), packet);
                     }
+
+                    let pes_header = PesHeader::try_new(&mut reader)?;
+
+                    if let Some(pts) = pes_header.pts {
+                        self.stats.pts(header.pid, pts);
+                    }
+
+                    let packet = Packet {
+                        buffer: BytesMut::new(),
+                        stream_id: pes_header.stream_id.clone(),
+                        pts: pes_header.pts,
+                        dts: pes_header.dts,
+                        expected_size: pes_header.payload_size(),
+                    };
+
+                    self.packets.insert(header.pid, packet);
                 }
 
-                if self.has_stream(&header.pid) {
-                    self.check_continutiy(&header);
-
-                    if header.adaptation_control.has_payload() {
-
-                        if header.pusi {
-                            if let Some(packet) = self.packets.get_mut(&header.pid) {
-                                if packet.buffer.len() != 0 {
-                                    let packet_clone = packet.clone();
-
-                                    let stream_type = self.pmt.clone().unwrap().streams[&header.pid].clone();
-
-                                    match packet.stream_id {
-                                        StreamId::Audio(_) => {
-                                            self.emit(DemuxerEvent::Audio(stream_type, packet_clone.buffer.freeze(), packet_clone.pts));
-                                        }
-                                        StreamId::Video(_) => {
-                                            self.emit(DemuxerEvent::Video(stream_type, packet_clone.buffer.freeze(), packet_clone.pts, packet_clone.dts));
-                                        },
-                                        _ => (),
-                                    }
-                                }
-                            }
-
-                            let pes_header = PesHeader::try_new(&mut reader)?;
-                            let packet = Packet {
-                                buffer: BytesMut::new(),
-                                stream_id: pes_header.stream_id,
-                                pts: pes_header.pts,
-                                dts: pes_header.dts,
-                            };
-
-                            self.packets.insert(header.pid, packet);
-                        }
+                let complete = match self.packets.get_mut(&header.pid) {
+                    Some(packet) => {
+                        let remaning = reader.get_remaining();
+                        packet.buffer.put(remaning);
 
-                        if let Some(packet) = self.packets.get_mut(&header.pid) {
-                            let remaning = reader.get_remaining();
-                            packet.buffer.put(remaning);
+                        match packet.expected_size {
+                            Some(expected_size) => packet.buffer.len() >= expected_size,
+                            None => false,
+                        }
+                    },
+                    None => false,
+                };
+
+                if complete {
+                    if let Some(mut packet) = self.packets.remove(&header.pid) {
+                        if let Some(expected_size) = packet.expected_size {
+                            packet.buffer.truncate(expected_size);
                         }
+
+                        self.emit_packet(program_number, header.pid, stream_type, packet);
                     }
                 }
             }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+    use crate::{crc, muxer::Muxer, pid::Pid, stream_type::StreamType, sync::SYNC_CONFIRM, DemuxerEvent};
+    use super::{Demuxer, ProgramSelection, SIZE, SYNC_BYTE};
+
+    #[test]
+    fn flushes_unbounded_pes_at_end_of_stream() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+
+        // too large for PES_packet_length, so it's written unbounded
+        let frame = vec![0x01; 70000];
+        let stream = muxer.write(video, &frame, 3000, None, true).unwrap();
+
+        let mut demuxer = Demuxer::new();
+        let events = demuxer.push(&stream).unwrap();
+        assert!(!events.iter().any(|event| matches!(event, DemuxerEvent::Video(..))));
+
+        let events = demuxer.flush();
+        assert_eq!(events.len(), 1);
+
+        match &events[0] {
+            DemuxerEvent::Video(_, _, _, data, pts, _) => {
+                assert_eq!(data.len(), frame.len());
+                assert_eq!(*pts, Some(3000));
+            },
+            event => panic!("unexpected {:?}", event),
+        }
+
+        assert!(demuxer.flush().is_empty());
+    }
+
+    #[test]
+    fn skips_malformed_pes_and_carries_on() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+
+        let mut stream = Vec::new();
+        for (pts, key) in [(3000, true), (6000, false), (9000, false)] {
+            stream.extend_from_slice(&muxer.write(video, &[0x01; 400], pts, None, key).unwrap());
+        }
+
+        // break the start code of the second PES
+        let raw_pid: u16 = video.into();
+        let second = stream.chunks(SIZE)
+            .enumerate()
+            .filter(|(_, packet)| packet[1] & 0x40 != 0 && u16::from(packet[1] & 0x1F) << 8 | u16::from(packet[2]) == raw_pid)
+            .nth(1)
+            .unwrap().0 * SIZE;
+        let start_code = stream[second..second + SIZE]
+            .windows(4)
+            .position(|window| window == [0x00, 0x00, 0x01, 0xE0])
+            .unwrap() + second;
+        stream[start_code + 2] = 0x02;
+
+        let mut demuxer = Demuxer::new();
+        let mut events = demuxer.push(&stream).unwrap();
+        events.extend(demuxer.flush());
+
+        let pts: Vec<_> = events.iter()
+            .filter_map(|event| match event {
+                DemuxerEvent::Video(_, _, _, _, pts, _) => *pts,
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(pts, vec![3000, 9000]);
+        assert_eq!(demuxer.stats().malformed_packets, 1);
+    }
+
+    #[test]
+    fn signals_discontinuity_on_pcr_pid() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+
+        let mut stream = muxer.write(video, &[0x01; 10], 12000, None, true).unwrap().to_vec();
+        let restart = stream.len();
+        stream.extend_from_slice(&muxer.write(video, &[0x01; 10], 900_000, None, true).unwrap());
+
+        // set the discontinuity_indicator on the PCR carrying packet after the restart
+        let raw_pid: u16 = video.into();
+        let offset = stream[restart..]
+            .chunks(SIZE)
+            .position(|packet| u16::from(packet[1] & 0x1F) << 8 | u16::from(packet[2]) == raw_pid)
+            .unwrap() * SIZE + restart;
+        stream[offset + 5] |= 0x80;
+
+        let mut demuxer = Demuxer::new();
+        let events: Vec<_> = demuxer.push(&stream).unwrap()
+            .into_iter()
+            .filter(|event| matches!(event, DemuxerEvent::Discontinuity(_) | DemuxerEvent::ClockRef(..)))
+            .collect();
+
+        assert!(matches!(events[..], [
+            DemuxerEvent::ClockRef(1, 3000),
+            DemuxerEvent::Discontinuity(1),
+            DemuxerEvent::ClockRef(1, 891_000),
+        ]));
+        assert_eq!(demuxer.stats().pcr_discontinuity_errors(), 0);
+    }
+
+    #[test]
+    fn reemits_stream_details_on_pmt_update() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&muxer.write(video, &[0x01; 10], 3000, None, true).unwrap());
+
+        let audio = muxer.add_stream(StreamType::AAC);
+        stream.extend_from_slice(&muxer.write(video, &[0x01; 10], 6000, None, true).unwrap());
+
+        let mut demuxer = Demuxer::new();
+        let details: Vec<_> = demuxer.push(&stream).unwrap()
+            .into_iter()
+            .filter_map(|event| match event {
+                DemuxerEvent::StreamDetails(_, streams) => Some(streams),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(details.len(), 2);
+        assert!(!details[0].contains_key(&audio));
+        assert_eq!(details[1][&audio], StreamType::AAC);
+    }
+
+    fn multi_program_stream() -> Vec<u8> {
+        let mut first = Muxer::with_program(1, Pid::from(0x1000));
+        let first_video = first.add_stream_with_pid(StreamType::H264, Pid::from(0x0100));
+
+        let mut second = Muxer::with_program(2, Pid::from(0x1001));
+        let second_video = second.add_stream_with_pid(StreamType::H265, Pid::from(0x0200));
+
+        let mut pat = BytesMut::new();
+        pat.put_u8(0x00);
+        pat.put_u16(0xB000 | (5 + 4 * 3 + 4));
+        pat.put_u16(1);
+        pat.put_u8(0xC1);
+        pat.put_u16(0x0000);
+        // network PID entry which must be ignored
+        pat.put_u32(0x0000_E010);
+        pat.put_u32(0x0001_F000);
+        pat.put_u32(0x0002_F001);
+        let crc = crc::sum32(&pat);
+        pat.put_u32(crc);
+
+        let mut stream = vec![SYNC_BYTE, 0x40, 0x00, 0x10, 0x00];
+        stream.extend_from_slice(&pat);
+        stream.resize(SIZE, 0xFF);
+
+        // skip the single program PATs the muxers write
+        stream.extend_from_slice(&first.write_tables()[SIZE..]);
+        stream.extend_from_slice(&second.write_tables()[SIZE..]);
+
+        for pts in [3000, 6000] {
+            stream.extend_from_slice(&first.write(first_video, &[0x01; 10], pts, None, false).unwrap());
+            stream.extend_from_slice(&second.write(second_video, &[0x02; 10], pts, None, false).unwrap());
+        }
+
+        stream
+    }
+
+    #[test]
+    fn demuxes_every_program() {
+        let mut demuxer = Demuxer::with_program_selection(ProgramSelection::All);
+        let events = demuxer.push(&multi_program_stream()).unwrap();
+
+        let mut details = vec![];
+        let mut videos = vec![];
+
+        for event in events {
+            match event {
+                DemuxerEvent::StreamDetails(program, _) => details.push(program),
+                DemuxerEvent::Video(program, _, stream_type, _, _, _) => videos.push((program, stream_type)),
+                _ => (),
+            }
+        }
+
+        assert_eq!(demuxer.program_count(), 2);
+        assert_eq!(demuxer.pcr_pid(2), Some(Pid::from(0x0200)));
+        assert_eq!(details, vec![1, 2]);
+        assert_eq!(videos, vec![
+            (1, StreamType::H264), (2, StreamType::H265),
+            (1, StreamType::H264), (2, StreamType::H265),
+        ]);
+    }
+
+    #[test]
+    fn selects_program_by_number() {
+        let mut demuxer = Demuxer::with_program_selection(ProgramSelection::Number(2));
+        let events = demuxer.push(&multi_program_stream()).unwrap();
+
+        assert!(events.iter().all(|event| match event {
+            DemuxerEvent::StreamDetails(program, _)
+            | DemuxerEvent::Video(program, ..)
+            | DemuxerEvent::Audio(program, ..)
+            | DemuxerEvent::ClockRef(program, _) => *program == 2,
+            _ => true,
+        }));
+        assert_eq!(demuxer.program_count(), 1);
+        assert_eq!(demuxer.pat_program_count(), 2);
+    }
+
+    /// A single packet carrying `section` with its CRC appended.
+    fn section_packet(pid: u16, section: &[u8]) -> Vec<u8> {
+        let mut section = BytesMut::from(section);
+        let crc = crc::sum32(&section);
+        section.put_u32(crc);
+
+        let mut packet = vec![SYNC_BYTE, 0x40 | (pid >> 8) as u8, pid as u8, 0x10, 0x00];
+        packet.extend_from_slice(&section);
+        packet.resize(SIZE, 0xFF);
+        packet
+    }
+
+    #[test]
+    fn drops_short_form_pat_and_pmt() {
+        let mut stream = section_packet(0x0000, &[0x00, 0x30, 0x0D, 0x00, 0x01, 0xC1, 0x00, 0x00, 0x00, 0x01, 0xF0, 0x00]);
+
+        let mut muxer = Muxer::with_program(1, Pid::from(0x1000));
+        let video = muxer.add_stream(StreamType::H264);
+        let tables = muxer.write_tables();
+        stream.extend_from_slice(&tables[..SIZE]);
+
+        // a PMT with the section_syntax_indicator cleared, its CRC goes unchecked
+        stream.extend_from_slice(&section_packet(0x1000, &[0x02, 0x30, 0x0D, 0x00, 0x01, 0xC1, 0x00, 0x00, 0xE1, 0x00, 0xF0, 0x00]));
+        stream.extend_from_slice(&muxer.write(video, &[0x01; 10], 3000, None, true).unwrap());
+
+        let mut demuxer = Demuxer::new();
+        let details: Vec<_> = demuxer.push(&stream).unwrap()
+            .into_iter()
+            .filter_map(|event| match event {
+                DemuxerEvent::StreamDetails(program, streams) => Some((program, streams)),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0].1[&video], StreamType::H264);
+        assert_eq!(demuxer.stats().pat_errors, 1);
+        assert_eq!(demuxer.stats().crc_errors(), 1);
+    }
+
+    #[test]
+    fn skips_pat_and_pmt_with_bad_lengths_or_reserved_bits() {
+        // reserved bits cleared
+        let mut stream = section_packet(0x0000, &[0x00, 0xB0, 0x0D, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x01, 0xF0, 0x00]);
+
+        // a PAT pointing at a PMT too short for its PCR_PID and program_info_length
+        stream.extend_from_slice(&section_packet(0x0000, &[0x00, 0xB0, 0x0D, 0x00, 0x01, 0xC1, 0x00, 0x00, 0x00, 0x01, 0xF0, 0x00]));
+        stream.extend_from_slice(&section_packet(0x1000, &[0x02, 0xB0, 0x09, 0x00, 0x01, 0xC1, 0x00, 0x00]));
+
+        // null packets so the sync layer confirms and releases the above
+        for _ in 0..SYNC_CONFIRM {
+            let mut null = vec![SYNC_BYTE, 0x1F, 0xFF, 0x10];
+            null.resize(SIZE, 0xFF);
+            stream.extend_from_slice(&null);
+        }
+
+        let mut demuxer = Demuxer::new();
+        let events = demuxer.push(&stream).unwrap();
 
-            Ok(self.return_queue.drain(..).collect())
+        assert!(!events.iter().any(|event| matches!(event, DemuxerEvent::StreamDetails(..))));
+        assert_eq!(demuxer.stats().malformed_packets, 2);
+        assert_eq!(demuxer.program_count(), 1);
     }
 }
@@ -7,4 +7,37 @@ pub enum DemuxError {
         expected: u8,
         found: u8,
     },
-}
\ No newline at end of file
+
+    #[error("invalid table_id (expected {expected:#04x}, found {found:#04x})")]
+    InvalidTableId {
+        expected: u8,
+        found: u8,
+    },
+
+    #[error("unsupported splice_command_type {0:#04x}")]
+    UnsupportedSpliceCommand(u8),
+
+    #[error("invalid PES start code {0:#08x}")]
+    InvalidPesStartCode(u32),
+
+    #[error("PES_header_data_length {0} too short for its timestamps")]
+    InvalidPesHeaderLength(usize),
+
+    #[error("reserved bits of section byte {0:#04x} are not set")]
+    InvalidSectionReserved(u8),
+
+    #[error("section_length {length} shorter than the minimum {minimum}")]
+    SectionTooShort {
+        length: u16,
+        minimum: u16,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum MuxError {
+    #[error("no stream registered for {0:?}")]
+    UnknownStream(crate::pid::Pid),
+
+    #[error("empty access unit for {0:?}")]
+    EmptyPayload(crate::pid::Pid),
+}
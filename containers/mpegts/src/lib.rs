@@ -3,22 +3,38 @@ use std::collections::HashMap;
 use bytes::Bytes;
 use pid::Pid;
 use stream_type::StreamType;
+use section::scte35::SpliceInfoSection;
 
 pub mod error;
 pub mod stream_type;
 pub mod pid;
 pub mod packet_header;
 pub mod demuxer;
+pub mod muxer;
+pub mod crc;
+pub mod sync;
+pub mod stats;
+pub mod retime;
 pub mod section;
 
 pub const HZ: u32 = 90_000;
 pub const PCR_CYCLE: u64 = 8_589_934_592; // 2 ** 33
 
 
+/// Events produced by the demuxer. Program scoped events carry the
+/// program_number they belong to as their first field.
 #[derive(Clone, Debug)]
 pub enum DemuxerEvent {
-    StreamDetails(HashMap<Pid, StreamType>),
-    Video(StreamType, Bytes, Option<u64>, Option<u64>),
-    Audio(StreamType, Bytes, Option<u64>),
-    ClockRef(u64),
+    StreamDetails(u16, HashMap<Pid, StreamType>),
+    /// PES payload of a video pid with its PTS and DTS
+    Video(u16, Pid, StreamType, Bytes, Option<u64>, Option<u64>),
+    /// PES payload of an audio pid with its PTS
+    Audio(u16, Pid, StreamType, Bytes, Option<u64>),
+    ClockRef(u16, u64),
+    /// The discontinuity_indicator was set on the program's PCR pid, the
+    /// following PCR and timestamps start a new time base.
+    Discontinuity(u16),
+    Splice(u16, SpliceInfoSection),
+    SyncAcquired(usize),
+    SyncLost,
 }
@@ -0,0 +1,505 @@
+use std::collections::HashMap;
+use anyhow::{Result, bail};
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    crc,
+    demuxer::{SIZE, SYNC_BYTE},
+    error::MuxError,
+    pid::Pid,
+    section::{descriptor::{EXTENSION_TAG, REGISTRATION_TAG}, pes_header::{PesHeader, StreamId}},
+    stream_type::StreamType,
+    HZ,
+    PCR_CYCLE,
+};
+
+pub const DEFAULT_PMT_PID: u16 = 0x1000;
+pub const DEFAULT_ES_PID: u16 = 0x0100;
+pub const DEFAULT_PROGRAM_NUMBER: u16 = 1;
+
+const PAT_TABLE_ID: u8 = 0x00;
+const PMT_TABLE_ID: u8 = 0x02;
+const PAYLOAD_SIZE: usize = SIZE - 4;
+
+/// How far the PCR runs ahead of the DTS it is sent with, giving a decoder
+/// 100ms to receive an access unit before it is due.
+pub const PCR_MUX_DELAY: u64 = HZ as u64 / 10;
+
+const OPUS_IDENTIFIER: &[u8; 4] = b"Opus";
+const AV1_IDENTIFIER: &[u8; 4] = b"AV01";
+const OPUS_AUDIO_DESCRIPTOR_TAG: u8 = 0x80;
+const OPUS_STEREO: u8 = 0x02;
+
+#[derive(Clone, Debug)]
+struct Stream {
+    pid: Pid,
+    stream_type: StreamType,
+    stream_id: StreamId,
+    /// ES_info descriptors written in the PMT
+    descriptors: Bytes,
+}
+
+pub struct Muxer {
+    transport_stream_id: u16,
+    program_number: u16,
+    pmt_pid: Pid,
+    pcr_pid: Option<Pid>,
+    version: u8,
+    tables_written: bool,
+
+    streams: Vec<Stream>,
+    continuity_counters: HashMap<Pid, u8>,
+}
+
+impl Default for Muxer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Muxer {
+
+    pub fn new() -> Muxer {
+        Muxer {
+            transport_stream_id: 1,
+            program_number: DEFAULT_PROGRAM_NUMBER,
+            pmt_pid: Pid::from(DEFAULT_PMT_PID),
+            pcr_pid: None,
+            version: 0,
+            tables_written: false,
+
+            streams: Vec::new(),
+            continuity_counters: HashMap::new(),
+        }
+    }
+
+    pub fn with_program(program_number: u16, pmt_pid: Pid) -> Muxer {
+        Muxer {
+            program_number,
+            pmt_pid,
+            ..Self::new()
+        }
+    }
+
+    /// Registers an elementary stream and returns the pid its access units
+    /// should be written to. The first video stream carries the PCR, falling
+    /// back to the first stream added when there is no video.
+    pub fn add_stream(&mut self, stream_type: StreamType) -> Pid {
+        let pid = Pid::from(DEFAULT_ES_PID + self.streams.len() as u16);
+        self.add_stream_with_pid(stream_type, pid)
+    }
+
+    pub fn add_stream_with_pid(&mut self, stream_type: StreamType, pid: Pid) -> Pid {
+        let descriptors = Self::descriptors(&stream_type, OPUS_STEREO);
+        self.push_stream(stream_type, pid, descriptors)
+    }
+
+    /// Registers an Opus stream with the given channel_config_code, the
+    /// streams added through `add_stream` are signalled as stereo.
+    pub fn add_opus_stream(&mut self, channel_config_code: u8) -> Pid {
+        let pid = Pid::from(DEFAULT_ES_PID + self.streams.len() as u16);
+        let descriptors = Self::descriptors(&StreamType::OPUS, channel_config_code);
+        self.push_stream(StreamType::OPUS, pid, descriptors)
+    }
+
+    fn push_stream(&mut self, stream_type: StreamType, pid: Pid, descriptors: Bytes) -> Pid {
+        let video_count = self.streams.iter().filter(|s| s.stream_type.is_video()).count() as u8;
+        let audio_count = self.streams.iter().filter(|s| s.stream_type.is_audio()).count() as u8;
+
+        let stream_id = match stream_type {
+            StreamType::H264 | StreamType::H265 | StreamType::AV1 => StreamId::Video(video_count),
+            StreamType::AAC | StreamType::MPEG1Audio | StreamType::MPEG2Audio => StreamId::Audio(audio_count),
+            // Opus and other private payloads go in private_stream_1
+            _ => StreamId::Unknown(0xBD),
+        };
+
+        if self.pcr_pid.is_none() || (stream_type.is_video() && !self.has_video()) {
+            self.pcr_pid = Some(pid);
+        }
+
+        self.streams.push(Stream { pid, stream_type, stream_id, descriptors });
+
+        // a PMT that has already gone out needs a new version
+        if self.tables_written {
+            self.version = (self.version + 1) & 0x1F;
+        }
+
+        pid
+    }
+
+    /// Streams without a stream_type of their own are written as private data
+    /// and identified by a registration descriptor.
+    fn descriptors(stream_type: &StreamType, opus_channel_config_code: u8) -> Bytes {
+        let mut descriptors = BytesMut::new();
+
+        match stream_type {
+            StreamType::OPUS => {
+                descriptors.put_u8(REGISTRATION_TAG);
+                descriptors.put_u8(4);
+                descriptors.put_slice(OPUS_IDENTIFIER);
+
+                descriptors.put_u8(EXTENSION_TAG);
+                descriptors.put_u8(2);
+                descriptors.put_u8(OPUS_AUDIO_DESCRIPTOR_TAG);
+                descriptors.put_u8(opus_channel_config_code);
+            },
+            StreamType::AV1 => {
+                descriptors.put_u8(REGISTRATION_TAG);
+                descriptors.put_u8(4);
+                descriptors.put_slice(AV1_IDENTIFIER);
+            },
+            _ => (),
+        }
+
+        descriptors.freeze()
+    }
+
+    fn pmt_stream_type(stream_type: &StreamType) -> u8 {
+        match stream_type {
+            StreamType::OPUS | StreamType::AV1 => StreamType::PrivateData.into(),
+            stream_type => stream_type.clone().into(),
+        }
+    }
+
+    fn has_video(&self) -> bool {
+        self.streams.iter().any(|s| s.stream_type.is_video())
+    }
+
+    /// Writes the PAT and PMT for the current set of streams.
+    pub fn write_tables(&mut self) -> Bytes {
+        let mut buffer = BytesMut::with_capacity(SIZE * 2);
+
+        let pat = self.build_pat();
+        self.write_section(&mut buffer, Pid::PAT, &pat);
+
+        let pmt = self.build_pmt();
+        let pmt_pid = self.pmt_pid;
+        self.write_section(&mut buffer, pmt_pid, &pmt);
+
+        self.tables_written = true;
+        buffer.freeze()
+    }
+
+    /// Writes a single access unit as a PES packet split over as many
+    /// transport packets as needed. H.264/H.265 are expected in Annex B
+    /// and AAC as ADTS frames. The PAT/PMT are repeated ahead of every key
+    /// frame on the PCR stream so that a reader can join at that point.
+    pub fn write(&mut self, pid: Pid, data: &[u8], pts: u64, dts: Option<u64>, key_frame: bool) -> Result<Bytes> {
+        let stream = match self.streams.iter().find(|s| s.pid == pid) {
+            Some(stream) => stream.clone(),
+            None => bail!(MuxError::UnknownStream(pid)),
+        };
+
+        if data.is_empty() {
+            bail!(MuxError::EmptyPayload(pid));
+        }
+
+        let mut buffer = BytesMut::with_capacity(data.len() + (data.len() / PAYLOAD_SIZE + 3) * SIZE);
+
+        let is_pcr_pid = self.pcr_pid == Some(pid);
+
+        if !self.tables_written || (is_pcr_pid && key_frame) {
+            let tables = self.write_tables();
+            buffer.put(tables);
+        }
+
+        // a DTS equal to the PTS is redundant
+        let dts = dts.filter(|dts| *dts != pts);
+        let mut pes = PesHeader::write(stream.stream_id, pts, dts, data.len());
+        pes.put_slice(data);
+
+        let pcr = if is_pcr_pid {
+            Some((dts.unwrap_or(pts) + PCR_CYCLE - PCR_MUX_DELAY) % PCR_CYCLE)
+        } else {
+            None
+        };
+        let random_access = key_frame || !stream.stream_type.is_video();
+
+        self.packetize(&mut buffer, pid, &pes, pcr, random_access);
+
+        Ok(buffer.freeze())
+    }
+
+    fn next_counter(&mut self, pid: Pid) -> u8 {
+        let counter = self.continuity_counters.entry(pid).or_insert(0x0F);
+        *counter = (*counter + 1) & 0x0F;
+        *counter
+    }
+
+    fn packetize(&mut self, buffer: &mut BytesMut, pid: Pid, payload: &[u8], pcr: Option<u64>, random_access: bool) {
+        let raw_pid: u16 = pid.into();
+        let mut offset = 0;
+
+        while offset < payload.len() {
+            let first = offset == 0;
+            let remaining = payload.len() - offset;
+
+            // adaptation field body, not including the length byte
+            let mut adaptation = BytesMut::new();
+
+            if first && (pcr.is_some() || random_access) {
+                let mut flags = 0;
+
+                if random_access {
+                    flags |= 0x40;
+                }
+
+                if pcr.is_some() {
+                    flags |= 0x10;
+                }
+
+                adaptation.put_u8(flags);
+
+                if let Some(pcr) = pcr {
+                    Self::write_pcr(&mut adaptation, pcr);
+                }
+            }
+
+            let mut has_adaptation = !adaptation.is_empty();
+            let mut adaptation_size = if has_adaptation { 1 + adaptation.len() } else { 0 };
+
+            // stuff the adaptation field so the payload ends on the packet boundary
+            if remaining < PAYLOAD_SIZE - adaptation_size {
+                adaptation_size = PAYLOAD_SIZE - remaining;
+                has_adaptation = true;
+
+                if adaptation_size > 1 && adaptation.is_empty() {
+                    adaptation.put_u8(0x00);
+                }
+
+                while 1 + adaptation.len() < adaptation_size {
+                    adaptation.put_u8(0xFF);
+                }
+            }
+
+            let payload_size = PAYLOAD_SIZE - adaptation_size;
+            let counter = self.next_counter(pid);
+
+            buffer.put_u8(SYNC_BYTE);
+            buffer.put_u16(if first { 0x4000 } else { 0x0000 } | (raw_pid & 0x1FFF));
+            buffer.put_u8(if has_adaptation { 0x30 } else { 0x10 } | counter);
+
+            if has_adaptation {
+                buffer.put_u8(adaptation.len() as u8);
+                buffer.put(adaptation);
+            }
+
+            buffer.put_slice(&payload[offset..offset + payload_size]);
+            offset += payload_size;
+        }
+    }
+
+    /// Writes a section starting in a new packet, continued over as many
+    /// packets as it needs with the last one padded out.
+    fn write_section(&mut self, buffer: &mut BytesMut, pid: Pid, section: &[u8]) {
+        let raw_pid: u16 = pid.into();
+        let mut remaining = section;
+        let mut first = true;
+
+        while first || !remaining.is_empty() {
+            let counter = self.next_counter(pid);
+
+            buffer.put_u8(SYNC_BYTE);
+            buffer.put_u16(if first { 0x4000 } else { 0x0000 } | (raw_pid & 0x1FFF));
+            buffer.put_u8(0x10 | counter);
+
+            let mut space = PAYLOAD_SIZE;
+            if first {
+                // pointer field
+                buffer.put_u8(0x00);
+                space -= 1;
+            }
+
+            let size = remaining.len().min(space);
+            buffer.put_slice(&remaining[..size]);
+            buffer.put_bytes(0xFF, space - size);
+
+            remaining = &remaining[size..];
+            first = false;
+        }
+    }
+
+    fn build_pat(&self) -> BytesMut {
+        let pmt_pid: u16 = self.pmt_pid.into();
+        let section_length = 5 + 4 + 4;
+
+        let mut section = BytesMut::new();
+        section.put_u8(PAT_TABLE_ID);
+        section.put_u16(0xB000 | section_length);
+        section.put_u16(self.transport_stream_id);
+        section.put_u8(0xC1 | (self.version << 1));
+        section.put_u8(0x00); // section_number
+        section.put_u8(0x00); // last_section_number
+
+        section.put_u16(self.program_number);
+        section.put_u16(0xE000 | pmt_pid);
+
+        let crc = crc::sum32(&section);
+        section.put_u32(crc);
+        section
+    }
+
+    fn build_pmt(&self) -> BytesMut {
+        let pcr_pid: u16 = self.pcr_pid.unwrap_or(Pid::NULL).into();
+        let descriptors_length: usize = self.streams.iter().map(|stream| stream.descriptors.len()).sum();
+        let section_length = (9 + 5 * self.streams.len() + descriptors_length + 4) as u16;
+
+        let mut section = BytesMut::new();
+        section.put_u8(PMT_TABLE_ID);
+        section.put_u16(0xB000 | section_length);
+        section.put_u16(self.program_number);
+        section.put_u8(0xC1 | (self.version << 1));
+        section.put_u8(0x00); // section_number
+        section.put_u8(0x00); // last_section_number
+
+        section.put_u16(0xE000 | pcr_pid);
+        section.put_u16(0xF000); // program_info_length
+
+        for stream in &self.streams {
+            let pid: u16 = stream.pid.into();
+
+            section.put_u8(Self::pmt_stream_type(&stream.stream_type));
+            section.put_u16(0xE000 | pid);
+            section.put_u16(0xF000 | stream.descriptors.len() as u16); // ES_info_length
+            section.put_slice(&stream.descriptors);
+        }
+
+        let crc = crc::sum32(&section);
+        section.put_u32(crc);
+        section
+    }
+
+    #[inline(always)]
+    fn write_pcr(buffer: &mut BytesMut, pcr: u64) {
+        let base = pcr & 0x1_FFFF_FFFF;
+
+        buffer.put_u8((base >> 25) as u8);
+        buffer.put_u8((base >> 17) as u8);
+        buffer.put_u8((base >> 9) as u8);
+        buffer.put_u8((base >> 1) as u8);
+        // low bit of the base, 6 reserved bits, and a zero extension
+        buffer.put_u8(((base & 0x01) as u8) << 7 | 0x7E);
+        buffer.put_u8(0x00);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{demuxer::{Demuxer, SIZE}, muxer::{Muxer, PCR_MUX_DELAY}, pid::Pid, stream_type::StreamType, DemuxerEvent, PCR_CYCLE};
+
+    #[test]
+    fn round_trips_through_demuxer() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+        let audio = muxer.add_stream(StreamType::AAC);
+
+        let frame: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let adts = vec![0xAB; 100];
+
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&muxer.write(video, &frame, 3600, Some(3000), true).unwrap());
+        stream.extend_from_slice(&muxer.write(audio, &adts, 3000, None, true).unwrap());
+        stream.extend_from_slice(&muxer.write(video, &frame, 7200, Some(6600), false).unwrap());
+        stream.extend_from_slice(&muxer.write(audio, &adts, 5000, None, true).unwrap());
+
+        assert_eq!(stream.len() % SIZE, 0);
+
+        let mut demuxer = Demuxer::new();
+        let events = demuxer.push(&stream).unwrap();
+
+        let mut details = None;
+        let mut videos = vec![];
+        let mut audios = vec![];
+        let mut clock_refs = vec![];
+
+        for event in events {
+            match event {
+                DemuxerEvent::StreamDetails(_, streams) => details = Some(streams),
+                DemuxerEvent::Video(_, _, _, data, pts, dts) => videos.push((data, pts, dts)),
+                DemuxerEvent::Audio(_, _, _, data, pts) => audios.push((data, pts)),
+                DemuxerEvent::ClockRef(_, pcr) => clock_refs.push(pcr),
+                _ => (),
+            }
+        }
+
+        let details = details.unwrap();
+        assert_eq!(details[&video], StreamType::H264);
+        assert_eq!(details[&audio], StreamType::AAC);
+
+        assert_eq!(clock_refs, vec![3000 + PCR_CYCLE - PCR_MUX_DELAY, 6600 + PCR_CYCLE - PCR_MUX_DELAY]);
+
+        assert_eq!(videos.len(), 2);
+        assert_eq!(&videos[0].0[..], &frame[..]);
+        assert_eq!(videos[0].1, Some(3600));
+        assert_eq!(videos[0].2, Some(3000));
+        assert_eq!(videos[1].1, Some(7200));
+
+        assert_eq!(audios.len(), 2);
+        assert_eq!(&audios[0].0[..], &adts[..]);
+        assert_eq!(audios[0].1, Some(3000));
+        assert_eq!(audios[1].1, Some(5000));
+
+        let stats = demuxer.stats();
+        assert_eq!(stats.packets as usize, stream.len() / SIZE);
+        assert_eq!(stats.continuity_errors(), 0);
+        assert_eq!(stats.crc_errors(), 0);
+    }
+
+    #[test]
+    fn splits_pmt_over_packets() {
+        let mut muxer = Muxer::new();
+        // 40 Opus streams give a PMT of well over one packet
+        let audios: Vec<Pid> = (0..40).map(|_| muxer.add_stream(StreamType::OPUS)).collect();
+        let stream = muxer.write(audios[0], &[0x01; 10], 3000, None, true).unwrap();
+
+        assert_eq!(stream.len() % SIZE, 0);
+        assert!(stream.len() / SIZE > 3);
+
+        let mut demuxer = Demuxer::new();
+        let events = demuxer.push(&stream).unwrap();
+
+        let details = events.iter().find_map(|event| match event {
+            DemuxerEvent::StreamDetails(_, streams) => Some(streams),
+            _ => None,
+        }).unwrap();
+
+        assert_eq!(details.len(), 40);
+        assert!(audios.iter().all(|pid| details[pid] == StreamType::OPUS));
+        assert_eq!(demuxer.stats().crc_errors(), 0);
+        assert_eq!(demuxer.stats().continuity_errors(), 0);
+    }
+
+    #[test]
+    fn signals_opus_with_registration_descriptor() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::AV1);
+        let stereo = muxer.add_stream(StreamType::OPUS);
+        let surround = muxer.add_opus_stream(0x06);
+
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&muxer.write(video, &[0x01; 10], 12000, None, true).unwrap());
+        stream.extend_from_slice(&muxer.write(stereo, &[0x02; 10], 12000, None, true).unwrap());
+
+        // private data with a registration descriptor, not the unofficial stream_types
+        let pmt = &stream[SIZE..2 * SIZE];
+        assert!(!pmt.windows(3).any(|window| window == [0x9c, 0xE1, 0x01]));
+        assert!(pmt.windows(4).any(|window| window == b"Opus"));
+
+        let mut demuxer = Demuxer::new();
+        let events = demuxer.push(&stream).unwrap();
+
+        let details = events.iter().find_map(|event| match event {
+            DemuxerEvent::StreamDetails(_, streams) => Some(streams),
+            _ => None,
+        }).unwrap();
+
+        assert_eq!(details[&video], StreamType::AV1);
+        assert_eq!(details[&stereo], StreamType::OPUS);
+        assert_eq!(details[&surround], StreamType::OPUS);
+
+        let pmt = demuxer.pmt(1).unwrap();
+        assert_eq!(pmt.opus_channel_config_code(&stereo), Some(0x02));
+        assert_eq!(pmt.opus_channel_config_code(&surround), Some(0x06));
+    }
+}
@@ -34,11 +34,16 @@ impl AdaptationControl {
 
 #[derive(Clone, Debug)]
 pub struct PacketHeader {
+    pub transport_error: bool,
     pub pid: Pid,
     pub pusi: bool,
     pub continuity_counter: u8,
     pub adaptation_control: AdaptationControl,
+    /// discontinuity_indicator from the adaptation field
+    pub discontinuity: bool,
     pub pcr: Option<u64>,
+    /// 27 MHz extension of the PCR, zero when no PCR is present
+    pub pcr_extension: u16,
     pub header_size: i64,
 }
 
@@ -54,7 +59,9 @@ impl PacketHeader {
         let forth_byte = reader.read_u8()?;
 
         let adaptation_control = AdaptationControl::from((forth_byte & 0x30)>>4);
+        let mut discontinuity = false;
         let mut pcr = None;
+        let mut pcr_extension = 0;
 
         if adaptation_control == AdaptationControl::AdaptationFieldOnly || adaptation_control == AdaptationControl::AdaptationFieldAndPayload
         {
@@ -67,11 +74,14 @@ impl PacketHeader {
                 let adapt_fields = reader.read_u8()?;
                 adapt_length -= 1;
 
+                discontinuity = adapt_fields & 0x80 != 0;
                 let pcr_flag = (adapt_fields >> 4) & 0x01 != 0;
 
                 if pcr_flag {
-                    pcr = Some(Self::read_pcr(reader)?);
-                    adapt_length -= 7;
+                    let (base, extension) = Self::read_pcr(reader)?;
+                    pcr = Some(base);
+                    pcr_extension = extension;
+                    adapt_length -= 6;
                 } 
 
                 reader.seek(SeekFrom::Current(adapt_length))?;
@@ -80,17 +90,25 @@ impl PacketHeader {
         }
 
         Ok(PacketHeader {
+            transport_error: (second_byte & 0x80) != 0,
             pid,
             pusi: (second_byte & 0x40) != 0,
             continuity_counter: forth_byte & 0xf,
             adaptation_control,
+            discontinuity,
             pcr,
+            pcr_extension,
             header_size,
         })
     }
 
+    /// The full PCR on the 27 MHz system clock
+    pub fn system_clock(&self) -> Option<u64> {
+        self.pcr.map(|base| base * 300 + u64::from(self.pcr_extension))
+    }
+
     #[inline(always)]
-    fn read_pcr(reader: &mut Cursor<Bytes>) -> Result<u64> {
+    fn read_pcr(reader: &mut Cursor<Bytes>) -> Result<(u64, u16)> {
         let mut pcr :u64 = 0;
         let mut val :u64 = reader.read_u8()? as u64;
 
@@ -108,9 +126,9 @@ impl PacketHeader {
         val = reader.read_u8()? as u64;
         pcr |= (val >> 7) & 0x01;
     
-        let _ext = (reader.read_u8()? as u16 & 0b1) << 8 | reader.read_u8()? as u16;
+        let ext = (val as u16 & 0b1) << 8 | reader.read_u8()? as u16;
     
-        Ok(pcr)
+        Ok((pcr, ext))
     }
 
 }
\ No newline at end of file
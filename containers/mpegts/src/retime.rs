@@ -0,0 +1,193 @@
+//! Reads and rewrites the clocks of single transport stream packets in place,
+//! for sources that replay a recording and have to move it onto a new time base.
+
+use crate::{demuxer::{SIZE, SYNC_BYTE}, PCR_CYCLE};
+
+/// Stream ids whose PES packets have no optional header, and so no timestamps
+const NO_PES_HEADER: [u8; 8] = [0xBC, 0xBE, 0xBF, 0xF0, 0xF1, 0xF2, 0xF8, 0xFF];
+
+/// The PCR base of the packet, if it carries one.
+pub fn pcr(packet: &[u8]) -> Option<u64> {
+    let offset = pcr_offset(packet)?;
+    Some(read_pcr(&packet[offset..]))
+}
+
+/// The PTS of the PES packet starting in this packet, if it has one.
+pub fn pts(packet: &[u8]) -> Option<u64> {
+    let (offset, _) = pes_timestamps(packet)?;
+    Some(read_timestamp(&packet[offset..]))
+}
+
+/// Moves the PCR, PTS and DTS of the packet `offset` ticks forward, wrapping at 2^33.
+pub fn shift_timestamps(packet: &mut [u8], offset: u64) {
+    if let Some(pcr_offset) = pcr_offset(packet) {
+        let base = (read_pcr(&packet[pcr_offset..]) + offset) % PCR_CYCLE;
+        write_pcr(&mut packet[pcr_offset..], base);
+    }
+
+    if let Some((pts_offset, has_dts)) = pes_timestamps(packet) {
+        let count = if has_dts { 2 } else { 1 };
+
+        for timestamp in packet[pts_offset..].chunks_mut(5).take(count) {
+            let value = (read_timestamp(timestamp) + offset) % PCR_CYCLE;
+            write_timestamp(timestamp, value);
+        }
+    }
+}
+
+/// Sets the discontinuity_indicator. Returns `false` when the packet has no
+/// adaptation field flags to set it in.
+pub fn set_discontinuity(packet: &mut [u8]) -> bool {
+    match adaptation_field_length(packet) {
+        Some(length) if length > 0 => {
+            packet[5] |= 0x80;
+            true
+        },
+        _ => false,
+    }
+}
+
+fn adaptation_field_length(packet: &[u8]) -> Option<usize> {
+    if packet.len() < SIZE || packet[0] != SYNC_BYTE || packet[3] & 0x20 == 0 {
+        return None;
+    }
+
+    Some(packet[4] as usize)
+}
+
+fn pcr_offset(packet: &[u8]) -> Option<usize> {
+    let length = adaptation_field_length(packet)?;
+
+    if length < 7 || packet[5] & 0x10 == 0 {
+        return None;
+    }
+
+    Some(6)
+}
+
+/// Offset of the PTS and whether a DTS follows it.
+fn pes_timestamps(packet: &[u8]) -> Option<(usize, bool)> {
+    if packet.len() < SIZE || packet[0] != SYNC_BYTE || packet[1] & 0x40 == 0 || packet[3] & 0x10 == 0 {
+        return None;
+    }
+
+    let start = match adaptation_field_length(packet) {
+        Some(length) => 5 + length,
+        None => 4,
+    };
+
+    let pes = packet.get(start..)?;
+    if pes.len() < 9 || pes[..3] != [0x00, 0x00, 0x01] || NO_PES_HEADER.contains(&pes[3]) {
+        return None;
+    }
+
+    let (has_pts, has_dts) = match pes[7] >> 6 {
+        2 => (true, false),
+        3 => (true, true),
+        _ => (false, false),
+    };
+
+    let size = if has_dts { 10 } else { 5 };
+    if !has_pts || pes.len() < 9 + size {
+        return None;
+    }
+
+    Some((start + 9, has_dts))
+}
+
+fn read_pcr(data: &[u8]) -> u64 {
+    (data[0] as u64) << 25
+        | (data[1] as u64) << 17
+        | (data[2] as u64) << 9
+        | (data[3] as u64) << 1
+        | (data[4] as u64) >> 7
+}
+
+/// Writes the 33 bit base, leaving the reserved bits and extension as they were.
+fn write_pcr(data: &mut [u8], base: u64) {
+    data[0] = (base >> 25) as u8;
+    data[1] = (base >> 17) as u8;
+    data[2] = (base >> 9) as u8;
+    data[3] = (base >> 1) as u8;
+    data[4] = ((base & 0x01) as u8) << 7 | (data[4] & 0x7F);
+}
+
+fn read_timestamp(data: &[u8]) -> u64 {
+    ((data[0] as u64 >> 1) & 0x07) << 30
+        | (data[1] as u64) << 22
+        | (data[2] as u64 >> 1) << 15
+        | (data[3] as u64) << 7
+        | data[4] as u64 >> 1
+}
+
+/// Writes the 33 bit timestamp, keeping the 4 bit prefix in front of it.
+fn write_timestamp(data: &mut [u8], value: u64) {
+    data[0] = (data[0] & 0xF0) | ((value >> 29) as u8 & 0x0E) | 0x01;
+    data[1] = (value >> 22) as u8;
+    data[2] = ((value >> 14) as u8 & 0xFE) | 0x01;
+    data[3] = (value >> 7) as u8;
+    data[4] = ((value << 1) as u8 & 0xFE) | 0x01;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{demuxer::{Demuxer, SIZE}, muxer::Muxer, stream_type::StreamType, DemuxerEvent, PCR_CYCLE};
+    use super::{pcr, pts, set_discontinuity, shift_timestamps};
+
+    #[test]
+    fn shifts_pcr_pts_and_dts() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+
+        let mut stream = muxer.write(video, &[0x01; 400], 15000, Some(12000), true).unwrap().to_vec();
+
+        let offset = PCR_CYCLE - 1000;
+        for packet in stream.chunks_mut(SIZE) {
+            shift_timestamps(packet, offset);
+        }
+
+        let first_pes = stream.chunks(SIZE).find(|packet| pts(packet).is_some()).unwrap();
+        assert_eq!(pts(first_pes), Some(14000));
+        assert_eq!(pcr(first_pes), Some(2000));
+
+        let mut demuxer = Demuxer::new();
+        let mut events = demuxer.push(&stream).unwrap();
+        events.extend(demuxer.flush());
+
+        let clock_refs: Vec<u64> = events.iter()
+            .filter_map(|event| match event {
+                DemuxerEvent::ClockRef(_, pcr) => Some(*pcr),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(clock_refs, vec![2000]);
+
+        let video = events.iter().find_map(|event| match event {
+            DemuxerEvent::Video(_, _, _, data, pts, dts) => Some((data.len(), *pts, *dts)),
+            _ => None,
+        });
+        assert_eq!(video, Some((400, Some(14000), Some(11000))));
+    }
+
+    #[test]
+    fn flags_discontinuity_in_existing_adaptation_field() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+        let mut stream = muxer.write(video, &[0x01; 10], 3000, None, true).unwrap().to_vec();
+        stream.extend_from_slice(&muxer.write(video, &[0x01; 10], 6000, None, true).unwrap());
+
+        let mut packets: Vec<Vec<u8>> = stream.chunks(SIZE).map(<[u8]>::to_vec).collect();
+
+        // the PAT has no adaptation field
+        assert!(!set_discontinuity(&mut packets[0]));
+        assert_eq!(packets[0], stream[..SIZE]);
+
+        let carrier = packets.iter_mut().rfind(|packet| pcr(packet).is_some()).unwrap();
+        assert!(set_discontinuity(carrier));
+
+        let mut demuxer = Demuxer::new();
+        let events = demuxer.push(&packets.concat()).unwrap();
+        assert!(events.iter().any(|event| matches!(event, DemuxerEvent::Discontinuity(_))));
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::{crc, pid::Pid};
+
+// table_id + section_syntax_indicator/section_length
+const SECTION_HEADER_SIZE: usize = 3;
+const STUFFING_BYTE: u8 = 0xFF;
+
+/// Reassembles PSI sections that are split over several transport packets.
+/// Sections are buffered per PID until `section_length` bytes have arrived
+/// and are only handed back once their CRC32 checks out.
+#[derive(Debug, Default)]
+pub struct SectionAssembler {
+    buffers: HashMap<Pid, BytesMut>,
+    crc_errors: u64,
+}
+
+impl SectionAssembler {
+
+    pub fn new() -> SectionAssembler {
+        SectionAssembler {
+            buffers: HashMap::new(),
+            crc_errors: 0,
+        }
+    }
+
+    /// Number of sections dropped for failing their CRC32
+    pub fn crc_errors(&self) -> u64 {
+        self.crc_errors
+    }
+
+    /// Feeds the payload of a single transport packet, returning every
+    /// section it completes.
+    pub fn push(&mut self, pid: Pid, pusi: bool, payload: Bytes) -> Vec<Bytes> {
+        let mut sections = vec![];
+        let mut payload = payload;
+
+        if pusi {
+            if payload.is_empty() {
+                return sections;
+            }
+
+            let pointer_field = payload.get_u8() as usize;
+
+            if pointer_field > payload.len() {
+                log::warn!("Invalid pointer field {} on {:?}", pointer_field, pid);
+                self.buffers.remove(&pid);
+                return sections;
+            }
+
+            // the bytes ahead of the pointer finish the section already in progress
+            let tail = payload.split_to(pointer_field);
+            if let Some(buffer) = self.buffers.get_mut(&pid) {
+                buffer.put(tail);
+            }
+            self.drain(pid, &mut sections);
+
+            // whatever is left over from the last section can't complete now
+            self.buffers.remove(&pid);
+            self.buffers.insert(pid, BytesMut::from(&payload[..]));
+        } else {
+            match self.buffers.get_mut(&pid) {
+                Some(buffer) => buffer.put(payload),
+                // we joined half way through a section, wait for the next start
+                None => return sections,
+            }
+        }
+
+        self.drain(pid, &mut sections);
+        sections
+    }
+
+    fn drain(&mut self, pid: Pid, sections: &mut Vec<Bytes>) {
+        let buffer = match self.buffers.get_mut(&pid) {
+            Some(buffer) => buffer,
+            None => return,
+        };
+
+        loop {
+            if buffer.is_empty() || buffer[0] == STUFFING_BYTE {
+                self.buffers.remove(&pid);
+                return;
+            }
+
+            if buffer.len() < SECTION_HEADER_SIZE {
+                return;
+            }
+
+            let section_length = (u16::from(buffer[1] & 0x0F) << 8 | u16::from(buffer[2])) as usize;
+            let total = SECTION_HEADER_SIZE + section_length;
+
+            if buffer.len() < total {
+                return;
+            }
+
+            let section = buffer.split_to(total).freeze();
+
+            // long form sections carry a CRC32 over the whole section, which
+            // sums to zero when the trailing CRC is included
+            let syntax_indicator = section[1] & 0x80 != 0;
+
+            if syntax_indicator && crc::sum32(&section) != 0 {
+                log::warn!("Dropping section with invalid CRC32 on {:?}", pid);
+                self.crc_errors += 1;
+                continue;
+            }
+
+            sections.push(section);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, Bytes, BytesMut};
+    use crate::{crc, pid::Pid, section::assembler::SectionAssembler};
+
+    fn section(body_len: usize) -> Bytes {
+        let mut section = BytesMut::new();
+        section.put_u8(0x02);
+        section.put_u16(0xB000 | (body_len + 4) as u16);
+        section.put_bytes(0x11, body_len);
+
+        let crc = crc::sum32(&section);
+        section.put_u32(crc);
+        section.freeze()
+    }
+
+    #[test]
+    fn reassembles_across_packets() {
+        let pid = Pid::from(0x1000);
+        let section = section(300);
+        let mut assembler = SectionAssembler::new();
+
+        let mut first = vec![0x00];
+        first.extend_from_slice(&section[..183]);
+
+        assert!(assembler.push(pid, true, Bytes::from(first)).is_empty());
+
+        let mut second = section[183..].to_vec();
+        second.resize(184, 0xFF);
+
+        let sections = assembler.push(pid, false, Bytes::from(second));
+        assert_eq!(sections, vec![section]);
+    }
+
+    #[test]
+    fn drops_invalid_crc() {
+        let pid = Pid::from(0x1000);
+        let mut section = section(20).to_vec();
+        section[5] ^= 0xFF;
+
+        let mut payload = vec![0x00];
+        payload.extend_from_slice(&section);
+        payload.resize(184, 0xFF);
+
+        let mut assembler = SectionAssembler::new();
+        assert!(assembler.push(pid, true, Bytes::from(payload)).is_empty());
+        assert_eq!(assembler.crc_errors(), 1);
+    }
+}
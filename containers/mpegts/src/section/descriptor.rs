@@ -0,0 +1,339 @@
+use std::io::{self, Cursor};
+use bytes::Bytes;
+use anyhow::Result;
+use byteorder::{ReadBytesExt, BigEndian};
+use bytesio::{bit_reader::BitReader, bytes_reader::BytesCursor};
+
+/// ISO/IEC 13818-1 2.6 and ETSI EN 300 468 6.2
+pub const REGISTRATION_TAG: u8 = 0x05;
+pub const ISO_639_LANGUAGE_TAG: u8 = 0x0A;
+pub const AVC_VIDEO_TAG: u8 = 0x28;
+pub const HEVC_VIDEO_TAG: u8 = 0x38;
+pub const VBI_TELETEXT_TAG: u8 = 0x46;
+pub const STREAM_IDENTIFIER_TAG: u8 = 0x52;
+pub const TELETEXT_TAG: u8 = 0x56;
+pub const SUBTITLING_TAG: u8 = 0x59;
+pub const AC3_TAG: u8 = 0x6A;
+pub const ENHANCED_AC3_TAG: u8 = 0x7A;
+pub const EXTENSION_TAG: u8 = 0x7F;
+pub const ATSC_AC3_TAG: u8 = 0x81;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Language {
+    pub code: String,
+    pub audio_type: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subtitling {
+    pub language: String,
+    pub subtitling_type: u8,
+    pub composition_page_id: u16,
+    pub ancillary_page_id: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Teletext {
+    pub language: String,
+    pub teletext_type: u8,
+    pub magazine_number: u8,
+    pub page_number: u8,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ac3Descriptor {
+    pub component_type: Option<u8>,
+    pub bsid: Option<u8>,
+    pub mainid: Option<u8>,
+    pub asvc: Option<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnhancedAc3Descriptor {
+    pub component_type: Option<u8>,
+    pub bsid: Option<u8>,
+    pub mainid: Option<u8>,
+    pub asvc: Option<u8>,
+    pub mixinfo_exists: bool,
+    pub substreams: Vec<u8>,
+}
+
+/// ATSC A/52 Annex A audio descriptor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AtscAc3Descriptor {
+    pub sample_rate_code: u8,
+    pub bsid: u8,
+    pub bit_rate_code: u8,
+    pub surround_mode: u8,
+    pub bsmod: u8,
+    pub num_channels: u8,
+    pub full_svc: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AvcVideoDescriptor {
+    pub profile_idc: u8,
+    pub constraint_flags: u8,
+    pub level_idc: u8,
+    pub still_present: bool,
+    pub twenty_four_hour_picture: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HevcVideoDescriptor {
+    pub profile_space: u8,
+    pub tier: bool,
+    pub profile_idc: u8,
+    pub profile_compatibility_flags: u32,
+    pub progressive_source: bool,
+    pub interlaced_source: bool,
+    pub non_packed_constraint: bool,
+    pub frame_only_constraint: bool,
+    pub level_idc: u8,
+    pub still_present: bool,
+    pub twenty_four_hour_picture: bool,
+    pub temporal_id: Option<(u8, u8)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Descriptor {
+    Registration { format_identifier: u32, additional_info: Bytes },
+    Iso639Language(Vec<Language>),
+    AvcVideo(AvcVideoDescriptor),
+    HevcVideo(HevcVideoDescriptor),
+    StreamIdentifier(u8),
+    Teletext(Vec<Teletext>),
+    Subtitling(Vec<Subtitling>),
+    Ac3(Ac3Descriptor),
+    EnhancedAc3(EnhancedAc3Descriptor),
+    AtscAc3(AtscAc3Descriptor),
+    Extension { tag_extension: u8, data: Bytes },
+    Unknown { tag: u8, data: Bytes },
+}
+
+impl Descriptor {
+
+    /// Reads a descriptor loop of `length` bytes.
+    pub fn read_all(reader: &mut Cursor<Bytes>, length: usize) -> Result<Vec<Descriptor>> {
+        let mut descriptors = vec![];
+        let end = reader.position() as usize + length;
+
+        while (reader.position() as usize) + 2 <= end {
+            let tag = reader.read_u8()?;
+            let descriptor_length = reader.read_u8()? as usize;
+            let data = reader.read_slice(descriptor_length)?;
+
+            match Self::try_new(tag, data.clone()) {
+                Ok(descriptor) => descriptors.push(descriptor),
+                Err(err) => {
+                    // a malformed descriptor shouldn't take the whole PMT with it
+                    log::debug!("Failed to parse descriptor {:#04x}: {}", tag, err);
+                    descriptors.push(Descriptor::Unknown { tag, data });
+                },
+            }
+        }
+
+        reader.set_position(end as u64);
+        Ok(descriptors)
+    }
+
+    pub fn try_new(tag: u8, data: Bytes) -> Result<Descriptor> {
+        let mut reader = Cursor::new(data.clone());
+
+        let descriptor = match tag {
+            REGISTRATION_TAG => Descriptor::Registration {
+                format_identifier: reader.read_u32::<BigEndian>()?,
+                additional_info: reader.get_remaining(),
+            },
+            ISO_639_LANGUAGE_TAG => {
+                let mut languages = vec![];
+
+                for _ in 0..data.len() / 4 {
+                    languages.push(Language {
+                        code: Self::read_language(&mut reader)?,
+                        audio_type: reader.read_u8()?,
+                    });
+                }
+
+                Descriptor::Iso639Language(languages)
+            },
+            AVC_VIDEO_TAG => {
+                let profile_idc = reader.read_u8()?;
+                let constraint_flags = reader.read_u8()?;
+                let level_idc = reader.read_u8()?;
+                let flags = reader.read_u8()?;
+
+                Descriptor::AvcVideo(AvcVideoDescriptor {
+                    profile_idc,
+                    constraint_flags,
+                    level_idc,
+                    still_present: flags & 0x80 != 0,
+                    twenty_four_hour_picture: flags & 0x40 != 0,
+                })
+            },
+            HEVC_VIDEO_TAG => Descriptor::HevcVideo(Self::read_hevc(data)?),
+            STREAM_IDENTIFIER_TAG => Descriptor::StreamIdentifier(reader.read_u8()?),
+            TELETEXT_TAG | VBI_TELETEXT_TAG => {
+                let mut pages = vec![];
+
+                for _ in 0..data.len() / 5 {
+                    let language = Self::read_language(&mut reader)?;
+                    let flags = reader.read_u8()?;
+
+                    pages.push(Teletext {
+                        language,
+                        teletext_type: flags >> 3,
+                        magazine_number: flags & 0x07,
+                        page_number: reader.read_u8()?,
+                    });
+                }
+
+                Descriptor::Teletext(pages)
+            },
+            SUBTITLING_TAG => {
+                let mut subtitles = vec![];
+
+                for _ in 0..data.len() / 8 {
+                    subtitles.push(Subtitling {
+                        language: Self::read_language(&mut reader)?,
+                        subtitling_type: reader.read_u8()?,
+                        composition_page_id: reader.read_u16::<BigEndian>()?,
+                        ancillary_page_id: reader.read_u16::<BigEndian>()?,
+                    });
+                }
+
+                Descriptor::Subtitling(subtitles)
+            },
+            AC3_TAG => {
+                let flags = reader.read_u8()?;
+                let mut ac3 = Ac3Descriptor::default();
+
+                if flags & 0x80 != 0 { ac3.component_type = Some(reader.read_u8()?); }
+                if flags & 0x40 != 0 { ac3.bsid = Some(reader.read_u8()?); }
+                if flags & 0x20 != 0 { ac3.mainid = Some(reader.read_u8()?); }
+                if flags & 0x10 != 0 { ac3.asvc = Some(reader.read_u8()?); }
+
+                Descriptor::Ac3(ac3)
+            },
+            ENHANCED_AC3_TAG => {
+                let flags = reader.read_u8()?;
+                let mut eac3 = EnhancedAc3Descriptor {
+                    mixinfo_exists: flags & 0x08 != 0,
+                    ..Default::default()
+                };
+
+                if flags & 0x80 != 0 { eac3.component_type = Some(reader.read_u8()?); }
+                if flags & 0x40 != 0 { eac3.bsid = Some(reader.read_u8()?); }
+                if flags & 0x20 != 0 { eac3.mainid = Some(reader.read_u8()?); }
+                if flags & 0x10 != 0 { eac3.asvc = Some(reader.read_u8()?); }
+
+                for mask in [0x04, 0x02, 0x01] {
+                    if flags & mask != 0 {
+                        eac3.substreams.push(reader.read_u8()?);
+                    }
+                }
+
+                Descriptor::EnhancedAc3(eac3)
+            },
+            ATSC_AC3_TAG => {
+                let mut bits = BitReader::new(reader);
+
+                Descriptor::AtscAc3(AtscAc3Descriptor {
+                    sample_rate_code: bits.read_bits(3)? as u8,
+                    bsid: bits.read_bits(5)? as u8,
+                    bit_rate_code: bits.read_bits(6)? as u8,
+                    surround_mode: bits.read_bits(2)? as u8,
+                    bsmod: bits.read_bits(3)? as u8,
+                    num_channels: bits.read_bits(4)? as u8,
+                    full_svc: bits.read_bit()?,
+                })
+            },
+            EXTENSION_TAG => Descriptor::Extension {
+                tag_extension: reader.read_u8()?,
+                data: reader.get_remaining(),
+            },
+            _ => Descriptor::Unknown { tag, data },
+        };
+
+        Ok(descriptor)
+    }
+
+    fn read_language(reader: &mut Cursor<Bytes>) -> io::Result<String> {
+        let code = reader.read_slice(3)?;
+        Ok(String::from_utf8_lossy(&code).to_string())
+    }
+
+    fn read_hevc(data: Bytes) -> Result<HevcVideoDescriptor> {
+        let mut bits = BitReader::new(Cursor::new(data));
+
+        let profile_space = bits.read_bits(2)? as u8;
+        let tier = bits.read_bit()?;
+        let profile_idc = bits.read_bits(5)? as u8;
+        let profile_compatibility_flags = bits.read_bits(32)? as u32;
+        let progressive_source = bits.read_bit()?;
+        let interlaced_source = bits.read_bit()?;
+        let non_packed_constraint = bits.read_bit()?;
+        let frame_only_constraint = bits.read_bit()?;
+        // copied_44bits
+        bits.read_bits(44)?;
+        let level_idc = bits.read_bits(8)? as u8;
+        let temporal_layer_subset = bits.read_bit()?;
+        let still_present = bits.read_bit()?;
+        let twenty_four_hour_picture = bits.read_bit()?;
+        bits.read_bits(5)?;
+
+        let mut temporal_id = None;
+
+        if temporal_layer_subset {
+            let min = bits.read_bits(3)? as u8;
+            bits.read_bits(5)?;
+            let max = bits.read_bits(3)? as u8;
+            bits.read_bits(5)?;
+
+            temporal_id = Some((min, max));
+        }
+
+        Ok(HevcVideoDescriptor {
+            profile_space,
+            tier,
+            profile_idc,
+            profile_compatibility_flags,
+            progressive_source,
+            interlaced_source,
+            non_packed_constraint,
+            frame_only_constraint,
+            level_idc,
+            still_present,
+            twenty_four_hour_picture,
+            temporal_id,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use bytes::Bytes;
+    use crate::section::descriptor::{Descriptor, Language, Ac3Descriptor};
+
+    #[test]
+    fn reads_descriptor_loop() {
+        let raw = Bytes::from(vec![
+            0x05, 0x04, b'O', b'p', b'u', b's',
+            0x0A, 0x04, b'e', b'n', b'g', 0x00,
+            0x52, 0x01, 0x03,
+            0x6A, 0x02, 0x40, 0x08,
+        ]);
+        let length = raw.len();
+
+        let descriptors = Descriptor::read_all(&mut Cursor::new(raw), length).unwrap();
+
+        assert_eq!(descriptors, vec![
+            Descriptor::Registration { format_identifier: 0x4F70_7573, additional_info: Bytes::new() },
+            Descriptor::Iso639Language(vec![Language { code: "eng".to_string(), audio_type: 0 }]),
+            Descriptor::StreamIdentifier(3),
+            Descriptor::Ac3(Ac3Descriptor { bsid: Some(8), ..Default::default() }),
+        ]);
+    }
+}
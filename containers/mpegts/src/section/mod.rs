@@ -1,3 +1,6 @@
+pub mod descriptor;
 pub mod pat;
 pub mod pmt;
 pub mod pes_header;
+pub mod assembler;
+pub mod scte35;
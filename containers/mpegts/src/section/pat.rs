@@ -1,14 +1,17 @@
 use std::io::{Cursor, Seek, SeekFrom};
 use bytes::Bytes;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use byteorder::{ReadBytesExt, BigEndian};
 
-use crate::pid::Pid;
+use crate::{error::DemuxError, pid::Pid};
+
+/// Header after the section_length field and the CRC, a PAT without programs
+const MIN_SECTION_LENGTH: u16 = 5 + 4;
 
 #[allow(unused)]
 #[derive(Clone, Debug)]
 pub struct Program {
-    program_number: u16,
+    pub program_number: u16,
     pub program_pid: Pid,
 }
 
@@ -18,6 +21,8 @@ pub struct PAT {
     table_id: u8,
     section_length: u16,
     stream_id: u16,
+    pub version: u8,
+    pub current_next: bool,
     pub programs: Vec<Program>
 }
 
@@ -27,18 +32,26 @@ impl PAT {
 
         let table_id = reader.read_u8()?;
         let section_length = reader.read_u16::<BigEndian>()? & 0x0FFF;
+        let programs_length = section_length
+            .checked_sub(MIN_SECTION_LENGTH)
+            .ok_or(DemuxError::SectionTooShort { length: section_length, minimum: MIN_SECTION_LENGTH })?;
+
         let stream_id = reader.read_u16::<BigEndian>()?;
 
         let reserved = reader.read_u8()?;
-        assert_eq!((reserved >> 6) & 0x03, 3);
+        if (reserved >> 6) & 0x03 != 3 {
+            bail!(DemuxError::InvalidSectionReserved(reserved));
+        }
+
+        let version = (reserved >> 1) & 0x1F;
+        let current_next = reserved & 0x01 != 0;
 
         // skip section_number and last_section_number
         reader.seek(SeekFrom::Current(2))?;
 
-        let program_count = section_length - 5 /*Header */ -4 /* crc */;
         let mut programs = vec![];
 
-        for _ in 0..(program_count / 4) {
+        for _ in 0..(programs_length / 4) {
             let program_number = reader.read_u16::<BigEndian>()?;
             let program_pid = Pid::from(reader.read_u16::<BigEndian>()? & 0x1fff);
 
@@ -52,6 +65,8 @@ impl PAT {
             table_id,
             section_length,
             stream_id,
+            version,
+            current_next,
             programs
         })
     }
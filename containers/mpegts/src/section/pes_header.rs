@@ -1,7 +1,9 @@
 use std::io::{Cursor, Seek, SeekFrom};
 use bytes::Bytes;
-use anyhow::Result;
+use anyhow::{bail, Result};
+use bytes::{BufMut, BytesMut};
 use byteorder::{ReadBytesExt, BigEndian};
+use crate::error::DemuxError;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum StreamId {
@@ -20,6 +22,16 @@ impl From<u8> for StreamId {
     }
 }
 
+impl From<StreamId> for u8 {
+    fn from(id: StreamId) -> u8 {
+        match id {
+            StreamId::Audio(v) => 0xC0 | (v & 0x1F),
+            StreamId::Video(v) => 0xE0 | (v & 0x0F),
+            StreamId::Unknown(v) => v,
+        }
+    }
+}
+
 
 #[allow(unused)]
 #[derive(Clone, Debug)]
@@ -36,7 +48,9 @@ impl PesHeader {
         let mut header_size = 6;
 
         let start_code:u32 = reader.read_u24::<BigEndian>()?;
-        assert_eq!(start_code, 1); //test for start code
+        if start_code != 1 {
+            bail!(DemuxError::InvalidPesStartCode(start_code));
+        }
 
         let stream_id = StreamId::from(reader.read_u8()?);
         let pes_length = reader.read_u16::<BigEndian>()?;
@@ -46,7 +60,9 @@ impl PesHeader {
         let flags: u8 = reader.read_u8()?;
         let pts_dts_flags = (flags >> 6) & 0x03;
 
-        let mut optional_remaining = reader.read_u8()? as usize;
+        let header_data_length = reader.read_u8()? as usize;
+        let mut optional_remaining = header_data_length;
+        header_size += 3 + header_data_length;
 
         let mut pts = None;
         let mut dts = None;
@@ -54,15 +70,19 @@ impl PesHeader {
         if pts_dts_flags == 2 || pts_dts_flags == 3
 		{
             pts = Some(Self::read_pts(reader)?);
-            optional_remaining -= 5;
 
             if pts_dts_flags == 3 {
                 dts = Some(Self::read_pts(reader)?);
-                optional_remaining -= 5;
             }
+
+            let timestamps_size = if pts_dts_flags == 3 { 10 } else { 5 };
+            optional_remaining = optional_remaining
+                .checked_sub(timestamps_size)
+                .ok_or(DemuxError::InvalidPesHeaderLength(header_data_length))?;
         }
 
-        header_size += optional_remaining;
+        // skip ESCR, ES rate, extensions and stuffing
+        reader.seek(SeekFrom::Current(optional_remaining as i64))?;
 
         Ok(PesHeader {
             header_size,
@@ -73,6 +93,16 @@ impl PesHeader {
         })
     }
 
+    /// Size of the elementary stream data, `None` when PES_packet_length
+    /// is 0 and the packet runs until the next one starts.
+    pub fn payload_size(&self) -> Option<usize> {
+        if self.size == 0 {
+            return None;
+        }
+
+        Some((self.size + 6).saturating_sub(self.header_size))
+    }
+
     fn read_pts(reader: &mut Cursor<Bytes>)-> Result<u64>{
         let mut pts: u64 = 0;
 
@@ -91,14 +121,56 @@ impl PesHeader {
     
         Ok(pts)
     }
+
+    /// Writes a PES header for a payload of `payload_size` bytes. The
+    /// PES_packet_length is left as 0 (unbounded) when it does not fit
+    /// in 16 bits, which is only allowed for video streams.
+    pub fn write(stream_id: StreamId, pts: u64, dts: Option<u64>, payload_size: usize) -> BytesMut {
+        let header_data_length: usize = if dts.is_some() { 10 } else { 5 };
+        let mut pes_length = 3 + header_data_length + payload_size;
+
+        if pes_length > 0xFFFF {
+            pes_length = 0;
+        }
+
+        let mut buffer = BytesMut::with_capacity(9 + header_data_length);
+        buffer.put_slice(&[0x00, 0x00, 0x01]);
+        buffer.put_u8(stream_id.into());
+        buffer.put_u16(pes_length as u16);
+
+        // marker bits '10', no scrambling, data_alignment_indicator set
+        buffer.put_u8(0x84);
+
+        match dts {
+            Some(dts) => {
+                buffer.put_u8(0xC0);
+                buffer.put_u8(header_data_length as u8);
+                Self::write_pts(&mut buffer, 0x03, pts);
+                Self::write_pts(&mut buffer, 0x01, dts);
+            },
+            None => {
+                buffer.put_u8(0x80);
+                buffer.put_u8(header_data_length as u8);
+                Self::write_pts(&mut buffer, 0x02, pts);
+            }
+        }
+
+        buffer
+    }
+
+    fn write_pts(buffer: &mut BytesMut, prefix: u8, pts: u64) {
+        buffer.put_u8((prefix << 4) | (((pts >> 30) & 0x07) as u8) << 1 | 0x01);
+        buffer.put_u16(((((pts >> 15) & 0x7fff) as u16) << 1) | 0x01);
+        buffer.put_u16((((pts & 0x7fff) as u16) << 1) | 0x01);
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use bytes::Bytes;
+    use bytes::{Bytes, BytesMut};
     use std::io::Cursor;
-    use crate::section::pes_header::PesHeader;
+    use crate::section::pes_header::{PesHeader, StreamId};
 
     #[test]
     fn decodes_pts() {
@@ -135,4 +207,24 @@ mod tests {
         assert_eq!(time, "0.766666666");
     }
 
+    #[test]
+    fn encodes_pts() {
+        let mut buffer = BytesMut::new();
+        PesHeader::write_pts(&mut buffer, 0x03, 72000);
+
+        assert_eq!(&buffer[..], &[0x31, 0x00, 0x05, 0x32, 0x81]);
+    }
+
+    #[test]
+    fn writes_header() {
+        let raw = PesHeader::write(StreamId::Video(0), 72000, Some(69000), 100);
+        let mut bytes = Cursor::new(raw.freeze());
+
+        let header = PesHeader::try_new(&mut bytes).unwrap();
+
+        assert_eq!(header.stream_id, StreamId::Video(0));
+        assert_eq!(header.size, 113);
+        assert_eq!(header.pts, Some(72000));
+        assert_eq!(header.dts, Some(69000));
+    }
 }
@@ -1,17 +1,31 @@
 use std::{io::{Cursor, Seek, SeekFrom}, collections::HashMap};
 use bytes::Bytes;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use byteorder::{ReadBytesExt, BigEndian};
 
-use crate::{pid::Pid, stream_type::StreamType};
+use crate::{error::DemuxError, pid::Pid, stream_type::StreamType, section::descriptor::Descriptor};
+
+/// Header after the section_length field, PCR_PID, program_info_length and
+/// the CRC, a PMT without descriptors or streams
+const MIN_SECTION_LENGTH: u16 = 5 + 4 + 4;
+
+const OPUS_IDENTIFIER: u32 = 0x4F70_7573; // "Opus"
+const AV1_IDENTIFIER: u32 = 0x4156_3031; // "AV01"
+const AC3_IDENTIFIER: u32 = 0x4143_2D33; // "AC-3"
+const EAC3_IDENTIFIER: u32 = 0x4541_4333; // "EAC3"
+const OPUS_AUDIO_DESCRIPTOR_TAG: u8 = 0x80; // user defined DVB extension
 
 #[allow(unused)]
 #[derive(Clone, Debug)]
 pub struct PMT {
     table_id: u8,
-    program_number: u16,
+    pub program_number: u16,
+    pub version: u8,
+    pub current_next: bool,
     pub pcr_pid: Pid,
     pub streams: HashMap<Pid, StreamType>,
+    pub descriptors: Vec<Descriptor>,
+    pub stream_descriptors: HashMap<Pid, Vec<Descriptor>>,
 }
 
 impl PMT {
@@ -20,10 +34,19 @@ impl PMT {
 
         let table_id = reader.read_u8()?;
         let section_length = reader.read_u16::<BigEndian>()? & 0x0FFF;
+        if section_length < MIN_SECTION_LENGTH {
+            bail!(DemuxError::SectionTooShort { length: section_length, minimum: MIN_SECTION_LENGTH });
+        }
+
         let program_number = reader.read_u16::<BigEndian>()?;
 
         let reserved = reader.read_u8()?;
-        assert_eq!((reserved >> 6) & 0x03, 3);
+        if (reserved >> 6) & 0x03 != 3 {
+            bail!(DemuxError::InvalidSectionReserved(reserved));
+        }
+
+        let version = (reserved >> 1) & 0x1F;
+        let current_next = reserved & 0x01 != 0;
 
         // seek past section_number and last_section_number
         reader.seek(SeekFrom::Current(2))?;
@@ -31,37 +54,139 @@ impl PMT {
         let pcr_pid = reader.read_u16::<BigEndian>()? & 0x1FFF;
     
         let program_info_length = reader.read_u16::<BigEndian>()? &  0xFFF;
+        let descriptors = Descriptor::read_all(reader, program_info_length as usize)?;
 
-        if program_info_length > 0 {
-            reader.seek(SeekFrom::Current(program_info_length as i64))?;
-        }
-
-        let mut remain_bytes = section_length - 4 - 9 - program_info_length;
+        // section_length counts from after the length field and includes the CRC
+        let end = 3 + section_length as u64 - 4;
         let mut streams = HashMap::new();
+        let mut stream_descriptors = HashMap::new();
 
-        ////////
-        //FIXME:    We should probbably use the readers own position
-        //          rather than relying on different value for how
-        //          we keep track of the remaning values. :(
-        //////// 
-        while remain_bytes > 0 {
+        while reader.position() + 5 <= end {
             let stream_type = StreamType::from(reader.read_u8()?);
             let pid = Pid::from(reader.read_u16::<BigEndian>()? & 0x1FFF);
 
-            streams.insert(pid, stream_type);
-            
-            //Skip over the extra info part
             let info_length = reader.read_u16::<BigEndian>()?  & 0x03FF;
-            reader.seek(SeekFrom::Current(info_length as i64))?;
+            let es_descriptors = Descriptor::read_all(reader, info_length as usize)?;
 
-            remain_bytes -= 5 + info_length;
+            streams.insert(pid, Self::resolve_stream_type(stream_type, &es_descriptors, &descriptors));
+            stream_descriptors.insert(pid, es_descriptors);
         }
 
         Ok(PMT {
             table_id,
             program_number,
+            version,
+            current_next,
             pcr_pid: Pid::from(pcr_pid),
-            streams
+            streams,
+            descriptors,
+            stream_descriptors,
         })
     }
-}
\ No newline at end of file
+
+    pub fn language(&self, pid: &Pid) -> Option<String> {
+        self.stream_descriptors.get(pid)?.iter().find_map(|descriptor| match descriptor {
+            Descriptor::Iso639Language(languages) => languages.first().map(|language| language.code.clone()),
+            Descriptor::Subtitling(subtitles) => subtitles.first().map(|subtitle| subtitle.language.clone()),
+            Descriptor::Teletext(pages) => pages.first().map(|page| page.language.clone()),
+            _ => None,
+        })
+    }
+
+    /// channel_config_code of the Opus audio descriptor, carried in a DVB
+    /// extension descriptor on the stream.
+    pub fn opus_channel_config_code(&self, pid: &Pid) -> Option<u8> {
+        self.stream_descriptors.get(pid)?.iter().find_map(|descriptor| match descriptor {
+            Descriptor::Extension { tag_extension: OPUS_AUDIO_DESCRIPTOR_TAG, data } => data.first().copied(),
+            _ => None,
+        })
+    }
+
+    /// Private data streams are only identifiable by their registration
+    /// descriptor, which may be at either the stream or program level.
+    fn resolve_stream_type(stream_type: StreamType, es_descriptors: &[Descriptor], descriptors: &[Descriptor]) -> StreamType {
+        if stream_type != StreamType::PrivateData {
+            return stream_type;
+        }
+
+        // DVB signals Dolby audio through its own descriptors instead of a registration
+        let dvb_audio = es_descriptors.iter().find_map(|descriptor| match descriptor {
+            Descriptor::Ac3(_) => Some(StreamType::AC3),
+            Descriptor::EnhancedAc3(_) => Some(StreamType::EAC3),
+            _ => None,
+        });
+
+        if let Some(stream_type) = dvb_audio {
+            return stream_type;
+        }
+
+        let registration = es_descriptors.iter().chain(descriptors.iter()).find_map(|descriptor| match descriptor {
+            Descriptor::Registration { format_identifier, .. } => Some(*format_identifier),
+            _ => None,
+        });
+
+        match registration {
+            Some(OPUS_IDENTIFIER) => StreamType::OPUS,
+            Some(AV1_IDENTIFIER) => StreamType::AV1,
+            Some(AC3_IDENTIFIER) => StreamType::AC3,
+            Some(EAC3_IDENTIFIER) => StreamType::EAC3,
+            _ => stream_type,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_private_data_by_registration() {
+        let section: &[u8] = &[
+            0x02, 0xB0, 0x1D, 0x00, 0x01, 0xC1, 0x00, 0x00, 0xE1, 0x00, 0xF0, 0x00,
+            // AV1 registered at the stream level
+            0x06, 0xE1, 0x00, 0xF0, 0x06, 0x05, 0x04, b'A', b'V', b'0', b'1',
+            // private data without a registration
+            0x06, 0xE1, 0x01, 0xF0, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let pmt = PMT::try_new(&mut Cursor::new(Bytes::from_static(section))).unwrap();
+
+        assert_eq!(pmt.streams[&Pid::from(0x100)], StreamType::AV1);
+        assert_eq!(pmt.streams[&Pid::from(0x101)], StreamType::PrivateData);
+    }
+
+    #[test]
+    fn resolves_dvb_dolby_audio() {
+        let section: &[u8] = &[
+            0x02, 0xB0, 0x22, 0x00, 0x01, 0xC1, 0x00, 0x00, 0xE1, 0x00, 0xF0, 0x00,
+            // DVB AC-3 and enhanced AC-3 descriptors on private data
+            0x06, 0xE1, 0x00, 0xF0, 0x03, 0x6A, 0x01, 0x00,
+            0x06, 0xE1, 0x01, 0xF0, 0x03, 0x7A, 0x01, 0x00,
+            // ATSC stream type
+            0x87, 0xE1, 0x02, 0xF0, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let pmt = PMT::try_new(&mut Cursor::new(Bytes::from_static(section))).unwrap();
+
+        assert_eq!(pmt.streams[&Pid::from(0x100)], StreamType::AC3);
+        assert_eq!(pmt.streams[&Pid::from(0x101)], StreamType::EAC3);
+        assert_eq!(pmt.streams[&Pid::from(0x102)], StreamType::EAC3);
+    }
+
+    #[test]
+    fn reads_opus_channel_config_code() {
+        let section: &[u8] = &[
+            0x02, 0xB0, 0x1C, 0x00, 0x01, 0xC1, 0x00, 0x00, 0xE1, 0x00, 0xF0, 0x00,
+            // Opus registration and a 5.1 audio descriptor
+            0x06, 0xE1, 0x00, 0xF0, 0x0A, 0x05, 0x04, b'O', b'p', b'u', b's', 0x7F, 0x02, 0x80, 0x06,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let pmt = PMT::try_new(&mut Cursor::new(Bytes::from_static(section))).unwrap();
+
+        assert_eq!(pmt.streams[&Pid::from(0x100)], StreamType::OPUS);
+        assert_eq!(pmt.opus_channel_config_code(&Pid::from(0x100)), Some(0x06));
+    }
+}
@@ -0,0 +1,437 @@
+use std::io::{self, Read};
+use bytes::Bytes;
+use anyhow::{Result, bail};
+use byteorder::{ReadBytesExt, BigEndian};
+use bytesio::bit_reader::BitReader;
+
+use crate::{error::DemuxError, PCR_CYCLE};
+
+/// ANSI/SCTE 35 2022
+pub const SPLICE_INFO_TABLE_ID: u8 = 0xFC;
+
+const SEGMENTATION_DESCRIPTOR_TAG: u8 = 0x02;
+const CUEI_IDENTIFIER: u32 = 0x4355_4549;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BreakDuration {
+    pub auto_return: bool,
+    pub duration: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpliceInsert {
+    pub splice_event_id: u32,
+    pub cancel: bool,
+    pub out_of_network: bool,
+    pub splice_immediate: bool,
+    pub splice_time: Option<u64>,
+    pub break_duration: Option<BreakDuration>,
+    pub unique_program_id: u16,
+    pub avail_num: u8,
+    pub avails_expected: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpliceCommand {
+    Null,
+    Schedule,
+    Insert(SpliceInsert),
+    TimeSignal(Option<u64>),
+    BandwidthReservation,
+    Private(u32),
+    Unknown(u8),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentationDescriptor {
+    pub segmentation_event_id: u32,
+    pub cancel: bool,
+    pub duration: Option<u64>,
+    pub upid_type: u8,
+    pub upid: Bytes,
+    pub segmentation_type_id: u8,
+    pub segment_num: u8,
+    pub segments_expected: u8,
+}
+
+impl SegmentationDescriptor {
+    /// Starts of a break, chapter or placement opportunity
+    pub fn is_start(&self) -> bool {
+        matches!(self.segmentation_type_id, 0x10 | 0x20 | 0x22 | 0x30 | 0x32 | 0x34 | 0x36 | 0x38 | 0x3A | 0x3C | 0x3E | 0x44 | 0x46)
+    }
+
+    pub fn is_end(&self) -> bool {
+        matches!(self.segmentation_type_id, 0x11 | 0x21 | 0x23 | 0x31 | 0x33 | 0x35 | 0x37 | 0x39 | 0x3B | 0x3D | 0x3F | 0x45 | 0x47)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpliceDescriptor {
+    Segmentation(SegmentationDescriptor),
+    Other { tag: u8, identifier: u32, data: Bytes },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpliceDirection {
+    /// Leaving the network feed (CUE-OUT)
+    Out,
+    /// Returning to the network feed (CUE-IN)
+    In,
+}
+
+#[derive(Clone, Debug)]
+pub struct SpliceInfoSection {
+    pub protocol_version: u8,
+    pub encrypted: bool,
+    pub pts_adjustment: u64,
+    pub tier: u16,
+    pub command: SpliceCommand,
+    pub descriptors: Vec<SpliceDescriptor>,
+    /// The complete section including the CRC, as carried in SCTE35-OUT/IN
+    pub data: Bytes,
+}
+
+impl SpliceInfoSection {
+
+    pub fn try_new(section: Bytes) -> Result<SpliceInfoSection> {
+        let mut reader = BitReader::new(io::Cursor::new(section.clone()));
+
+        let table_id = reader.read_u8()?;
+        if table_id != SPLICE_INFO_TABLE_ID {
+            bail!(DemuxError::InvalidTableId { expected: SPLICE_INFO_TABLE_ID, found: table_id });
+        }
+
+        // section_syntax_indicator, private_indicator, sap_type and section_length
+        reader.read_u16::<BigEndian>()?;
+
+        let protocol_version = reader.read_u8()?;
+        let encrypted = reader.read_bit()?;
+        let _encryption_algorithm = reader.read_bits(6)?;
+        let pts_adjustment = reader.read_bits(33)?;
+        let _cw_index = reader.read_u8()?;
+        let tier = reader.read_bits(12)? as u16;
+        let splice_command_length = reader.read_bits(12)? as usize;
+        let splice_command_type = reader.read_u8()?;
+
+        if encrypted {
+            // we have no keys, pass the section on untouched
+            return Ok(SpliceInfoSection {
+                protocol_version,
+                encrypted,
+                pts_adjustment,
+                tier,
+                command: SpliceCommand::Unknown(splice_command_type),
+                descriptors: vec![],
+                data: section,
+            });
+        }
+
+        let command = match splice_command_type {
+            0x00 => SpliceCommand::Null,
+            0x04 => {
+                Self::skip(&mut reader, splice_command_length)?;
+                SpliceCommand::Schedule
+            },
+            0x05 => SpliceCommand::Insert(Self::read_splice_insert(&mut reader)?),
+            0x06 => SpliceCommand::TimeSignal(Self::read_splice_time(&mut reader)?),
+            0x07 => SpliceCommand::BandwidthReservation,
+            0xFF => {
+                let identifier = reader.read_u32::<BigEndian>()?;
+                Self::skip(&mut reader, splice_command_length.saturating_sub(4))?;
+                SpliceCommand::Private(identifier)
+            },
+            _ => {
+                // legacy streams may signal 0xFFF for the command length
+                if splice_command_length == 0xFFF {
+                    bail!(DemuxError::UnsupportedSpliceCommand(splice_command_type));
+                }
+
+                Self::skip(&mut reader, splice_command_length)?;
+                SpliceCommand::Unknown(splice_command_type)
+            },
+        };
+
+        let mut descriptor_loop_length = reader.read_u16::<BigEndian>()? as usize;
+        let mut descriptors = vec![];
+
+        while descriptor_loop_length >= 2 {
+            let tag = reader.read_u8()?;
+            let length = reader.read_u8()? as usize;
+
+            let mut data = vec![0; length];
+            reader.read_exact(&mut data)?;
+            descriptor_loop_length = descriptor_loop_length.saturating_sub(2 + length);
+
+            descriptors.push(Self::read_descriptor(tag, Bytes::from(data))?);
+        }
+
+        Ok(SpliceInfoSection {
+            protocol_version,
+            encrypted,
+            pts_adjustment,
+            tier,
+            command,
+            descriptors,
+            data: section,
+        })
+    }
+
+    /// The splice point with pts_adjustment applied, `None` means splice
+    /// immediately.
+    pub fn splice_time(&self) -> Option<u64> {
+        let pts = match &self.command {
+            SpliceCommand::Insert(insert) => insert.splice_time,
+            SpliceCommand::TimeSignal(pts) => *pts,
+            _ => None,
+        };
+
+        pts.map(|pts| (pts + self.pts_adjustment) % PCR_CYCLE)
+    }
+
+    pub fn duration(&self) -> Option<u64> {
+        match &self.command {
+            SpliceCommand::Insert(insert) => insert.break_duration.as_ref().map(|b| b.duration),
+            _ => self.segmentation().and_then(|s| s.duration),
+        }
+    }
+
+    pub fn event_id(&self) -> Option<u32> {
+        match &self.command {
+            SpliceCommand::Insert(insert) => Some(insert.splice_event_id),
+            _ => self.segmentation().map(|s| s.segmentation_event_id),
+        }
+    }
+
+    pub fn is_cancel(&self) -> bool {
+        match &self.command {
+            SpliceCommand::Insert(insert) => insert.cancel,
+            _ => self.segmentation().map(|s| s.cancel).unwrap_or(false),
+        }
+    }
+
+    /// Whether the cue takes us out to, or back from, an ad break. Commands
+    /// that are neither (splice_null, heartbeats) return `None`.
+    pub fn direction(&self) -> Option<SpliceDirection> {
+        match &self.command {
+            SpliceCommand::Insert(insert) if !insert.cancel => {
+                if insert.out_of_network {
+                    Some(SpliceDirection::Out)
+                } else {
+                    Some(SpliceDirection::In)
+                }
+            },
+            SpliceCommand::TimeSignal(_) => {
+                let segmentation = self.segmentation()?;
+
+                if segmentation.cancel {
+                    None
+                } else if segmentation.is_start() {
+                    Some(SpliceDirection::Out)
+                } else if segmentation.is_end() {
+                    Some(SpliceDirection::In)
+                } else {
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    fn segmentation(&self) -> Option<&SegmentationDescriptor> {
+        self.descriptors.iter().find_map(|descriptor| match descriptor {
+            SpliceDescriptor::Segmentation(segmentation) => Some(segmentation),
+            _ => None,
+        })
+    }
+
+    fn skip(reader: &mut BitReader, count: usize) -> Result<()> {
+        let mut skipped = vec![0; count];
+        reader.read_exact(&mut skipped)?;
+        Ok(())
+    }
+
+    fn read_splice_time(reader: &mut BitReader) -> Result<Option<u64>> {
+        let time_specified = reader.read_bit()?;
+
+        if time_specified {
+            reader.read_bits(6)?;
+            return Ok(Some(reader.read_bits(33)?));
+        }
+
+        reader.read_bits(7)?;
+        Ok(None)
+    }
+
+    fn read_break_duration(reader: &mut BitReader) -> Result<BreakDuration> {
+        let auto_return = reader.read_bit()?;
+        reader.read_bits(6)?;
+        let duration = reader.read_bits(33)?;
+
+        Ok(BreakDuration { auto_return, duration })
+    }
+
+    fn read_splice_insert(reader: &mut BitReader) -> Result<SpliceInsert> {
+        let splice_event_id = reader.read_u32::<BigEndian>()?;
+        let cancel = reader.read_bit()?;
+        reader.read_bits(7)?;
+
+        let mut insert = SpliceInsert {
+            splice_event_id,
+            cancel,
+            out_of_network: false,
+            splice_immediate: false,
+            splice_time: None,
+            break_duration: None,
+            unique_program_id: 0,
+            avail_num: 0,
+            avails_expected: 0,
+        };
+
+        if cancel {
+            return Ok(insert);
+        }
+
+        insert.out_of_network = reader.read_bit()?;
+        let program_splice = reader.read_bit()?;
+        let duration_flag = reader.read_bit()?;
+        insert.splice_immediate = reader.read_bit()?;
+        reader.read_bits(4)?;
+
+        if program_splice && !insert.splice_immediate {
+            insert.splice_time = Self::read_splice_time(reader)?;
+        }
+
+        if !program_splice {
+            let component_count = reader.read_u8()?;
+
+            for _ in 0..component_count {
+                let _component_tag = reader.read_u8()?;
+
+                if !insert.splice_immediate {
+                    // all components splice together in practice, keep the first
+                    let splice_time = Self::read_splice_time(reader)?;
+                    insert.splice_time = insert.splice_time.or(splice_time);
+                }
+            }
+        }
+
+        if duration_flag {
+            insert.break_duration = Some(Self::read_break_duration(reader)?);
+        }
+
+        insert.unique_program_id = reader.read_u16::<BigEndian>()?;
+        insert.avail_num = reader.read_u8()?;
+        insert.avails_expected = reader.read_u8()?;
+
+        Ok(insert)
+    }
+
+    fn read_descriptor(tag: u8, data: Bytes) -> Result<SpliceDescriptor> {
+        let mut reader = BitReader::new(io::Cursor::new(data.clone()));
+        let identifier = reader.read_u32::<BigEndian>()?;
+
+        if tag != SEGMENTATION_DESCRIPTOR_TAG || identifier != CUEI_IDENTIFIER {
+            return Ok(SpliceDescriptor::Other { tag, identifier, data: data.slice(4..) });
+        }
+
+        let segmentation_event_id = reader.read_u32::<BigEndian>()?;
+        let cancel = reader.read_bit()?;
+        reader.read_bits(7)?;
+
+        let mut descriptor = SegmentationDescriptor {
+            segmentation_event_id,
+            cancel,
+            duration: None,
+            upid_type: 0,
+            upid: Bytes::new(),
+            segmentation_type_id: 0,
+            segment_num: 0,
+            segments_expected: 0,
+        };
+
+        if cancel {
+            return Ok(SpliceDescriptor::Segmentation(descriptor));
+        }
+
+        let program_segmentation = reader.read_bit()?;
+        let duration_flag = reader.read_bit()?;
+        // delivery_not_restricted_flag and the restriction flags
+        reader.read_bits(6)?;
+
+        if !program_segmentation {
+            let component_count = reader.read_u8()?;
+
+            for _ in 0..component_count {
+                // component_tag, reserved and pts_offset
+                reader.read_bits(48)?;
+            }
+        }
+
+        if duration_flag {
+            descriptor.duration = Some(reader.read_bits(40)?);
+        }
+
+        descriptor.upid_type = reader.read_u8()?;
+        let upid_length = reader.read_u8()? as usize;
+
+        let mut upid = vec![0; upid_length];
+        reader.read_exact(&mut upid)?;
+        descriptor.upid = Bytes::from(upid);
+
+        descriptor.segmentation_type_id = reader.read_u8()?;
+        descriptor.segment_num = reader.read_u8()?;
+        descriptor.segments_expected = reader.read_u8()?;
+
+        Ok(SpliceDescriptor::Segmentation(descriptor))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use crate::section::scte35::{SpliceCommand, SpliceDirection, SpliceInfoSection};
+
+    fn decode_hex(hex: &str) -> Bytes {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect::<Vec<u8>>()
+            .into()
+    }
+
+    #[test]
+    fn parses_splice_insert() {
+        // SCTE 35 2022, 14.2 splice_insert
+        let section = SpliceInfoSection::try_new(decode_hex(
+            "fc302f000000000000fffff014054800008f7feffe7369c02efe0052ccf500000000000a0008435545490000013562dba30a"
+        )).unwrap();
+
+        match &section.command {
+            SpliceCommand::Insert(insert) => {
+                assert_eq!(insert.splice_event_id, 0x4800008f);
+                assert!(insert.out_of_network);
+                assert_eq!(insert.splice_time, Some(0x07369c02e));
+                assert_eq!(insert.break_duration.as_ref().unwrap().duration, 0x00052ccf5);
+                assert!(insert.break_duration.as_ref().unwrap().auto_return);
+            },
+            command => panic!("unexpected {:?}", command),
+        }
+
+        assert_eq!(section.direction(), Some(SpliceDirection::Out));
+        assert_eq!(section.duration(), Some(0x00052ccf5));
+    }
+
+    #[test]
+    fn parses_time_signal_with_segmentation() {
+        // SCTE 35 2022, 14.1 time_signal placement opportunity start
+        let section = SpliceInfoSection::try_new(decode_hex(
+            "fc3034000000000000fffff00506fe72bd0050001e021c435545494800008e7fcf0001a599b00808000000002ca0a18a3402009ac9d17e"
+        )).unwrap();
+
+        assert_eq!(section.command, SpliceCommand::TimeSignal(Some(0x072bd0050)));
+        assert_eq!(section.event_id(), Some(0x4800008e));
+        assert_eq!(section.duration(), Some(0x0001a599b0));
+        assert_eq!(section.direction(), Some(SpliceDirection::Out));
+    }
+}
@@ -0,0 +1,410 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{packet_header::PacketHeader, pid::Pid, HZ, PCR_CYCLE};
+
+/// 27 MHz system clock
+const SYSTEM_CLOCK: u64 = 27_000_000;
+const SYSTEM_CLOCK_CYCLE: u64 = PCR_CYCLE * 300;
+
+/// ETSI TR 101 290 limits, on the 27 MHz clock unless noted
+const PAT_INTERVAL: u64 = SYSTEM_CLOCK / 2;
+const PMT_INTERVAL: u64 = SYSTEM_CLOCK / 2;
+const PCR_INTERVAL: u64 = SYSTEM_CLOCK / 10;
+/// ±500 ns
+const PCR_ACCURACY: u64 = 14;
+/// 700 ms on the 90 kHz clock
+const PTS_INTERVAL: u64 = HZ as u64 * 7 / 10;
+
+/// PCR gaps beyond this are a jump in the clock rather than sparse PCRs
+const PCR_DISCONTINUITY: u64 = SYSTEM_CLOCK;
+
+/// Error counters for a single PID.
+#[derive(Clone, Debug, Default)]
+pub struct PidStats {
+    pub packets: u64,
+    pub continuity_errors: u64,
+    pub transport_errors: u64,
+    pub crc_errors: u64,
+    pub pcr_discontinuity_errors: u64,
+    pub pcr_repetition_errors: u64,
+    pub pcr_accuracy_errors: u64,
+    pub pts_errors: u64,
+
+    /// Time between the last two PCRs and the longest seen so far
+    pub pcr_interval: Option<Duration>,
+    pub pcr_interval_max: Duration,
+    /// Deviation of the last PCR from the one predicted by the transport
+    /// rate, and the largest seen so far
+    pub pcr_jitter: Option<Duration>,
+    pub pcr_jitter_max: Duration,
+
+    continuity_counter: Option<u8>,
+    duplicate: bool,
+    last_pcr: Option<(u64, u64)>,
+    ticks_per_packet: Option<f64>,
+    last_pts: Option<u64>,
+}
+
+/// Transport stream health following the priority 1 and 2 checks of
+/// ETSI TR 101 290. Timeouts are measured against the first PCR PID seen
+/// rather than the wall clock, so they hold for paced and unpaced input.
+#[derive(Clone, Debug, Default)]
+pub struct DemuxerStats {
+    pub packets: u64,
+    pub sync_losses: u64,
+    pub pat_errors: u64,
+    pub pmt_errors: u64,
+    /// Packets whose header, PES header or sections could not be parsed
+    pub malformed_packets: u64,
+    pub pids: HashMap<Pid, PidStats>,
+
+    clock_pid: Option<Pid>,
+    last_pat: Option<u64>,
+    last_pmt: HashMap<Pid, Option<u64>>,
+}
+
+impl DemuxerStats {
+
+    pub fn new() -> DemuxerStats {
+        DemuxerStats::default()
+    }
+
+    pub fn continuity_errors(&self) -> u64 {
+        self.sum(|stats| stats.continuity_errors)
+    }
+
+    pub fn transport_errors(&self) -> u64 {
+        self.sum(|stats| stats.transport_errors)
+    }
+
+    pub fn crc_errors(&self) -> u64 {
+        self.sum(|stats| stats.crc_errors)
+    }
+
+    pub fn pcr_discontinuity_errors(&self) -> u64 {
+        self.sum(|stats| stats.pcr_discontinuity_errors)
+    }
+
+    pub fn pcr_repetition_errors(&self) -> u64 {
+        self.sum(|stats| stats.pcr_repetition_errors)
+    }
+
+    pub fn pcr_accuracy_errors(&self) -> u64 {
+        self.sum(|stats| stats.pcr_accuracy_errors)
+    }
+
+    pub fn pts_errors(&self) -> u64 {
+        self.sum(|stats| stats.pts_errors)
+    }
+
+    pub fn pcr_interval_max(&self) -> Duration {
+        self.pids.values().map(|stats| stats.pcr_interval_max).max().unwrap_or_default()
+    }
+
+    pub fn pcr_jitter_max(&self) -> Duration {
+        self.pids.values().map(|stats| stats.pcr_jitter_max).max().unwrap_or_default()
+    }
+
+    fn sum(&self, field: impl Fn(&PidStats) -> u64) -> u64 {
+        self.pids.values().map(field).sum()
+    }
+
+    pub(crate) fn sync_lost(&mut self) {
+        self.sync_losses += 1;
+    }
+
+    pub(crate) fn malformed_packet(&mut self) {
+        self.malformed_packets += 1;
+    }
+
+    pub(crate) fn packet(&mut self, header: &PacketHeader) {
+        self.packets += 1;
+
+        if header.pid.is_null() {
+            return;
+        }
+
+        let stats = self.pids.entry(header.pid).or_default();
+        stats.packets += 1;
+
+        if header.transport_error {
+            // nothing else in the header can be trusted
+            stats.transport_errors += 1;
+            return;
+        }
+
+        let counter = header.continuity_counter;
+        let has_payload = header.adaptation_control.has_payload();
+
+        if let (Some(last), false) = (stats.continuity_counter, header.discontinuity) {
+            // the counter only advances on packets carrying a payload
+            let expected = if has_payload { (last + 1) & 0x0F } else { last };
+
+            if counter == expected {
+                stats.duplicate = false;
+            } else if has_payload && counter == last && !stats.duplicate {
+                // a single repeat of a packet is allowed
+                stats.duplicate = true;
+            } else {
+                log::debug!("Continuity error on {:?}, expected {} found {}", header.pid, expected, counter);
+                stats.continuity_errors += 1;
+                stats.duplicate = false;
+            }
+        }
+
+        stats.continuity_counter = Some(counter);
+    }
+
+    pub(crate) fn crc_error(&mut self, pid: Pid) {
+        self.pids.entry(pid).or_default().crc_errors += 1;
+    }
+
+    pub(crate) fn pat(&mut self) {
+        self.last_pat = self.now();
+    }
+
+    pub(crate) fn pat_error(&mut self) {
+        self.pat_errors += 1;
+    }
+
+    /// Sets the PMT PIDs the PAT currently points at
+    pub(crate) fn pmt_pids(&mut self, pids: impl IntoIterator<Item = Pid>) {
+        let now = self.now();
+        let mut last_pmt = HashMap::new();
+
+        for pid in pids {
+            let last = self.last_pmt.get(&pid).cloned().unwrap_or(now);
+            last_pmt.insert(pid, last);
+        }
+
+        self.last_pmt = last_pmt;
+    }
+
+    pub(crate) fn pmt(&mut self, pid: Pid) {
+        let now = self.now();
+        self.last_pmt.insert(pid, now);
+    }
+
+    /// `signalled` is set when the packet carried the discontinuity_indicator,
+    /// in which case a jump in the clock is expected.
+    pub(crate) fn pcr(&mut self, pid: Pid, pcr: u64, signalled: bool) {
+        let packets = self.packets;
+        let stats = self.pids.entry(pid).or_default();
+        let mut discontinuity = false;
+
+        if let Some((last_pcr, last_packets)) = stats.last_pcr {
+            let interval = (pcr + SYSTEM_CLOCK_CYCLE - last_pcr) % SYSTEM_CLOCK_CYCLE;
+
+            if signalled || interval > PCR_DISCONTINUITY {
+                if !signalled {
+                    log::debug!("Unsignalled PCR discontinuity on {:?}", pid);
+                    stats.pcr_discontinuity_errors += 1;
+                }
+
+                stats.ticks_per_packet = None;
+                discontinuity = true;
+            } else {
+                if interval > PCR_INTERVAL {
+                    stats.pcr_repetition_errors += 1;
+                }
+
+                let duration = Self::to_duration(interval);
+                stats.pcr_interval = Some(duration);
+                stats.pcr_interval_max = stats.pcr_interval_max.max(duration);
+
+                let distance = packets - last_packets;
+
+                if let Some(ticks_per_packet) = stats.ticks_per_packet {
+                    let predicted = (ticks_per_packet * distance as f64).round() as u64;
+                    let jitter = interval.abs_diff(predicted);
+
+                    if jitter > PCR_ACCURACY {
+                        stats.pcr_accuracy_errors += 1;
+                    }
+
+                    let duration = Self::to_duration(jitter);
+                    stats.pcr_jitter = Some(duration);
+                    stats.pcr_jitter_max = stats.pcr_jitter_max.max(duration);
+                }
+
+                if distance > 0 {
+                    let rate = interval as f64 / distance as f64;
+
+                    // smooth the transport rate so VBR muxing doesn't read as jitter
+                    stats.ticks_per_packet = Some(match stats.ticks_per_packet {
+                        Some(current) => current * 0.9 + rate * 0.1,
+                        None => rate,
+                    });
+                }
+            }
+        }
+
+        stats.last_pcr = Some((pcr, packets));
+
+        if *self.clock_pid.get_or_insert(pid) == pid {
+            self.check_timeouts(pcr, discontinuity);
+        }
+    }
+
+    pub(crate) fn pts(&mut self, pid: Pid, pts: u64) {
+        let stats = self.pids.entry(pid).or_default();
+
+        if let Some(last) = stats.last_pts {
+            let interval = (pts + PCR_CYCLE - last) % PCR_CYCLE;
+
+            // reordered frames step back a little, which reads as a near full cycle
+            if interval > PTS_INTERVAL && interval < PCR_CYCLE - PTS_INTERVAL {
+                stats.pts_errors += 1;
+            }
+        }
+
+        stats.last_pts = Some(pts);
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.clock_pid
+            .and_then(|pid| self.pids.get(&pid))
+            .and_then(|stats| stats.last_pcr)
+            .map(|(pcr, _)| pcr)
+    }
+
+    fn check_timeouts(&mut self, now: u64, discontinuity: bool) {
+        let elapsed = |since: u64| (now + SYSTEM_CLOCK_CYCLE - since) % SYSTEM_CLOCK_CYCLE;
+
+        // start timing from here if we haven't seen the tables yet or the clock jumped
+        let last_pat = match self.last_pat {
+            Some(last_pat) if !discontinuity => last_pat,
+            _ => now,
+        };
+
+        if elapsed(last_pat) > PAT_INTERVAL {
+            self.pat_errors += 1;
+            self.last_pat = Some(now);
+        } else {
+            self.last_pat = Some(last_pat);
+        }
+
+        for last_pmt in self.last_pmt.values_mut() {
+            let last = match last_pmt {
+                Some(last) if !discontinuity => *last,
+                _ => now,
+            };
+
+            if elapsed(last) > PMT_INTERVAL {
+                self.pmt_errors += 1;
+                *last_pmt = Some(now);
+            } else {
+                *last_pmt = Some(last);
+            }
+        }
+    }
+
+    fn to_duration(ticks: u64) -> Duration {
+        Duration::from_nanos(ticks * 1000 / 27)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{packet_header::{AdaptationControl, PacketHeader}, pid::Pid, stats::DemuxerStats};
+
+    const PCR_PID: Pid = Pid::Other(0x100);
+
+    fn header(pid: Pid, continuity_counter: u8) -> PacketHeader {
+        PacketHeader {
+            transport_error: false,
+            pid,
+            pusi: false,
+            continuity_counter,
+            adaptation_control: AdaptationControl::PayloadOnly,
+            discontinuity: false,
+            pcr: None,
+            pcr_extension: 0,
+            header_size: 4,
+        }
+    }
+
+    #[test]
+    fn counts_continuity_errors() {
+        let pid = Pid::from(0x100);
+        let mut stats = DemuxerStats::new();
+
+        for counter in [14, 15, 0, 0, 1, 3, 4] {
+            stats.packet(&header(pid, counter));
+        }
+
+        let mut corrupt = header(pid, 9);
+        corrupt.transport_error = true;
+        stats.packet(&corrupt);
+
+        // the repeated 0 is a permitted duplicate, 1 -> 3 is not
+        assert_eq!(stats.continuity_errors(), 1);
+        assert_eq!(stats.transport_errors(), 1);
+        assert_eq!(stats.pids[&pid].packets, 8);
+    }
+
+    #[test]
+    fn checks_pcr_intervals_and_jitter() {
+        let mut stats = DemuxerStats::new();
+        let mut pcr = 0;
+
+        for interval in [40, 40, 40, 150] {
+            for counter in 0..10 {
+                stats.packet(&header(PCR_PID, counter));
+            }
+
+            pcr += interval * 27_000;
+            stats.pcr(PCR_PID, pcr, false);
+        }
+
+        // the long gap is both late and off the transport rate
+        assert_eq!(stats.pcr_repetition_errors(), 1);
+        assert_eq!(stats.pcr_accuracy_errors(), 1);
+        assert_eq!(stats.pcr_interval_max().as_millis(), 150);
+
+        stats.pcr(PCR_PID, 0, false);
+        assert_eq!(stats.pcr_discontinuity_errors(), 1);
+
+        // a signalled jump isn't an error
+        stats.pcr(PCR_PID, 1 << 40, true);
+        assert_eq!(stats.pcr_discontinuity_errors(), 1);
+    }
+
+    #[test]
+    fn times_out_missing_tables() {
+        let pmt_pid = Pid::from(0x1000);
+        let mut stats = DemuxerStats::new();
+
+        stats.pcr(PCR_PID, 0, false);
+        stats.pat();
+        stats.pmt_pids([pmt_pid]);
+
+        for ms in (100..=1000).step_by(100) {
+            stats.pcr(PCR_PID, ms * 27_000, false);
+
+            if ms <= 500 {
+                stats.pmt(pmt_pid);
+            }
+        }
+
+        assert_eq!(stats.pat_errors, 1);
+        assert_eq!(stats.pmt_errors, 0);
+
+        stats.pcr(PCR_PID, 1100 * 27_000, false);
+        assert_eq!(stats.pmt_errors, 1);
+    }
+
+    #[test]
+    fn flags_pts_gaps() {
+        let pid = Pid::from(0x101);
+        let mut stats = DemuxerStats::new();
+
+        // reordered frames are fine, a second long gap is not
+        for pts in [3000, 9000, 6000, 12000, 102000] {
+            stats.pts(pid, pts);
+        }
+
+        assert_eq!(stats.pts_errors(), 1);
+    }
+}
@@ -4,11 +4,15 @@
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum StreamType {
     PrivateData, // PES packets containing private data
+    MPEG1Audio,
+    MPEG2Audio,
     AAC,
     H264,
     H265,
     AV1,
     OPUS,
+    AC3,
+    EAC3,
     SCTE35,
     Unknown(u8),
 }
@@ -17,11 +21,15 @@ impl StreamType {
     pub fn is_video(&self) -> bool {
         match self {
             StreamType::PrivateData => false, //TODO: Good Question
+            StreamType::MPEG1Audio => false,
+            StreamType::MPEG2Audio => false,
             StreamType::AAC => false,
             StreamType::H264 => true,
             StreamType::H265 => true,
             StreamType::AV1 => true,
             StreamType::OPUS => false,
+            StreamType::AC3 => false,
+            StreamType::EAC3 => false,
             StreamType::SCTE35 => false,
             StreamType::Unknown(_) => false,
         }
@@ -30,11 +38,15 @@ impl StreamType {
     pub fn is_audio(&self) -> bool {
         match self {
             StreamType::PrivateData => false, //TODO: Good Question
+            StreamType::MPEG1Audio => true,
+            StreamType::MPEG2Audio => true,
             StreamType::AAC => true,
             StreamType::H264 => false,
             StreamType::H265 => false,
             StreamType::AV1 => false,
             StreamType::OPUS => true,
+            StreamType::AC3 => true,
+            StreamType::EAC3 => true,
             StreamType::SCTE35 => false,
             StreamType::Unknown(_) => false,
         }
@@ -44,11 +56,15 @@ impl StreamType {
 impl From<u8> for StreamType {
     fn from(d: u8) -> Self {
         match d {
+            0x03 => StreamType::MPEG1Audio,
+            0x04 => StreamType::MPEG2Audio,
             0x06 => StreamType::PrivateData,
             0x0F => StreamType::AAC,
             0x1B => StreamType::H264,
             0x24 => StreamType::H265,
+            0x81 => StreamType::AC3,
             0x86 => StreamType::SCTE35,
+            0x87 => StreamType::EAC3,
             0x9c => StreamType::OPUS,
             0x9f => StreamType::AV1,
             _ => StreamType::Unknown(d)
@@ -59,11 +75,15 @@ impl From<u8> for StreamType {
 impl From<StreamType> for u8 {
     fn from(st: StreamType) -> u8 {
         match st {
+            StreamType::MPEG1Audio => 0x03,
+            StreamType::MPEG2Audio => 0x04,
             StreamType::PrivateData => 0x06,
             StreamType::AAC => 0x0F,
             StreamType::H264 => 0x1B,
             StreamType::H265 => 0x24,
+            StreamType::AC3 => 0x81,
             StreamType::SCTE35 => 0x86,
+            StreamType::EAC3 => 0x87,
             StreamType::OPUS => 0x9c,
             StreamType::AV1 => 0x9f,
             StreamType::Unknown(d) => d,
@@ -0,0 +1,204 @@
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::demuxer::{SIZE, SYNC_BYTE};
+
+/// 188 byte TS, 192 byte M2TS (4 byte timestamp prefix) and 204 byte TS
+/// with Reed-Solomon parity.
+pub const PACKET_SIZES: [usize; 3] = [188, 192, 204];
+
+/// Number of consecutive sync bytes needed before we trust a packet size.
+pub const SYNC_CONFIRM: usize = 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyncEvent {
+    /// A 188 byte transport packet, with any M2TS prefix or parity removed
+    Packet(Bytes),
+    Acquired(usize),
+    Lost,
+}
+
+/// Splits an arbitrary byte stream into transport packets. Partial packets
+/// are held between calls and on a missing sync byte we hunt for a new
+/// packet boundary instead of giving up on the stream.
+#[derive(Debug, Default)]
+pub struct PacketSync {
+    buffer: BytesMut,
+    packet_size: Option<usize>,
+}
+
+impl PacketSync {
+
+    pub fn new() -> PacketSync {
+        PacketSync {
+            buffer: BytesMut::new(),
+            packet_size: None,
+        }
+    }
+
+    pub fn packet_size(&self) -> Option<usize> {
+        self.packet_size
+    }
+
+    pub fn push(&mut self, buf: &[u8]) -> Vec<SyncEvent> {
+        self.buffer.extend_from_slice(buf);
+
+        let mut events = vec![];
+
+        loop {
+            let packet_size = match self.packet_size {
+                Some(packet_size) => packet_size,
+                None => match self.hunt() {
+                    Some(packet_size) => {
+                        log::debug!("Acquired sync with {} byte packets", packet_size);
+                        self.packet_size = Some(packet_size);
+                        events.push(SyncEvent::Acquired(packet_size));
+                        packet_size
+                    },
+                    None => break,
+                },
+            };
+
+            if self.buffer.len() < packet_size {
+                break;
+            }
+
+            let offset = Self::sync_offset(packet_size);
+
+            if self.buffer[offset] != SYNC_BYTE {
+                log::warn!("Lost sync, expected {:#04x} found {:#04x}", SYNC_BYTE, self.buffer[offset]);
+                self.packet_size = None;
+                self.buffer.advance(1);
+                events.push(SyncEvent::Lost);
+                continue;
+            }
+
+            let unit = self.buffer.split_to(packet_size).freeze();
+            events.push(SyncEvent::Packet(unit.slice(offset..offset + SIZE)));
+        }
+
+        events
+    }
+
+    /// Where the sync byte sits inside a packet of the given size
+    fn sync_offset(packet_size: usize) -> usize {
+        if packet_size == 192 { 4 } else { 0 }
+    }
+
+    /// Looks for a run of sync bytes at a fixed stride, dropping anything
+    /// in front of it. Returns the packet size once locked.
+    fn hunt(&mut self) -> Option<usize> {
+        let mut position = 0;
+
+        while let Some(found) = self.buffer[position..].iter().position(|byte| *byte == SYNC_BYTE) {
+            let sync = position + found;
+            let mut undecided = false;
+
+            for packet_size in PACKET_SIZES {
+                let offset = Self::sync_offset(packet_size);
+
+                if sync < offset {
+                    continue;
+                }
+
+                if sync + packet_size * (SYNC_CONFIRM - 1) >= self.buffer.len() {
+                    // not enough data yet to rule this size in or out
+                    undecided = true;
+                    continue;
+                }
+
+                let locked = (1..SYNC_CONFIRM).all(|n| self.buffer[sync + packet_size * n] == SYNC_BYTE);
+
+                if locked {
+                    self.buffer.advance(sync - offset);
+                    return Some(packet_size);
+                }
+            }
+
+            if undecided {
+                self.buffer.advance(sync.saturating_sub(4));
+                return None;
+            }
+
+            position = sync + 1;
+        }
+
+        // nothing that looks like a sync byte, keep a little in case the
+        // next read completes an M2TS prefix
+        let keep = self.buffer.len().min(4);
+        self.buffer.advance(self.buffer.len() - keep);
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{demuxer::{SIZE, SYNC_BYTE}, sync::{PacketSync, SyncEvent}};
+
+    fn packets(count: usize, packet_size: usize) -> Vec<u8> {
+        let offset = if packet_size == 192 { 4 } else { 0 };
+        let mut data = vec![];
+
+        for n in 0..count {
+            let mut packet = vec![0xAA; packet_size];
+            packet[offset] = SYNC_BYTE;
+            packet[offset + 1] = n as u8;
+            data.extend_from_slice(&packet);
+        }
+
+        data
+    }
+
+    fn count_packets(events: &[SyncEvent]) -> usize {
+        events.iter().filter(|event| matches!(event, SyncEvent::Packet(_))).count()
+    }
+
+    #[test]
+    fn resyncs_after_junk_and_split_reads() {
+        let mut data = vec![0x00, 0x47, 0x12, 0x34];
+        data.extend(packets(10, SIZE));
+
+        let mut sync = PacketSync::new();
+        let mut events = vec![];
+
+        for chunk in data.chunks(100) {
+            events.extend(sync.push(chunk));
+        }
+
+        assert_eq!(events[0], SyncEvent::Acquired(SIZE));
+        assert_eq!(count_packets(&events), 10);
+    }
+
+    #[test]
+    fn detects_packet_sizes() {
+        for packet_size in [192, 204] {
+            let mut sync = PacketSync::new();
+            let events = sync.push(&packets(8, packet_size));
+
+            assert_eq!(sync.packet_size(), Some(packet_size));
+            assert_eq!(count_packets(&events), 8);
+
+            match &events[1] {
+                SyncEvent::Packet(packet) => {
+                    assert_eq!(packet.len(), SIZE);
+                    assert_eq!(packet[0], SYNC_BYTE);
+                    assert_eq!(packet[1], 0);
+                },
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_lost_sync() {
+        let mut data = packets(5, SIZE);
+        data.extend_from_slice(&[0x01; 50]);
+        data.extend(packets(5, SIZE));
+
+        let mut sync = PacketSync::new();
+        let events = sync.push(&data);
+
+        assert!(events.contains(&SyncEvent::Lost));
+        assert_eq!(count_packets(&events), 10);
+    }
+}
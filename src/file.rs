@@ -0,0 +1,482 @@
+use std::{fmt, io::{ErrorKind, SeekFrom}, net::SocketAddr, time::Duration};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use mpegts::{demuxer::{ProgramSelection, SIZE, SYNC_BYTE}, retime, HZ, PCR_CYCLE};
+use tokio::{fs::File, io::{AsyncReadExt, AsyncSeekExt, BufReader}, time::{sleep_until, Instant}};
+use crate::{option::FileInput, session::{ManagerHandle, connection::Connection, source::IngestSource}};
+
+/// Seven transport stream packets, the usual datagram payload
+const CHUNK_SIZE: usize = 7 * SIZE;
+
+/// How far into the file to look for a PCR before pacing by PTS instead, about 2MB.
+const PROBE_PACKETS: usize = 10_000;
+
+/// Clock steps larger than this, either way, are a break in the recording.
+/// Pacing restarts from the new value instead of waiting for it or racing to
+/// catch up.
+const MAX_CLOCK_JUMP: u64 = HZ as u64;
+
+/// Longest a single `recv` waits for a packet to come due. An empty chunk is
+/// returned in between, so a slow clock isn't taken for a quiet source by the
+/// connection timeout.
+const MAX_WAIT: Duration = Duration::from_millis(500);
+
+/// Assumed spacing of the last clock sample when a pass only has one.
+const DEFAULT_CLOCK_INTERVAL: u64 = HZ as u64 / 10;
+
+type Packet = [u8; SIZE];
+
+/// Publishes a transport stream file as a live stream.
+pub struct FileService {
+    manager_handle: ManagerHandle,
+    input: FileInput,
+    looping: bool,
+    program_selection: ProgramSelection,
+}
+
+impl FileService {
+    pub fn new(manager_handle: ManagerHandle, input: FileInput, looping: bool, program_selection: ProgramSelection) -> Self {
+        Self {
+            manager_handle,
+            input,
+            looping,
+            program_selection,
+        }
+    }
+
+    pub async fn run(self) {
+        if let Err(err) = self.handle_file().await {
+            log::error!("{}: {}", self.input, err);
+        }
+    }
+
+    async fn handle_file(&self) -> Result<()> {
+        let source = FileSource::open(self.input.clone(), self.looping).await?;
+        log::info!("Publishing {} paced by its {}", self.input, source.clock);
+
+        Connection::new(0, source, self.manager_handle.clone(), self.program_selection.clone()).run().await
+    }
+}
+
+/// The timestamps a file is paced by, those of a single pid.
+#[derive(Clone, Copy, Debug)]
+enum Clock {
+    Pcr(u16),
+    Pts(u16),
+}
+
+impl Clock {
+    fn read(&self, packet: &Packet) -> Option<u64> {
+        match *self {
+            Clock::Pcr(pid) if pid == packet_pid(packet) => retime::pcr(packet),
+            Clock::Pts(pid) if pid == packet_pid(packet) => retime::pts(packet),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Clock::Pcr(pid) => write!(f, "PCR on pid {}", pid),
+            Clock::Pts(pid) => write!(f, "PTS on pid {}", pid),
+        }
+    }
+}
+
+fn packet_pid(packet: &Packet) -> u16 {
+    u16::from(packet[1] & 0x1F) << 8 | u16::from(packet[2])
+}
+
+/// Reads whole packets, skipping to the next sync byte when the file is off by some bytes.
+struct PacketReader {
+    reader: BufReader<File>,
+}
+
+impl PacketReader {
+    async fn read(&mut self) -> Result<Option<Packet>> {
+        let mut packet = [0; SIZE];
+        let mut filled = 0;
+
+        loop {
+            match self.reader.read_exact(&mut packet[filled..]).await {
+                Ok(_) => {},
+                // a trailing partial packet is dropped
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err.into()),
+            }
+
+            match packet.iter().position(|byte| *byte == SYNC_BYTE) {
+                Some(0) => return Ok(Some(packet)),
+                Some(start) => {
+                    packet.copy_within(start.., 0);
+                    filled = SIZE - start;
+                },
+                None => filled = 0,
+            }
+        }
+    }
+
+    async fn rewind(&mut self) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(0)).await?;
+        Ok(())
+    }
+
+    /// Picks the PCR pid if there is one near the start of the file, otherwise
+    /// the first pid with a PTS.
+    async fn probe(&mut self) -> Result<Option<Clock>> {
+        let mut clock = None;
+
+        for _ in 0..PROBE_PACKETS {
+            let Some(packet) = self.read().await? else { break };
+
+            if retime::pcr(&packet).is_some() {
+                clock = Some(Clock::Pcr(packet_pid(&packet)));
+                break;
+            }
+
+            if clock.is_none() && retime::pts(&packet).is_some() {
+                clock = Some(Clock::Pts(packet_pid(&packet)));
+            }
+        }
+
+        self.rewind().await?;
+        Ok(clock)
+    }
+}
+
+/// Span of the clock over one pass through the file, before any loop offset.
+#[derive(Default)]
+struct Pass {
+    first: Option<u64>,
+    last: u64,
+    interval: u64,
+}
+
+impl Pass {
+    fn update(&mut self, timestamp: u64) {
+        if self.first.is_none() {
+            self.first = Some(timestamp);
+            self.last = timestamp;
+            return;
+        }
+
+        let diff = (timestamp + PCR_CYCLE - self.last) % PCR_CYCLE;
+        if diff > 0 && diff < PCR_CYCLE / 2 {
+            if diff <= MAX_CLOCK_JUMP {
+                self.interval = diff;
+            }
+            self.last = timestamp;
+        }
+    }
+
+    /// How far the next pass has to be moved on to follow this one.
+    fn duration(&self) -> Option<u64> {
+        let first = self.first?;
+        let interval = if self.interval > 0 { self.interval } else { DEFAULT_CLOCK_INTERVAL };
+
+        Some((self.last + PCR_CYCLE - first) % PCR_CYCLE + interval)
+    }
+}
+
+/// Maps clock values onto the wall clock, starting from the first one seen.
+#[derive(Default)]
+struct Pacer {
+    start: Option<Instant>,
+    last: u64,
+    elapsed: u64,
+}
+
+impl Pacer {
+    fn deadline(&mut self, timestamp: u64) -> Instant {
+        let Some(start) = self.start else {
+            return self.restart(timestamp);
+        };
+
+        let diff = (timestamp + PCR_CYCLE - self.last) % PCR_CYCLE;
+
+        if diff <= MAX_CLOCK_JUMP {
+            self.elapsed += diff;
+            self.last = timestamp;
+        } else if PCR_CYCLE - diff > MAX_CLOCK_JUMP {
+            log::warn!("Clock jumped from {} to {}, restarting pacing", self.last, timestamp);
+            return self.restart(timestamp);
+        }
+        // a small step back, e.g. reordered PTS, is paced at the highest value so far
+
+        start + Duration::from_micros(self.elapsed * 1_000_000 / HZ as u64)
+    }
+
+    fn restart(&mut self, timestamp: u64) -> Instant {
+        let now = Instant::now();
+        self.start = Some(now);
+        self.last = timestamp;
+        self.elapsed = 0;
+        now
+    }
+}
+
+/// A transport stream file read out in real time. When looping, every pass is
+/// moved on by the duration of the ones before it, so the timestamps keep
+/// counting up, and the discontinuity_indicator is set on the first clock
+/// packet of the pass that carries an adaptation field.
+pub struct FileSource {
+    input: FileInput,
+    looping: bool,
+    reader: PacketReader,
+    clock: Clock,
+    offset: u64,
+    pass: Pass,
+    pacer: Pacer,
+    discontinuity: bool,
+    held: Option<(Packet, Option<Instant>)>,
+}
+
+impl FileSource {
+    pub async fn open(input: FileInput, looping: bool) -> Result<Self> {
+        let file = File::open(&input.path).await?;
+        let mut reader = PacketReader { reader: BufReader::new(file) };
+
+        let Some(clock) = reader.probe().await? else {
+            bail!("no PCR or PTS to pace the file by");
+        };
+
+        Ok(Self {
+            input,
+            looping,
+            reader,
+            clock,
+            offset: 0,
+            pass: Pass::default(),
+            pacer: Pacer::default(),
+            discontinuity: false,
+            held: None,
+        })
+    }
+
+    /// The next packet, on the new time base, and when it is due.
+    async fn next_packet(&mut self) -> Result<Option<(Packet, Option<Instant>)>> {
+        let mut packet = loop {
+            match self.reader.read().await? {
+                Some(packet) => break packet,
+                None if self.looping => self.restart().await?,
+                None => return Ok(None),
+            }
+        };
+
+        let timestamp = self.clock.read(&packet);
+
+        if self.offset > 0 {
+            retime::shift_timestamps(&mut packet, self.offset);
+        }
+
+        let Some(timestamp) = timestamp else {
+            return Ok(Some((packet, None)));
+        };
+
+        if self.discontinuity && retime::set_discontinuity(&mut packet) {
+            self.discontinuity = false;
+        }
+
+        self.pass.update(timestamp);
+        let deadline = self.pacer.deadline((timestamp + self.offset) % PCR_CYCLE);
+
+        Ok(Some((packet, Some(deadline))))
+    }
+
+    async fn restart(&mut self) -> Result<()> {
+        let Some(duration) = self.pass.duration() else {
+            bail!("{} has no {} to pace by", self.input.path.display(), self.clock);
+        };
+
+        self.offset = (self.offset + duration) % PCR_CYCLE;
+        self.pass = Pass::default();
+        self.discontinuity = true;
+        self.reader.rewind().await?;
+
+        log::info!("Looping {}", self.input);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IngestSource for FileSource {
+    fn stream_id(&self) -> Option<String> {
+        Some(self.input.stream_name.clone())
+    }
+
+    fn peer(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    async fn recv(&mut self) -> Result<Option<Bytes>> {
+        let mut chunk = BytesMut::with_capacity(CHUNK_SIZE);
+
+        while chunk.len() < CHUNK_SIZE {
+            let next = match self.held.take() {
+                Some(held) => Some(held),
+                None => self.next_packet().await?,
+            };
+
+            let Some((packet, deadline)) = next else { break };
+
+            if let Some(deadline) = deadline.filter(|deadline| *deadline > Instant::now()) {
+                // send what is due now, the rest waits for the next call
+                if !chunk.is_empty() {
+                    self.held = Some((packet, Some(deadline)));
+                    break;
+                }
+
+                let wake = deadline.min(Instant::now() + MAX_WAIT);
+                sleep_until(wake).await;
+
+                if wake < deadline {
+                    self.held = Some((packet, Some(deadline)));
+                    break;
+                }
+            }
+
+            chunk.extend_from_slice(&packet);
+        }
+
+        // an empty chunk with a packet held back is a wait that was cut short
+        if chunk.is_empty() && self.held.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(chunk.freeze()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+    use mpegts::{demuxer::SIZE, muxer::Muxer, retime, stream_type::StreamType, HZ, PCR_CYCLE};
+    use tokio::time::Instant;
+    use crate::{option::FileInput, session::source::IngestSource};
+    use super::{FileSource, Pacer, Pass, DEFAULT_CLOCK_INTERVAL, MAX_CLOCK_JUMP, MAX_WAIT};
+
+    const FRAME: u64 = HZ as u64 / 25;
+
+    #[test]
+    fn pass_spans_clock_across_wrap() {
+        let mut pass = Pass::default();
+        assert_eq!(pass.duration(), None);
+
+        pass.update(PCR_CYCLE - FRAME);
+        assert_eq!(pass.duration(), Some(DEFAULT_CLOCK_INTERVAL));
+
+        pass.update(0);
+        pass.update(FRAME);
+        assert_eq!(pass.duration(), Some(3 * FRAME));
+    }
+
+    #[test]
+    fn pass_ignores_steps_back_and_keeps_interval_over_jumps() {
+        let mut pass = Pass::default();
+
+        pass.update(0);
+        pass.update(FRAME);
+        pass.update(FRAME / 2);
+        assert_eq!(pass.duration(), Some(2 * FRAME));
+
+        // the break still counts towards the pass, but not as the clock interval
+        pass.update(FRAME + MAX_CLOCK_JUMP * 2);
+        assert_eq!(pass.duration(), Some(FRAME + MAX_CLOCK_JUMP * 2 + FRAME));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pacer_follows_clock_and_restarts_on_jumps() {
+        let mut pacer = Pacer::default();
+        let start = Instant::now();
+        let millis = |ticks: u64| Duration::from_millis(ticks * 1000 / HZ as u64);
+
+        assert_eq!(pacer.deadline(PCR_CYCLE - FRAME), start);
+        assert_eq!(pacer.deadline(0), start + millis(FRAME));
+        assert_eq!(pacer.deadline(FRAME), start + millis(2 * FRAME));
+
+        // a small step back is paced at the highest value so far
+        assert_eq!(pacer.deadline(FRAME / 2), start + millis(2 * FRAME));
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let restarted = Instant::now();
+
+        // forwards or backwards, a jump is paced from now
+        assert_eq!(pacer.deadline(FRAME + MAX_CLOCK_JUMP + 1), restarted);
+        assert_eq!(pacer.deadline(0), restarted);
+        assert_eq!(pacer.deadline(FRAME), restarted + millis(FRAME));
+    }
+
+    /// Writes frames on a PCR carrying video pid to a file named after the test.
+    fn write_file(name: &str, timestamps: &[u64]) -> FileInput {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+
+        let mut data = Vec::new();
+        for timestamp in timestamps {
+            data.extend_from_slice(&muxer.write(video, &[0x00, 0x00, 0x01, 0x65], *timestamp, None, true).unwrap());
+        }
+
+        let path: PathBuf = std::env::temp_dir().join(format!("stream_kit_{}_{}.ts", name, std::process::id()));
+        std::fs::write(&path, data).unwrap();
+
+        FileInput { stream_name: name.to_owned(), path }
+    }
+
+    async fn clock_values(source: &mut FileSource, count: usize) -> Vec<(u64, bool)> {
+        let mut values = Vec::new();
+
+        while values.len() < count {
+            let chunk = source.recv().await.unwrap().unwrap();
+            for packet in chunk.chunks(SIZE) {
+                if let Some(pcr) = retime::pcr(packet) {
+                    values.push((pcr, packet[5] & 0x80 != 0));
+                }
+            }
+        }
+
+        values
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn loops_onto_following_timestamps() {
+        let input = write_file("loops", &[FRAME, 2 * FRAME]);
+        let path = input.path.clone();
+        let mut source = FileSource::open(input, true).await.unwrap();
+
+        let values = clock_values(&mut source, 4).await;
+        std::fs::remove_file(path).unwrap();
+
+        // the PCR runs behind the PTS, so this also wraps
+        let clock = |frames: u64| (values[0].0 + frames * FRAME) % PCR_CYCLE;
+        assert_eq!(values, vec![
+            (clock(0), false),
+            (clock(1), false),
+            // the second pass follows on a frame later and is flagged
+            (clock(2), true),
+            (clock(3), false),
+        ]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn returns_empty_chunks_while_waiting() {
+        let interval = MAX_CLOCK_JUMP * 9 / 10;
+        let input = write_file("waits", &[0, interval]);
+        let path = input.path.clone();
+        let mut source = FileSource::open(input, false).await.unwrap();
+
+        assert!(!source.recv().await.unwrap().unwrap().is_empty());
+
+        // the second frame is due in 900ms, longer than a single wait
+        let start = Instant::now();
+        assert!(source.recv().await.unwrap().unwrap().is_empty());
+        assert_eq!(start.elapsed(), MAX_WAIT);
+
+        assert!(!source.recv().await.unwrap().unwrap().is_empty());
+        assert_eq!(start.elapsed(), Duration::from_millis(interval * 1000 / HZ as u64));
+
+        assert!(source.recv().await.unwrap().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+}
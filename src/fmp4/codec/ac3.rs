@@ -0,0 +1,69 @@
+use ac3::SyncFrameHeader;
+use bytes::Bytes;
+
+use mp4::{
+    types::{
+        ac3::Ac3,
+        dac3::Dac3,
+        dec3::{Dec3, IndependentSubstream},
+        ec3::Ec3,
+        stsd::{AudioSampleEntry, SampleEntry},
+        trun::{TrunSample, TrunSampleFlag},
+    },
+    DynBox,
+};
+
+/// Builds an ac-3 or ec-3 entry from the syncframes making up one sample.
+pub fn stsd_entry(headers: &[SyncFrameHeader]) -> Option<DynBox> {
+    let first = headers.first()?;
+
+    // the channel count is ignored by readers, the layout is in the dac3/dec3
+    let audio_sample_entry = SampleEntry::new(AudioSampleEntry::new(2, 16, first.sample_rate));
+
+    if !first.is_eac3() {
+        let dac3 = Dac3::new(first.fscod, first.bsid, first.bsmod, first.acmod, first.lfeon, first.frmsizecod >> 1);
+        return Some(Ac3::new(audio_sample_entry, dac3, None).into());
+    }
+
+    let mut independent_substreams: Vec<IndependentSubstream> = Vec::new();
+    for header in headers {
+        match independent_substreams.last_mut() {
+            Some(substream) if header.dependent => {
+                substream.num_dep_sub += 1;
+                substream.chan_loc |= header.chan_loc();
+            }
+            _ => independent_substreams.push(IndependentSubstream {
+                fscod: header.fscod,
+                bsid: header.bsid,
+                asvc: false,
+                bsmod: header.bsmod,
+                acmod: header.acmod,
+                lfeon: header.lfeon,
+                num_dep_sub: 0,
+                chan_loc: 0,
+            }),
+        }
+    }
+
+    let data_rate = headers.iter().map(|header| header.bit_rate()).sum::<u32>() as u16;
+
+    Some(Ec3::new(audio_sample_entry, Dec3::new(data_rate, independent_substreams), None).into())
+}
+
+pub fn trun_sample(duration: u32, data: &Bytes) -> TrunSample {
+    TrunSample {
+        duration: Some(duration),
+        composition_time_offset: None,
+        flags: Some(TrunSampleFlag {
+            reserved: 0,
+            is_leading: 0,
+            sample_degradation_priority: 0,
+            sample_depends_on: 2,
+            sample_has_redundancy: 0,
+            sample_is_depended_on: 0,
+            sample_is_non_sync_sample: false,
+            sample_padding_value: 0,
+        }),
+        size: Some(data.len() as u32),
+    }
+}
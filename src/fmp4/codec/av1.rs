@@ -0,0 +1,64 @@
+use anyhow::Result;
+use av1::{AV1CodecConfigurationRecord, ObuHeader, seq::SequenceHeaderObu};
+use bytes::Bytes;
+use bytesio::bit_reader::BitReader;
+use mp4::{types::{av01::Av01, av1c::Av1C, stsd::{VisualSampleEntry, SampleEntry}, colr::{Colr, ColorType}, trun::{TrunSample, TrunSampleFlag}}, DynBox};
+
+pub fn stsd_entry(config: AV1CodecConfigurationRecord) -> Result<DynBox> {
+    let seq = sequence_header(&config)?;
+
+    let colr = Colr::new(ColorType::Nclx {
+        color_primaries: seq.color_config.color_primaries as u16,
+        matrix_coefficients: seq.color_config.matrix_coefficients as u16,
+        transfer_characteristics: seq.color_config.transfer_characteristics as u16,
+        full_range_flag: seq.color_config.full_color_range,
+    });
+
+    Ok(
+        Av01::new(
+            SampleEntry::new(VisualSampleEntry::new(
+                seq.max_frame_width as u16,
+                seq.max_frame_height as u16,
+                Some(colr),
+            )),
+            Av1C::new(config),
+            None,
+        ).into()
+    )
+}
+
+pub fn frame_size(config: &AV1CodecConfigurationRecord) -> Result<(u32, u32)> {
+    let seq = sequence_header(config)?;
+    Ok((seq.max_frame_width as u32, seq.max_frame_height as u32))
+}
+
+fn sequence_header(config: &AV1CodecConfigurationRecord) -> Result<SequenceHeaderObu> {
+    let (header, data) = ObuHeader::parse(&mut BitReader::from(config.config_obu.clone()))?;
+    Ok(SequenceHeaderObu::parse(header, data)?)
+}
+
+pub fn trun_sample(
+    keyframe: bool,
+    duration: u32,
+    data: &Bytes,
+) -> Result<TrunSample> {
+    Ok(TrunSample {
+        composition_time_offset: None,
+        duration: Some(duration),
+        flags: Some(TrunSampleFlag {
+            reserved: 0,
+            is_leading: 0,
+            sample_degradation_priority: 0,
+            sample_depends_on: if keyframe {
+                2
+            } else {
+                1
+            },
+            sample_has_redundancy: 0,
+            sample_is_depended_on: 0,
+            sample_is_non_sync_sample: !keyframe,
+            sample_padding_value: 0,
+        }),
+        size: Some(data.len() as u32),
+    })
+}
@@ -1,33 +1,65 @@
 use anyhow::Result;
 use bytes::Bytes;
 use h265::config::HEVCDecoderConfigurationRecord;
-use mp4::{types::{hev1::Hev1, stsd::{VisualSampleEntry, SampleEntry}, hvcc::HvcC, colr::{Colr, ColorType}, trun::{TrunSample, TrunSampleFlag}}, DynBox};
+use mp4::{types::{hev1::Hev1, hvc1::Hvc1, stsd::{VisualSampleEntry, SampleEntry}, hvcc::HvcC, colr::{Colr, ColorType}, trun::{TrunSample, TrunSampleFlag}}, DynBox};
 
 pub fn stsd_entry(config: HEVCDecoderConfigurationRecord) -> Result<DynBox> {
+    Ok(
+        Hev1::new(
+            visual_sample_entry(&config),
+            HvcC::new(config),
+            None,
+        ).into()
+    )
+}
 
-    
-    // let colr = sps.color_config.as_ref().map(|color_config| {
-    //     Colr::new(ColorType::Nclx {
-    //         color_primaries: color_config.color_primaries as u16,
-    //         matrix_coefficients: color_config.matrix_coefficients as u16,
-    //         transfer_characteristics: color_config.transfer_characteristics as u16,
-    //         full_range_flag: color_config.full_range,
-    //     })
-    // });
+/// Sample entry for streams whose samples carry no parameter sets.
+pub fn hvc1_stsd_entry(mut config: HEVCDecoderConfigurationRecord) -> Result<DynBox> {
+    config.array_completeness = true;
 
     Ok(
-        Hev1::new(
-            SampleEntry::new(VisualSampleEntry::new(
-                1280 as u16,
-                720 as u16,
-                None,
-            )),
+        Hvc1::new(
+            visual_sample_entry(&config),
             HvcC::new(config),
             None,
         ).into()
     )
 }
 
+fn visual_sample_entry(config: &HEVCDecoderConfigurationRecord) -> SampleEntry<VisualSampleEntry> {
+    let colr = config.color_config.as_ref().map(|color_config| {
+        Colr::new(ColorType::Nclx {
+            color_primaries: color_config.color_primaries as u16,
+            matrix_coefficients: color_config.matrix_coefficients as u16,
+            transfer_characteristics: color_config.transfer_characteristics as u16,
+            full_range_flag: color_config.full_range,
+        })
+    });
+
+    let mut visual_sample_entry = VisualSampleEntry::new(
+        config.width as u16,
+        config.height as u16,
+        colr,
+    );
+
+    if let Some(pasp) = visual_sample_entry.pasp.as_mut() {
+        pasp.h_spacing = config.sar_width;
+        pasp.v_spacing = config.sar_height;
+    }
+
+    SampleEntry::new(visual_sample_entry)
+}
+
+/// Track header size, the coded width stretched by the sample aspect ratio.
+pub fn display_size(config: &HEVCDecoderConfigurationRecord) -> (u32, u32) {
+    if config.sar_width == 0 || config.sar_height == 0 {
+        return (config.width, config.height);
+    }
+
+    let width = config.width as u64 * config.sar_width as u64 / config.sar_height as u64;
+    (width as u32, config.height)
+}
+
 pub fn trun_sample(
     keyframe: bool,
     composition_time_offset: u32,
@@ -1,3 +1,26 @@
+use mp4::DynBox;
+
 pub mod aac;
+pub mod ac3;
+pub mod av1;
 pub mod h264;
-pub mod h265;
\ No newline at end of file
+pub mod h265;
+pub mod mp3;
+pub mod opus;
+
+/// RFC 6381 codecs parameter of a sample entry, for the CODECS attribute of the playlist.
+pub fn codecs_parameter(entry: &DynBox) -> Option<String> {
+    let codec = match entry {
+        DynBox::Avc1(avc1) => avc1.codec().ok()?.to_string(),
+        DynBox::Hev1(hev1) => hev1.codec().ok()?.to_string(),
+        DynBox::Hvc1(hvc1) => hvc1.codec().ok()?.to_string(),
+        DynBox::Av01(av01) => av01.codec().ok()?.to_string(),
+        DynBox::Mp4a(mp4a) => mp4a.codec().ok()?.to_string(),
+        DynBox::Opus(opus) => opus.codec().ok()?.to_string(),
+        DynBox::Ac3(ac3) => ac3.codec().ok()?.to_string(),
+        DynBox::Ec3(ec3) => ec3.codec().ok()?.to_string(),
+        _ => return None,
+    };
+
+    Some(codec)
+}
@@ -0,0 +1,63 @@
+use bytes::Bytes;
+use mp3::FrameHeader;
+
+use mp4::{
+    types::{
+        esds::{
+            descriptor::types::{decoder_config::DecoderConfigDescriptor, es::EsDescriptor},
+            Esds,
+        },
+        mp4a::Mp4a,
+        stsd::{AudioSampleEntry, SampleEntry},
+        trun::{TrunSample, TrunSampleFlag},
+    },
+    DynBox,
+};
+
+pub fn stsd_entry(header: &FrameHeader) -> DynBox {
+    let bit_rate = header.bit_rate * 1000;
+
+    Mp4a::new(
+        SampleEntry::new(AudioSampleEntry::new(
+            header.channels().into(),
+            16,
+            header.sample_rate,
+        )),
+        Esds::new(EsDescriptor::new(
+            2,
+            0,
+            Some(0),
+            None,
+            Some(0),
+            Some(DecoderConfigDescriptor::new(
+                0x6B, // mpeg-1 audio
+                0x05, // audio stream
+                bit_rate,
+                bit_rate,
+                // the frame headers are all a decoder needs
+                None,
+            )),
+            None,
+        )),
+        None,
+    )
+    .into()
+}
+
+pub fn trun_sample(duration: u32, data: &Bytes) -> TrunSample {
+    TrunSample {
+        duration: Some(duration),
+        composition_time_offset: None,
+        flags: Some(TrunSampleFlag {
+            reserved: 0,
+            is_leading: 0,
+            sample_degradation_priority: 0,
+            sample_depends_on: 2,
+            sample_has_redundancy: 0,
+            sample_is_depended_on: 0,
+            sample_is_non_sync_sample: false,
+            sample_padding_value: 0,
+        }),
+        size: Some(data.len() as u32),
+    }
+}
@@ -0,0 +1,56 @@
+use bytes::Bytes;
+use opus::ChannelConfig;
+
+use mp4::{
+    types::{
+        dops::{ChannelMappingTable, Dops},
+        opus::Opus,
+        stsd::{AudioSampleEntry, SampleEntry},
+        trun::{TrunSample, TrunSampleFlag},
+    },
+    DynBox,
+};
+
+pub fn stsd_entry(channel_config: &ChannelConfig, pre_skip: u16) -> DynBox {
+    // mapping family 0 has an implied mono or stereo layout and no table
+    let channel_mapping_table = (channel_config.mapping_family != 0).then(|| ChannelMappingTable {
+        stream_count: channel_config.stream_count,
+        coupled_count: channel_config.coupled_count,
+        channel_mapping: channel_config.mapping.clone(),
+    });
+
+    Opus::new(
+        SampleEntry::new(AudioSampleEntry::new(
+            channel_config.channels.into(),
+            16,
+            opus::SAMPLE_RATE,
+        )),
+        Dops::new(
+            channel_config.channels,
+            pre_skip,
+            opus::SAMPLE_RATE,
+            channel_config.mapping_family,
+            channel_mapping_table,
+        ),
+        None,
+    )
+    .into()
+}
+
+pub fn trun_sample(duration: u32, data: &Bytes) -> TrunSample {
+    TrunSample {
+        duration: Some(duration),
+        composition_time_offset: None,
+        flags: Some(TrunSampleFlag {
+            reserved: 0,
+            is_leading: 0,
+            sample_degradation_priority: 0,
+            sample_depends_on: 2,
+            sample_has_redundancy: 0,
+            sample_is_depended_on: 0,
+            sample_is_non_sync_sample: false,
+            sample_padding_value: 0,
+        }),
+        size: Some(data.len() as u32),
+    }
+}
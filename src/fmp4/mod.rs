@@ -1,17 +1,66 @@
-use std::{sync::Arc, cmp::max};
-use crate::{session::{ManagerHandle, trigger_channel, ChannelMessage, Watcher, Message, Codec}, hls::{SegmentStores, segment_store::SegmentStore}, Opt};
-use anyhow::Result;
+use std::{sync::Arc, cmp::max, collections::HashMap};
+use crate::{session::{ManagerHandle, trigger_channel, ChannelMessage, Watcher, Message, Codec, Cue, CueKind}, hls::{self, SegmentStores, segment_store::{SegmentStore, DateRange}}, Opt};
+use anyhow::{bail, Result};
 use bytes::{Bytes, BytesMut, BufMut};
 use bytesio::bytes_writer::BytesWriter;
 use h264::H264Coder;
 use h265::H265Coder;
+use av1::{AV1CodecConfigurationRecord, TemporalUnit};
 use aac::{AacCoder, aac_codec::RawAacStreamCodec};
-use mp4::{types::{trun::Trun, moof::Moof, mfhd::Mfhd, traf::Traf, tfhd::Tfhd, tfdt::Tfdt, mdat::Mdat, mvex::Mvex, trex::Trex, stsz::Stsz, vmhd::Vmhd, stco::Stco, stsc::Stsc, stts::Stts, stsd::Stsd, stbl::Stbl, minf::Minf, hdlr::{Hdlr, HandlerType}, mdia::Mdia, tkhd::Tkhd, trak::Trak, mvhd::Mvhd, moov::Moov, ftyp::{FourCC, Ftyp}, mdhd::Mdhd, smhd::Smhd}, BoxType, DynBox};
+use opus::ChannelConfig;
+use ac3::{SyncFrameHeader, SyncFrameReader};
+use mp3::{FrameHeader, FrameReader};
+use mp4::{types::{trun::{Trun, TrunSample}, moof::Moof, mfhd::Mfhd, traf::Traf, tfhd::Tfhd, tfdt::Tfdt, mdat::Mdat, mvex::Mvex, trex::Trex, stsz::Stsz, vmhd::Vmhd, stco::Stco, stsc::Stsc, stts::Stts, stsd::Stsd, stbl::Stbl, minf::Minf, hdlr::{Hdlr, HandlerType}, mdia::Mdia, tkhd::Tkhd, trak::Trak, mvhd::Mvhd, moov::Moov, ftyp::{FourCC, Ftyp}, mdhd::Mdhd, smhd::Smhd}, BoxType, DynBox};
 use time::{OffsetDateTime, Duration};
 use common::FormatReader;
 
 pub mod codec;
 
+/// PCR steps beyond this are treated as a new time base
+const MAX_PCR_GAP: u64 = mpegts::HZ as u64;
+/// Gaps between consecutive video frames beyond this are rebased even
+/// without a PCR discontinuity
+const MAX_FRAME_GAP: u64 = 10 * mpegts::HZ as u64;
+
+/// A video frame as a single mp4 sample.
+struct VideoFrame {
+    key_frame: bool,
+    data: Bytes,
+    /// Decode time on the output timeline
+    timestamp: u64,
+    composition_offset: u64,
+    program_date_time: OffsetDateTime,
+}
+
+impl VideoFrame {
+    /// Packages the NAL units of an access unit with 4 byte length prefixes,
+    /// `None` when there are none to write.
+    fn from_nal_units(key_frame: bool, nal_units: Vec<Vec<u8>>, timestamp: u64, composition_offset: u64, program_date_time: OffsetDateTime) -> Option<Self> {
+        if nal_units.is_empty() {
+            return None;
+        }
+
+        let mut data = BytesMut::new();
+        for nal_unit in nal_units {
+            data.put_u32(nal_unit.len() as u32);
+            data.extend(nal_unit);
+        }
+
+        Some(Self {
+            key_frame,
+            data: data.freeze(),
+            timestamp,
+            composition_offset,
+            program_date_time,
+        })
+    }
+}
+
+/// Presentation minus decode time, across a wrap of the 33 bit clock.
+fn composition_offset(pts: u64, dts: u64) -> u64 {
+    (pts + mpegts::PCR_CYCLE - dts) % mpegts::PCR_CYCLE
+}
+
 pub struct Mp4fWriter {
     stream_name: String,
     watcher: Watcher,
@@ -20,23 +69,52 @@ pub struct Mp4fWriter {
     latest_pcr_value: Option<i64>,
     latest_pcr_timestamp_90khz: u64,
     latest_pcr_datetime: Option<OffsetDateTime>,
+    latest_pcr_diff: u64,
+
+    pcr_discontinuity: bool,
+    rebase_pending: bool,
+    discontinuity_at: Option<u64>,
+    video_duration: u64,
 
     partial_begin_timestamp: Option<u32>,
     part_duration: f32,
+    segment_duration: f32,
+    segment_begin_timestamp: Option<u64>,
+    audio_end_timestamp: Option<u64>,
+    vod_retention: std::time::Duration,
     initialization_segment_dispatched: bool,
 
+    tracks: Option<Vec<Codec>>,
     h264_coder: H264Coder,
     h265_coder: H265Coder,
+    /// HEVC is written as hvc1 with the parameter sets only in the hvcC,
+    /// cleared once they change mid-stream and the writer falls back to hev1
+    hvc1: bool,
+    init_parameter_sets: Option<Vec<h265::nal::Unit>>,
+    parameter_sets_changed_at: Option<u64>,
+    av1_config: Option<AV1CodecConfigurationRecord>,
     aac_coder: AacCoder,
     aac_config: Option<RawAacStreamCodec>,
-    aac_writer: BytesWriter,
-
-    next_h264: Option<(bool, Vec<Vec<u8>>, u64, u64, OffsetDateTime)>,
-    current_h264: Option<(bool, Vec<Vec<u8>>, u64, u64, OffsetDateTime)>,
+    /// Channel layout from the PMT and the pre-skip of the first access unit
+    opus_config: Option<(ChannelConfig, u16)>,
+    ac3_reader: SyncFrameReader,
+    /// Syncframe headers of the first AC-3 or E-AC-3 sample
+    ac3_config: Option<Vec<SyncFrameHeader>>,
+    mp3_reader: FrameReader,
+    mp3_config: Option<FrameHeader>,
+    audio_writer: BytesWriter,
+
+    /// The last video frame, written out once the next one gives it a duration
+    pending_video: Option<VideoFrame>,
+
+    pending_cues: Vec<(u64, OffsetDateTime, Cue)>,
+    open_cues: HashMap<u32, OffsetDateTime>,
 }
 
 impl Mp4fWriter {
     fn new(opt: &Opt, stream_name: String, watcher: Watcher, stores: SegmentStores) -> Self {
+        let hvc1 = opt.hvc1_streams.iter().any(|name| name == "*" || *name == stream_name);
+
         Self {
             stream_name,
             watcher,
@@ -45,24 +123,50 @@ impl Mp4fWriter {
             latest_pcr_value: None,
             latest_pcr_timestamp_90khz: 0,
             latest_pcr_datetime: None,
+            latest_pcr_diff: 0,
+
+            pcr_discontinuity: false,
+            rebase_pending: false,
+            discontinuity_at: None,
+            video_duration: 0,
 
             partial_begin_timestamp: None,
             part_duration: opt.part_duration,
+            segment_duration: opt.segment_duration,
+            segment_begin_timestamp: None,
+            audio_end_timestamp: None,
+            vod_retention: std::time::Duration::from_secs(opt.vod_retention),
             initialization_segment_dispatched: false,
 
+            tracks: None,
             h264_coder: H264Coder::new(),
             h265_coder: H265Coder::new(),
+            hvc1,
+            init_parameter_sets: None,
+            parameter_sets_changed_at: None,
+            av1_config: None,
             aac_coder: AacCoder::new(),
             aac_config: None,
-            aac_writer: BytesWriter::default(),
-            next_h264: None,
-            current_h264: None,
+            opus_config: None,
+            ac3_reader: SyncFrameReader::new(),
+            ac3_config: None,
+            mp3_reader: FrameReader::new(),
+            mp3_config: None,
+            audio_writer: BytesWriter::default(),
+            pending_video: None,
+
+            pending_cues: Vec::new(),
+            open_cues: HashMap::new(),
         }
     }
 
     async fn run(&mut self) -> Result<()> {
         while let Ok(packet) = self.watcher.recv().await {
             match packet {
+                Message::Tracks(tracks) => {
+                    log::debug!("{} tracks {:?}", self.stream_name, tracks);
+                    self.tracks = Some(tracks);
+                },
                 Message::ClockRef(pcr) => {
                     self.handle_pcr(pcr).await?;
                 },
@@ -74,15 +178,69 @@ impl Mp4fWriter {
                             Codec::H265 => {
                                 self.handle_h265_video(packet.data, packet.pts, packet.dts).await?;
                             },
+                            Codec::AV1 => {
+                                self.handle_av1_video(packet.data, packet.pts).await?;
+                            },
                             Codec::AAC => {
-                                self.handle_audio(packet.data, packet.pts).await?;
+                                self.handle_aac_audio(packet.data, packet.pts).await?;
+                            },
+                            Codec::OPUS(channel_config_code) => {
+                                self.handle_opus_audio(packet.data, packet.pts, channel_config_code).await?;
+                            },
+                            Codec::AC3 | Codec::EAC3 => {
+                                self.handle_ac3_audio(packet.data, packet.pts).await?;
+                            },
+                            Codec::MP3 => {
+                                self.handle_mp3_audio(packet.data, packet.pts).await?;
                             },
                         }
                 },
 
+                Message::Cue(cue) => {
+                    self.handle_cue(cue).await?;
+                },
+
+                Message::Discontinuity => {
+                    self.pcr_discontinuity = true;
+                },
+
                 Message::Disconnect => break,
             }
         }
+
+        // the session is only closed once any reconnect grace period is over
+        self.finish().await
+    }
+
+    /// Ends the playlist at the last frame written and keeps the store
+    /// around for replay until the retention time is up.
+    async fn finish(&mut self) -> Result<()> {
+        // the frame still waiting on its duration is dropped
+        let end_timestamp = match (&self.pending_video, self.audio_end_timestamp) {
+            (Some(frame), _) => frame.timestamp as u32,
+            (None, Some(audio_end_timestamp)) => audio_end_timestamp as u32,
+            (None, None) => self.partial_begin_timestamp.unwrap_or_default(),
+        };
+
+        let id = match self.stores.write().await.get_mut(&self.stream_name) {
+            Some(store) => {
+                store.finish(end_timestamp)?;
+                store.id()
+            },
+            None => return Ok(()),
+        };
+
+        log::info!("Stream {} ended, keeping it for {:?}", self.stream_name, self.vod_retention);
+
+        let stores = Arc::clone(&self.stores);
+        let stream_name = self.stream_name.clone();
+        let vod_retention = self.vod_retention;
+
+        tokio::spawn(async move {
+            tokio::time::sleep(vod_retention).await;
+            hls::remove_store(&stores, &stream_name, id).await;
+        });
+
         Ok(())
     }
 
@@ -90,15 +248,37 @@ impl Mp4fWriter {
         let prc_value: i64 = (pcr as i64 - mpegts::HZ as i64 + mpegts::PCR_CYCLE as i64) % mpegts::PCR_CYCLE as i64;
 
         let mut pcr_diff = 0;
+        let mut discontinuity = false;
 
         if let Some(latest_pcr_value) = self.latest_pcr_value {
             pcr_diff = (prc_value - latest_pcr_value + mpegts::PCR_CYCLE as i64) % mpegts::PCR_CYCLE as i64;
+
+            if self.pcr_discontinuity || pcr_diff as u64 > MAX_PCR_GAP {
+                log::info!("{} PCR discontinuity, rebasing timestamps", self.stream_name);
+
+                // assume the clock kept its usual pace across the jump, the
+                // next video frame lines the timeline up exactly
+                pcr_diff = self.latest_pcr_diff as i64;
+                self.rebase_pending = true;
+                discontinuity = true;
+            } else {
+                self.latest_pcr_diff = pcr_diff as u64;
+            }
         }
 
+        self.pcr_discontinuity = false;
+
         self.latest_pcr_timestamp_90khz += pcr_diff as u64;
             
         if let Some(latest_pcr_datetime) = self.latest_pcr_datetime {
-            self.latest_pcr_datetime = Some(latest_pcr_datetime + Duration::seconds_f64(pcr_diff as f64 / mpegts::HZ as f64))
+            let mut datetime = latest_pcr_datetime + Duration::seconds_f64(pcr_diff as f64 / mpegts::HZ as f64);
+
+            // time has moved on while the source was away, e.g. a publisher reconnecting
+            if discontinuity {
+                datetime = max(datetime, OffsetDateTime::now_utc() - Duration::SECOND);
+            }
+
+            self.latest_pcr_datetime = Some(datetime);
         } else {
             self.latest_pcr_datetime = Some(OffsetDateTime::now_utc() - Duration::SECOND);
         }
@@ -107,425 +287,798 @@ impl Mp4fWriter {
         Ok(())
     }
 
-    async fn handle_audio(&mut self, data: Bytes, pts: u64) ->Result<()> {
-        if self.latest_pcr_value.is_none() {
-            return Ok(());
+    /// Until the PMT has been seen both kinds of track are expected.
+    fn has_video(&self) -> bool {
+        match &self.tracks {
+            Some(tracks) => tracks.iter().any(|codec| matches!(codec, Codec::H264 | Codec::H265 | Codec::AV1)),
+            None => true,
         }
+    }
 
-        let latest_pcr_value = self.latest_pcr_value.unwrap();
-        let timestamp: u64 = ((pts as i64 - latest_pcr_value as i64 + mpegts::PCR_CYCLE as i64) as u64 % mpegts::PCR_CYCLE as u64) + self.latest_pcr_timestamp_90khz as u64;
+    fn has_audio(&self) -> bool {
+        match &self.tracks {
+            Some(tracks) => tracks.iter().any(Codec::is_audio),
+            None => true,
+        }
+    }
 
-        match self.aac_coder.read_format(aac::AudioDataTransportStream, &data)? {
-            Some(aac) => {
-                for acc_smaple in aac {
-                    if let Some(codec) = acc_smaple.codec {
-                        if self.aac_config.is_none() {
-                            self.aac_config = Some(codec.clone());
-                        }
-                    
+    /// Places a video frame straight after the previous one when the time
+    /// base has changed, either signalled through the PCR or seen as a jump
+    /// in the frame timestamps. The offset is kept for every later frame.
+    fn rebase(&mut self, timestamp: u64) -> u64 {
+        let previous = match &self.pending_video {
+            Some(frame) => frame.timestamp,
+            None => {
+                self.rebase_pending = false;
+                return timestamp;
+            },
+        };
 
-                        let content = Bytes::from(acc_smaple.data);
-                        let duration = 1024 * mpegts::HZ / codec.sampling_frequency_index.to_freq();
+        let jumped = timestamp <= previous || timestamp - previous > MAX_FRAME_GAP;
 
+        if !self.rebase_pending && !jumped {
+            return timestamp;
+        }
 
-                        let traf = Traf::new(
-                            Tfhd::new(2, None, None, Some(duration), None, None),
-                            Some(Tfdt::new(timestamp as u64)),
-                            Some(Trun::new(vec![codec::aac::trun_sample(&content)?], None)),
-                        );
+        let expected = previous + max(self.video_duration, 1);
+        log::debug!("{} rebasing video timestamp {} to {}", self.stream_name, timestamp, expected);
 
-                        let mut moof = Moof::new(Mfhd::new(0), vec![traf]);
-                        let moof_size = moof.size();
-            
-                        let traf = moof
-                            .traf
-                            .get_mut(0)
-                            .expect("we just created the moof with a traf");
-            
-                        let trun = traf
-                            .trun
-                            .as_mut()
-                            .expect("we just created the video traf with a trun");
-            
-                        // So the video offset will be the size of the moof + 8 bytes for the mdat header.
-                        trun.data_offset = Some(moof_size as i32 + 8);
-                        moof.mux(&mut self.aac_writer)?;
+        self.latest_pcr_timestamp_90khz = max(self.latest_pcr_timestamp_90khz as i64 + expected as i64 - timestamp as i64, 0) as u64;
+        self.rebase_pending = false;
+        self.discontinuity_at = Some(expected);
 
-                        Mdat::new(vec![content]).mux(&mut self.aac_writer)?;
-                    }
-                }
+        expected
+    }
+
+    /// Audio-only streams have no video frames to line the new time base up
+    /// with, so the audio following a discontinuity is placed straight after
+    /// what was written before it.
+    fn rebase_audio(&mut self, timestamp: u64) -> u64 {
+        if self.has_video() {
+            return timestamp;
+        }
+
+        let previous = match self.audio_end_timestamp {
+            Some(audio_end_timestamp) => audio_end_timestamp,
+            None => {
+                self.rebase_pending = false;
+                return timestamp;
             },
-            None => todo!(),
         };
 
-        Ok(())
+        if !self.rebase_pending && timestamp.abs_diff(previous) <= MAX_FRAME_GAP {
+            return timestamp;
+        }
+
+        log::debug!("{} rebasing audio timestamp {} to {}", self.stream_name, timestamp, previous);
+
+        self.latest_pcr_timestamp_90khz = max(self.latest_pcr_timestamp_90khz as i64 + previous as i64 - timestamp as i64, 0) as u64;
+        self.rebase_pending = false;
+        self.discontinuity_at = Some(previous);
+
+        previous
     }
 
-    async fn handle_h265_video(&mut self, data: Bytes, pts: u64, dts: Option<u64>) -> Result<()> {
-        if self.latest_pcr_value.is_none() {
+    async fn handle_cue(&mut self, cue: Cue) -> Result<()> {
+        if cue.cancel {
+            self.pending_cues.retain(|(_, _, pending)| pending.id != cue.id);
             return Ok(());
         }
 
-        if self.latest_pcr_datetime.is_none() {
-            return Ok(());
-        }
+        let (latest_pcr_value, latest_pcr_datetime) = match (self.latest_pcr_value, self.latest_pcr_datetime) {
+            (Some(value), Some(datetime)) => (value, datetime),
+            _ => return Ok(()),
+        };
 
-        let dts: u64 = match dts {
-            Some(dts) => dts,
-            None => pts,
+        // splice_immediate cues take effect on the next frame
+        let (timestamp, program_date_time) = match cue.pts {
+            Some(pts) => {
+                let offset = (pts as i64 - latest_pcr_value + mpegts::PCR_CYCLE as i64) as u64 % mpegts::PCR_CYCLE;
+                (offset + self.latest_pcr_timestamp_90khz, latest_pcr_datetime + Duration::seconds_f64(offset as f64 / mpegts::HZ as f64))
+            },
+            None => (self.latest_pcr_timestamp_90khz, latest_pcr_datetime),
         };
 
-        let latest_pcr_value = self.latest_pcr_value.unwrap();
-        let latest_pcr_datetime = self.latest_pcr_datetime.unwrap();
-        let cts: u64 = (pts as u64 - dts + mpegts::PCR_CYCLE as u64) % mpegts::PCR_CYCLE as u64;
+        log::debug!("{} scheduled {:?} cue {} at {}", self.stream_name, cue.kind, cue.id, timestamp);
+        self.pending_cues.push((timestamp, program_date_time, cue));
 
-        let timestamp: u64 = ((dts as i64 - latest_pcr_value as i64 + mpegts::PCR_CYCLE as i64) as u64 % mpegts::PCR_CYCLE as u64) + self.latest_pcr_timestamp_90khz as u64;
+        Ok(())
+    }
 
-        let program_date_time = latest_pcr_datetime + Duration::seconds_f64(((dts as f64 - latest_pcr_value as f64 + mpegts::PCR_CYCLE as f64) % mpegts::PCR_CYCLE as f64) / mpegts::HZ as f64);
- 
-        let mut samples:Vec<Vec<u8>> = Vec::new();
-        let mut keyframe_in_samples = false;
+    /// Cues whose splice point has been reached by a frame starting at `timestamp`.
+    fn take_due_cues(&mut self, timestamp: u64) -> Vec<(OffsetDateTime, Cue)> {
+        let (due, pending): (Vec<_>, Vec<_>) = self.pending_cues
+            .drain(..)
+            .partition(|(splice_timestamp, _, _)| *splice_timestamp <= timestamp);
+
+        self.pending_cues = pending;
+        due.into_iter().map(|(_, program_date_time, cue)| (program_date_time, cue)).collect()
+    }
 
-        match self.h265_coder.read_format(h265::annexb::AnnexB, &data)? {
-            Some(avc) => {
-                let nalus: Vec<h265::nal::Unit> = avc.into();
-                for nalu in nalus {
-                    use h265::nal::NaluType::*;
-                    match &nalu.kind {
-                        NaluTypeSliceIdr => {
-                            keyframe_in_samples = true;
-                            samples.push(nalu.into());
+    async fn publish_cues(&mut self, cues: Vec<(OffsetDateTime, Cue)>) {
+        let mut lock = self.stores.write().await;
+
+        let store = match lock.get_mut(&self.stream_name) {
+            Some(store) => store,
+            None => return,
+        };
+
+        for (program_date_time, cue) in cues {
+            let id = format!("splice-{:X}", cue.id);
+            let duration = cue.duration.map(|duration| duration as f64 / mpegts::HZ as f64);
+
+            let date_range = match cue.kind {
+                CueKind::Out => {
+                    self.open_cues.insert(cue.id, program_date_time);
+
+                    DateRange {
+                        id,
+                        start_date: program_date_time,
+                        end_date: None,
+                        duration: None,
+                        planned_duration: duration,
+                        scte35_out: Some(cue.data),
+                        scte35_in: None,
+                    }
+                },
+                CueKind::In => {
+                    // an IN must repeat the START-DATE of the OUT sharing its ID
+                    match self.open_cues.remove(&cue.id) {
+                        Some(start_date) => DateRange {
+                            id,
+                            start_date,
+                            end_date: Some(program_date_time),
+                            duration: Some((program_date_time - start_date).as_seconds_f64()),
+                            planned_duration: None,
+                            scte35_out: None,
+                            scte35_in: Some(cue.data),
                         },
-                        NaluTypeSliceTrailR => {
-                            samples.push(nalu.into());
+                        None => DateRange {
+                            id,
+                            start_date: program_date_time,
+                            end_date: None,
+                            duration: None,
+                            planned_duration: None,
+                            scte35_out: None,
+                            scte35_in: Some(cue.data),
                         },
-                        _ => continue,
                     }
-                }
-            },
-            None => {},
-        };
+                },
+            };
 
-        if samples.len() == 0 {
-            self.next_h264 = None;
-        } else {
-            self.next_h264 = Some((keyframe_in_samples, samples, timestamp, cts, program_date_time));
+            store.push_date_range(date_range);
         }
+    }
 
-        let mut has_idr = false;
-        let mut begin_timestamp: Option<u64> = None;
-        let mut begin_program_date_time: Option<OffsetDateTime> = None;
-        let mut writer = BytesWriter::default();
+    /// AAC is written in the timescale of its sample rate, 1024 samples per frame.
+    async fn handle_aac_audio(&mut self, data: Bytes, pts: u64) -> Result<()> {
+        let Some((timestamp, program_date_time)) = self.audio_timestamp(pts) else {
+            return Ok(());
+        };
 
-        if let Some((has_key_frame, samples, dts, cts, pdt)) = self.current_h264.clone() {
-            has_idr = has_key_frame;
-            begin_timestamp = Some(dts);
-            begin_program_date_time = Some(pdt);
-            let duration = timestamp - dts;
+        let mut samples = Vec::new();
 
-            //re-package to ebsp
-            let mut content = BytesMut::new();
+        // a PES can carry several ADTS frames, each follows on from the last
+        for aac_sample in self.aac_coder.read_format(aac::AudioDataTransportStream, &data)?.into_iter().flatten() {
+            let Some(codec) = aac_sample.codec else {
+                continue;
+            };
 
-            for sample in samples {
-                content.put_u32(sample.len() as u32);
-                content.extend(sample);
+            if self.aac_config.is_none() {
+                self.aac_config = Some(codec);
             }
-            
-            let content = content.freeze();
 
-            let mut traf = Traf::new(
-                Tfhd::new(1, None, None, Some(duration as u32), None, None),
-                Some(Tfdt::new(dts as u64)),
-                Some(Trun::new(vec![codec::h265::trun_sample(has_idr, cts as u32, duration as u32, &content)?], None)),
-            );
+            let content = Bytes::from(aac_sample.data);
+            samples.push((codec::aac::trun_sample(&content)?, content));
+        }
 
-            traf.optimize();
-            
-            let mut moof = Moof::new(Mfhd::new(0), vec![traf]);
-            let moof_size = moof.size();
+        self.write_audio(timestamp, program_date_time, samples).await
+    }
 
-            let traf = moof
-                .traf
-                .get_mut(0)
-                .expect("we just created the moof with a traf");
+    /// Opus frames are written in a 48 kHz timescale, converted from the
+    /// 90 kHz PES timestamps. Opus durations are all multiples of 2.5 ms so
+    /// they convert exactly.
+    async fn handle_opus_audio(&mut self, data: Bytes, pts: u64, channel_config_code: u8) -> Result<()> {
+        let Some((timestamp, program_date_time)) = self.audio_timestamp(pts) else {
+            return Ok(());
+        };
 
-            let trun = traf
-                .trun
-                .as_mut()
-                .expect("we just created the video traf with a trun");
+        let mut samples = Vec::new();
 
-            // So the video offset will be the size of the moof + 8 bytes for the mdat header.
-            trun.data_offset = Some(moof_size as i32 + 8);
-            moof.mux(&mut writer)?;
+        for access_unit in opus::read_access_units(&data)? {
+            let duration = match opus::packet_duration(&access_unit.data) {
+                Some(duration) => duration,
+                None => continue,
+            };
 
-            Mdat::new(vec![content]).mux(&mut writer)?;
+            match &self.opus_config {
+                None => {
+                    let channel_config = ChannelConfig::from_code(channel_config_code).unwrap_or_else(|| {
+                        log::warn!("{} unsupported Opus channel_config_code {:#04x}, assuming stereo", self.stream_name, channel_config_code);
+                        ChannelConfig::default()
+                    });
+                    self.opus_config = Some((channel_config, access_unit.start_trim));
+                },
+                // fragments can't express trims, only the initial pre-skip makes it into the dOps
+                Some(_) if access_unit.start_trim != 0 || access_unit.end_trim != 0 => {
+                    log::debug!("{} ignoring Opus trim {}/{}", self.stream_name, access_unit.start_trim, access_unit.end_trim);
+                },
+                Some(_) => {},
+            }
+
+            samples.push((codec::opus::trun_sample(duration, &access_unit.data), access_unit.data));
         }
 
-        (self.next_h264, self.current_h264) = (self.current_h264.clone(), self.next_h264.clone());
+        self.write_audio(timestamp, program_date_time, samples).await
+    }
+
+    /// AC-3 and E-AC-3 are written in the timescale of their sample rate.
+    /// An E-AC-3 sample is an independent substream 0 syncframe together with
+    /// every syncframe up to the next one.
+    async fn handle_ac3_audio(&mut self, data: Bytes, pts: u64) -> Result<()> {
+        let Some((timestamp, program_date_time)) = self.audio_timestamp(pts) else {
+            return Ok(());
+        };
 
-        if has_idr && self.aac_config.is_some() && !self.initialization_segment_dispatched {
-            if let Some(idr) = &self.h265_coder.dcr {
-                let video_config = idr.clone();
+        let mut syncframes: Vec<(Vec<SyncFrameHeader>, BytesMut)> = Vec::new();
+        for frame in self.ac3_reader.push(&data)? {
+            let starts_sample = !frame.header.dependent && frame.header.substream_id == 0;
+            if starts_sample || syncframes.is_empty() {
+                syncframes.push((Vec::new(), BytesMut::new()));
+            }
 
-                let width = 1280;
-                let height = 720;
+            if let Some((headers, sample)) = syncframes.last_mut() {
+                headers.push(frame.header);
+                sample.put(frame.data);
+            }
+        }
 
-                let video_entry = codec::h265::stsd_entry(video_config)?;
-                let audio_config = self.aac_config.clone().unwrap();
+        let mut samples = Vec::new();
 
-                let audio_entry = codec::aac::stsd_entry(audio_config)?;
+        for (headers, sample) in syncframes {
+            let duration = headers[0].samples();
 
-                log::trace!("mp4 init segment written");
-    
-                self.write_init_sgment(video_entry, audio_entry, width, height).await?;
+            if self.ac3_config.is_none() {
+                self.ac3_config = Some(headers);
             }
 
-            self.initialization_segment_dispatched = true;
+            let content = sample.freeze();
+            samples.push((codec::ac3::trun_sample(duration, &content), content));
         }
 
-        if begin_timestamp.is_none(){
-            return Ok(())
+        self.write_audio(timestamp, program_date_time, samples).await
+    }
+
+    /// MPEG audio is written in the timescale of its sample rate, a sample per frame.
+    async fn handle_mp3_audio(&mut self, data: Bytes, pts: u64) -> Result<()> {
+        let Some((timestamp, program_date_time)) = self.audio_timestamp(pts) else {
+            return Ok(());
+        };
+
+        let mut samples = Vec::new();
+
+        for frame in self.mp3_reader.push(&data)? {
+            if self.mp3_config.is_none() {
+                self.mp3_config = Some(frame.header.clone());
+            }
+
+            samples.push((codec::mp3::trun_sample(frame.header.samples(), &frame.data), frame.data));
         }
 
-        self.proccess_segments(has_idr, begin_timestamp.unwrap() as u32, begin_program_date_time.unwrap()).await?;
+        self.write_audio(timestamp, program_date_time, samples).await
+    }
 
-        let mut lock = self.stores.write().await;
+    /// Where the audio of a PES with timestamp `pts` lands on the output
+    /// timeline, rebased if needed, and its wall clock time. `None` until the
+    /// PCR has been seen.
+    fn audio_timestamp(&mut self, pts: u64) -> Option<(u64, OffsetDateTime)> {
+        let (latest_pcr_value, latest_pcr_datetime) = match (self.latest_pcr_value, self.latest_pcr_datetime) {
+            (Some(value), Some(datetime)) => (value, datetime),
+            _ => return None,
+        };
 
-        if let Some(store) = lock.get_mut(&self.stream_name) {
-            let aac_bytes = self.aac_writer.extract_current_bytes();
-            store.push(writer.dispose());
-            store.push(aac_bytes.freeze());
+        let offset = (pts as i64 - latest_pcr_value + mpegts::PCR_CYCLE as i64) as u64 % mpegts::PCR_CYCLE;
+        let timestamp = self.rebase_audio(offset + self.latest_pcr_timestamp_90khz);
+        let program_date_time = latest_pcr_datetime + Duration::seconds_f64(offset as f64 / mpegts::HZ as f64);
+
+        Some((timestamp, program_date_time))
+    }
+
+    /// Writes the samples of a PES one after the other from `timestamp`,
+    /// converting between the 90 kHz timeline and the timescale of the audio
+    /// track, which is that of the first config seen.
+    async fn write_audio(&mut self, timestamp: u64, program_date_time: OffsetDateTime, samples: Vec<(TrunSample, Bytes)>) -> Result<()> {
+        // nothing has been written before the first config
+        let Some(timescale) = self.audio_timescale() else {
+            return Ok(());
+        };
+
+        let timescale = timescale as u64;
+        let mut end_timestamp = timestamp;
+
+        for (trun_sample, content) in samples {
+            let duration = trun_sample.duration.unwrap_or_default() as u64;
+            self.write_audio_fragment(end_timestamp * timescale / mpegts::HZ as u64, trun_sample, content)?;
+
+            end_timestamp += duration * mpegts::HZ as u64 / timescale;
         }
 
+        self.audio_end_timestamp = Some(end_timestamp);
+        self.push_audio(timestamp, program_date_time).await
+    }
+
+    /// Writes a single audio sample as its own moof/mdat on track 2.
+    fn write_audio_fragment(&mut self, base_media_decode_time: u64, trun_sample: TrunSample, content: Bytes) -> Result<()> {
+        let traf = Traf::new(
+            Tfhd::new(2, None, None, trun_sample.duration, None, None),
+            Some(Tfdt::new(base_media_decode_time)),
+            Some(Trun::new(vec![trun_sample], None)),
+        );
+
+        let mut moof = Moof::new(Mfhd::new(0), vec![traf]);
+        let moof_size = moof.size();
+
+        let trun = moof.traf[0]
+            .trun
+            .as_mut()
+            .expect("we just created the audio traf with a trun");
+
+        // the samples start straight after the moof and the 8 byte mdat header
+        trun.data_offset = Some(moof_size as i32 + 8);
+        moof.mux(&mut self.audio_writer)?;
+
+        Mdat::new(vec![content]).mux(&mut self.audio_writer)?;
         Ok(())
     }
 
-    async fn handle_h264_video(&mut self, data: Bytes, pts: u64, dts: Option<u64>) -> Result<()> {
-        if self.latest_pcr_value.is_none() {
+    /// Audio-only streams are segmented on the audio frames, otherwise the
+    /// buffered fragments wait to be written out alongside the next video frame.
+    async fn push_audio(&mut self, timestamp: u64, program_date_time: OffsetDateTime) -> Result<()> {
+        if self.has_video() {
             return Ok(());
         }
 
-        if self.latest_pcr_datetime.is_none() {
-            return Ok(());
+        if !self.initialization_segment_dispatched {
+            if let Some(audio) = self.audio_entry()? {
+                self.write_init_sgment(None, Some(audio)).await?;
+                self.initialization_segment_dispatched = true;
+            }
         }
 
-        let dts: u64 = match dts {
-            Some(dts) => dts,
-            None => pts,
+        // every audio frame is a sync sample, so cut on the duration target
+        let segment_duration = (self.segment_duration * mpegts::HZ as f32) as u64;
+        let cues = self.take_due_cues(timestamp);
+        let cut_segment = match self.segment_begin_timestamp {
+            // a new time base starts its own segment
+            Some(segment_begin_timestamp) => timestamp >= segment_begin_timestamp + segment_duration || !cues.is_empty() || self.discontinuity_at.is_some(),
+            None => true,
         };
 
-        let latest_pcr_value = self.latest_pcr_value.unwrap();
-        let latest_pcr_datetime = self.latest_pcr_datetime.unwrap();
-        let cts: u64 = (pts as u64 - dts + mpegts::PCR_CYCLE as u64) % mpegts::PCR_CYCLE as u64;
+        if cut_segment {
+            self.segment_begin_timestamp = Some(timestamp);
+        }
+
+        self.proccess_segments(cut_segment, timestamp as u32, program_date_time).await?;
+
+        if !cues.is_empty() {
+            self.publish_cues(cues).await;
+        }
+
+        let mut lock = self.stores.write().await;
+
+        if let Some(store) = lock.get_mut(&self.stream_name) {
+            let audio_bytes = self.audio_writer.extract_current_bytes();
+            store.push(audio_bytes.freeze());
+        }
+
+        Ok(())
+    }
 
-        let timestamp: u64 = ((dts as i64 - latest_pcr_value as i64 + mpegts::PCR_CYCLE as i64) as u64 % mpegts::PCR_CYCLE as u64) + self.latest_pcr_timestamp_90khz as u64;
+    async fn handle_h264_video(&mut self, data: Bytes, pts: u64, dts: Option<u64>) -> Result<()> {
+        let dts = dts.unwrap_or(pts);
+        let Some((timestamp, program_date_time)) = self.video_timestamp(dts) else {
+            return Ok(());
+        };
 
-        let program_date_time = latest_pcr_datetime + Duration::seconds_f64(((dts as f64 - latest_pcr_value as f64 + mpegts::PCR_CYCLE as f64) % mpegts::PCR_CYCLE as f64) / mpegts::HZ as f64);
- 
-        let mut samples:Vec<Vec<u8>> = Vec::new();
+        let mut samples: Vec<Vec<u8>> = Vec::new();
         let mut keyframe_in_samples = false;
 
-        match self.h264_coder.read_format(h264::AnnexB, &data)? {
-            Some(avc) => {
-                let nalus: Vec<h264::nal::Unit> = avc.into();
-                for nalu in nalus {
-                    use h264::nal::UnitType::*;
-                    match &nalu.kind {
-                        IdrPicture => {
-                            keyframe_in_samples = true;
-                            samples.push(nalu.into());
-                        },
-                        NonIdrPicture => {
-                            samples.push(nalu.into());
-                        },
-                        _ => continue,
-                    }
+        if let Some(avc) = self.h264_coder.read_format(h264::AnnexB, &data)? {
+            let nalus: Vec<h264::nal::Unit> = avc.into();
+            for nalu in nalus {
+                use h264::nal::UnitType::*;
+                match &nalu.kind {
+                    IdrPicture => {
+                        keyframe_in_samples = true;
+                        samples.push(nalu.into());
+                    },
+                    NonIdrPicture => {
+                        samples.push(nalu.into());
+                    },
+                    _ => continue,
                 }
-            },
-            None => {},
+            }
+        }
+
+        let frame = VideoFrame::from_nal_units(keyframe_in_samples, samples, timestamp, composition_offset(pts, dts), program_date_time);
+        self.write_video(Codec::H264, frame, timestamp).await
+    }
+
+    async fn handle_h265_video(&mut self, data: Bytes, pts: u64, dts: Option<u64>) -> Result<()> {
+        let dts = dts.unwrap_or(pts);
+        let Some((timestamp, program_date_time)) = self.video_timestamp(dts) else {
+            return Ok(());
         };
 
-        if samples.len() == 0 {
-            self.next_h264 = None;
-        } else {
-            self.next_h264 = Some((keyframe_in_samples, samples, timestamp, cts, program_date_time));
+        let mut samples: Vec<Vec<u8>> = Vec::new();
+        let mut keyframe_in_samples = false;
+
+        if let Some(avc) = self.h265_coder.read_format(h265::annexb::AnnexB, &data)? {
+            // the hvcC in the init segment no longer describes the stream
+            if self.hvc1 && self.init_parameter_sets.as_ref().is_some_and(|sets| *sets != self.hevc_parameter_sets()) {
+                log::warn!("{} HEVC parameter sets changed, falling back to hev1", self.stream_name);
+                self.hvc1 = false;
+                self.parameter_sets_changed_at = Some(timestamp);
+            }
+
+            let nalus: Vec<h265::nal::Unit> = avc.into();
+            for nalu in nalus {
+                use h265::nal::NaluType::*;
+                match &nalu.kind {
+                    NaluTypeSliceIdr => {
+                        // hev1 repeats the parameter sets in band ahead of each key frame
+                        if !keyframe_in_samples && !self.hvc1 {
+                            samples.extend(self.hevc_parameter_sets().into_iter().map(Vec::from));
+                        }
+                        keyframe_in_samples = true;
+                        samples.push(nalu.into());
+                    },
+                    NaluTypeSliceTrailR => {
+                        samples.push(nalu.into());
+                    },
+                    _ => continue,
+                }
+            }
         }
 
-        let mut has_idr = false;
-        let mut begin_timestamp: Option<u64> = None;
-        let mut begin_program_date_time: Option<OffsetDateTime> = None;
-        let mut writer = BytesWriter::default();
+        let frame = VideoFrame::from_nal_units(keyframe_in_samples, samples, timestamp, composition_offset(pts, dts), program_date_time);
+        self.write_video(Codec::H265, frame, timestamp).await
+    }
 
-        if let Some((has_key_frame, samples, dts, cts, pdt)) = self.current_h264.clone() {
-            has_idr = has_key_frame;
-            begin_timestamp = Some(dts);
-            begin_program_date_time = Some(pdt);
-            let duration = timestamp - dts;
+    async fn handle_av1_video(&mut self, data: Bytes, pts: u64) -> Result<()> {
+        // AV1 has no frame reordering in the stream, dts is always pts
+        let Some((timestamp, program_date_time)) = self.video_timestamp(pts) else {
+            return Ok(());
+        };
 
-            //re-package to ebsp
-            let mut content = BytesMut::new();
+        let temporal_unit = TemporalUnit::from_start_codes(&data)?;
 
-            for sample in samples {
-                content.put_u32(sample.len() as u32);
-                content.extend(sample);
-            }
-            
-            let content = content.freeze();
+        if let Some(sequence_header) = temporal_unit.sequence_header {
+            self.av1_config = Some(AV1CodecConfigurationRecord::from_sequence_header(sequence_header)?);
+        }
 
-            let mut traf = Traf::new(
-                Tfhd::new(1, None, None, Some(duration as u32), None, None),
-                Some(Tfdt::new(dts as u64)),
-                Some(Trun::new(vec![codec::h264::trun_sample(has_idr, cts as u32, duration as u32, &content)?], None)),
-            );
+        // OBUs already carry their own sizes
+        let frame = (!temporal_unit.data.is_empty()).then(|| VideoFrame {
+            key_frame: temporal_unit.key_frame,
+            data: temporal_unit.data,
+            timestamp,
+            composition_offset: 0,
+            program_date_time,
+        });
 
-            traf.optimize();
-            
-            let mut moof = Moof::new(Mfhd::new(0), vec![traf]);
-            let moof_size = moof.size();
+        self.write_video(Codec::AV1, frame, timestamp).await
+    }
 
-            let traf = moof
-                .traf
-                .get_mut(0)
-                .expect("we just created the moof with a traf");
+    /// Where a video frame with decode timestamp `dts` lands on the output
+    /// timeline, rebased if needed, and its wall clock time. `None` until the
+    /// PCR has been seen.
+    fn video_timestamp(&mut self, dts: u64) -> Option<(u64, OffsetDateTime)> {
+        let (latest_pcr_value, latest_pcr_datetime) = match (self.latest_pcr_value, self.latest_pcr_datetime) {
+            (Some(value), Some(datetime)) => (value, datetime),
+            _ => return None,
+        };
 
-            let trun = traf
-                .trun
-                .as_mut()
-                .expect("we just created the video traf with a trun");
+        let offset = (dts as i64 - latest_pcr_value + mpegts::PCR_CYCLE as i64) as u64 % mpegts::PCR_CYCLE;
+        let timestamp = self.rebase(offset + self.latest_pcr_timestamp_90khz);
+        let program_date_time = latest_pcr_datetime + Duration::seconds_f64(offset as f64 / mpegts::HZ as f64);
 
-            // So the video offset will be the size of the moof + 8 bytes for the mdat header.
-            trun.data_offset = Some(moof_size as i32 + 8);
-            moof.mux(&mut writer)?;
+        Some((timestamp, program_date_time))
+    }
 
-            Mdat::new(vec![content]).mux(&mut writer)?;
-        }
+    /// Writes out the frame held back by the previous call, now that a frame
+    /// at `timestamp` gives it its duration, and holds `frame` back instead.
+    async fn write_video(&mut self, codec: Codec, frame: Option<VideoFrame>, timestamp: u64) -> Result<()> {
+        let Some(previous) = std::mem::replace(&mut self.pending_video, frame) else {
+            return Ok(());
+        };
 
-        (self.next_h264, self.current_h264) = (self.current_h264.clone(), self.next_h264.clone());
+        let duration = timestamp - previous.timestamp;
+        self.video_duration = duration;
 
-        if has_idr && self.aac_config.is_some() && !self.initialization_segment_dispatched {
-            if let Some(idr) = &self.h264_coder.dcr {
-                let video_config = idr.clone();
+        let trun_sample = match codec {
+            Codec::H264 => codec::h264::trun_sample(previous.key_frame, previous.composition_offset as u32, duration as u32, &previous.data)?,
+            Codec::H265 => codec::h265::trun_sample(previous.key_frame, previous.composition_offset as u32, duration as u32, &previous.data)?,
+            Codec::AV1 => codec::av1::trun_sample(previous.key_frame, duration as u32, &previous.data)?,
+            _ => bail!("{:?} is not a video codec", codec),
+        };
+
+        let mut traf = Traf::new(
+            Tfhd::new(1, None, None, Some(duration as u32), None, None),
+            Some(Tfdt::new(previous.timestamp)),
+            Some(Trun::new(vec![trun_sample], None)),
+        );
+
+        traf.optimize();
 
-                let width = video_config.width;
-                let height = video_config.height;
+        let mut moof = Moof::new(Mfhd::new(0), vec![traf]);
+        let moof_size = moof.size();
 
-                let video_entry = codec::h264::stsd_entry(video_config)?;
+        let trun = moof.traf[0]
+            .trun
+            .as_mut()
+            .expect("we just created the video traf with a trun");
 
-                let audio_config = self.aac_config.clone().unwrap();
+        // So the video offset will be the size of the moof + 8 bytes for the mdat header.
+        trun.data_offset = Some(moof_size as i32 + 8);
+
+        let mut writer = BytesWriter::default();
+        moof.mux(&mut writer)?;
+        Mdat::new(vec![previous.data]).mux(&mut writer)?;
+
+        // switching the sample entry to hev1 needs a new init segment from
+        // the first key frame carrying the new parameter sets
+        if let Some(changed_at) = self.parameter_sets_changed_at {
+            if previous.key_frame && previous.timestamp >= changed_at {
+                self.initialization_segment_dispatched = false;
+                self.discontinuity_at = Some(changed_at);
+                self.parameter_sets_changed_at = None;
+            }
+        }
 
-                let audio_entry = codec::aac::stsd_entry(audio_config)?;
+        if previous.key_frame && self.audio_ready() && !self.initialization_segment_dispatched {
+            if let Some(video) = self.video_entry(&codec)? {
+                let audio_entry = self.audio_entry()?;
 
                 log::trace!("mp4 init segment written");
-    
-                self.write_init_sgment(video_entry, audio_entry, width, height).await?;
+
+                self.write_init_sgment(Some(video), audio_entry).await?;
             }
 
             self.initialization_segment_dispatched = true;
         }
 
-        if begin_timestamp.is_none(){
-            return Ok(())
-        }
+        // splice points always start a new segment, the encoder is expected
+        // to have placed a key frame there
+        let cues = self.take_due_cues(previous.timestamp);
+        let cut_segment = previous.key_frame || !cues.is_empty();
 
-        self.proccess_segments(has_idr, begin_timestamp.unwrap() as u32, begin_program_date_time.unwrap()).await?;
+        self.proccess_segments(cut_segment, previous.timestamp as u32, previous.program_date_time).await?;
+
+        if !cues.is_empty() {
+            self.publish_cues(cues).await;
+        }
 
         let mut lock = self.stores.write().await;
 
         if let Some(store) = lock.get_mut(&self.stream_name) {
-            let aac_bytes = self.aac_writer.extract_current_bytes();
+            let audio_bytes = self.audio_writer.extract_current_bytes();
             store.push(writer.dispose());
-            store.push(aac_bytes.freeze());
+            store.push(audio_bytes.freeze());
         }
 
         Ok(())
     }
 
+    /// The video sample entry with the track size, once the codec config is known.
+    fn video_entry(&mut self, codec: &Codec) -> Result<Option<(DynBox, u32, u32)>> {
+        match codec {
+            Codec::H264 => {
+                let Some(video_config) = self.h264_coder.dcr.clone() else { return Ok(None) };
+                let (width, height) = (video_config.width, video_config.height);
+
+                Ok(Some((codec::h264::stsd_entry(video_config)?, width, height)))
+            },
+            Codec::H265 => {
+                let Some(video_config) = self.h265_coder.dcr.clone() else { return Ok(None) };
+                let (width, height) = codec::h265::display_size(&video_config);
+
+                let video_entry = if self.hvc1 {
+                    codec::h265::hvc1_stsd_entry(video_config)?
+                } else {
+                    codec::h265::stsd_entry(video_config)?
+                };
+                self.init_parameter_sets = Some(self.hevc_parameter_sets());
+
+                Ok(Some((video_entry, width, height)))
+            },
+            Codec::AV1 => {
+                let Some(video_config) = self.av1_config.clone() else { return Ok(None) };
+                let (width, height) = codec::av1::frame_size(&video_config)?;
+
+                Ok(Some((codec::av1::stsd_entry(video_config)?, width, height)))
+            },
+            _ => Ok(None),
+        }
+    }
+
     async fn proccess_segments(&mut self, has_keyframe: bool, begin_timestamp: u32, program_date_time: OffsetDateTime) -> Result<()> {
+        // a key frame closes the partial once it is over its target, anything else once it reaches it
+        let next_partial = self.next_partial(begin_timestamp, has_keyframe);
         let mut lock = self.stores.write().await;
 
         if let Some(store) = lock.get_mut(&self.stream_name) {
+            if let Some(partial_begin_timestamp) = next_partial {
+                self.partial_begin_timestamp = Some(partial_begin_timestamp);
+                store.continuous_partial(partial_begin_timestamp, false)?;
+            }
+
             if has_keyframe {
-                if self.partial_begin_timestamp.is_some() {
-                    let part_diff = begin_timestamp - self.partial_begin_timestamp.unwrap();
-    
-                    if ((self.part_duration * mpegts::HZ as f32).floor() as u32) < part_diff {
-                        let part_duration = (self.part_duration as f32 * mpegts::HZ as f32).floor() as u32;
-                        let partial_begin_timestamp = begin_timestamp - max(0, part_diff - part_duration);
-                        self.partial_begin_timestamp = Some(partial_begin_timestamp);
-                        store.continuous_partial(partial_begin_timestamp, false)?;
-                    }
-                }
-                
                 self.partial_begin_timestamp = Some(begin_timestamp);
                 store.continuous_segment(begin_timestamp, true, program_date_time)?;
-            } else if self.partial_begin_timestamp.is_some() {
-                let part_diff = begin_timestamp - self.partial_begin_timestamp.unwrap();
-                if (self.part_duration * mpegts::HZ as f32).floor() as u32 <= part_diff {
-                    let part_duration = (self.part_duration as f32 * mpegts::HZ as f32).floor() as u32;
-                    let partial_begin_timestamp = begin_timestamp - max(0, part_diff - part_duration);
-
-                    self.partial_begin_timestamp = Some(partial_begin_timestamp);
-                    store.continuous_partial(partial_begin_timestamp, false)?;
+
+                if let Some(discontinuity_at) = self.discontinuity_at {
+                    if begin_timestamp >= discontinuity_at as u32 {
+                        store.mark_discontinuity();
+                        self.discontinuity_at = None;
+                    }
                 }
             }
         }
         Ok(())
     }
 
-    async fn write_init_sgment(&mut self, video_entry :DynBox, audio_entry :DynBox, width: u32, height: u32 ) -> Result<()> {
+    /// Start of the next partial when the current one has run its duration
+    /// by `timestamp`. Timestamps that went backwards restart the partial
+    /// timing from there instead.
+    fn next_partial(&mut self, timestamp: u32, key_frame: bool) -> Option<u32> {
+        let partial_begin_timestamp = self.partial_begin_timestamp?;
+        let part_duration = (self.part_duration * mpegts::HZ as f32).floor() as u32;
+
+        let Some(part_diff) = timestamp.checked_sub(partial_begin_timestamp) else {
+            log::debug!("{} timestamp {} before partial start {}", self.stream_name, timestamp, partial_begin_timestamp);
+            self.partial_begin_timestamp = Some(timestamp);
+            return None;
+        };
+
+        let due = if key_frame { part_diff > part_duration } else { part_diff >= part_duration };
+        if !due {
+            return None;
+        }
+
+        partial_begin_timestamp.checked_add(part_duration)
+    }
+
+    /// VPS, SPS and PPS last seen by the HEVC coder.
+    fn hevc_parameter_sets(&self) -> Vec<h265::nal::Unit> {
+        match &self.h265_coder.dcr {
+            Some(dcr) => dcr.vps.iter().chain(&dcr.sps).chain(&dcr.pps).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The init segment can be written once every announced audio track has its config.
+    fn audio_ready(&self) -> bool {
+        !self.has_audio() || self.aac_config.is_some() || self.opus_config.is_some() || self.ac3_config.is_some() || self.mp3_config.is_some()
+    }
+
+    /// The audio sample entry together with the timescale of its track.
+    fn audio_entry(&self) -> Result<Option<(DynBox, u32)>> {
+        if !self.has_audio() {
+            return Ok(None);
+        }
+
+        let Some(timescale) = self.audio_timescale() else {
+            return Ok(None);
+        };
+
+        if let Some(audio_config) = &self.aac_config {
+            return Ok(Some((codec::aac::stsd_entry(audio_config.clone())?, timescale)));
+        }
+
+        if let Some((channel_config, pre_skip)) = &self.opus_config {
+            return Ok(Some((codec::opus::stsd_entry(channel_config, *pre_skip), timescale)));
+        }
+
+        if let Some(headers) = &self.ac3_config {
+            return Ok(codec::ac3::stsd_entry(headers).map(|entry| (entry, timescale)));
+        }
+
+        if let Some(header) = &self.mp3_config {
+            return Ok(Some((codec::mp3::stsd_entry(header), timescale)));
+        }
+
+        Ok(None)
+    }
+
+    /// Every audio codec is written in the timescale of its sample rate.
+    fn audio_timescale(&self) -> Option<u32> {
+        if let Some(audio_config) = &self.aac_config {
+            return Some(audio_config.sampling_frequency_index.to_freq());
+        }
+
+        if self.opus_config.is_some() {
+            return Some(opus::SAMPLE_RATE);
+        }
+
+        if let Some(headers) = &self.ac3_config {
+            return Some(headers[0].sample_rate);
+        }
+
+        self.mp3_config.as_ref().map(|header| header.sample_rate)
+    }
+
+    /// Video is always track 1 and audio track 2, whichever of them are present.
+    async fn write_init_sgment(&mut self, video: Option<(DynBox, u32, u32)>, audio: Option<(DynBox, u32)>) -> Result<()> {
         let mut writer: BytesWriter = BytesWriter::default();
         let compatiable_brands = vec![FourCC::Isom, FourCC::Avc1, FourCC::Mp41];
 
-        Ftyp::new(FourCC::Isom, 1, compatiable_brands.clone()).mux(&mut writer)?;
-        Moov::new(
-            Mvhd::new(0, 0, mpegts::HZ as u32, 0, 1),
-            vec![
-                Trak::new(
-                    Tkhd::new(0, 0, 1, 0, Some((width, height))),
-                    None,
-                    Mdia::new(
-                        Mdhd::new(0, 0, mpegts::HZ as u32, 0),
-                        Hdlr::new(HandlerType::Vide, "VideoHandler".to_string()),
-                        Minf::new(
-                            Stbl::new(
-                                Stsd::new(vec![video_entry]),
-                                Stts::new(vec![]),
-                                Stsc::new(vec![]),
-                                Stco::new(vec![]),
-                                Some(Stsz::new(0, vec![])),
-                            ),
-                            Some(Vmhd::new()),
-                            None,
+        let mut traks = Vec::new();
+        let mut trexs = Vec::new();
+
+        let codecs: Vec<String> = video.iter().map(|(entry, ..)| entry)
+            .chain(audio.iter().map(|(entry, _)| entry))
+            .filter_map(codec::codecs_parameter)
+            .collect();
+        let resolution = video.as_ref().map(|(_, width, height)| (*width, *height));
+
+        if let Some((video_entry, width, height)) = video {
+            traks.push(Trak::new(
+                Tkhd::new(0, 0, 1, 0, Some((width, height))),
+                None,
+                Mdia::new(
+                    Mdhd::new(0, 0, mpegts::HZ as u32, 0),
+                    Hdlr::new(HandlerType::Vide, "VideoHandler".to_string()),
+                    Minf::new(
+                        Stbl::new(
+                            Stsd::new(vec![video_entry]),
+                            Stts::new(vec![]),
+                            Stsc::new(vec![]),
+                            Stco::new(vec![]),
+                            Some(Stsz::new(0, vec![])),
                         ),
+                        Some(Vmhd::new()),
+                        None,
                     ),
                 ),
-                Trak::new(
-                    Tkhd::new(0, 0, 2, 0, None),
-                    None,
-                    Mdia::new(
-                        Mdhd::new(0, 0, mpegts::HZ as u32, 0),
-                        Hdlr::new(HandlerType::Soun, "SoundHandler".to_string()),
-                        Minf::new(
-                            Stbl::new(
-                                Stsd::new(vec![audio_entry]),
-                                Stts::new(vec![]),
-                                Stsc::new(vec![]),
-                                Stco::new(vec![]),
-                                Some(Stsz::new(0, vec![])),
-                            ),
-                            None,
-                            Some(Smhd::new()),
+            ));
+            trexs.push(Trex::new(1));
+        }
+
+        if let Some((audio_entry, timescale)) = audio {
+            traks.push(Trak::new(
+                Tkhd::new(0, 0, 2, 0, None),
+                None,
+                Mdia::new(
+                    Mdhd::new(0, 0, timescale, 0),
+                    Hdlr::new(HandlerType::Soun, "SoundHandler".to_string()),
+                    Minf::new(
+                        Stbl::new(
+                            Stsd::new(vec![audio_entry]),
+                            Stts::new(vec![]),
+                            Stsc::new(vec![]),
+                            Stco::new(vec![]),
+                            Some(Stsz::new(0, vec![])),
                         ),
+                        None,
+                        Some(Smhd::new()),
                     ),
                 ),
-            ],
-            Some(Mvex::new(vec![Trex::new(1), Trex::new(2)], None)),
+            ));
+            trexs.push(Trex::new(2));
+        }
+
+        Ftyp::new(FourCC::Isom, 1, compatiable_brands.clone()).mux(&mut writer)?;
+        Moov::new(
+            Mvhd::new(0, 0, mpegts::HZ as u32, 0, 3),
+            traks,
+            Some(Mvex::new(trexs, None)),
         )
         .mux(&mut writer)?;
 
         let mut lock = self.stores.write().await;
         if let Some(store) = lock.get_mut(&self.stream_name) {
             store.set_init_segment(writer.dispose())?;
+            store.set_stream_info(codecs, resolution);
         }
 
         Ok(())
@@ -558,6 +1111,10 @@ impl Service {
         while let Some((stream_name, watcher)) = trigger_handle.recv().await {
             let mut lock = stores.write().await;
             match lock.get_mut(&stream_name) {
+                Some(store) if !store.is_live() => {
+                    log::info!("replacing finished stream store {}", stream_name);
+                    lock.insert(stream_name.clone(), SegmentStore::new(&self.opt));
+                }
                 Some(_) => {
                     log::warn!("duplicate stream store {}", stream_name);
                 }
@@ -576,4 +1133,195 @@ impl Service {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+    use bytes::Bytes;
+    use clap::Parser;
+    use tokio::sync::{broadcast, RwLock};
+    use crate::{hls::{segment_store::SegmentStore, SegmentStores}, session::Codec, Opt};
+    use super::Mp4fWriter;
+
+    /// MPEG-1 Layer III, 128 kbit/s, 44.1 kHz, 1152 samples
+    const MP3_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x40];
+    const MP3_FRAME_SIZE: usize = 417;
+    const MP3_FRAME_DURATION: u64 = 1152 * mpegts::HZ as u64 / 44100;
+
+    fn writer(tracks: Vec<Codec>) -> (Mp4fWriter, SegmentStores) {
+        let opt = Opt::parse_from(["stream_kit", "--part-duration", "1", "--segment-duration", "4"]);
+        let stores: SegmentStores = Arc::new(RwLock::new(HashMap::new()));
+        stores.try_write().unwrap().insert("test".to_owned(), SegmentStore::new(&opt));
+
+        let (_, watcher) = broadcast::channel(1);
+        let mut writer = Mp4fWriter::new(&opt, "test".to_owned(), watcher, Arc::clone(&stores));
+        writer.tracks = Some(tracks);
+
+        (writer, stores)
+    }
+
+    /// Writes `seconds` of MP3 from `pts` on, a frame per PES.
+    async fn write_mp3(writer: &mut Mp4fWriter, pts: u64, seconds: u64) -> u64 {
+        let mut frame = MP3_HEADER.to_vec();
+        frame.resize(MP3_FRAME_SIZE, 0x55);
+
+        let mut pts = pts;
+        for _ in 0..seconds * mpegts::HZ as u64 / MP3_FRAME_DURATION {
+            writer.handle_mp3_audio(Bytes::from(frame.clone()), pts).await.unwrap();
+            pts += MP3_FRAME_DURATION;
+        }
+
+        pts
+    }
+
+    #[tokio::test]
+    async fn marks_discontinuity_in_audio_only_stream() {
+        let (mut writer, stores) = writer(vec![Codec::MP3]);
+
+        writer.handle_pcr(900_000).await.unwrap();
+        write_mp3(&mut writer, 900_000, 9).await;
+
+        // the source restarts with a clock 5 seconds behind
+        writer.pcr_discontinuity = true;
+        writer.handle_pcr(900_000 + 4 * mpegts::HZ as u64).await.unwrap();
+        write_mp3(&mut writer, 900_000 + 4 * mpegts::HZ as u64, 9).await;
+
+        let manifest = stores.read().await["test"].get_manifest_text().await.unwrap();
+        assert_eq!(manifest.matches("#EXT-X-DISCONTINUITY\n").count(), 1);
+
+        let durations: Vec<f64> = manifest
+            .lines()
+            .filter_map(|line| line.strip_prefix("#EXTINF:"))
+            .map(|duration| duration.parse().unwrap())
+            .collect();
+
+        // segments are cut at the discontinuity and none run backwards or over
+        assert!(durations.len() >= 4);
+        assert!(durations.iter().all(|duration| *duration > 0.0 && *duration < 4.1), "{:?}", durations);
+    }
+
+    /// 2560x1440 main profile SPS with VUI.
+    const SPS: &[u8] = b"B\x01\x01\x01@\0\0\x03\0\x90\0\0\x03\0\0\x03\0\x99\xa0\x01@ \x05\xa1e\x95R\x90\x84d_\xf8\xc0Z\x80\x80\x80\x82\0\0\x03\0\x02\0\0\x03\x01 \xc0\x0b\xbc\xa2\0\x02bX\0\x011-\x08";
+    const VPS: &[u8] = &[0x40, 0x01, 0x0C, 0x01, 0xFF, 0xFF, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x99, 0x95, 0x98, 0x09];
+    const FRAME_DURATION: u64 = 3000;
+
+    /// An HEVC access unit in Annex B, a key frame is preceded by the
+    /// parameter sets with `pps_id` as the last byte of the PPS.
+    fn hevc_access_unit(key_frame: bool, pps_id: u8) -> Bytes {
+        let pps = [0x44, 0x01, 0xC1, 0x72, 0xB4, 0x62, 0x40, 0xFF, 0xFF, pps_id];
+        let slice: &[u8] = if key_frame { &[0x26, 0x01, 0xAF, 0x11, 0x22] } else { &[0x02, 0x01, 0xD0, 0x33, 0x44] };
+
+        let nalus: Vec<&[u8]> = if key_frame { vec![VPS, SPS, &pps, slice] } else { vec![slice] };
+        let mut data = Vec::new();
+        for nalu in nalus {
+            data.extend_from_slice(&[0x00, 0x00, 0x00, 0x01]);
+            data.extend_from_slice(nalu);
+        }
+
+        Bytes::from(data)
+    }
+
+    /// Writes `seconds` of video from `pts` on, starting with a key frame.
+    async fn write_hevc(writer: &mut Mp4fWriter, pts: u64, seconds: u64, pps_id: u8) -> u64 {
+        let mut pts = pts;
+        for frame in 0..seconds * mpegts::HZ as u64 / FRAME_DURATION {
+            writer.handle_h265_video(hevc_access_unit(frame == 0, pps_id), pts, None).await.unwrap();
+            pts += FRAME_DURATION;
+        }
+
+        pts
+    }
+
+    async fn segment_data(stores: &SegmentStores, msn: usize) -> Bytes {
+        let mut lock = stores.write().await;
+        let mut receiver = lock.get_mut("test").unwrap().segment(msn).await.unwrap();
+        receiver.recv().await.flatten().unwrap()
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[tokio::test]
+    async fn hvc1_strips_parameter_sets_from_samples() {
+        let (mut writer, stores) = writer(vec![Codec::H265]);
+        writer.hvc1 = true;
+
+        writer.handle_pcr(900_000).await.unwrap();
+        let pts = write_hevc(&mut writer, 900_000, 2, 1).await;
+        write_hevc(&mut writer, pts, 1, 1).await;
+
+        assert!(writer.hvc1);
+
+        let lock = stores.read().await;
+        let init_segment = lock["test"].init_segment(0).unwrap();
+        assert!(contains(&init_segment, b"hvc1"));
+        assert!(contains(&init_segment, SPS));
+        assert!(lock["test"].init_segment(1).is_none());
+        drop(lock);
+
+        let segment = segment_data(&stores, 0).await;
+        assert!(contains(&segment, &[0x26, 0x01, 0xAF, 0x11, 0x22]));
+        assert!(!contains(&segment, SPS));
+        assert!(!contains(&segment, VPS));
+    }
+
+    #[tokio::test]
+    async fn hev1_repeats_parameter_sets_in_samples() {
+        let (mut writer, stores) = writer(vec![Codec::H265]);
+
+        writer.handle_pcr(900_000).await.unwrap();
+        let pts = write_hevc(&mut writer, 900_000, 2, 1).await;
+        write_hevc(&mut writer, pts, 1, 1).await;
+
+        let init_segment = stores.read().await["test"].init_segment(0).unwrap();
+        assert!(contains(&init_segment, b"hev1"));
+
+        let segment = segment_data(&stores, 0).await;
+        assert!(contains(&segment, SPS));
+        assert!(contains(&segment, VPS));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_hev1_under_new_map_when_parameter_sets_change() {
+        let (mut writer, stores) = writer(vec![Codec::H265]);
+        writer.hvc1 = true;
+
+        writer.handle_pcr(900_000).await.unwrap();
+        let pts = write_hevc(&mut writer, 900_000, 2, 1).await;
+        let pts = write_hevc(&mut writer, pts, 2, 2).await;
+        write_hevc(&mut writer, pts, 1, 2).await;
+
+        assert!(!writer.hvc1);
+
+        let lock = stores.read().await;
+        let store = &lock["test"];
+        assert!(contains(&store.init_segment(0).unwrap(), b"hvc1"));
+        assert!(contains(&store.init_segment(1).unwrap(), b"hev1"));
+        assert_eq!(store.init_segment_ready(), store.init_segment(1));
+
+        let manifest = store.get_manifest_text().await.unwrap();
+        assert_eq!(manifest.matches("#EXT-X-MAP:").count(), 2, "{}", manifest);
+        assert!(manifest.contains("#EXT-X-MAP:URI=\"init.mp4?map=0\""));
+        assert!(manifest.contains("#EXT-X-DISCONTINUITY\n#EXT-X-MAP:URI=\"init.mp4?map=1\""), "{}", manifest);
+        drop(lock);
+
+        // the hvc1 segment keeps its samples as they were, the hev1 one carries the new parameter sets
+        assert!(!contains(&segment_data(&stores, 0).await, SPS));
+        assert!(contains(&segment_data(&stores, 1).await, &[0xFF, 0xFF, 2]));
+    }
+
+    #[test]
+    fn restarts_partials_when_timestamps_go_back() {
+        let (mut writer, _) = writer(vec![Codec::MP3]);
+        writer.partial_begin_timestamp = Some(2 * mpegts::HZ);
+
+        assert_eq!(writer.next_partial(mpegts::HZ, false), None);
+        assert_eq!(writer.partial_begin_timestamp, Some(mpegts::HZ));
+
+        assert_eq!(writer.next_partial(mpegts::HZ + 100, false), None);
+        assert_eq!(writer.next_partial(2 * mpegts::HZ, false), Some(2 * mpegts::HZ));
+        assert_eq!(writer.next_partial(2 * mpegts::HZ, true), None);
+        assert_eq!(writer.next_partial(2 * mpegts::HZ + 1, true), Some(2 * mpegts::HZ));
+    }
+}
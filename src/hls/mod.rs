@@ -1,28 +1,74 @@
 use anyhow::Result;
-use std::{sync::Arc, collections::HashMap};
+use std::{sync::Arc, collections::HashMap, net::SocketAddr};
+use mpegts::demuxer::ProgramSelection;
 use tokio::sync::RwLock;
-use crate::routes;
+use crate::{routes, session::ManagerHandle};
 use self::segment_store::SegmentStore;
 
 pub mod segment_store;
 pub type SegmentStores = Arc<RwLock<HashMap<String, SegmentStore>>>;
 
+/// Removes a finished stream's store, unless a new publisher has taken the
+/// name over since and it now holds a different store.
+pub async fn remove_store(stores: &SegmentStores, stream_name: &str, id: u64) -> bool {
+    let mut lock = stores.write().await;
+
+    if lock.get(stream_name).map(|store| store.id() == id).unwrap_or(false) {
+        log::info!("Removing stream store {}", stream_name);
+        lock.remove(stream_name);
+        return true;
+    }
+
+    false
+}
+
 pub struct Service {
+    manager_handle: ManagerHandle,
+    program_selection: ProgramSelection,
 }
 
 impl Service {
-    pub fn new() -> Self {
-        Self { }
+    pub fn new(manager_handle: ManagerHandle, program_selection: ProgramSelection) -> Self {
+        Self { manager_handle, program_selection }
     }
 
     pub async fn run(self, stores: SegmentStores, port: u32)-> Result<()> {
-        let app = routes::create_app(stores);
+        let app = routes::create_app(stores, self.manager_handle, self.program_selection);
         log::info!("starting HLS server at 127.0.0.1:3000");
     
         let _ = axum::Server::bind(&"127.0.0.1:3000".parse().unwrap())
-            .serve(app.into_make_service())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             .await;
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+    use clap::Parser;
+    use tokio::sync::RwLock;
+    use crate::Opt;
+    use super::{remove_store, segment_store::SegmentStore, SegmentStores};
+
+    #[tokio::test]
+    async fn keeps_store_taken_over_by_new_publisher() {
+        let opt = Opt::parse_from(["stream_kit"]);
+        let stores: SegmentStores = Arc::new(RwLock::new(HashMap::new()));
+
+        let finished = SegmentStore::new(&opt);
+        let finished_id = finished.id();
+        stores.write().await.insert("test".to_owned(), finished);
+
+        // a publisher takes the name over before the retention is up
+        let current = SegmentStore::new(&opt);
+        let current_id = current.id();
+        stores.write().await.insert("test".to_owned(), current);
+
+        assert!(!remove_store(&stores, "test", finished_id).await);
+        assert!(stores.read().await.contains_key("test"));
+
+        assert!(remove_store(&stores, "test", current_id).await);
+        assert!(!stores.read().await.contains_key("test"));
+    }
+}
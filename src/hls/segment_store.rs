@@ -1,5 +1,6 @@
 use std::collections::VecDeque;
 use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
 use bytes::{Bytes, BytesMut, BufMut};
 use anyhow::Result;
 use time::{OffsetDateTime, format_description::well_known::Rfc3339};
@@ -7,6 +8,53 @@ use tokio::sync::mpsc::{self, UnboundedSender, UnboundedReceiver};
 
 use crate::Opt;
 
+static NEXT_STORE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// An `EXT-X-DATERANGE` entry, used to carry SCTE-35 cues into the playlist.
+#[derive(Clone, Debug)]
+pub struct DateRange {
+    pub id: String,
+    pub start_date: OffsetDateTime,
+    pub end_date: Option<OffsetDateTime>,
+    pub duration: Option<f64>,
+    pub planned_duration: Option<f64>,
+    pub scte35_out: Option<Bytes>,
+    pub scte35_in: Option<Bytes>,
+}
+
+impl DateRange {
+    fn write(&self, manifest: &mut String) -> Result<()> {
+        write!(manifest, "#EXT-X-DATERANGE:ID=\"{}\",START-DATE=\"{}\"", self.id, self.start_date.format(&Rfc3339)?)?;
+
+        if let Some(end_date) = self.end_date {
+            write!(manifest, ",END-DATE=\"{}\"", end_date.format(&Rfc3339)?)?;
+        }
+
+        if let Some(duration) = self.duration {
+            write!(manifest, ",DURATION={:.03}", duration)?;
+        }
+
+        if let Some(planned_duration) = self.planned_duration {
+            write!(manifest, ",PLANNED-DURATION={:.03}", planned_duration)?;
+        }
+
+        if let Some(data) = &self.scte35_out {
+            write!(manifest, ",SCTE35-OUT=0x{}", Self::hex(data))?;
+        }
+
+        if let Some(data) = &self.scte35_in {
+            write!(manifest, ",SCTE35-IN=0x{}", Self::hex(data))?;
+        }
+
+        writeln!(manifest)?;
+        Ok(())
+    }
+
+    fn hex(data: &Bytes) -> String {
+        data.iter().map(|byte| format!("{:02X}", byte)).collect()
+    }
+}
+
 #[derive(Debug)]
 struct PartialSegment {
     data: BytesMut,
@@ -64,10 +112,14 @@ struct Segment {
     program_datetime: OffsetDateTime,
     queues: Vec<UnboundedSender<Option<Bytes>>>,
     data: BytesMut,
+    date_ranges: Vec<DateRange>,
+    discontinuity: bool,
+    /// Index of the init segment the samples decode against
+    map: Option<usize>,
 }
 
 impl Segment {
-    fn new(num: usize, begin_pts: u32, key_frame: bool, program_datetime: OffsetDateTime) -> Self {
+    fn new(num: usize, begin_pts: u32, key_frame: bool, program_datetime: OffsetDateTime, map: Option<usize>) -> Self {
         let mut partials = Vec::new();
         partials.push(PartialSegment::new(begin_pts, key_frame));
 
@@ -80,6 +132,9 @@ impl Segment {
             partials,
             queues: Vec::new(),
             data: BytesMut::new(),
+            date_ranges: Vec::new(),
+            discontinuity: false,
+            map,
         }
     }
 
@@ -149,8 +204,13 @@ impl Segment {
 }
 
 pub struct SegmentStore {
-    init_segment: Bytes,
+    /// Tells a store apart from a later one published under the same name
+    id: u64,
+    /// Every init segment written so far, a new one is added when the
+    /// sample entry changes and older segments keep referring to theirs
+    init_segments: Vec<Bytes>,
     media_sequence: usize,
+    discontinuity_sequence: usize,
     published: bool,
     windows_size: Option<usize>,
     part_duration: f32,
@@ -158,6 +218,9 @@ pub struct SegmentStore {
     version: usize,
     is_live: bool,
     manifest_body: Option<String>,
+    /// RFC 6381 codecs of the tracks in the init segment
+    codecs: Vec<String>,
+    resolution: Option<(u32, u32)>,
     segments: VecDeque<Segment>,
     outdated: VecDeque<Segment>,
 }
@@ -165,25 +228,58 @@ pub struct SegmentStore {
 impl SegmentStore {
     pub fn new(opt: &Opt) -> SegmentStore {
         SegmentStore {
-            init_segment: Bytes::new(),
+            id: NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed),
+            init_segments: Vec::new(),
             media_sequence: 0,
+            discontinuity_sequence: 0,
             published: false,
-            windows_size: Some(opt.window_size),
+            // without a window every segment is kept for replay
+            windows_size: if opt.keep_segments { None } else { Some(opt.window_size) },
             part_duration: opt.part_duration,
             low_latency_mode: false,
             version: 9,
             is_live: true,
             manifest_body: None,
+            codecs: Vec::new(),
+            resolution: None,
             segments: VecDeque::new(),
             outdated: VecDeque::new(),
         }
     }
 
-    pub fn init_segment_ready(&self) -> Option<Bytes> {
-        if self.init_segment.len() != 0 {
-            return Some(self.init_segment.clone());
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.is_live
+    }
+
+    /// Closes the segment in progress at `end_pts` and ends the playlist.
+    pub fn finish(&mut self, end_pts: u32) -> Result<()> {
+        if let Some(segment) = self.segments.front_mut() {
+            if segment.data.is_empty() {
+                // nothing made it into the segment that was just opened
+                self.segments.pop_front();
+                self.media_sequence -= 1;
+            } else {
+                segment.complete(end_pts);
+            }
         }
-        None
+
+        self.is_live = false;
+        self.published = !self.segments.is_empty();
+        self.generate_manfiest()
+    }
+
+    /// The latest init segment.
+    pub fn init_segment_ready(&self) -> Option<Bytes> {
+        self.init_segments.last().cloned()
+    }
+
+    /// The init segment published as `map`.
+    pub fn init_segment(&self, map: usize) -> Option<Bytes> {
+        self.init_segments.get(map).cloned()
     }
 
     pub fn continuous_partial(&mut self, end_pts: u32, key_frame: bool) -> Result<()> {
@@ -205,12 +301,18 @@ impl SegmentStore {
     }
 
     fn new_segment(&mut self, begin_pts: u32, key_frame: bool, program_datetime: OffsetDateTime) {
-        self.segments.push_front(Segment::new(self.media_sequence, begin_pts, key_frame, program_datetime));
+        let map = self.init_segments.len().checked_sub(1);
+        self.segments.push_front(Segment::new(self.media_sequence, begin_pts, key_frame, program_datetime, map));
         self.media_sequence += 1;
 
         if let Some(window_size) = self.windows_size {
             while window_size < self.segments.len() {
                 if let Some(last_segment) = self.segments.pop_back() {
+                    // the discontinuity sequence counts the tags that have left the playlist
+                    if last_segment.discontinuity {
+                        self.discontinuity_sequence += 1;
+                    }
+
                     self.outdated.push_back(last_segment);
                 }
             }
@@ -238,6 +340,20 @@ impl SegmentStore {
         }
     }
 
+    /// Marks the segment currently being written as starting a new time base.
+    pub fn mark_discontinuity(&mut self) {
+        if let Some(segment) = self.segments.front_mut() {
+            segment.discontinuity = true;
+        }
+    }
+
+    /// Attaches a date range to the segment currently being written.
+    pub fn push_date_range(&mut self, date_range: DateRange) {
+        if let Some(segment) = self.segments.front_mut() {
+            segment.date_ranges.push(date_range);
+        }
+    }
+
     #[inline(always)]
     fn target_duration(&self) -> f64 {
         let mut max: f64 = 1.0;
@@ -309,21 +425,41 @@ impl SegmentStore {
             writeln!(manifest, "#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={}", self.part_duration * (3.001 as f32))?;
         }
 
-        if !self.is_live {
-            writeln!(manifest, "#EXT-X-PLAYLIST-TYPE:VOD")?;
-            writeln!(manifest, "#EXT-X-ALLOW-CACHE:YES")?;
-        }
-
-        if self.init_segment_ready().is_some() {
-            writeln!(manifest, "#EXT-X-MAP:URI=\"init.mp4\"")?;
+        // a playlist holding the whole stream is an EVENT, and VOD once finished
+        if self.windows_size.is_none() {
+            if self.is_live {
+                writeln!(manifest, "#EXT-X-PLAYLIST-TYPE:EVENT")?;
+            } else {
+                writeln!(manifest, "#EXT-X-PLAYLIST-TYPE:VOD")?;
+                writeln!(manifest, "#EXT-X-ALLOW-CACHE:YES")?;
+            }
         }
 
         writeln!(manifest, "#EXT-X-MEDIA-SEQUENCE:{}", self.media_sequence)?;
+        writeln!(manifest, "#EXT-X-DISCONTINUITY-SEQUENCE:{}", self.discontinuity_sequence)?;
+
+        let mut current_map = None;
 
         for (seq, segment) in self.segments.iter().enumerate() {
             let msn = self.media_sequence + seq;
             writeln!(manifest, "")?; //Blank new line
+
+            if segment.discontinuity {
+                writeln!(manifest, "#EXT-X-DISCONTINUITY")?;
+            }
+
+            // each init segment has its own URI so players never reuse a cached one that no longer applies
+            if let Some(map) = segment.map.filter(|map| current_map != Some(*map)) {
+                current_map = Some(map);
+                writeln!(manifest, "#EXT-X-MAP:URI=\"init.mp4?map={}\"", map)?;
+            }
+
             writeln!(manifest, "#EXT-X-PROGRAM-DATE-TIME:{}", segment.program_datetime.format(&Rfc3339)?)?;
+
+            for date_range in &segment.date_ranges {
+                date_range.write(&mut manifest)?;
+            }
+
             if self.low_latency_mode {
                 
                 if seq >= self.segments.len() - 4 {
@@ -347,13 +483,134 @@ impl SegmentStore {
                 writeln!(manifest, "segment.m4s?msn={}", msn as i64 - 15)?;
             }
         }
+
+        if !self.is_live {
+            writeln!(manifest, "#EXT-X-ENDLIST")?;
+        }
+
         self.manifest_body = Some(manifest);
         Ok(())
     }
 
+    /// Adds an init segment for the segments opened from now on.
     pub fn set_init_segment(&mut self, data: Bytes) -> Result<()> {
-        self.init_segment = data;
+        self.init_segments.push(data);
         Ok(())
     }
 
+    pub fn set_stream_info(&mut self, codecs: Vec<String>, resolution: Option<(u32, u32)>) {
+        self.codecs = codecs;
+        self.resolution = resolution;
+    }
+
+    /// Peak bit rate over the completed segments, as BANDWIDTH requires.
+    fn peak_bandwidth(&self) -> u64 {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment.duration() {
+                Some(duration) if duration > 0.0 => Some((segment.data.len() as f64 * 8.0 / duration) as u64),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// A multivariant playlist with the media playlist as its only variant,
+    /// so players can tell what the stream carries before loading it.
+    pub fn get_multivariant_manifest_text(&self) -> Result<Option<String>> {
+        if !self.published || self.codecs.is_empty() {
+            return Ok(None);
+        }
+
+        let mut manifest = String::new();
+
+        writeln!(manifest, "#EXTM3U")?;
+        writeln!(manifest, "#EXT-X-VERSION:{}", self.version)?;
+        write!(manifest, "#EXT-X-STREAM-INF:BANDWIDTH={},CODECS=\"{}\"", self.peak_bandwidth(), self.codecs.join(","))?;
+
+        if let Some((width, height)) = self.resolution {
+            write!(manifest, ",RESOLUTION={}x{}", width, height)?;
+        }
+
+        writeln!(manifest)?;
+        writeln!(manifest, "playlist.m3u8")?;
+
+        Ok(Some(manifest))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use clap::Parser;
+    use time::OffsetDateTime;
+    use crate::Opt;
+    use super::SegmentStore;
+
+    const SEGMENT_DURATION: u32 = 4 * mpegts::HZ;
+
+    /// A store with `count` segments completed and an empty one opened after them.
+    fn store_with_segments(args: &[&str], count: u32) -> SegmentStore {
+        let opt = Opt::parse_from([&["stream_kit"], args].concat());
+        let mut store = SegmentStore::new(&opt);
+        store.set_init_segment(Bytes::from_static(b"init")).unwrap();
+
+        for index in 0..=count {
+            store.continuous_segment(index * SEGMENT_DURATION, true, OffsetDateTime::UNIX_EPOCH).unwrap();
+            if index < count {
+                store.push(Bytes::from_static(b"moof"));
+            }
+        }
+
+        store
+    }
+
+    fn manifest(store: &SegmentStore) -> String {
+        store.manifest_body.clone().unwrap()
+    }
+
+    #[test]
+    fn kept_segments_are_an_event_then_vod() {
+        let mut store = store_with_segments(&["--keep-segments", "--window-size", "3"], 5);
+
+        let live = manifest(&store);
+        assert!(live.contains("#EXT-X-PLAYLIST-TYPE:EVENT"));
+        assert!(!live.contains("#EXT-X-ENDLIST"));
+        assert_eq!(live.matches("#EXTINF:4.000000").count(), 5);
+
+        store.push(Bytes::from_static(b"moof"));
+        store.finish(5 * SEGMENT_DURATION + mpegts::HZ).unwrap();
+
+        let vod = manifest(&store);
+        assert!(!store.is_live());
+        assert!(vod.contains("#EXT-X-PLAYLIST-TYPE:VOD"));
+        assert!(!vod.contains("#EXT-X-PLAYLIST-TYPE:EVENT"));
+        assert_eq!(vod.matches("#EXTINF:").count(), 6);
+        assert!(vod.contains("#EXTINF:1.000000"));
+        assert!(vod.ends_with("#EXT-X-ENDLIST\n"));
+    }
+
+    #[test]
+    fn windowed_stream_ends_without_playlist_type() {
+        let mut store = store_with_segments(&["--window-size", "3"], 5);
+        store.finish(5 * SEGMENT_DURATION).unwrap();
+
+        let vod = manifest(&store);
+        assert!(!vod.contains("#EXT-X-PLAYLIST-TYPE"));
+        // the window still applies, the empty segment that was opened last is dropped
+        assert_eq!(vod.matches("#EXTINF:").count(), 2);
+        assert!(vod.ends_with("#EXT-X-ENDLIST\n"));
+    }
+
+    #[test]
+    fn finish_drops_empty_open_segment() {
+        let mut store = store_with_segments(&["--keep-segments"], 2);
+        store.finish(2 * SEGMENT_DURATION).unwrap();
+
+        let vod = manifest(&store);
+        assert_eq!(vod.matches("#EXTINF:").count(), 2);
+        assert_eq!(vod.matches("#EXT-X-PROGRAM-DATE-TIME").count(), 2);
+        assert!(vod.ends_with("#EXT-X-ENDLIST\n"));
+    }
 }
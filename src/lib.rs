@@ -5,6 +5,10 @@ pub mod hls;
 pub mod session;
 pub mod fmp4;
 
+#[cfg(feature = "srt")]
 pub mod srt;
+pub mod rtmp;
+pub mod udp;
+pub mod file;
 
 pub use option::Opt;
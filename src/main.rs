@@ -1,7 +1,10 @@
-use std::{sync::Arc, collections::HashMap};
+use std::{sync::Arc, collections::HashMap, time::Duration};
 use lazy_static::*;
-use stream_kit::{Opt, srt::SrtService, session::manager::SessionManager, fmp4, hls::{SegmentStores, self}};
+use mpegts::demuxer::ProgramSelection;
+use stream_kit::{Opt, rtmp::RtmpService, udp::UdpService, file::FileService, session::manager::SessionManager, fmp4, hls::{SegmentStores, self}};
 use log::LevelFilter;
+#[cfg(feature = "srt")]
+use stream_kit::srt::{SrtService, SrtPullService};
 use anyhow::Result;
 use tokio::sync::RwLock;
 
@@ -33,8 +36,16 @@ async fn main() -> Result<()> {
     log::info!("Starting StreamKit {{ \"Version\": \"{CARGO_PKG_VERSION}\", \"GitSha\": \"{GIT_SHA}\" }}");
 
     let mut handles = Vec::new();
-    let manager = SessionManager::new();
+    let manager = SessionManager::new(Duration::from_secs(opt.reconnect_grace_period));
     let manager_handle = manager.handle();
+    let rtmp_listen = opt.rtmp_listen;
+    let udp_inputs = opt.udp_inputs.clone();
+    let file_inputs = opt.file_inputs.clone();
+    let loop_file_inputs = opt.loop_file_inputs;
+    let program_selection = ProgramSelection::from(opt.programs);
+    #[cfg(feature = "srt")]
+    let srt_listeners = opt.srt_listeners.clone();
+    let srt_pulls = opt.srt_pulls.clone();
 
     //
     // Spawn stream manager to distribute streams
@@ -50,15 +61,53 @@ async fn main() -> Result<()> {
             _ = fmp4::Service::new(manager_handle_t, opt).run(Arc::clone(&SESSION_STORES)).await;
          }));
 
+         let manager_handle_t = manager_handle.clone();
+         let program_selection_t = program_selection.clone();
          handles.push(tokio::spawn(async move {
-            _ = hls::Service::new().run(Arc::clone(&SESSION_STORES), 3000).await;
+            _ = hls::Service::new(manager_handle_t, program_selection_t).run(Arc::clone(&SESSION_STORES), 3000).await;
         }));
     }
     
     //
     //  Handle the SRt input and deplexing
     // 
-    handles.push(tokio::spawn(SrtService::new(manager_handle).run(9000)));
+    #[cfg(feature = "srt")]
+    for listen in srt_listeners {
+        handles.push(tokio::spawn(SrtService::new(manager_handle.clone(), listen, program_selection.clone()).run()));
+    }
+
+    //
+    //  Pull streams from remote SRT listeners
+    //
+    #[cfg(feature = "srt")]
+    for pull in srt_pulls {
+        handles.push(tokio::spawn(SrtPullService::new(manager_handle.clone(), pull, program_selection.clone()).run()));
+    }
+    #[cfg(not(feature = "srt"))]
+    if !srt_pulls.is_empty() {
+        log::warn!("Built without the srt feature, ignoring the SRT pulls");
+    }
+
+    //
+    //  Handle RTMP publishers
+    //
+    if let Some(addr) = rtmp_listen {
+        handles.push(tokio::spawn(RtmpService::new(manager_handle.clone()).run(addr)));
+    }
+
+    //
+    //  Handle the UDP inputs
+    //
+    for input in udp_inputs {
+        handles.push(tokio::spawn(UdpService::new(manager_handle.clone(), input, program_selection.clone()).run()));
+    }
+
+    //
+    //  Publish the file inputs
+    //
+    for input in file_inputs {
+        handles.push(tokio::spawn(FileService::new(manager_handle.clone(), input, loop_file_inputs, program_selection.clone()).run()));
+    }
 
     for handle in handles {
         handle.await?;
@@ -1,4 +1,5 @@
 use lazy_static::lazy_static;
+use mpegts::stats::DemuxerStats;
 use prometheus::{
     opts, register_int_gauge_vec, IntGaugeVec,
 };
@@ -11,4 +12,138 @@ lazy_static! {
     )
     .expect("Can't create a metric");
 
-}
\ No newline at end of file
+    pub static ref STREAMKIT_TS_PACKETS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_PACKETS", "Transport stream packets received"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_SYNC_LOSS_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_SYNC_LOSS_ERRORS", "TR 101 290 1.1 TS_sync_loss count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_PAT_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_PAT_ERRORS", "TR 101 290 1.3 PAT_error count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_CONTINUITY_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_CONTINUITY_ERRORS", "TR 101 290 1.4 Continuity_count_error count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_PMT_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_PMT_ERRORS", "TR 101 290 1.5 PMT_error count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_TRANSPORT_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_TRANSPORT_ERRORS", "TR 101 290 2.1 Transport_error count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_CRC_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_CRC_ERRORS", "TR 101 290 2.2 CRC_error count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_PCR_REPETITION_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_PCR_REPETITION_ERRORS", "TR 101 290 2.3a PCR_repetition_error count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_PCR_DISCONTINUITY_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_PCR_DISCONTINUITY_ERRORS", "TR 101 290 2.3b PCR_discontinuity_indicator_error count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_PCR_ACCURACY_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_PCR_ACCURACY_ERRORS", "TR 101 290 2.4 PCR_accuracy_error count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_PTS_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_PTS_ERRORS", "TR 101 290 2.5 PTS_error count"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_PCR_INTERVAL_MAX: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_PCR_INTERVAL_MAX", "Longest interval between PCRs in milliseconds"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_PCR_JITTER_MAX: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_PCR_JITTER_MAX", "Largest PCR jitter in nanoseconds"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+    pub static ref STREAMKIT_TS_MALFORMED_PACKETS: IntGaugeVec = register_int_gauge_vec!(
+        opts!("STREAMKIT_TS_MALFORMED_PACKETS", "Transport stream packets dropped as unparsable"),
+        &["stream"]
+    )
+    .expect("Can't create a metric");
+
+}
+
+fn ts_gauges() -> [&'static IntGaugeVec; 14] {
+    [
+        &STREAMKIT_TS_PACKETS,
+        &STREAMKIT_TS_SYNC_LOSS_ERRORS,
+        &STREAMKIT_TS_PAT_ERRORS,
+        &STREAMKIT_TS_CONTINUITY_ERRORS,
+        &STREAMKIT_TS_PMT_ERRORS,
+        &STREAMKIT_TS_TRANSPORT_ERRORS,
+        &STREAMKIT_TS_CRC_ERRORS,
+        &STREAMKIT_TS_PCR_REPETITION_ERRORS,
+        &STREAMKIT_TS_PCR_DISCONTINUITY_ERRORS,
+        &STREAMKIT_TS_PCR_ACCURACY_ERRORS,
+        &STREAMKIT_TS_PTS_ERRORS,
+        &STREAMKIT_TS_PCR_INTERVAL_MAX,
+        &STREAMKIT_TS_PCR_JITTER_MAX,
+        &STREAMKIT_TS_MALFORMED_PACKETS,
+    ]
+}
+
+/// Publishes the demuxer's transport stream stats for an incoming stream.
+pub fn observe_ts_stats(stream_name: &str, stats: &DemuxerStats) {
+    let values = [
+        stats.packets,
+        stats.sync_losses,
+        stats.pat_errors,
+        stats.continuity_errors(),
+        stats.pmt_errors,
+        stats.transport_errors(),
+        stats.crc_errors(),
+        stats.pcr_repetition_errors(),
+        stats.pcr_discontinuity_errors(),
+        stats.pcr_accuracy_errors(),
+        stats.pts_errors(),
+        stats.pcr_interval_max().as_millis() as u64,
+        stats.pcr_jitter_max().as_nanos() as u64,
+        stats.malformed_packets,
+    ];
+
+    for (gauge, value) in ts_gauges().into_iter().zip(values) {
+        gauge.with_label_values(&[stream_name]).set(value as i64);
+    }
+}
+
+/// Drops the series for a stream once its publisher has gone.
+pub fn remove_ts_stats(stream_name: &str) {
+    for gauge in ts_gauges() {
+        let _ = gauge.remove_label_values(&[stream_name]);
+    }
+}
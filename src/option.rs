@@ -6,6 +6,8 @@ use clap::Parser;
 use std::str::FromStr;
 use std::path::PathBuf;
 use std::env::VarError;
+use std::net::{IpAddr, SocketAddr};
+use mpegts::demuxer::ProgramSelection;
 
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
@@ -65,11 +67,487 @@ impl FromStr for LogLevel {
     }
 }
 
+/// A UDP transport stream input, `{stream name}={address}:{port}` with an
+/// optional `@{interface address}` used to join multicast groups.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct UdpInput {
+    pub stream_name: String,
+    pub address: SocketAddr,
+    pub interface: Option<IpAddr>,
+}
+
+#[derive(Debug)]
+pub struct UdpInputError {
+    pub given_input: String,
+}
+
+impl Display for UdpInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "UDP input '{}' is invalid. Expected '{{stream name}}={{address}}:{{port}}', optionally followed by '@{{interface address}}'.",
+            self.given_input
+        )
+    }
+}
+
+impl std::error::Error for UdpInputError {}
+
+impl Display for UdpInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.stream_name, self.address)?;
+        if let Some(interface) = self.interface {
+            write!(f, "@{}", interface)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for UdpInput {
+    type Err = UdpInputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || UdpInputError { given_input: s.to_owned() };
+
+        let (stream_name, address) = s.trim().split_once('=').ok_or_else(err)?;
+        let (address, interface) = match address.split_once('@') {
+            Some((address, interface)) => (address, Some(interface.parse().map_err(|_| err())?)),
+            None => (address, None),
+        };
+
+        if stream_name.is_empty() || stream_name.contains('/') {
+            return Err(err());
+        }
+
+        Ok(Self {
+            stream_name: stream_name.to_owned(),
+            address: address.parse().map_err(|_| err())?,
+            interface,
+        })
+    }
+}
+
+impl TryFrom<String> for UdpInput {
+    type Error = UdpInputError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Options listeners and pulls both take
+const SRT_SOCKET_OPTIONS: [&str; 7] = ["latency", "passphrase", "pbkeylen", "maxbw", "rcvbuf", "sndbuf", "payload_size"];
+
+/// Options that only mean something when calling out
+const SRT_CALLER_OPTIONS: [&str; 3] = ["streamid", "mode", "localport"];
+
+/// SRT socket options, given after a `?` the way `srt://` URLs take them,
+/// e.g. `latency=200&passphrase=0123456789&pbkeylen=16`. Unset options keep
+/// the libsrt defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SrtOptions {
+    /// Milliseconds
+    pub latency: Option<u64>,
+    pub passphrase: Option<String>,
+    /// AES key length in bytes, 16, 24 or 32
+    pub pbkeylen: Option<u8>,
+    /// Bytes per second, -1 for no limit and 0 to follow the input rate
+    pub maxbw: Option<i64>,
+    pub rcvbuf: Option<i32>,
+    pub sndbuf: Option<i32>,
+    pub payload_size: Option<i32>,
+    pub stream_id: Option<String>,
+    /// `mode=rendezvous`, both sides call each other
+    pub rendezvous: bool,
+    /// Port bound for rendezvous, the remote port when not given
+    pub local_port: Option<u16>,
+}
+
+impl SrtOptions {
+    fn parse(query: &str) -> Option<Self> {
+        let mut options = Self::default();
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=')?;
+
+            match key {
+                "latency" => options.latency = Some(value.parse().ok()?),
+                "passphrase" if (10..=79).contains(&value.len()) => options.passphrase = Some(value.to_owned()),
+                "pbkeylen" if ["16", "24", "32"].contains(&value) => options.pbkeylen = Some(value.parse().ok()?),
+                "maxbw" => options.maxbw = Some(value.parse().ok().filter(|maxbw| *maxbw >= -1)?),
+                "rcvbuf" => options.rcvbuf = Some(value.parse().ok().filter(|size| *size > 0)?),
+                "sndbuf" => options.sndbuf = Some(value.parse().ok().filter(|size| *size > 0)?),
+                "payload_size" => options.payload_size = Some(value.parse().ok().filter(|size| *size > 0)?),
+                "streamid" => options.stream_id = Some(value.to_owned()),
+                "mode" if value == "caller" => options.rendezvous = false,
+                "mode" if value == "rendezvous" => options.rendezvous = true,
+                "localport" => options.local_port = Some(value.parse().ok()?),
+                _ => return None,
+            }
+        }
+
+        Some(options)
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The options as a query, with the passphrase only in the clear when
+    /// `redact` is false.
+    fn query(&self, redact: bool) -> String {
+        let mut pairs = Vec::new();
+
+        if let Some(latency) = self.latency {
+            pairs.push(format!("latency={}", latency));
+        }
+        if let Some(passphrase) = &self.passphrase {
+            pairs.push(format!("passphrase={}", if redact { "***" } else { passphrase }));
+        }
+        if let Some(pbkeylen) = self.pbkeylen {
+            pairs.push(format!("pbkeylen={}", pbkeylen));
+        }
+        if let Some(maxbw) = self.maxbw {
+            pairs.push(format!("maxbw={}", maxbw));
+        }
+        if let Some(rcvbuf) = self.rcvbuf {
+            pairs.push(format!("rcvbuf={}", rcvbuf));
+        }
+        if let Some(sndbuf) = self.sndbuf {
+            pairs.push(format!("sndbuf={}", sndbuf));
+        }
+        if let Some(payload_size) = self.payload_size {
+            pairs.push(format!("payload_size={}", payload_size));
+        }
+        if let Some(stream_id) = &self.stream_id {
+            pairs.push(format!("streamid={}", stream_id));
+        }
+        if self.rendezvous {
+            pairs.push("mode=rendezvous".to_owned());
+        }
+        if let Some(local_port) = self.local_port {
+            pairs.push(format!("localport={}", local_port));
+        }
+
+        pairs.join("&")
+    }
+}
+
+/// Hides the passphrase, so the options can be logged.
+impl Display for SrtOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.query(true))
+    }
+}
+
+/// An address SRT publishers connect to, `{address}:{port}` optionally
+/// followed by `?{options}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SrtListen {
+    pub address: SocketAddr,
+    pub options: SrtOptions,
+}
+
+#[derive(Debug)]
+pub struct SrtListenError {
+    pub given_listener: String,
+}
+
+impl Display for SrtListenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "SRT listener '{}' is invalid. Expected '{{address}}:{{port}}', optionally followed by '?{{options}}' with {}.",
+            self.given_listener,
+            SRT_SOCKET_OPTIONS.join(", "),
+        )
+    }
+}
+
+impl std::error::Error for SrtListenError {}
+
+impl Display for SrtListen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.address)?;
+        if !self.options.is_empty() {
+            write!(f, "?{}", self.options)?;
+        }
+        Ok(())
+    }
+}
+
+impl SrtListen {
+    /// As it was given, passphrase included, to hand on through the environment.
+    fn to_env(&self) -> String {
+        match self.options.is_empty() {
+            true => self.address.to_string(),
+            false => format!("{}?{}", self.address, self.options.query(false)),
+        }
+    }
+}
+
+impl FromStr for SrtListen {
+    type Err = SrtListenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || SrtListenError { given_listener: s.to_owned() };
+
+        let (address, query) = s.trim().split_once('?').unwrap_or((s.trim(), ""));
+        let options = SrtOptions::parse(query).ok_or_else(err)?;
+
+        // only meaningful when calling out
+        if options.rendezvous || options.local_port.is_some() || options.stream_id.is_some() {
+            return Err(err());
+        }
+
+        Ok(Self {
+            address: address.parse().map_err(|_| err())?,
+            options,
+        })
+    }
+}
+
+impl TryFrom<String> for SrtListen {
+    type Error = SrtListenError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// A remote SRT listener the stream is pulled from, `{stream name}={host}:{port}`
+/// optionally followed by `?{options}`. With `mode=rendezvous` the remote
+/// side calls back instead of listening.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SrtPull {
+    pub stream_name: String,
+    pub address: String,
+    pub options: SrtOptions,
+}
+
+#[derive(Debug)]
+pub struct SrtPullError {
+    pub given_pull: String,
+}
+
+impl Display for SrtPullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "SRT pull '{}' is invalid. Expected '{{stream name}}={{host}}:{{port}}', optionally followed by '?{{options}}' with {}.",
+            self.given_pull,
+            [&SRT_SOCKET_OPTIONS[..], &SRT_CALLER_OPTIONS[..]].concat().join(", "),
+        )
+    }
+}
+
+impl std::error::Error for SrtPullError {}
+
+impl Display for SrtPull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.stream_name, self.address)?;
+        if !self.options.is_empty() {
+            write!(f, "?{}", self.options)?;
+        }
+        Ok(())
+    }
+}
+
+impl SrtPull {
+    /// As it was given, passphrase included, to hand on through the environment.
+    fn to_env(&self) -> String {
+        match self.options.is_empty() {
+            true => format!("{}={}", self.stream_name, self.address),
+            false => format!("{}={}?{}", self.stream_name, self.address, self.options.query(false)),
+        }
+    }
+}
+
+impl FromStr for SrtPull {
+    type Err = SrtPullError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || SrtPullError { given_pull: s.to_owned() };
+
+        let (stream_name, rest) = s.trim().split_once('=').ok_or_else(err)?;
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (host, port) = address.rsplit_once(':').ok_or_else(err)?;
+
+        if stream_name.is_empty() || stream_name.contains('/') || host.is_empty() || port.parse::<u16>().is_err() {
+            return Err(err());
+        }
+
+        Ok(Self {
+            stream_name: stream_name.to_owned(),
+            address: address.to_owned(),
+            options: SrtOptions::parse(query).ok_or_else(err)?,
+        })
+    }
+}
+
+impl TryFrom<String> for SrtPull {
+    type Error = SrtPullError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// A transport stream file published as a live stream, `{stream name}={path}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FileInput {
+    pub stream_name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct FileInputError {
+    pub given_input: String,
+}
+
+impl Display for FileInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "File input '{}' is invalid. Expected '{{stream name}}={{path}}'.",
+            self.given_input
+        )
+    }
+}
+
+impl std::error::Error for FileInputError {}
+
+impl Display for FileInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.stream_name, self.path.display())
+    }
+}
+
+impl FromStr for FileInput {
+    type Err = FileInputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || FileInputError { given_input: s.to_owned() };
+
+        let (stream_name, path) = s.trim().split_once('=').ok_or_else(err)?;
+
+        if stream_name.is_empty() || stream_name.contains('/') || path.is_empty() {
+            return Err(err());
+        }
+
+        Ok(Self {
+            stream_name: stream_name.to_owned(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl TryFrom<String> for FileInput {
+    type Error = FileInputError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Which programs of an ingested transport stream are published, `all`,
+/// `first` or a program_number.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Programs {
+    #[default]
+    All,
+    First,
+    Number(u16),
+}
+
+#[derive(Debug)]
+pub struct ProgramsError {
+    pub given_programs: String,
+}
+
+impl Display for ProgramsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Programs '{}' is invalid. Accepted values are 'all', 'first', or a program number.",
+            self.given_programs
+        )
+    }
+}
+
+impl std::error::Error for ProgramsError {}
+
+impl Display for Programs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Programs::All => Display::fmt("all", f),
+            Programs::First => Display::fmt("first", f),
+            Programs::Number(number) => Display::fmt(number, f),
+        }
+    }
+}
+
+impl FromStr for Programs {
+    type Err = ProgramsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "all" => Ok(Programs::All),
+            "first" => Ok(Programs::First),
+            // program_number 0 is the network PID, not a program
+            number => match number.parse() {
+                Ok(number) if number != 0 => Ok(Programs::Number(number)),
+                _ => Err(ProgramsError { given_programs: s.to_owned() }),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Programs {
+    type Error = ProgramsError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Programs> for ProgramSelection {
+    fn from(programs: Programs) -> Self {
+        match programs {
+            Programs::All => ProgramSelection::All,
+            Programs::First => ProgramSelection::First,
+            Programs::Number(number) => ProgramSelection::Number(number),
+        }
+    }
+}
+
 
 const STREAMKIT_LOG_LEVEL: &str = "STREAMKIT_LOG_LEVEL";
 const STREAMKIT_ENABLE_METRICS: &str = "STREAMKIT_ENABLE_METRICS";
 const STREAMKIT_PART_SIZE: &str = "STREAMKIT_ENABLE_METRICS";
 const STREAMKIT_WINDOW_SIZE: &str = "STREAMKIT_ENABLE_METRICS";
+const STREAMKIT_RECONNECT_GRACE_PERIOD: &str = "STREAMKIT_RECONNECT_GRACE_PERIOD";
+const STREAMKIT_KEEP_SEGMENTS: &str = "STREAMKIT_KEEP_SEGMENTS";
+const STREAMKIT_VOD_RETENTION: &str = "STREAMKIT_VOD_RETENTION";
+const STREAMKIT_SEGMENT_DURATION: &str = "STREAMKIT_SEGMENT_DURATION";
+const STREAMKIT_HVC1_STREAMS: &str = "STREAMKIT_HVC1_STREAMS";
+const STREAMKIT_RTMP_LISTEN: &str = "STREAMKIT_RTMP_LISTEN";
+const STREAMKIT_UDP_INPUTS: &str = "STREAMKIT_UDP_INPUTS";
+const STREAMKIT_FILE_INPUTS: &str = "STREAMKIT_FILE_INPUTS";
+const STREAMKIT_LOOP_FILE_INPUTS: &str = "STREAMKIT_LOOP_FILE_INPUTS";
+const STREAMKIT_SRT_PULLS: &str = "STREAMKIT_SRT_PULLS";
+const STREAMKIT_SRT_LISTENERS: &str = "STREAMKIT_SRT_LISTENERS";
+const STREAMKIT_PROGRAMS: &str = "STREAMKIT_PROGRAMS";
+
+const DEFAULT_RECONNECT_GRACE_PERIOD: u64 = 10;
+const DEFAULT_VOD_RETENTION: u64 = 300;
+const DEFAULT_SEGMENT_DURATION: f32 = 4.0;
+const DEFAULT_SRT_LISTENER: &str = "127.0.0.1:9000";
 
 const DEFAULT_CONFIG_FILE_PATH: &str = "./config.toml";
 
@@ -101,10 +579,107 @@ pub struct Opt {
     #[serde(default)]
     pub part_duration: f32,
 
+    /// Target segment duration in seconds for streams without video. Streams
+    /// with video are cut on their key frames instead.
+    #[clap(long, env = STREAMKIT_SEGMENT_DURATION, default_value_t = DEFAULT_SEGMENT_DURATION)]
+    #[serde(default = "default_segment_duration")]
+    pub segment_duration: f32,
+
     //Sets the windows size (rewind window) for the HLS stream.
     #[clap(long, env = STREAMKIT_WINDOW_SIZE, default_value_t = 15)]
     #[serde(default)]
     pub window_size: usize,
+
+    /// Seconds a stream is kept after its publisher drops. A publisher
+    /// reconnecting with the same streamid inside this window continues the
+    /// existing playlist after a discontinuity, 0 tears the stream down straight away.
+    #[clap(long, env = STREAMKIT_RECONNECT_GRACE_PERIOD, default_value_t = DEFAULT_RECONNECT_GRACE_PERIOD)]
+    #[serde(default = "default_reconnect_grace_period")]
+    pub reconnect_grace_period: u64,
+
+    /// Keeps every segment of a stream instead of just the window. The playlist
+    /// is published as an EVENT while live so the whole stream can be replayed
+    /// as VOD once it ends.
+    #[clap(long, env = STREAMKIT_KEEP_SEGMENTS)]
+    #[serde(default)]
+    pub keep_segments: bool,
+
+    /// Seconds a finished stream's playlist and segments stay available after
+    /// the publisher has gone, 0 removes them straight away.
+    #[clap(long, env = STREAMKIT_VOD_RETENTION, default_value_t = DEFAULT_VOD_RETENTION)]
+    #[serde(default = "default_vod_retention")]
+    pub vod_retention: u64,
+
+    /// Streamids whose HEVC video is written as `hvc1`, with the parameter
+    /// sets only in the init segment as Apple devices expect, `*` selects
+    /// every stream. Other streams use `hev1` and repeat them in band.
+    #[clap(long, env = STREAMKIT_HVC1_STREAMS, value_delimiter = ',')]
+    #[serde(default)]
+    pub hvc1_streams: Vec<String>,
+
+    /// Address RTMP publishers connect to, e.g. `0.0.0.0:1935`. RTMP is
+    /// not accepted unless one is given.
+    #[clap(long, env = STREAMKIT_RTMP_LISTEN)]
+    #[serde(default)]
+    pub rtmp_listen: Option<SocketAddr>,
+
+    /// Transport streams received over UDP, plain or RTP wrapped, e.g.
+    /// `feed=239.1.1.1:5000@10.0.0.2`. Multicast addresses are joined on the
+    /// given interface, or the default one. Comma separated.
+    #[clap(long, env = STREAMKIT_UDP_INPUTS, value_delimiter = ',')]
+    #[serde(default)]
+    pub udp_inputs: Vec<UdpInput>,
+
+    /// Transport stream files published as live streams, paced in real time
+    /// by their PCR, or PTS when they have none, e.g. `soak=/media/bars.ts`.
+    /// Comma separated.
+    #[clap(long, env = STREAMKIT_FILE_INPUTS, value_delimiter = ',')]
+    #[serde(default)]
+    pub file_inputs: Vec<FileInput>,
+
+    /// Restarts file inputs from the beginning when they end, with the
+    /// timestamps carried on and a discontinuity signalled at each loop.
+    #[clap(long, env = STREAMKIT_LOOP_FILE_INPUTS)]
+    #[serde(default)]
+    pub loop_file_inputs: bool,
+
+    /// Addresses SRT publishers connect to, each with its own socket options
+    /// given as in `srt://` URLs, e.g. `0.0.0.0:9000?latency=200&passphrase=0123456789`.
+    /// Comma separated.
+    #[clap(long, env = STREAMKIT_SRT_LISTENERS, value_delimiter = ',', default_value = DEFAULT_SRT_LISTENER)]
+    #[serde(default = "default_srt_listeners")]
+    pub srt_listeners: Vec<SrtListen>,
+
+    /// Remote SRT listeners to call and pull a stream from, e.g.
+    /// `encoder1=10.0.0.5:9000?latency=400`, or peers to meet with
+    /// `mode=rendezvous`. Calls that fail or drop are retried with a
+    /// backoff. Comma separated.
+    #[clap(long, env = STREAMKIT_SRT_PULLS, value_delimiter = ',')]
+    #[serde(default)]
+    pub srt_pulls: Vec<SrtPull>,
+
+    /// Programs published from multi-program transport streams: `all`,
+    /// `first`, or a program number. When the PAT lists more than one
+    /// program each is published as `{stream name}-{program number}`.
+    #[clap(long, env = STREAMKIT_PROGRAMS, default_value_t)]
+    #[serde(default)]
+    pub programs: Programs,
+}
+
+fn default_reconnect_grace_period() -> u64 {
+    DEFAULT_RECONNECT_GRACE_PERIOD
+}
+
+fn default_vod_retention() -> u64 {
+    DEFAULT_VOD_RETENTION
+}
+
+fn default_segment_duration() -> f32 {
+    DEFAULT_SEGMENT_DURATION
+}
+
+fn default_srt_listeners() -> Vec<SrtListen> {
+    vec![DEFAULT_SRT_LISTENER.parse().unwrap()]
 }
 
 
@@ -158,13 +733,51 @@ impl Opt {
             enable_metrics: enable_metrics_route,
             config_file_path: _,
             part_duration,
+            segment_duration,
             window_size: _,
+            reconnect_grace_period,
+            keep_segments,
+            vod_retention,
+            hvc1_streams,
+            rtmp_listen,
+            udp_inputs,
+            file_inputs,
+            loop_file_inputs,
+            srt_listeners,
+            srt_pulls,
+            programs,
         } = self;
 
         export_to_env_if_not_present(STREAMKIT_LOG_LEVEL, log_level.to_string());
         export_to_env_if_not_present(STREAMKIT_ENABLE_METRICS,enable_metrics_route.to_string());
         export_to_env_if_not_present(STREAMKIT_PART_SIZE,part_duration.to_string());
+        export_to_env_if_not_present(STREAMKIT_SEGMENT_DURATION, segment_duration.to_string());
         //export_to_env_if_not_present(STREAMKIT_WINDOW_SIZE, window_size.to_string());
+        export_to_env_if_not_present(STREAMKIT_RECONNECT_GRACE_PERIOD, reconnect_grace_period.to_string());
+        export_to_env_if_not_present(STREAMKIT_KEEP_SEGMENTS, keep_segments.to_string());
+        export_to_env_if_not_present(STREAMKIT_VOD_RETENTION, vod_retention.to_string());
+        if !hvc1_streams.is_empty() {
+            export_to_env_if_not_present(STREAMKIT_HVC1_STREAMS, hvc1_streams.join(","));
+        }
+        if let Some(rtmp_listen) = rtmp_listen {
+            export_to_env_if_not_present(STREAMKIT_RTMP_LISTEN, rtmp_listen.to_string());
+        }
+        if !udp_inputs.is_empty() {
+            let udp_inputs: Vec<String> = udp_inputs.iter().map(UdpInput::to_string).collect();
+            export_to_env_if_not_present(STREAMKIT_UDP_INPUTS, udp_inputs.join(","));
+        }
+        if !file_inputs.is_empty() {
+            let file_inputs: Vec<String> = file_inputs.iter().map(FileInput::to_string).collect();
+            export_to_env_if_not_present(STREAMKIT_FILE_INPUTS, file_inputs.join(","));
+        }
+        export_to_env_if_not_present(STREAMKIT_LOOP_FILE_INPUTS, loop_file_inputs.to_string());
+        let srt_listeners: Vec<String> = srt_listeners.iter().map(SrtListen::to_env).collect();
+        export_to_env_if_not_present(STREAMKIT_SRT_LISTENERS, srt_listeners.join(","));
+        if !srt_pulls.is_empty() {
+            let srt_pulls: Vec<String> = srt_pulls.iter().map(SrtPull::to_env).collect();
+            export_to_env_if_not_present(STREAMKIT_SRT_PULLS, srt_pulls.join(","));
+        }
+        export_to_env_if_not_present(STREAMKIT_PROGRAMS, programs.to_string());
     }
 }
 
@@ -175,4 +788,83 @@ where
     if let Err(VarError::NotPresent) = std::env::var(key) {
         std::env::set_var(key, value);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::{Programs, SrtListen, SrtOptions, SrtPull};
+
+    #[test]
+    fn parses_programs() {
+        assert_eq!("all".parse::<Programs>().unwrap(), Programs::All);
+        assert_eq!(" First ".parse::<Programs>().unwrap(), Programs::First);
+        assert_eq!("2".parse::<Programs>().unwrap(), Programs::Number(2));
+
+        assert!("0".parse::<Programs>().is_err());
+        assert!("65536".parse::<Programs>().is_err());
+        assert!("some".parse::<Programs>().is_err());
+
+        assert_eq!(Programs::Number(2).to_string().parse::<Programs>().unwrap(), Programs::Number(2));
+    }
+
+    #[test]
+    fn parses_srt_options() {
+        let options = SrtOptions::parse("latency=200&passphrase=0123456789&pbkeylen=16&maxbw=-1&rcvbuf=1000&sndbuf=2000&payload_size=1316").unwrap();
+        assert_eq!(options, SrtOptions {
+            latency: Some(200),
+            passphrase: Some("0123456789".to_owned()),
+            pbkeylen: Some(16),
+            maxbw: Some(-1),
+            rcvbuf: Some(1000),
+            sndbuf: Some(2000),
+            payload_size: Some(1316),
+            ..SrtOptions::default()
+        });
+        assert_eq!(SrtOptions::parse(""), Some(SrtOptions::default()));
+
+        assert!(SrtOptions::parse("latency").is_none());
+        assert!(SrtOptions::parse("latency=-1").is_none());
+        assert!(SrtOptions::parse("passphrase=short").is_none());
+        assert!(SrtOptions::parse("pbkeylen=20").is_none());
+        assert!(SrtOptions::parse("maxbw=-2").is_none());
+        assert!(SrtOptions::parse("rcvbuf=0").is_none());
+        assert!(SrtOptions::parse("mode=listener").is_none());
+        assert!(SrtOptions::parse("unknown=1").is_none());
+    }
+
+    #[test]
+    fn parses_srt_listeners() {
+        let listen: SrtListen = "0.0.0.0:9000?latency=200".parse().unwrap();
+        assert_eq!(listen.address, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(listen.options.latency, Some(200));
+
+        // calling out options and host names are for pulls
+        assert!("0.0.0.0:9000?mode=rendezvous".parse::<SrtListen>().is_err());
+        assert!("0.0.0.0:9000?streamid=live".parse::<SrtListen>().is_err());
+        assert!("localhost:9000".parse::<SrtListen>().is_err());
+    }
+
+    #[test]
+    fn parses_srt_pulls() {
+        let pull: SrtPull = "encoder1=10.0.0.5:9000?mode=rendezvous&localport=9001&streamid=live".parse().unwrap();
+        assert_eq!(pull.stream_name, "encoder1");
+        assert_eq!(pull.address, "10.0.0.5:9000");
+        assert!(pull.options.rendezvous);
+        assert_eq!(pull.options.local_port, Some(9001));
+        assert_eq!(pull.options.stream_id.as_deref(), Some("live"));
+
+        assert!("encoder1=10.0.0.5".parse::<SrtPull>().is_err());
+        assert!("=10.0.0.5:9000".parse::<SrtPull>().is_err());
+        assert!("encoder1=10.0.0.5:9000?latency=x".parse::<SrtPull>().is_err());
+    }
+
+    #[test]
+    fn redacts_srt_passphrase_outside_the_environment() {
+        let pull: SrtPull = "encoder1=10.0.0.5:9000?latency=200&passphrase=0123456789".parse().unwrap();
+        assert_eq!(pull.to_string(), "encoder1=10.0.0.5:9000?latency=200&passphrase=***");
+        assert_eq!(pull.to_env().parse::<SrtPull>().unwrap(), pull);
+
+        let listen: SrtListen = "0.0.0.0:9000?passphrase=0123456789".parse().unwrap();
+        assert_eq!(listen.to_string(), "0.0.0.0:9000?passphrase=***");
+        assert_eq!(listen.to_env().parse::<SrtListen>().unwrap(), listen);
+    }
+}
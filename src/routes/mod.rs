@@ -1,20 +1,67 @@
-use std::convert::Infallible;
+use std::{convert::Infallible, net::SocketAddr, sync::atomic::{AtomicU64, Ordering}};
 
-use axum::{Router, routing::get, extract::{Path, State, Query}, http::{header, Response, StatusCode, Method}, body::Body, response::IntoResponse};
+use axum::{Router, routing::{get, post}, extract::{Path, State, Query, BodyStream, ConnectInfo, FromRef}, http::{header, Response, StatusCode, Method}, body::Body, response::IntoResponse};
 use bytes::Bytes;
 use serde::Deserialize;
 use tower_http::cors::CorsLayer;
-use futures::stream;
-use crate::hls::SegmentStores;
+use futures::{stream, StreamExt};
+use mpegts::demuxer::ProgramSelection;
+use crate::{hls::SegmentStores, session::{ManagerHandle, connection::Connection, source::StreamSource}};
 
-pub fn create_app(store: SegmentStores) -> Router {
+static INGEST_CLIENT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone)]
+pub struct AppState {
+    store: SegmentStores,
+    manager_handle: ManagerHandle,
+    program_selection: ProgramSelection,
+}
+
+impl FromRef<AppState> for SegmentStores {
+    fn from_ref(state: &AppState) -> Self {
+        state.store.clone()
+    }
+}
+
+impl FromRef<AppState> for ManagerHandle {
+    fn from_ref(state: &AppState) -> Self {
+        state.manager_handle.clone()
+    }
+}
+
+impl FromRef<AppState> for ProgramSelection {
+    fn from_ref(state: &AppState) -> Self {
+        state.program_selection.clone()
+    }
+}
+
+pub fn create_app(store: SegmentStores, manager_handle: ManagerHandle, program_selection: ProgramSelection) -> Router {
     Router::new()
+        .route("/ingest/:id", post(ingest).put(ingest))
+        .route("/:id/master.m3u8", get(multivariant_playlist))
         .route("/:id/playlist.m3u8", get(playlist))
         .route("/:id/segment.m4s", get(segment))
         .route("/:id/part.m4s", get(part))
         .route("/:id/init.mp4", get(init_segment))
         .layer(CorsLayer::new().allow_methods([Method::GET]))
-        .with_state(store)
+        .with_state(AppState { store, manager_handle, program_selection })
+}
+
+/// Publishes a transport stream sent as the request body, usually with
+/// chunked transfer encoding. The stream ends with the body.
+async fn ingest(Path(stream_name): Path<String>, ConnectInfo(peer): ConnectInfo<SocketAddr>, State(manager_handle): State<ManagerHandle>, State(program_selection): State<ProgramSelection>, body: BodyStream) -> impl IntoResponse {
+    let id = INGEST_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+
+    let body = body.map(|chunk| chunk.map_err(anyhow::Error::from)).boxed();
+    let conn = Connection::new(id, StreamSource::new(stream_name, Some(peer), body), manager_handle, program_selection);
+
+    match conn.run().await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(err) => {
+            log::error!("{}", err);
+            StatusCode::BAD_REQUEST
+        },
+    }
 }
 
 // Overide due the specific naming
@@ -82,6 +129,24 @@ async fn playlist(Path(stream_name): Path<String>, Query(query): Query<LlhlsQuer
     }    
 }
 
+async fn multivariant_playlist(Path(stream_name): Path<String>, State(state): State<SegmentStores>) -> impl IntoResponse {
+    let lock = state.read().await;
+
+    if let Some(Ok(Some(manifest))) = lock.get(&stream_name).map(|store| store.get_multivariant_manifest_text()) {
+        return Response::builder()
+            .header(header::CONTENT_TYPE, "application/x-mpegURL")
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(header::CACHE_CONTROL, "max-age=0")
+            .body(Body::from(manifest))
+            .unwrap()
+    }
+
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::empty())
+        .unwrap()
+}
+
 #[derive(Deserialize)]
 struct Segment {
     msn: Option<usize>,
@@ -151,14 +216,25 @@ async fn part(Path(stream_name): Path<String>, Query(query): Query<Partial>, Sta
         .unwrap()
 }
 
-async fn init_segment(Path(stream_name): Path<String>, State(state): State<SegmentStores>) -> impl IntoResponse {
+#[derive(Deserialize)]
+struct InitSegment {
+    map: Option<usize>,
+}
+
+/// Init segments named by `map` never change, without it the latest one is served.
+async fn init_segment(Path(stream_name): Path<String>, Query(query): Query<InitSegment>, State(state): State<SegmentStores>) -> impl IntoResponse {
     let lock = state.read().await;
 
     if let Some(store) = lock.get(&stream_name) {
-        if let Some(init_bytes) = store.init_segment_ready() {
+        let (init_bytes, cache_control) = match query.map {
+            Some(map) => (store.init_segment(map), "max-age=31536000"),
+            None => (store.init_segment_ready(), "max-age=0"),
+        };
+
+        if let Some(init_bytes) = init_bytes {
             return Response::builder()
                     .header("Content-Type", "video/mp4")
-                    .header("Cache-Control", "max-age=31536000")
+                    .header("Cache-Control", cache_control)
                     .body(Body::from(init_bytes))
                     .unwrap()
         }
@@ -169,4 +245,60 @@ async fn init_segment(Path(stream_name): Path<String>, State(state): State<Segme
         .body(Body::empty())
         .unwrap()
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::{Ipv4Addr, SocketAddr}, sync::Arc};
+    use mpegts::{demuxer::ProgramSelection, muxer::Muxer, stream_type::StreamType};
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, sync::{mpsc, RwLock}};
+    use crate::session::{ChannelMessage, Codec, Message};
+    use super::create_app;
+
+    #[tokio::test]
+    async fn publishes_request_body() {
+        let (manager_handle, mut manager) = mpsc::unbounded_channel();
+        let app = create_app(Arc::new(RwLock::new(HashMap::new())), manager_handle, ProgramSelection::All);
+
+        let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+        let mut body = muxer.write_tables().to_vec();
+        body.extend_from_slice(&muxer.write(video, &[0x00, 0x00, 0x01, 0x65], 3000, None, true).unwrap());
+
+        let mut request = format!("POST /ingest/test HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", address, body.len()).into_bytes();
+        request.extend_from_slice(&body);
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(&request).await.unwrap();
+
+        let (session, mut messages) = mpsc::unbounded_channel();
+        match manager.recv().await {
+            Some(ChannelMessage::Create((stream_name, responder))) => {
+                assert_eq!(stream_name, "test");
+                responder.send(session).unwrap();
+            },
+            _ => panic!("expected the request to create a session"),
+        }
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 204"), "{}", response);
+
+        // the end of the body releases the stream
+        assert!(matches!(manager.recv().await, Some(ChannelMessage::Release(stream_name)) if stream_name == "test"));
+
+        assert!(matches!(messages.recv().await, Some(Message::Tracks(tracks)) if tracks == vec![Codec::H264]));
+        let mut packets = 0;
+        while let Ok(message) = messages.try_recv() {
+            if let Message::Packet(packet) = message {
+                assert_eq!(packet.pts, 3000);
+                packets += 1;
+            }
+        }
+        assert_eq!(packets, 1);
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use anyhow::{bail, Result};
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::message::{RtmpMessage, ABORT, SET_CHUNK_SIZE};
+
+pub const DEFAULT_CHUNK_SIZE: usize = 128;
+const MAX_CHUNK_SIZE: usize = 0xFF_FFFF;
+const EXTENDED_TIMESTAMP: u32 = 0xFF_FFFF;
+
+/// Header state kept per chunk stream, later chunks only carry what changed.
+#[derive(Default)]
+struct ChunkStream {
+    timestamp: u32,
+    timestamp_delta: u32,
+    length: usize,
+    type_id: u8,
+    stream_id: u32,
+    extended: bool,
+    payload: BytesMut,
+}
+
+/// Reassembles RTMP messages from the interleaved chunk streams.
+pub struct ChunkReader {
+    chunk_size: usize,
+    streams: HashMap<u32, ChunkStream>,
+    bytes_read: u64,
+}
+
+impl Default for ChunkReader {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            streams: HashMap::new(),
+            bytes_read: 0,
+        }
+    }
+}
+
+impl ChunkReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Total bytes read off the connection, used for acknowledgements
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    pub async fn read_message<R>(&mut self, reader: &mut R) -> Result<RtmpMessage>
+    where
+        R: AsyncRead + Unpin,
+    {
+        loop {
+            let message = match self.read_chunk(reader).await? {
+                Some(message) => message,
+                None => continue,
+            };
+
+            // both change how the following chunks are read so they're handled here
+            match message.type_id {
+                SET_CHUNK_SIZE if message.payload.len() >= 4 => {
+                    let chunk_size = (message.payload.clone().get_u32() & 0x7FFF_FFFF) as usize;
+                    if chunk_size == 0 {
+                        bail!("invalid chunk size 0");
+                    }
+
+                    self.chunk_size = chunk_size.min(MAX_CHUNK_SIZE);
+                    log::debug!("Peer chunk size set to {}", self.chunk_size);
+                },
+                ABORT if message.payload.len() >= 4 => {
+                    let csid = message.payload.clone().get_u32();
+                    if let Some(stream) = self.streams.get_mut(&csid) {
+                        stream.payload.clear();
+                    }
+                },
+                _ => {},
+            }
+
+            return Ok(message);
+        }
+    }
+
+    async fn read_chunk<R>(&mut self, reader: &mut R) -> Result<Option<RtmpMessage>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut basic_header = [0; 3];
+        read_exact(reader, &mut basic_header[..1], &mut self.bytes_read).await?;
+
+        let fmt = basic_header[0] >> 6;
+        let csid = match basic_header[0] & 0x3F {
+            0 => {
+                read_exact(reader, &mut basic_header[1..2], &mut self.bytes_read).await?;
+                64 + basic_header[1] as u32
+            },
+            1 => {
+                read_exact(reader, &mut basic_header[1..3], &mut self.bytes_read).await?;
+                64 + basic_header[1] as u32 + basic_header[2] as u32 * 256
+            },
+            csid => csid as u32,
+        };
+
+        let mut header = [0; 11];
+        let header_length = [11, 7, 3, 0][fmt as usize];
+        read_exact(reader, &mut header[..header_length], &mut self.bytes_read).await?;
+
+        let extended = if fmt < 3 {
+            be24(&header[0..3]) == EXTENDED_TIMESTAMP
+        } else {
+            match self.streams.get(&csid) {
+                Some(stream) => stream.extended,
+                None => bail!("chunk stream {} continued before it started", csid),
+            }
+        };
+
+        let timestamp = if extended {
+            let mut extended_timestamp = [0; 4];
+            read_exact(reader, &mut extended_timestamp, &mut self.bytes_read).await?;
+            u32::from_be_bytes(extended_timestamp)
+        } else {
+            be24(&header[0..3])
+        };
+
+        let stream = self.streams.entry(csid).or_default();
+
+        if fmt < 3 && !stream.payload.is_empty() {
+            log::warn!("Chunk stream {} started a new message before finishing the last one", csid);
+            stream.payload.clear();
+        }
+
+        let starting = stream.payload.is_empty();
+
+        if fmt < 3 {
+            stream.extended = extended;
+        }
+
+        match fmt {
+            0 => {
+                // a type 3 chunk starting the next message uses the absolute timestamp as its delta
+                stream.timestamp = timestamp;
+                stream.timestamp_delta = timestamp;
+                stream.length = be24(&header[3..6]) as usize;
+                stream.type_id = header[6];
+                stream.stream_id = u32::from_le_bytes([header[7], header[8], header[9], header[10]]);
+            },
+            1 => {
+                stream.timestamp_delta = timestamp;
+                stream.timestamp = stream.timestamp.wrapping_add(timestamp);
+                stream.length = be24(&header[3..6]) as usize;
+                stream.type_id = header[6];
+            },
+            2 => {
+                stream.timestamp_delta = timestamp;
+                stream.timestamp = stream.timestamp.wrapping_add(timestamp);
+            },
+            _ => {
+                // a type 3 chunk starting a new message repeats the last delta
+                if starting {
+                    stream.timestamp = stream.timestamp.wrapping_add(stream.timestamp_delta);
+                }
+            },
+        }
+
+        let remaining = stream.length - stream.payload.len();
+        let chunk_length = remaining.min(self.chunk_size);
+
+        let offset = stream.payload.len();
+        stream.payload.resize(offset + chunk_length, 0);
+        read_exact(reader, &mut stream.payload[offset..], &mut self.bytes_read).await?;
+
+        if stream.payload.len() < stream.length {
+            return Ok(None);
+        }
+
+        Ok(Some(RtmpMessage {
+            type_id: stream.type_id,
+            stream_id: stream.stream_id,
+            timestamp: stream.timestamp,
+            payload: stream.payload.split().freeze(),
+        }))
+    }
+}
+
+/// Splits outgoing messages into chunks. Everything is sent with full
+/// headers, we only send a handful of messages.
+pub struct ChunkWriter {
+    chunk_size: usize,
+}
+
+impl Default for ChunkWriter {
+    fn default() -> Self {
+        Self { chunk_size: DEFAULT_CHUNK_SIZE }
+    }
+}
+
+impl ChunkWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size;
+    }
+
+    pub fn write(&self, csid: u8, message: &RtmpMessage) -> Vec<u8> {
+        let extended = message.timestamp >= EXTENDED_TIMESTAMP;
+        let timestamp = if extended { EXTENDED_TIMESTAMP } else { message.timestamp };
+
+        let mut out = Vec::with_capacity(message.payload.len() + 16);
+        out.push(csid & 0x3F);
+        out.extend(&timestamp.to_be_bytes()[1..]);
+        out.extend(&(message.payload.len() as u32).to_be_bytes()[1..]);
+        out.push(message.type_id);
+        out.extend(message.stream_id.to_le_bytes());
+        if extended {
+            out.extend(message.timestamp.to_be_bytes());
+        }
+
+        for (i, chunk) in message.payload.chunks(self.chunk_size).enumerate() {
+            if i > 0 {
+                out.push(0xC0 | (csid & 0x3F));
+                if extended {
+                    out.extend(message.timestamp.to_be_bytes());
+                }
+            }
+
+            out.extend(chunk);
+        }
+
+        out
+    }
+}
+
+fn be24(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32
+}
+
+async fn read_exact<R>(reader: &mut R, buf: &mut [u8], bytes_read: &mut u64) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    reader.read_exact(buf).await?;
+    *bytes_read += buf.len() as u64;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use crate::rtmp::message::{RtmpMessage, ABORT, AUDIO, SET_CHUNK_SIZE, VIDEO};
+    use super::{ChunkReader, ChunkWriter, DEFAULT_CHUNK_SIZE};
+
+    async fn read_all(mut data: &[u8]) -> Vec<RtmpMessage> {
+        let mut reader = ChunkReader::new();
+        let mut messages = Vec::new();
+
+        while !data.is_empty() {
+            messages.push(reader.read_message(&mut data).await.unwrap());
+        }
+
+        messages
+    }
+
+    fn summary(messages: &[RtmpMessage]) -> Vec<(u8, u32, u32, Vec<u8>)> {
+        messages
+            .iter()
+            .map(|message| (message.type_id, message.stream_id, message.timestamp, message.payload.to_vec()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reads_every_header_type() {
+        let chunks = [
+            // type 0: timestamp 1000, length 3, video on stream 1
+            &[0x04, 0x00, 0x03, 0xE8, 0x00, 0x00, 0x03, VIDEO, 0x01, 0x00, 0x00, 0x00, 1, 2, 3][..],
+            // type 1: delta 40, length 2, audio
+            &[0x44, 0x00, 0x00, 0x28, 0x00, 0x00, 0x02, AUDIO, 4, 5],
+            // type 2: delta 20
+            &[0x84, 0x00, 0x00, 0x14, 6, 7],
+            // type 3: everything repeated, including the delta
+            &[0xC4, 8, 9],
+        ].concat();
+
+        assert_eq!(summary(&read_all(&chunks).await), vec![
+            (VIDEO, 1, 1000, vec![1, 2, 3]),
+            (AUDIO, 1, 1040, vec![4, 5]),
+            (AUDIO, 1, 1060, vec![6, 7]),
+            (AUDIO, 1, 1080, vec![8, 9]),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn type_3_after_type_0_uses_the_timestamp_as_delta() {
+        let chunks = [
+            &[0x04, 0x00, 0x00, 0x21, 0x00, 0x00, 0x01, AUDIO, 0x01, 0x00, 0x00, 0x00, 1][..],
+            &[0xC4, 2],
+            &[0xC4, 3],
+        ].concat();
+
+        let timestamps: Vec<u32> = read_all(&chunks).await.iter().map(|message| message.timestamp).collect();
+        assert_eq!(timestamps, vec![33, 66, 99]);
+    }
+
+    #[tokio::test]
+    async fn reassembles_interleaved_chunk_streams() {
+        let video: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let audio: Vec<u8> = (0..200).map(|i| !i as u8).collect();
+
+        let chunks = [
+            &[0x04, 0x00, 0x00, 0x0A, 0x00, 0x00, 0xC8, VIDEO, 0x01, 0x00, 0x00, 0x00][..],
+            &video[..DEFAULT_CHUNK_SIZE],
+            // chunk stream 70 takes the two byte basic header
+            &[0x00, 70 - 64, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC8, AUDIO, 0x01, 0x00, 0x00, 0x00],
+            &audio[..DEFAULT_CHUNK_SIZE],
+            &[0xC0, 70 - 64],
+            &audio[DEFAULT_CHUNK_SIZE..],
+            &[0xC4],
+            &video[DEFAULT_CHUNK_SIZE..],
+        ].concat();
+
+        assert_eq!(summary(&read_all(&chunks).await), vec![
+            (AUDIO, 1, 20, audio),
+            (VIDEO, 1, 10, video),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reads_extended_timestamps() {
+        let message = RtmpMessage {
+            type_id: VIDEO,
+            stream_id: 1,
+            timestamp: 0x0123_4567,
+            payload: Bytes::from(vec![0x17; 300]),
+        };
+
+        // the extended timestamp is repeated on every continuation chunk
+        let chunks = ChunkWriter::new().write(4, &message);
+        assert_eq!(&chunks[1..4], &[0xFF, 0xFF, 0xFF]);
+        assert_eq!(&chunks[12..16], &[0x01, 0x23, 0x45, 0x67]);
+        assert_eq!(&chunks[16 + DEFAULT_CHUNK_SIZE..16 + DEFAULT_CHUNK_SIZE + 5], &[0xC4, 0x01, 0x23, 0x45, 0x67]);
+
+        assert_eq!(summary(&read_all(&chunks).await), summary(&[message]));
+    }
+
+    #[tokio::test]
+    async fn follows_set_chunk_size_and_abort() {
+        let mut writer = ChunkWriter::new();
+        let mut chunks = writer.write(2, &RtmpMessage::set_chunk_size(256));
+
+        writer.set_chunk_size(256);
+        chunks.extend(writer.write(4, &RtmpMessage {
+            type_id: VIDEO,
+            stream_id: 1,
+            timestamp: 0,
+            payload: Bytes::from(vec![0x17; 200]),
+        }));
+
+        // a 300 byte message on chunk stream 6, abandoned after its first chunk
+        chunks.extend([0x06, 0x00, 0x00, 0x0A, 0x00, 0x01, 0x2C, AUDIO, 0x01, 0x00, 0x00, 0x00]);
+        chunks.extend([0xAF; 256]);
+        chunks.extend(writer.write(2, &RtmpMessage {
+            type_id: ABORT,
+            stream_id: 0,
+            timestamp: 0,
+            payload: Bytes::copy_from_slice(&6u32.to_be_bytes()),
+        }));
+
+        // so the next type 3 chunk starts a new message
+        chunks.push(0xC6);
+        chunks.extend([0xAF; 256]);
+        chunks.push(0xC6);
+        chunks.extend([0xAF; 44]);
+
+        let messages = read_all(&chunks).await;
+        let types: Vec<u8> = messages.iter().map(|message| message.type_id).collect();
+        assert_eq!(types, vec![SET_CHUNK_SIZE, VIDEO, ABORT, AUDIO]);
+
+        assert_eq!(messages[1].payload.len(), 200);
+        assert_eq!(messages[3].payload.len(), 300);
+        assert_eq!(messages[3].timestamp, 20);
+    }
+}
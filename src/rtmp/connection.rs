@@ -0,0 +1,610 @@
+use std::time::Duration;
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use common::{FormatReader, FormatWriter};
+use flv::{Amf0Value, AudioPacket, AudioTag, VideoCodec, VideoPacket, VideoTag};
+use aac::{AacCoder, AudioDataTransportStream, Raw};
+use h264::{Avcc, H264Coder};
+use h265::{hvcc::Hvcc, H265Coder};
+use tokio::{io::{AsyncWriteExt, BufReader}, net::{TcpStream, tcp::{OwnedReadHalf, OwnedWriteHalf}}, sync::oneshot, time::timeout};
+use crate::session::{ManagerHandle, Handle, ChannelMessage, Message, Packet, Codec};
+
+use super::{chunk::{ChunkReader, ChunkWriter}, handshake::handshake, message::{self, RtmpMessage}};
+
+const TIME_OUT: Duration = Duration::from_secs(5);
+
+const CHUNK_SIZE: u32 = 4096;
+const WINDOW_ACK_SIZE: u32 = 2_500_000;
+const CONTROL_CSID: u8 = 2;
+const COMMAND_CSID: u8 = 3;
+/// Only a single stream is published per connection
+const STREAM_ID: u32 = 1;
+/// Commands and metadata take a few hundred bytes, anything much larger
+/// isn't decoded
+const MAX_COMMAND_SIZE: usize = 64 * 1024;
+
+/// The session clock is only moved on in steps of at least 100ms, audio and
+/// video timestamps are interleaved and may go back a little.
+const CLOCK_STEP: u64 = mpegts::HZ as u64 / 10;
+
+const FOURCC_AVC1: u32 = u32::from_be_bytes(*b"avc1");
+const FOURCC_HVC1: u32 = u32::from_be_bytes(*b"hvc1");
+const FOURCC_MP4A: u32 = u32::from_be_bytes(*b"mp4a");
+
+enum State {
+    Connecting,
+    Publishing(String, Handle),
+    Disconnecting,
+}
+
+pub struct Connection {
+    id: u64,
+    manager_handle: ManagerHandle,
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    chunk_reader: ChunkReader,
+    chunk_writer: ChunkWriter,
+    app_name: Option<String>,
+    peer_window: Option<u32>,
+    acknowledged: u64,
+    tracks: Vec<Codec>,
+    h264_coder: Option<H264Coder>,
+    h265_coder: Option<H265Coder>,
+    aac_coder: Option<AacCoder>,
+    clock: Option<u64>,
+    state: State,
+}
+
+impl Connection {
+    pub fn new(id: u64, stream: TcpStream, manager_handle: ManagerHandle) -> Self {
+        let (reader, writer) = stream.into_split();
+
+        Self {
+            id,
+            manager_handle,
+            reader: BufReader::new(reader),
+            writer,
+            chunk_reader: ChunkReader::new(),
+            chunk_writer: ChunkWriter::new(),
+            app_name: None,
+            peer_window: None,
+            acknowledged: 0,
+            tracks: Vec::new(),
+            h264_coder: None,
+            h265_coder: None,
+            aac_coder: None,
+            clock: None,
+            state: State::Connecting,
+        }
+    }
+
+    pub async fn run(mut self) -> Result<()> {
+        let result = self.serve().await;
+
+        // release the stream whichever way the publisher went away
+        self.disconnect()?;
+        result
+    }
+
+    async fn serve(&mut self) -> Result<()> {
+        timeout(TIME_OUT, handshake(&mut self.reader, &mut self.writer)).await??;
+
+        loop {
+            if let State::Disconnecting = self.state {
+                log::debug!("Disconnecting...");
+                return Ok(());
+            }
+
+            match timeout(TIME_OUT, self.chunk_reader.read_message(&mut self.reader)).await {
+                Ok(Ok(message)) => {
+                    self.handle_message(message).await?;
+                    self.acknowledge().await?;
+                },
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    async fn handle_message(&mut self, message: RtmpMessage) -> Result<()> {
+        match message.type_id {
+            message::COMMAND_AMF0 | message::COMMAND_AMF3 | message::DATA_AMF0 | message::DATA_AMF3 if message.payload.len() > MAX_COMMAND_SIZE => {
+                bail!("Client {} sent a {} byte command", self.id, message.payload.len());
+            },
+            message::COMMAND_AMF0 | message::COMMAND_AMF3 => self.handle_command(message).await?,
+            message::DATA_AMF0 | message::DATA_AMF3 => self.handle_data(message)?,
+            message::VIDEO => self.handle_video(message)?,
+            message::AUDIO => self.handle_audio(message)?,
+            message::WINDOW_ACK_SIZE if message.payload.len() >= 4 => {
+                self.peer_window = Some(u32::from_be_bytes([message.payload[0], message.payload[1], message.payload[2], message.payload[3]]));
+            },
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    async fn handle_command(&mut self, message: RtmpMessage) -> Result<()> {
+        let values = message.amf0_values()?;
+
+        let name = values.first().and_then(Amf0Value::as_str).unwrap_or_default();
+        let transaction_id = values.get(1).and_then(Amf0Value::as_number).unwrap_or_default();
+
+        log::debug!("Client {} sent {}", self.id, name);
+
+        match name {
+            "connect" => {
+                self.app_name = values
+                    .get(2)
+                    .and_then(|command_object| command_object.get("app"))
+                    .and_then(Amf0Value::as_str)
+                    .map(|app| app.trim_matches('/').to_owned());
+
+                self.send(CONTROL_CSID, &RtmpMessage::window_ack_size(WINDOW_ACK_SIZE)).await?;
+                self.send(CONTROL_CSID, &RtmpMessage::set_peer_bandwidth(WINDOW_ACK_SIZE)).await?;
+                self.send(CONTROL_CSID, &RtmpMessage::set_chunk_size(CHUNK_SIZE)).await?;
+                self.chunk_writer.set_chunk_size(CHUNK_SIZE as usize);
+
+                self.send(COMMAND_CSID, &RtmpMessage::command(0, &[
+                    Amf0Value::String("_result".into()),
+                    Amf0Value::Number(transaction_id),
+                    Amf0Value::Object(vec![
+                        ("fmsVer".into(), Amf0Value::String("FMS/3,0,1,123".into())),
+                        ("capabilities".into(), Amf0Value::Number(31.0)),
+                    ]),
+                    status("NetConnection.Connect.Success", "Connection succeeded."),
+                ])).await?;
+            },
+            "releaseStream" | "FCPublish" => {
+                self.send(COMMAND_CSID, &RtmpMessage::command(0, &[
+                    Amf0Value::String("_result".into()),
+                    Amf0Value::Number(transaction_id),
+                    Amf0Value::Null,
+                ])).await?;
+            },
+            "createStream" => {
+                self.send(COMMAND_CSID, &RtmpMessage::command(0, &[
+                    Amf0Value::String("_result".into()),
+                    Amf0Value::Number(transaction_id),
+                    Amf0Value::Null,
+                    Amf0Value::Number(STREAM_ID as f64),
+                ])).await?;
+            },
+            "publish" => {
+                // OBS and ffmpeg pass tokens and options after a '?'
+                let stream_key = values
+                    .get(3)
+                    .and_then(Amf0Value::as_str)
+                    .and_then(|key| key.split('?').next())
+                    .unwrap_or_default()
+                    .to_owned();
+
+                self.publish(stream_key, message.stream_id).await?;
+            },
+            "FCUnpublish" | "deleteStream" | "closeStream" => self.disconnect()?,
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    /// The stream key names the stream, publishers that only set an app
+    /// (e.g. `rtmp://host/test`) are published under the app name.
+    async fn publish(&mut self, stream_key: String, stream_id: u32) -> Result<()> {
+        if let State::Publishing(..) = self.state {
+            return Ok(());
+        }
+
+        let stream_name = match (stream_key.is_empty(), &self.app_name) {
+            (false, _) => stream_key,
+            (true, Some(app_name)) if !app_name.is_empty() => app_name.clone(),
+            _ => {
+                self.send(COMMAND_CSID, &RtmpMessage::command(stream_id, &[
+                    Amf0Value::String("onStatus".into()),
+                    Amf0Value::Number(0.0),
+                    Amf0Value::Null,
+                    status("NetStream.Publish.BadName", "Missing stream key."),
+                ])).await?;
+
+                bail!("Client {} published without a stream key", self.id);
+            },
+        };
+
+        // stream names end up as a single path segment of the playlist url
+        let stream_name = stream_name.replace('/', "-");
+
+        let (request, response) = oneshot::channel();
+        self.manager_handle
+            .send(ChannelMessage::Create((stream_name.clone(), request)))?;
+        let session_sender = response.await?;
+
+        self.send(CONTROL_CSID, &RtmpMessage::stream_begin(stream_id)).await?;
+        self.send(COMMAND_CSID, &RtmpMessage::command(stream_id, &[
+            Amf0Value::String("onStatus".into()),
+            Amf0Value::Number(0.0),
+            Amf0Value::Null,
+            status("NetStream.Publish.Start", &format!("{} is now published.", stream_name)),
+        ])).await?;
+
+        log::info!("Client {} publishing {}", self.id, stream_name);
+
+        // tracks already known from the metadata
+        if !self.tracks.is_empty() {
+            session_sender.send(Message::Tracks(self.tracks.clone()))?;
+        }
+
+        self.state = State::Publishing(stream_name, session_sender);
+        Ok(())
+    }
+
+    /// onMetaData, sent either on its own or through @setDataFrame. Codec ids
+    /// are the legacy FLV ids or, with enhanced RTMP, FourCCs.
+    fn handle_data(&mut self, message: RtmpMessage) -> Result<()> {
+        let values = message.amf0_values()?;
+
+        if !values.iter().any(|value| value.as_str() == Some("onMetaData")) {
+            return Ok(());
+        }
+
+        let metadata = match values.iter().find(|value| matches!(value, Amf0Value::EcmaArray(_) | Amf0Value::Object(_))) {
+            Some(metadata) => metadata,
+            None => return Ok(()),
+        };
+
+        log::info!("Client {} metadata: {:?}", self.id, metadata);
+
+        let video = match metadata.get("videocodecid") {
+            Some(Amf0Value::Number(codec_id)) => match *codec_id as u32 {
+                7 | FOURCC_AVC1 => Some(Codec::H264),
+                12 | FOURCC_HVC1 => Some(Codec::H265),
+                _ => None,
+            },
+            Some(Amf0Value::String(fourcc)) => match fourcc.as_str() {
+                "avc1" => Some(Codec::H264),
+                "hvc1" => Some(Codec::H265),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let audio = match metadata.get("audiocodecid") {
+            Some(Amf0Value::Number(codec_id)) if matches!(*codec_id as u32, 10 | FOURCC_MP4A) => Some(Codec::AAC),
+            Some(Amf0Value::String(fourcc)) if fourcc == "mp4a" => Some(Codec::AAC),
+            _ => None,
+        };
+
+        for codec in video.into_iter().chain(audio) {
+            self.add_track(codec)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_video(&mut self, message: RtmpMessage) -> Result<()> {
+        let tag = match VideoTag::parse(message.payload) {
+            Ok(tag) => tag,
+            Err(err) => {
+                log::warn!("Client {} sent unsupported video: {}", self.id, err);
+                return Ok(());
+            },
+        };
+
+        let (composition_time, data) = match tag.packet {
+            VideoPacket::SequenceStart(config) => {
+                // a new sequence header replaces the parameter sets
+                match tag.codec {
+                    VideoCodec::Avc => {
+                        let mut coder = H264Coder::new();
+                        coder.read_format(Avcc, &config)?;
+                        self.h264_coder = Some(coder);
+                        self.add_track(Codec::H264)?;
+                    },
+                    VideoCodec::Hevc => {
+                        let mut coder = H265Coder::new();
+                        coder.read_format(Hvcc, &config)?;
+                        self.h265_coder = Some(coder);
+                        self.add_track(Codec::H265)?;
+                    },
+                }
+
+                return Ok(());
+            },
+            VideoPacket::CodedFrames { composition_time, data } => (composition_time, data),
+            VideoPacket::SequenceEnd | VideoPacket::Command => return Ok(()),
+        };
+
+        // frames ahead of their sequence header can't be decoded
+        let (codec, data) = match tag.codec {
+            VideoCodec::Avc => match &mut self.h264_coder {
+                Some(coder) => match coder.read_format(Avcc, &data)? {
+                    Some(avc) => (Codec::H264, coder.write_format(h264::AnnexB, avc)?),
+                    None => return Ok(()),
+                },
+                None => return Ok(()),
+            },
+            VideoCodec::Hevc => match &mut self.h265_coder {
+                Some(coder) => match coder.read_format(Hvcc, &data)? {
+                    Some(hevc) => (Codec::H265, coder.write_format(h265::annexb::AnnexB, hevc)?),
+                    None => return Ok(()),
+                },
+                None => return Ok(()),
+            },
+        };
+
+        let dts = timestamp_90khz(message.timestamp);
+        let pts = (dts as i64 + composition_time as i64 * 90).rem_euclid(mpegts::PCR_CYCLE as i64) as u64;
+
+        self.send_packet(Packet {
+            codec,
+            data: Bytes::from(data),
+            pts,
+            dts: Some(dts),
+        })
+    }
+
+    fn handle_audio(&mut self, message: RtmpMessage) -> Result<()> {
+        let tag = match AudioTag::parse(message.payload) {
+            Ok(tag) => tag,
+            Err(err) => {
+                log::warn!("Client {} sent unsupported audio: {}", self.id, err);
+                return Ok(());
+            },
+        };
+
+        let data = match tag.packet {
+            AudioPacket::SequenceHeader(config) => {
+                let mut coder = AacCoder::new();
+                coder.set_asc(&config[..])?;
+                self.aac_coder = Some(coder);
+                return self.add_track(Codec::AAC);
+            },
+            AudioPacket::Raw(data) => data,
+        };
+
+        let adts = match &mut self.aac_coder {
+            Some(coder) => match coder.read_format(Raw, &data)? {
+                Some(aac) => coder.write_format(AudioDataTransportStream, aac)?,
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+
+        self.send_packet(Packet {
+            codec: Codec::AAC,
+            data: Bytes::from(adts),
+            pts: timestamp_90khz(message.timestamp),
+            dts: None,
+        })
+    }
+
+    fn add_track(&mut self, codec: Codec) -> Result<()> {
+        if self.tracks.contains(&codec) {
+            return Ok(());
+        }
+
+        self.tracks.push(codec);
+
+        if let State::Publishing(_, session) = &self.state {
+            session.send(Message::Tracks(self.tracks.clone()))?;
+        }
+
+        Ok(())
+    }
+
+    /// RTMP has no clock reference of its own, the media timestamps drive the session clock.
+    fn send_packet(&mut self, packet: Packet) -> Result<()> {
+        let session = match &self.state {
+            State::Publishing(_, session) => session,
+            _ => return Ok(()),
+        };
+
+        let dts = packet.dts.unwrap_or(packet.pts);
+        let advance = match self.clock {
+            Some(clock) => {
+                let diff = (dts + mpegts::PCR_CYCLE - clock) % mpegts::PCR_CYCLE;
+                (CLOCK_STEP..mpegts::PCR_CYCLE / 2).contains(&diff)
+            },
+            None => true,
+        };
+
+        if advance {
+            session.send(Message::ClockRef(dts))?;
+            self.clock = Some(dts);
+        }
+
+        session.send(Message::Packet(packet))?;
+        Ok(())
+    }
+
+    async fn acknowledge(&mut self) -> Result<()> {
+        let window = match self.peer_window {
+            Some(window) => window as u64,
+            None => return Ok(()),
+        };
+
+        let bytes_read = self.chunk_reader.bytes_read();
+        if bytes_read - self.acknowledged >= window {
+            self.send(CONTROL_CSID, &RtmpMessage::acknowledgement(bytes_read as u32)).await?;
+            self.acknowledged = bytes_read;
+        }
+
+        Ok(())
+    }
+
+    async fn send(&mut self, csid: u8, message: &RtmpMessage) -> Result<()> {
+        let chunks = self.chunk_writer.write(csid, message);
+        self.writer.write_all(&chunks).await?;
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        // the manager disconnects the session once the reconnect grace period is over
+        if let State::Publishing(stream_name, _) = &self.state {
+            self.manager_handle.send(ChannelMessage::Release(stream_name.clone()))?;
+        }
+
+        self.state = State::Disconnecting;
+        Ok(())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        log::info!("Client {} disconnected", self.id);
+    }
+}
+
+fn status(code: &str, description: &str) -> Amf0Value {
+    Amf0Value::Object(vec![
+        ("level".into(), Amf0Value::String("status".into())),
+        ("code".into(), Amf0Value::String(code.into())),
+        ("description".into(), Amf0Value::String(description.into())),
+    ])
+}
+
+/// RTMP timestamps are in milliseconds
+fn timestamp_90khz(timestamp: u32) -> u64 {
+    timestamp as u64 * 90 % mpegts::PCR_CYCLE
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use flv::Amf0Value;
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}, sync::mpsc, task::JoinHandle};
+    use crate::{rtmp::{chunk::{ChunkReader, ChunkWriter}, message::{self, RtmpMessage}}, session::{ChannelMessage, ManagerHandle}};
+    use super::{Connection, COMMAND_CSID, MAX_COMMAND_SIZE, STREAM_ID};
+
+    /// Publisher side of a connection, past the handshake.
+    struct Client {
+        stream: TcpStream,
+        chunk_reader: ChunkReader,
+        chunk_writer: ChunkWriter,
+    }
+
+    impl Client {
+        async fn connect(manager_handle: ManagerHandle) -> (Client, JoinHandle<anyhow::Result<()>>) {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+            let (peer, _) = listener.accept().await.unwrap();
+            let connection = tokio::spawn(Connection::new(0, peer, manager_handle).run());
+
+            let c1: Vec<u8> = (0..1536).map(|i| i as u8).collect();
+            stream.write_all(&[3]).await.unwrap();
+            stream.write_all(&c1).await.unwrap();
+
+            let mut s0_s1_s2 = vec![0; 1 + 1536 * 2];
+            stream.read_exact(&mut s0_s1_s2).await.unwrap();
+            assert_eq!(s0_s1_s2[0], 3);
+            assert_eq!(&s0_s1_s2[1 + 1536..], &c1[..]);
+
+            // C2 echoes S1
+            stream.write_all(&s0_s1_s2[1..1 + 1536]).await.unwrap();
+
+            let client = Client {
+                stream,
+                chunk_reader: ChunkReader::new(),
+                chunk_writer: ChunkWriter::new(),
+            };
+
+            (client, connection)
+        }
+
+        async fn command(&mut self, stream_id: u32, values: &[Amf0Value]) {
+            let chunks = self.chunk_writer.write(COMMAND_CSID, &RtmpMessage::command(stream_id, values));
+            self.stream.write_all(&chunks).await.unwrap();
+        }
+
+        /// The next command from the server, skipping control messages.
+        async fn reply(&mut self) -> Vec<Amf0Value> {
+            loop {
+                let message = self.chunk_reader.read_message(&mut self.stream).await.unwrap();
+                if message.type_id == message::COMMAND_AMF0 {
+                    return message.amf0_values().unwrap();
+                }
+            }
+        }
+    }
+
+    fn code(values: &[Amf0Value]) -> Option<&str> {
+        values.get(3)?.get("code")?.as_str()
+    }
+
+    #[tokio::test]
+    async fn publishes_under_the_stream_key() {
+        let (manager_handle, mut manager) = mpsc::unbounded_channel();
+        let (mut client, connection) = Client::connect(manager_handle).await;
+
+        client.command(0, &[
+            Amf0Value::String("connect".into()),
+            Amf0Value::Number(1.0),
+            Amf0Value::Object(vec![("app".into(), Amf0Value::String("live".into()))]),
+        ]).await;
+
+        let reply = client.reply().await;
+        assert_eq!(reply[0].as_str(), Some("_result"));
+        assert_eq!(reply[1].as_number(), Some(1.0));
+        assert_eq!(code(&reply), Some("NetConnection.Connect.Success"));
+
+        client.command(0, &[
+            Amf0Value::String("createStream".into()),
+            Amf0Value::Number(2.0),
+            Amf0Value::Null,
+        ]).await;
+
+        let reply = client.reply().await;
+        assert_eq!(reply[0].as_str(), Some("_result"));
+        assert_eq!(reply[1].as_number(), Some(2.0));
+        assert_eq!(reply[3].as_number(), Some(STREAM_ID as f64));
+
+        client.command(STREAM_ID, &[
+            Amf0Value::String("publish".into()),
+            Amf0Value::Number(3.0),
+            Amf0Value::Null,
+            Amf0Value::String("test?token=secret".into()),
+            Amf0Value::String("live".into()),
+        ]).await;
+
+        let (session, _messages) = mpsc::unbounded_channel();
+        match manager.recv().await {
+            Some(ChannelMessage::Create((stream_name, responder))) => {
+                assert_eq!(stream_name, "test");
+                responder.send(session).unwrap();
+            },
+            _ => panic!("expected the connection to create a session"),
+        }
+
+        let reply = client.reply().await;
+        assert_eq!(reply[0].as_str(), Some("onStatus"));
+        assert_eq!(code(&reply), Some("NetStream.Publish.Start"));
+
+        client.command(STREAM_ID, &[
+            Amf0Value::String("deleteStream".into()),
+            Amf0Value::Number(4.0),
+            Amf0Value::Null,
+            Amf0Value::Number(STREAM_ID as f64),
+        ]).await;
+
+        connection.await.unwrap().unwrap();
+        assert!(matches!(manager.recv().await, Some(ChannelMessage::Release(stream_name)) if stream_name == "test"));
+    }
+
+    #[tokio::test]
+    async fn refuses_oversized_commands() {
+        let (manager_handle, _manager) = mpsc::unbounded_channel();
+        let (mut client, connection) = Client::connect(manager_handle).await;
+
+        // deeply nested objects, which must not reach the decoder
+        let mut payload = [0x03, 0x00, 0x00].repeat(MAX_COMMAND_SIZE / 3 + 1);
+        payload.insert(0, 0x03);
+
+        let chunks = client.chunk_writer.write(COMMAND_CSID, &RtmpMessage {
+            type_id: message::COMMAND_AMF0,
+            stream_id: 0,
+            timestamp: 0,
+            payload: Bytes::from(payload),
+        });
+        client.stream.write_all(&chunks).await.unwrap();
+
+        assert!(connection.await.unwrap().is_err());
+    }
+}
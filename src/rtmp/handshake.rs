@@ -0,0 +1,42 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{bail, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const RTMP_VERSION: u8 = 3;
+const HANDSHAKE_SIZE: usize = 1536;
+
+/// Plain (unsigned) RTMP handshake. Publishers accept it, only Flash
+/// players insisted on the digest scheme.
+pub async fn handshake<R, W>(reader: &mut R, writer: &mut W) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let version = reader.read_u8().await?;
+    if version != RTMP_VERSION {
+        bail!("unsupported RTMP version {}", version);
+    }
+
+    let mut c1 = [0; HANDSHAKE_SIZE];
+    reader.read_exact(&mut c1).await?;
+
+    let mut s0_s1_s2 = Vec::with_capacity(1 + HANDSHAKE_SIZE * 2);
+    s0_s1_s2.push(RTMP_VERSION);
+
+    // S1: time, zero, then filler the client echoes back in C2
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u32;
+    s0_s1_s2.extend(time.to_be_bytes());
+    s0_s1_s2.extend([0; 4]);
+    s0_s1_s2.extend((0..HANDSHAKE_SIZE - 8).map(|i| (i as u32).wrapping_mul(2_654_435_761).wrapping_add(time) as u8));
+
+    // S2 echoes C1
+    s0_s1_s2.extend(c1);
+
+    writer.write_all(&s0_s1_s2).await?;
+    writer.flush().await?;
+
+    let mut c2 = [0; HANDSHAKE_SIZE];
+    reader.read_exact(&mut c2).await?;
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use flv::Amf0Value;
+
+pub const SET_CHUNK_SIZE: u8 = 1;
+pub const ABORT: u8 = 2;
+pub const ACKNOWLEDGEMENT: u8 = 3;
+pub const USER_CONTROL: u8 = 4;
+pub const WINDOW_ACK_SIZE: u8 = 5;
+pub const SET_PEER_BANDWIDTH: u8 = 6;
+pub const AUDIO: u8 = 8;
+pub const VIDEO: u8 = 9;
+pub const DATA_AMF3: u8 = 15;
+pub const COMMAND_AMF3: u8 = 17;
+pub const DATA_AMF0: u8 = 18;
+pub const COMMAND_AMF0: u8 = 20;
+
+const STREAM_BEGIN: u16 = 0;
+
+#[derive(Debug, Clone)]
+pub struct RtmpMessage {
+    pub type_id: u8,
+    pub stream_id: u32,
+    pub timestamp: u32,
+    pub payload: Bytes,
+}
+
+impl RtmpMessage {
+    fn control(type_id: u8, payload: Bytes) -> Self {
+        Self { type_id, stream_id: 0, timestamp: 0, payload }
+    }
+
+    fn u32_control(type_id: u8, value: u32) -> Self {
+        Self::control(type_id, Bytes::copy_from_slice(&value.to_be_bytes()))
+    }
+
+    pub fn set_chunk_size(chunk_size: u32) -> Self {
+        Self::u32_control(SET_CHUNK_SIZE, chunk_size & 0x7FFF_FFFF)
+    }
+
+    pub fn acknowledgement(sequence_number: u32) -> Self {
+        Self::u32_control(ACKNOWLEDGEMENT, sequence_number)
+    }
+
+    pub fn window_ack_size(window: u32) -> Self {
+        Self::u32_control(WINDOW_ACK_SIZE, window)
+    }
+
+    /// Dynamic limit type
+    pub fn set_peer_bandwidth(window: u32) -> Self {
+        let mut payload = BytesMut::with_capacity(5);
+        payload.put_u32(window);
+        payload.put_u8(2);
+        Self::control(SET_PEER_BANDWIDTH, payload.freeze())
+    }
+
+    pub fn stream_begin(stream_id: u32) -> Self {
+        let mut payload = BytesMut::with_capacity(6);
+        payload.put_u16(STREAM_BEGIN);
+        payload.put_u32(stream_id);
+        Self::control(USER_CONTROL, payload.freeze())
+    }
+
+    pub fn command(stream_id: u32, values: &[Amf0Value]) -> Self {
+        let mut payload = BytesMut::new();
+        for value in values {
+            value.encode(&mut payload);
+        }
+
+        Self { type_id: COMMAND_AMF0, stream_id, timestamp: 0, payload: payload.freeze() }
+    }
+
+    /// The AMF0 body of a command or data message. The AMF3 variants only
+    /// differ by a leading format byte, the values themselves are still AMF0.
+    pub fn amf0_values(&self) -> Result<Vec<Amf0Value>, flv::FlvError> {
+        match self.type_id {
+            COMMAND_AMF3 | DATA_AMF3 if !self.payload.is_empty() => Amf0Value::decode_all(&self.payload[1..]),
+            _ => Amf0Value::decode_all(&self.payload),
+        }
+    }
+}
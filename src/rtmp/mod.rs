@@ -0,0 +1,56 @@
+use std::net::SocketAddr;
+use anyhow::Result;
+use tokio::net::{TcpListener, TcpStream};
+use crate::session::ManagerHandle;
+
+mod chunk;
+mod connection;
+mod handshake;
+mod message;
+
+use connection::Connection;
+
+pub struct RtmpService {
+    manager_handle: ManagerHandle,
+    client_id: u64,
+}
+
+impl RtmpService {
+    pub fn new(manager_handle: ManagerHandle) -> Self {
+        Self {
+            manager_handle,
+            client_id: 0,
+        }
+    }
+
+    pub async fn run(mut self, addr: SocketAddr) {
+        if let Err(err) = self.handle_rtmp(addr).await {
+            log::error!("{}", err);
+        }
+    }
+
+    async fn handle_rtmp(&mut self, addr: SocketAddr) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        log::info!("Listening for RTMP connections on {}", addr);
+
+        loop {
+            let (peer_stream, peer_addr) = listener.accept().await?;
+
+            self.process(peer_stream, peer_addr);
+            self.client_id += 1;
+        }
+    }
+
+    fn process(&self, stream: TcpStream, peer: SocketAddr) {
+        log::info!("New RTMP client connection: {}, ({})", &self.client_id, &peer);
+
+        let id = self.client_id;
+        let conn = Connection::new(id, stream, self.manager_handle.clone());
+
+        tokio::spawn(async move {
+            if let Err(err) = conn.run().await {
+                log::error!("{}", err);
+            }
+        });
+    }
+}
@@ -1,17 +1,17 @@
-use std::time::Duration;
+use std::{collections::HashMap, time::{Duration, Instant}};
 use anyhow::Result;
-use mpegts::{demuxer::Demuxer, DemuxerEvent, stream_type::StreamType};
-use srt_rs::stream::SrtStream;
+use mpegts::{demuxer::{Demuxer, ProgramSelection}, pid::Pid, DemuxerEvent, stream_type::StreamType, section::scte35::SpliceDirection};
 use tokio::{time::timeout, sync::oneshot};
-use crate::session::Message;
+use crate::{metrics, session::Message};
 
-use super::{ManagerHandle, Handle, ChannelMessage, Packet, Codec};
+use super::{source::IngestSource, ManagerHandle, Handle, ChannelMessage, Packet, Codec, Cue, CueKind};
 
 const TIME_OUT: std::time::Duration = Duration::from_secs(5);
+const STATS_INTERVAL: std::time::Duration = Duration::from_secs(1);
 
 enum State {
     Initializing,
-    Publishing(Handle),
+    Publishing(HashMap<u16, (String, Handle)>),
     Disconnecting,
 }
 
@@ -19,22 +19,32 @@ pub struct Connection {
     id: u64,
     manager_handle: ManagerHandle,
     app_name: Option<String>,
-    stream: SrtStream,
+    source: Box<dyn IngestSource>,
     demuxer: Demuxer,
+    /// The audio stream published for each program
+    audio_pids: HashMap<u16, Pid>,
+    stats_reported: Instant,
     state: State,
 }
 
 impl Connection{
-    pub fn new(id: u64, stream: SrtStream, manager_handle: ManagerHandle) -> Self {
+    pub fn new<S: IngestSource + 'static>(id: u64, source: S, manager_handle: ManagerHandle, program_selection: ProgramSelection) -> Self {
 
-        let app_name = stream.get_stream_id().ok();
+        let app_name = source.stream_id();
+
+        match source.peer() {
+            Some(peer) => log::info!("New client connection: {}, ({})", id, peer),
+            None => log::info!("New client connection: {}, ({})", id, app_name.as_deref().unwrap_or_default()),
+        }
 
         Self {
             id,
-            stream,
+            source: Box::new(source),
             manager_handle,
             app_name,
-            demuxer: Demuxer::new(),
+            demuxer: Demuxer::with_program_selection(program_selection),
+            audio_pids: HashMap::new(),
+            stats_reported: Instant::now(),
             state: State::Initializing,
         }
     }
@@ -43,16 +53,31 @@ impl Connection{
         loop {
             match &mut self.state {
                 State::Initializing | State::Publishing(_) => {
-                    let mut buf = [0; 1316];
-                    let message = self.stream.recvmsg2(&mut buf);
+                    let message = self.source.recv();
 
-                    match timeout(TIME_OUT, message).await? {
-                        Ok((size, _)) => {
-                            for event in self.demuxer.push(&mut buf[..size])? {
-                                self.handle_event(event).await?;
+                    // a source going quiet is treated like one disconnecting
+                    match timeout(TIME_OUT, message).await {
+                        Ok(Ok(Some(data))) => {
+                            // bad packets are skipped by the demuxer, the publisher stays connected
+                            match self.demuxer.push(&data) {
+                                Ok(events) => {
+                                    for event in events {
+                                        self.handle_event(event).await?;
+                                    }
+                                },
+                                Err(err) => log::warn!("Client {} sent unreadable data: {}", self.id, err),
                             }
+
+                            self.report_stats();
                         }
-                        _ => self.disconnect()?,
+                        _ => {
+                            // hand over anything still waiting on a PES start
+                            for event in self.demuxer.flush() {
+                                self.handle_event(event).await?;
+                            }
+
+                            self.disconnect()?
+                        },
                     }
                 }
                 State::Disconnecting => {
@@ -63,76 +88,217 @@ impl Connection{
         }
     }
 
+    /// Single program streams are published under the stream id, each
+    /// program of a multi-program stream gets its own `{stream id}-{program}`.
+    /// This goes by the programs listed in the PAT rather than the ones
+    /// selected, so a program is named the same whatever the selection.
+    fn stream_name(&self, program_number: u16) -> String {
+        let app_name = self.app_name.clone().unwrap();
+
+        if self.demuxer.pat_program_count() > 1 {
+            format!("{}-{}", app_name, program_number)
+        } else {
+            app_name
+        }
+    }
+
+    fn session(&self, program_number: u16) -> Option<&Handle> {
+        match &self.state {
+            State::Publishing(sessions) => sessions.get(&program_number).map(|(_, handle)| handle),
+            _ => None,
+        }
+    }
+
+    /// The codec a stream is published as, `None` for streams that are not packaged.
+    fn codec(&self, program_number: u16, pid: &Pid, stream_type: &StreamType) -> Option<Codec> {
+        match stream_type {
+            StreamType::H264 => Some(Codec::H264),
+            StreamType::H265 => Some(Codec::H265),
+            StreamType::AV1 => Some(Codec::AV1),
+            StreamType::AAC => Some(Codec::AAC),
+            StreamType::OPUS => Some(Codec::OPUS(self.opus_channel_config_code(program_number, pid))),
+            StreamType::AC3 => Some(Codec::AC3),
+            StreamType::EAC3 => Some(Codec::EAC3),
+            StreamType::MPEG1Audio | StreamType::MPEG2Audio => Some(Codec::MP3),
+            _ => None,
+        }
+    }
+
+    /// Channel config of an Opus stream, stereo when the PMT has no Opus audio descriptor for it.
+    fn opus_channel_config_code(&self, program_number: u16, pid: &Pid) -> u8 {
+        self.demuxer
+            .pmt(program_number)
+            .and_then(|pmt| pmt.opus_channel_config_code(pid))
+            .unwrap_or(0x02)
+    }
+
     async fn handle_event(&mut self, event: DemuxerEvent) -> Result<()> {
         match event {
-            DemuxerEvent::StreamDetails(streams) => {
+            DemuxerEvent::StreamDetails(program_number, streams) => {
+                log::info!("Stream Info (program {}): {:?}", program_number, &streams);
+
+                if let Some(pmt) = self.demuxer.pmt(program_number) {
+                    for pid in streams.keys() {
+                        if let Some(language) = pmt.language(pid) {
+                            log::info!("{:?} language: {}", pid, language);
+                        }
+                    }
+                }
+
+                // a rendition carries a single audio track, the one on the lowest pid
+                let mut pids: Vec<&Pid> = streams.keys().collect();
+                pids.sort_by_key(|pid| u16::from(**pid));
+
+                let mut tracks = Vec::new();
+                let mut audio_pid = None;
+
+                for pid in pids {
+                    let Some(codec) = self.codec(program_number, pid, &streams[pid]) else {
+                        continue;
+                    };
+
+                    if codec.is_audio() {
+                        if audio_pid.is_some() {
+                            log::info!("Program {} dropping additional audio stream {:?}", program_number, pid);
+                            continue;
+                        }
+
+                        audio_pid = Some(*pid);
+                    }
+
+                    tracks.push(codec);
+                }
+
+                match audio_pid {
+                    Some(pid) => self.audio_pids.insert(program_number, pid),
+                    None => self.audio_pids.remove(&program_number),
+                };
+
+                // PMT updates for a program that is already publishing
+                if let Some(session) = self.session(program_number) {
+                    session.send(Message::Tracks(tracks))?;
+                    return Ok(());
+                }
+
                 let (request, response) = oneshot::channel();
-                let app_name = self.app_name.clone().unwrap();
+                let stream_name = self.stream_name(program_number);
 
                 self.manager_handle
-                    .send(ChannelMessage::Create((app_name, request)))?;
+                    .send(ChannelMessage::Create((stream_name.clone(), request)))?;
                 let session_sender = response.await?;
 
-                self.state = State::Publishing(session_sender);
+                if let State::Initializing = self.state {
+                    self.state = State::Publishing(HashMap::new());
+                }
+
+                session_sender.send(Message::Tracks(tracks))?;
 
-                log::info!("Stream Info: {:?}", &streams);
+                if let State::Publishing(sessions) = &mut self.state {
+                    sessions.insert(program_number, (stream_name, session_sender));
+                }
             },
 
-            DemuxerEvent::Video(stream_type, data, pts, dts) => {
-                if let State::Publishing(session) = &mut self.state {
-
-                    if stream_type == StreamType::H264 {
-                        let packet = Packet {
-                            codec: Codec::H264,
-                            data,
-                            pts: pts.unwrap(),
-                            dts,
-                        };
-
-                        session.send(Message::Packet(packet))?;
-                    } else if stream_type == StreamType::H265 {
-                        let packet = Packet {
-                            codec: Codec::H265,
-                            data,
-                            pts: pts.unwrap(),
-                            dts,
-                        };
-
-                        session.send(Message::Packet(packet))?;
-                    }
-                    
+            DemuxerEvent::Video(program_number, pid, stream_type, data, pts, dts) => {
+                let Some(codec) = self.codec(program_number, &pid, &stream_type) else {
+                    return Ok(());
+                };
+
+                // the PTS is optional in a PES header, a frame without one can't be placed
+                let Some(pts) = pts else {
+                    log::debug!("Client {} skipped {:?} PES without PTS", self.id, pid);
+                    return Ok(());
+                };
+
+                if let Some(session) = self.session(program_number) {
+                    session.send(Message::Packet(Packet { codec, data, pts, dts }))?;
                 }
             },
-            DemuxerEvent::Audio(stream_type, data, pts) => {
-                if let State::Publishing(session) = &mut self.state {
-
-                    let packet = Packet {
-                        codec: Codec::AAC,
-                        data,
-                        pts: pts.unwrap(),
-                        dts: None,
-                    };
+            DemuxerEvent::Audio(program_number, pid, stream_type, data, pts) => {
+                if self.audio_pids.get(&program_number) != Some(&pid) {
+                    return Ok(());
+                }
 
-                    session.send(Message::Packet(packet))?;
+                let Some(codec) = self.codec(program_number, &pid, &stream_type) else {
+                    return Ok(());
+                };
+
+                let Some(pts) = pts else {
+                    log::debug!("Client {} skipped {:?} PES without PTS", self.id, pid);
+                    return Ok(());
+                };
+
+                if let Some(session) = self.session(program_number) {
+                    session.send(Message::Packet(Packet { codec, data, pts, dts: None }))?;
                 }
             },
-            DemuxerEvent::ClockRef(dcr) => {
-                if let State::Publishing(session) = &mut self.state {
+            DemuxerEvent::ClockRef(program_number, dcr) => {
+                if let Some(session) = self.session(program_number) {
                     session.send(Message::ClockRef(dcr))?;
                 }
             },
+            DemuxerEvent::Discontinuity(program_number) => {
+                if let Some(session) = self.session(program_number) {
+                    session.send(Message::Discontinuity)?;
+                }
+            },
+            DemuxerEvent::Splice(program_number, splice) => {
+                if let Some(session) = self.session(program_number) {
+                    let kind = match splice.direction() {
+                        Some(SpliceDirection::Out) => CueKind::Out,
+                        Some(SpliceDirection::In) => CueKind::In,
+                        // cancels don't carry a direction, the writer only needs the id
+                        None if splice.is_cancel() => CueKind::Out,
+                        None => return Ok(()),
+                    };
+
+                    let cue = Cue {
+                        id: splice.event_id().unwrap_or_default(),
+                        kind,
+                        cancel: splice.is_cancel(),
+                        pts: splice.splice_time(),
+                        duration: splice.duration(),
+                        data: splice.data,
+                    };
+
+                    session.send(Message::Cue(cue))?;
+                }
+            },
+            DemuxerEvent::SyncAcquired(packet_size) => {
+                log::debug!("Client {} synced on {} byte packets", self.id, packet_size);
+            },
+            DemuxerEvent::SyncLost => {
+                log::warn!("Client {} lost transport stream sync", self.id);
+            },
         }
 
         Ok(())
     }
 
+    /// Transport stream stats describe the whole link, so they're labelled
+    /// with the stream id even when it carries several programs.
+    fn report_stats(&mut self) {
+        if self.stats_reported.elapsed() < STATS_INTERVAL {
+            return;
+        }
+
+        if let Some(app_name) = &self.app_name {
+            metrics::observe_ts_stats(app_name, self.demuxer.stats());
+        }
+
+        self.stats_reported = Instant::now();
+    }
+
     fn disconnect(&mut self) -> Result<()> {
-        if let State::Publishing(session) = &mut self.state {
-            let app_name = self.app_name.clone().unwrap();
+        if let Some(app_name) = &self.app_name {
+            metrics::remove_ts_stats(app_name);
+        }
 
-            session.send(Message::Disconnect)?;
 
-            self.manager_handle.send(ChannelMessage::Release(app_name))?;
+        if let State::Publishing(sessions) = &mut self.state {
+            // the manager disconnects the session once the reconnect grace period is over
+            for (_, (stream_name, _)) in sessions.drain() {
+                self.manager_handle.send(ChannelMessage::Release(stream_name))?;
+            }
         }
 
         self.state = State::Disconnecting;
@@ -145,4 +311,104 @@ impl Drop for Connection {
     fn drop(&mut self) {
         log::info!("Client {} disconnected", self.id);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use anyhow::Result;
+    use bytes::Bytes;
+    use futures::{stream::{self, BoxStream}, StreamExt};
+    use mpegts::{demuxer::ProgramSelection, muxer::Muxer, pid::Pid, stream_type::StreamType};
+    use tokio::{sync::mpsc, time::Instant};
+    use crate::session::{source::StreamSource, ChannelMessage, Codec, Message};
+    use super::{Connection, TIME_OUT};
+
+    /// Publishes the chunks under "test" and returns what the session was sent.
+    async fn publish(chunks: BoxStream<'static, Result<Bytes>>) -> Vec<Message> {
+        let (manager_handle, mut manager) = mpsc::unbounded_channel();
+        let source = StreamSource::new("test".to_owned(), None, chunks);
+        let connection = tokio::spawn(Connection::new(0, source, manager_handle, ProgramSelection::All).run());
+
+        let (session, mut messages) = mpsc::unbounded_channel();
+        match manager.recv().await {
+            Some(ChannelMessage::Create((stream_name, responder))) => {
+                assert_eq!(stream_name, "test");
+                responder.send(session).unwrap();
+            },
+            _ => panic!("expected the connection to create a session"),
+        }
+
+        connection.await.unwrap().unwrap();
+        assert!(matches!(manager.recv().await, Some(ChannelMessage::Release(stream_name)) if stream_name == "test"));
+
+        let mut received = Vec::new();
+        while let Ok(message) = messages.try_recv() {
+            received.push(message);
+        }
+
+        received
+    }
+
+    #[tokio::test]
+    async fn publishes_lowest_audio_pid_only() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+        let aac = muxer.add_stream_with_pid(StreamType::AAC, Pid::from(0x111));
+        let ac3 = muxer.add_stream_with_pid(StreamType::AC3, Pid::from(0x110));
+
+        let mut chunks = vec![muxer.write_tables()];
+        for n in 0..3 {
+            let pts = 3000 * n;
+            chunks.push(muxer.write(video, &[0x00, 0x00, 0x01, 0x65], pts, None, true).unwrap());
+            chunks.push(muxer.write(aac, &[0xFF; 16], pts, None, true).unwrap());
+            chunks.push(muxer.write(ac3, &[0x0B, 0x77, 0x00, 0x00], pts, None, true).unwrap());
+        }
+
+        let messages = publish(stream::iter(chunks).map(Ok).boxed()).await;
+
+        match messages.first() {
+            Some(Message::Tracks(tracks)) => assert_eq!(tracks, &vec![Codec::H264, Codec::AC3]),
+            message => panic!("expected tracks first, got {:?}", message),
+        }
+
+        let codecs: Vec<Codec> = messages.iter()
+            .filter_map(|message| match message {
+                Message::Packet(packet) => Some(packet.codec.clone()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(codecs.iter().filter(|codec| **codec == Codec::H264).count(), 3);
+        assert_eq!(codecs.iter().filter(|codec| **codec == Codec::AC3).count(), 3);
+        assert!(!codecs.contains(&Codec::AAC));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn releases_source_that_goes_quiet() {
+        let mut muxer = Muxer::new();
+        let video = muxer.add_stream(StreamType::H264);
+        let mut chunk = muxer.write_tables().to_vec();
+        chunk.extend_from_slice(&muxer.write(video, &[0x00, 0x00, 0x01, 0x65], 3000, None, true).unwrap());
+
+        let started = Instant::now();
+        let chunks = stream::iter([Ok(Bytes::from(chunk))]).chain(stream::pending()).boxed();
+        let messages = publish(chunks).await;
+
+        assert!(started.elapsed() >= TIME_OUT && started.elapsed() < TIME_OUT + Duration::from_secs(1));
+        assert!(matches!(messages.first(), Some(Message::Tracks(tracks)) if tracks == &vec![Codec::H264]));
+
+        // the frame still waiting on the next PES start is handed over on the way out
+        assert!(messages.iter().any(|message| matches!(message, Message::Packet(packet) if packet.pts == 3000)));
+    }
+
+    #[tokio::test]
+    async fn ends_on_source_error() {
+        let (manager_handle, mut manager) = mpsc::unbounded_channel::<ChannelMessage>();
+        let chunks = stream::iter([Err(anyhow::anyhow!("connection reset"))]).boxed();
+        let source = StreamSource::new("test".to_owned(), None, chunks);
+
+        // nothing was published, so there is nothing to release
+        Connection::new(0, source, manager_handle, ProgramSelection::All).run().await.unwrap();
+        assert!(manager.try_recv().is_err());
+    }
+}
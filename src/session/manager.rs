@@ -1,8 +1,8 @@
-use std::{sync::Arc, collections::HashMap};
+use std::{sync::Arc, collections::HashMap, time::Duration};
 use tokio::sync::{RwLock, mpsc, broadcast};
 use crate::Opt;
 
-use super::{ManagerHandle, ChannelReceiver, Trigger, Handle, OutgoingBroadcast, ChannelMessage, Message};
+use super::{RESERVED_STREAM_NAMES, ManagerHandle, ChannelReceiver, Trigger, Handle, OutgoingBroadcast, ChannelMessage, Message};
 use anyhow::{Result, bail};
 
 
@@ -11,11 +11,17 @@ pub struct SessionManager {
     incoming: ChannelReceiver,
     channels: Arc<RwLock<HashMap<String, (Handle, OutgoingBroadcast)>>>,
     triggers: Arc<RwLock<HashMap<String, Vec<Trigger>>>>,
+
+    grace_period: Duration,
+    /// Released sessions waiting out the grace period, keyed to the release
+    /// so a stale expiry can't tear down a publisher that came back.
+    released: HashMap<String, u64>,
+    next_release: u64,
 }
 
 impl SessionManager {
 
-    pub fn new() -> Self {
+    pub fn new(grace_period: Duration) -> Self {
         let (handle, incoming) = mpsc::unbounded_channel();
         let channels = Arc::new(RwLock::new(HashMap::new()));
         let triggers = Arc::new(RwLock::new(HashMap::new()));
@@ -25,6 +31,10 @@ impl SessionManager {
             incoming,
             channels,
             triggers,
+
+            grace_period,
+            released: HashMap::new(),
+            next_release: 0,
         }
     }
 
@@ -35,6 +45,27 @@ impl SessionManager {
     async fn process_message(&mut self, message: ChannelMessage) -> Result<()> {
         match message {
             ChannelMessage::Create((name, responder)) => {
+                // dropping the responder fails the publisher
+                if RESERVED_STREAM_NAMES.contains(&name.as_str()) {
+                    bail!("Refusing to publish under the reserved stream name {}", name);
+                }
+
+                if self.released.remove(&name).is_some() {
+                    let sessions = self.channels.read().await;
+
+                    if let Some((handle, _)) = sessions.get(&name) {
+                        log::info!("Publisher for {} reconnected, resuming stream", name);
+
+                        // the new publisher starts its own time base
+                        handle.send(Message::Discontinuity)?;
+
+                        if let Err(_) = responder.send(handle.clone()) {
+                            bail!("Failed to send response");
+                        }
+
+                        return Ok(());
+                    }
+                }
 
                 let (handle, mut incoming) = mpsc::unbounded_channel();
                 let (outgoing, _watcher) = broadcast::channel(64);
@@ -74,8 +105,31 @@ impl SessionManager {
             },
 
             ChannelMessage::Release(name) => {
-                let mut sessions = self.channels.write().await;
-                sessions.remove(&name);
+                if self.grace_period.is_zero() {
+                    self.teardown(&name).await?;
+                    return Ok(());
+                }
+
+                let release = self.next_release;
+                self.next_release += 1;
+                self.released.insert(name.clone(), release);
+
+                log::info!("Publisher for {} released, keeping the stream for {:?}", name, self.grace_period);
+
+                let handle = self.handle.clone();
+                let grace_period = self.grace_period;
+
+                tokio::spawn(async move {
+                    tokio::time::sleep(grace_period).await;
+                    let _ = handle.send(ChannelMessage::Expire((name, release)));
+                });
+            }
+
+            ChannelMessage::Expire((name, release)) => {
+                if self.released.get(&name) == Some(&release) {
+                    self.released.remove(&name);
+                    self.teardown(&name).await?;
+                }
             }
 
             ChannelMessage::Join((name, responder)) => {
@@ -97,6 +151,18 @@ impl SessionManager {
         Ok(())
     }
 
+    async fn teardown(&mut self, name: &str) -> Result<()> {
+        log::info!("Tearing down stream {}", name);
+
+        let mut sessions = self.channels.write().await;
+
+        if let Some((handle, _)) = sessions.remove(name) {
+            handle.send(Message::Disconnect)?;
+        }
+
+        Ok(())
+    }
+
     pub async fn run(mut self) {
         while let Some(message) = self.incoming.recv().await {
             if let Err(err) = self.process_message(message).await {
@@ -104,4 +170,97 @@ impl SessionManager {
             };
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tokio::{sync::oneshot, time::sleep};
+    use crate::session::{ChannelMessage, Handle, ManagerHandle};
+    use super::SessionManager;
+
+    const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+    fn start() -> ManagerHandle {
+        let manager = SessionManager::new(GRACE_PERIOD);
+        let handle = manager.handle();
+        tokio::spawn(manager.run());
+        handle
+    }
+
+    async fn create(manager: &ManagerHandle, name: &str) -> Handle {
+        let (request, response) = oneshot::channel();
+        manager.send(ChannelMessage::Create((name.to_owned(), request))).unwrap();
+        response.await.unwrap()
+    }
+
+    async fn is_published(manager: &ManagerHandle, name: &str) -> bool {
+        let (request, response) = oneshot::channel();
+        manager.send(ChannelMessage::Join((name.to_owned(), request))).unwrap();
+        response.await.is_ok()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resumes_stream_reconnected_within_grace_period() {
+        let manager = start();
+        let first = create(&manager, "test").await;
+
+        manager.send(ChannelMessage::Release("test".to_owned())).unwrap();
+        sleep(GRACE_PERIOD / 2).await;
+
+        let second = create(&manager, "test").await;
+        assert!(first.same_channel(&second));
+
+        // the expiry of the first release must not tear down the resumed stream
+        sleep(GRACE_PERIOD).await;
+        assert!(is_published(&manager, "test").await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tears_down_stream_once_grace_period_expires() {
+        let manager = start();
+        let handle = create(&manager, "test").await;
+
+        manager.send(ChannelMessage::Release("test".to_owned())).unwrap();
+        sleep(GRACE_PERIOD / 2).await;
+        assert!(is_published(&manager, "test").await);
+
+        sleep(GRACE_PERIOD).await;
+        assert!(!is_published(&manager, "test").await);
+        assert!(handle.is_closed());
+
+        // a publisher coming back after that starts a new stream
+        let next = create(&manager, "test").await;
+        assert!(!handle.same_channel(&next));
+    }
+
+    #[tokio::test]
+    async fn refuses_reserved_stream_names() {
+        let manager = start();
+
+        let (request, response) = oneshot::channel();
+        manager.send(ChannelMessage::Create(("ingest".to_owned(), request))).unwrap();
+        assert!(response.await.is_err());
+        assert!(!is_published(&manager, "ingest").await);
+
+        create(&manager, "ingest-1").await;
+        assert!(is_published(&manager, "ingest-1").await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn only_latest_release_expires() {
+        let manager = start();
+        create(&manager, "test").await;
+
+        manager.send(ChannelMessage::Release("test".to_owned())).unwrap();
+        sleep(GRACE_PERIOD / 2).await;
+        create(&manager, "test").await;
+        manager.send(ChannelMessage::Release("test".to_owned())).unwrap();
+
+        // past the first release's expiry, inside the second's
+        sleep(GRACE_PERIOD * 3 / 4).await;
+        assert!(is_published(&manager, "test").await);
+
+        sleep(GRACE_PERIOD / 2).await;
+        assert!(!is_published(&manager, "test").await);
+    }
+}
@@ -2,12 +2,25 @@ use bytes::Bytes;
 use tokio::sync::{mpsc, oneshot, broadcast};
 pub mod manager;
 pub mod connection;
+pub mod source;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Codec {
     H264,
     H265,
+    AV1,
     AAC,
+    /// channel_config_code from the Opus audio descriptor
+    OPUS(u8),
+    AC3,
+    EAC3,
+    MP3,
+}
+
+impl Codec {
+    pub fn is_audio(&self) -> bool {
+        matches!(self, Codec::AAC | Codec::OPUS(_) | Codec::AC3 | Codec::EAC3 | Codec::MP3)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -18,20 +31,51 @@ pub struct Packet {
     pub dts: Option<u64>
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CueKind {
+    Out,
+    In,
+}
+
+/// An SCTE-35 splice point. `pts` is `None` for splice_immediate cues and
+/// `data` is the raw splice_info_section.
+#[derive(Clone, Debug)]
+pub struct Cue {
+    pub id: u32,
+    pub kind: CueKind,
+    pub cancel: bool,
+    pub pts: Option<u64>,
+    pub duration: Option<u64>,
+    pub data: Bytes,
+}
+
 pub enum ChannelMessage {
     Create((StreamName, Responder<Handle>)),
     Release(StreamName),
+    /// Sent by the manager to itself once a released stream's reconnect
+    /// grace period has run out.
+    Expire((StreamName, u64)),
     Join((StreamName, Responder<(Handle, Watcher)>)),
     RegisterTrigger(Event, Trigger),
 }
 
 #[derive(Clone, Debug)]
 pub enum Message {
+    /// Codecs of the tracks announced in the PMT, sent before their packets
+    Tracks(Vec<Codec>),
     ClockRef(DCR),
     Packet(Packet),
+    Cue(Cue),
+    /// The source signalled a new time base, the next ClockRef is not
+    /// continuous with the previous one.
+    Discontinuity,
     Disconnect,
 }
 
+/// Names no stream can be published under, playback of `/ingest/...` would
+/// be taken by the HTTP ingest route.
+pub const RESERVED_STREAM_NAMES: [&str; 1] = ["ingest"];
+
 pub fn trigger_channel() -> (Trigger, TriggerHandle) {
     mpsc::unbounded_channel()
 }
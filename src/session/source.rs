@@ -0,0 +1,50 @@
+use std::net::SocketAddr;
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt};
+
+/// A publisher's transport stream, whatever it is carried over.
+#[async_trait]
+pub trait IngestSource: Send {
+    /// Name the stream is published under, `None` if the publisher didn't give one.
+    fn stream_id(&self) -> Option<String>;
+
+    /// Address of the publisher, for sources that have one.
+    fn peer(&self) -> Option<SocketAddr>;
+
+    /// The next chunk of the transport stream, `None` once the source has ended.
+    async fn recv(&mut self) -> Result<Option<Bytes>>;
+}
+
+/// Any stream of byte chunks, e.g. an HTTP request body, published under a fixed name.
+pub struct StreamSource {
+    stream_name: String,
+    peer: Option<SocketAddr>,
+    stream: BoxStream<'static, Result<Bytes>>,
+}
+
+impl StreamSource {
+    pub fn new(stream_name: String, peer: Option<SocketAddr>, stream: BoxStream<'static, Result<Bytes>>) -> Self {
+        Self {
+            stream_name,
+            peer,
+            stream,
+        }
+    }
+}
+
+#[async_trait]
+impl IngestSource for StreamSource {
+    fn stream_id(&self) -> Option<String> {
+        Some(self.stream_name.clone())
+    }
+
+    fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    async fn recv(&mut self) -> Result<Option<Bytes>> {
+        self.stream.next().await.transpose()
+    }
+}
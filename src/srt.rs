@@ -1,35 +1,114 @@
-use std::net::SocketAddr;
-use crate::session::{ManagerHandle, connection::Connection};
-use anyhow::Result;
-use srt_rs::stream::SrtStream;
+use std::{net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs}, sync::Once, time::Duration};
+use crate::{option::{SrtListen, SrtOptions, SrtPull}, session::{ManagerHandle, connection::Connection, source::IngestSource}};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use mpegts::demuxer::ProgramSelection;
+use srt_rs::{SrtBuilder, socket::KeyLength, stream::SrtStream};
+
+/// Size of a typical SRT payload, seven transport stream packets
+const PAYLOAD_SIZE: usize = 1316;
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+static STARTUP: Once = Once::new();
+
+fn startup() {
+    STARTUP.call_once(|| {
+        srt_rs::startup().expect("Failed to start SRT libs");
+        srt_rs::log::log::set_level(srt_rs::log::log::Level::Debug);
+
+        let version = srt_rs::version();
+        log::info!("Using srt Version: {}.{}.{}", version.0, version.1, version.2);
+    });
+}
+
+pub struct SrtSource {
+    stream: SrtStream,
+    peer: SocketAddr,
+    /// Set for pulled streams, otherwise the caller's streamid is used
+    stream_name: Option<String>,
+}
+
+#[async_trait]
+impl IngestSource for SrtSource {
+    fn stream_id(&self) -> Option<String> {
+        self.stream_name.clone().or_else(|| self.stream.get_stream_id().ok())
+    }
+
+    fn peer(&self) -> Option<SocketAddr> {
+        Some(self.peer)
+    }
+
+    async fn recv(&mut self) -> Result<Option<Bytes>> {
+        let mut buf = [0; PAYLOAD_SIZE];
+        let (size, _) = self.stream.recvmsg2(&mut buf).await?;
+        Ok(Some(Bytes::copy_from_slice(&buf[..size])))
+    }
+}
+
+/// Turns the configured options into a builder, the sockets it creates have
+/// them set before binding or connecting.
+fn builder(options: &SrtOptions) -> Result<SrtBuilder> {
+    let mut builder = srt_rs::builder();
+
+    if let Some(latency) = options.latency {
+        builder = builder.latency(Duration::from_millis(latency));
+    }
+    if let Some(passphrase) = &options.passphrase {
+        builder = builder.passphrase(passphrase);
+    }
+    if let Some(pbkeylen) = options.pbkeylen {
+        builder = builder.pbkeylen(KeyLength::try_from(i32::from(pbkeylen))?);
+    }
+    if let Some(maxbw) = options.maxbw {
+        builder = builder.maxbw(maxbw);
+    }
+    if let Some(rcvbuf) = options.rcvbuf {
+        builder = builder.rcvbuf(rcvbuf);
+    }
+    if let Some(sndbuf) = options.sndbuf {
+        builder = builder.sndbuf(sndbuf);
+    }
+    if let Some(payload_size) = options.payload_size {
+        builder = builder.payload_size(payload_size);
+    }
+    if let Some(stream_id) = &options.stream_id {
+        builder = builder.stream_id(stream_id);
+    }
+
+    Ok(builder)
+}
 
 pub struct SrtService {
     manager_handle: ManagerHandle,
+    listen: SrtListen,
+    program_selection: ProgramSelection,
     client_id: u64,
 }
 
 impl SrtService {
-    pub fn new(manager_handle: ManagerHandle) -> Self {
-        srt_rs::startup().expect("Failed to start SRT libs");
-        srt_rs::log::log::set_level(srt_rs::log::log::Level::Debug);
+    pub fn new(manager_handle: ManagerHandle, listen: SrtListen, program_selection: ProgramSelection) -> Self {
+        startup();
 
-        let version = srt_rs::version();
-        log::info!("Using srt Version: {}.{}.{}", version.0, version.1, version.2);
         Self {
             manager_handle,
+            listen,
+            program_selection,
             client_id: 0,
         }
     }
 
-    pub async fn run(mut self, port: i32) {
-        if let Err(err) = self.handle_srt(port).await {
-            log::error!("{}", err);
+    pub async fn run(mut self) {
+        if let Err(err) = self.handle_srt().await {
+            log::error!("{}: {}", self.listen.address, err);
         }
     }
 
-    async fn handle_srt(&mut self, port: i32) -> Result<()> {
-        let addr = format!("127.0.0.1:{}", port);
-        let test = srt_rs::builder().listen(&addr, 1)?;
+    async fn handle_srt(&mut self) -> Result<()> {
+        let addr = self.listen.address;
+        let test = builder(&self.listen.options)?.listen(addr, 1)?;
         log::info!("Listening for SRT connections on {}", addr);
 
         loop {
@@ -41,10 +120,8 @@ impl SrtService {
     }
 
     fn process(&self, stream: SrtStream, peer: SocketAddr) {
-        log::info!("New client connection: {}, ({})", &self.client_id, &peer);
-
         let id = self.client_id;
-        let conn = Connection::new(id, stream, self.manager_handle.clone());
+        let conn = Connection::new(id, SrtSource { stream, peer, stream_name: None }, self.manager_handle.clone(), self.program_selection.clone());
 
         tokio::spawn(async move {
             if let Err(err) = conn.run().await {
@@ -55,3 +132,122 @@ impl SrtService {
 
 }
 
+/// Delays between calls to a remote, doubling while the calls fail and
+/// starting over once one has gone through.
+struct Backoff {
+    delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self { delay: MIN_RECONNECT_DELAY }
+    }
+}
+
+impl Backoff {
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.delay = MIN_RECONNECT_DELAY;
+    }
+}
+
+/// Calls a remote SRT listener, or meets a rendezvous peer, and publishes what
+/// it sends under a fixed stream name. Failed or dropped calls are retried,
+/// backing off from one second up to thirty while the remote stays unreachable.
+pub struct SrtPullService {
+    manager_handle: ManagerHandle,
+    pull: SrtPull,
+    program_selection: ProgramSelection,
+    client_id: u64,
+}
+
+impl SrtPullService {
+    pub fn new(manager_handle: ManagerHandle, pull: SrtPull, program_selection: ProgramSelection) -> Self {
+        startup();
+
+        Self {
+            manager_handle,
+            pull,
+            program_selection,
+            client_id: 0,
+        }
+    }
+
+    pub async fn run(mut self) {
+        let mut backoff = Backoff::default();
+
+        loop {
+            let result = self.pull().await;
+            if result.is_ok() {
+                backoff.reset();
+            }
+
+            let delay = backoff.next_delay();
+            match result {
+                Ok(()) => log::info!("SRT pull {} ended, calling again in {:?}", self.pull.stream_name, delay),
+                Err(err) => log::warn!("SRT pull {} from {}: {}, retrying in {:?}", self.pull.stream_name, self.pull.address, err, delay),
+            }
+
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Returns once a successful call has ended.
+    async fn pull(&mut self) -> Result<()> {
+        let builder = builder(&self.pull.options)?;
+
+        let (stream, peer) = if self.pull.options.rendezvous {
+            let remote = self.pull.address.to_socket_addrs()?.next().ok_or_else(|| anyhow!("{} did not resolve", self.pull.address))?;
+            let port = self.pull.options.local_port.unwrap_or(remote.port());
+            let local = match remote {
+                SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port),
+                SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port),
+            };
+
+            builder.rendezvous(local, remote)?.await?
+        } else {
+            builder.connect(&self.pull.address)?.await?
+        };
+        log::info!("Pulling SRT stream {} from {}", self.pull.stream_name, peer);
+
+        let source = SrtSource {
+            stream,
+            peer,
+            stream_name: Some(self.pull.stream_name.clone()),
+        };
+        let conn = Connection::new(self.client_id, source, self.manager_handle.clone(), self.program_selection.clone());
+        self.client_id += 1;
+
+        if let Err(err) = conn.run().await {
+            log::error!("{}", err);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{Backoff, MAX_RECONNECT_DELAY, MIN_RECONNECT_DELAY};
+
+    #[test]
+    fn backs_off_up_to_max_and_resets() {
+        let mut backoff = Backoff::default();
+
+        let delays: Vec<Duration> = (0..7).map(|_| backoff.next_delay()).collect();
+        let expected: Vec<Duration> = [1, 2, 4, 8, 16, 30, 30].into_iter().map(Duration::from_secs).collect();
+        assert_eq!(delays, expected);
+        assert_eq!(delays.last(), Some(&MAX_RECONNECT_DELAY));
+
+        // a call that went through starts the next failures from the minimum again
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), MIN_RECONNECT_DELAY);
+        assert_eq!(backoff.next_delay(), MIN_RECONNECT_DELAY * 2);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{collections::VecDeque, net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr}, sync::Arc};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use mpegts::demuxer::ProgramSelection;
+use tokio::net::UdpSocket;
+use crate::{option::UdpInput, session::{ManagerHandle, connection::Connection, source::IngestSource}};
+
+use self::rtp::{JitterBuffer, RtpPacket};
+
+mod rtp;
+
+const MAX_DATAGRAM_SIZE: usize = 65_536;
+
+/// Enough to ride out reordering on a LAN or a few hops of WAN, about 30ms
+/// at 10Mbit/s with 7 TS packets per datagram.
+const JITTER_BUFFER_DEPTH: usize = 32;
+
+/// Receives a transport stream over UDP and publishes it under a fixed
+/// stream name. The stream is released when the datagrams stop and
+/// published again as soon as they come back.
+pub struct UdpService {
+    manager_handle: ManagerHandle,
+    input: UdpInput,
+    program_selection: ProgramSelection,
+    client_id: u64,
+}
+
+impl UdpService {
+    pub fn new(manager_handle: ManagerHandle, input: UdpInput, program_selection: ProgramSelection) -> Self {
+        Self {
+            manager_handle,
+            input,
+            program_selection,
+            client_id: 0,
+        }
+    }
+
+    pub async fn run(mut self) {
+        if let Err(err) = self.handle_udp().await {
+            log::error!("{}: {}", self.input, err);
+        }
+    }
+
+    async fn handle_udp(&mut self) -> Result<()> {
+        let socket = Arc::new(self.bind().await?);
+
+        loop {
+            // wait for the sender to (re)appear
+            let (_, peer) = socket.peek_from(&mut [0; 1]).await?;
+
+            let source = UdpSource::new(Arc::clone(&socket), self.input.stream_name.clone(), peer);
+            let conn = Connection::new(self.client_id, source, self.manager_handle.clone(), self.program_selection.clone());
+
+            if let Err(err) = conn.run().await {
+                log::error!("{}", err);
+            }
+
+            self.client_id += 1;
+        }
+    }
+
+    async fn bind(&self) -> Result<UdpSocket> {
+        let address = self.input.address;
+
+        if !address.ip().is_multicast() {
+            let socket = UdpSocket::bind(address).await?;
+            log::info!("Listening for UDP transport streams on {}", address);
+            return Ok(socket);
+        }
+
+        let socket = match address.ip() {
+            IpAddr::V4(group) => {
+                let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), address.port())).await?;
+                let interface = match self.input.interface {
+                    Some(IpAddr::V4(interface)) => interface,
+                    _ => Ipv4Addr::UNSPECIFIED,
+                };
+                socket.join_multicast_v4(group, interface)?;
+                socket
+            },
+            IpAddr::V6(group) => {
+                let socket = UdpSocket::bind(SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), address.port())).await?;
+                // the default interface, IPv6 joins take an interface index
+                socket.join_multicast_v6(&group, 0)?;
+                socket
+            },
+        };
+
+        log::info!("Joined multicast group {}", address);
+        Ok(socket)
+    }
+}
+
+/// Datagrams from a UDP input with any RTP framing removed, in sequence order.
+pub struct UdpSource {
+    socket: Arc<UdpSocket>,
+    stream_name: String,
+    peer: SocketAddr,
+    jitter_buffer: JitterBuffer,
+    datagram: Vec<u8>,
+    ready: VecDeque<Bytes>,
+}
+
+impl UdpSource {
+    fn new(socket: Arc<UdpSocket>, stream_name: String, peer: SocketAddr) -> Self {
+        Self {
+            socket,
+            stream_name,
+            peer,
+            jitter_buffer: JitterBuffer::new(JITTER_BUFFER_DEPTH),
+            datagram: vec![0; MAX_DATAGRAM_SIZE],
+            ready: VecDeque::new(),
+        }
+    }
+
+}
+
+#[async_trait]
+impl IngestSource for UdpSource {
+    fn stream_id(&self) -> Option<String> {
+        Some(self.stream_name.clone())
+    }
+
+    /// The sender of the latest datagram
+    fn peer(&self) -> Option<SocketAddr> {
+        Some(self.peer)
+    }
+
+    async fn recv(&mut self) -> Result<Option<Bytes>> {
+        loop {
+            if let Some(payload) = self.ready.pop_front() {
+                return Ok(Some(payload));
+            }
+
+            let (size, peer) = self.socket.recv_from(&mut self.datagram).await?;
+            let datagram = Bytes::copy_from_slice(&self.datagram[..size]);
+            self.peer = peer;
+
+            if rtp::is_rtp(&datagram) {
+                match RtpPacket::parse(datagram) {
+                    Some(packet) => self.ready.extend(self.jitter_buffer.push(packet)),
+                    None => log::debug!("{} dropping malformed RTP packet", self.stream_name),
+                }
+            } else {
+                self.ready.push_back(datagram);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::{Ipv4Addr, SocketAddr}, sync::Arc};
+    use bytes::{BufMut, Bytes, BytesMut};
+    use tokio::net::UdpSocket;
+    use crate::session::source::IngestSource;
+    use super::UdpSource;
+
+    fn rtp(sequence_number: u16, payload: &[u8]) -> Bytes {
+        let mut data = BytesMut::new();
+        data.put_u8(0x80);
+        data.put_u8(33);
+        data.put_u16(sequence_number);
+        data.put_u32(0);
+        data.put_u32(0x1234_5678);
+        data.put_slice(payload);
+        data.freeze()
+    }
+
+    #[tokio::test]
+    async fn receives_rtp_in_order_and_bare_datagrams_as_sent() {
+        let localhost = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let socket = UdpSocket::bind(localhost).await.unwrap();
+        let sender = UdpSocket::bind(localhost).await.unwrap();
+        sender.connect(socket.local_addr().unwrap()).await.unwrap();
+
+        let mut source = UdpSource::new(Arc::new(socket), "test".to_owned(), sender.local_addr().unwrap());
+
+        for (sequence_number, payload) in [(1, [0x47, 1]), (3, [0x47, 3]), (2, [0x47, 2])] {
+            sender.send(&rtp(sequence_number, &payload)).await.unwrap();
+        }
+        sender.send(&[0x47, 4]).await.unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            received.push(source.recv().await.unwrap().unwrap());
+        }
+
+        assert_eq!(received, vec![&[0x47, 1][..], &[0x47, 2], &[0x47, 3], &[0x47, 4]]);
+        assert_eq!(source.peer(), Some(sender.local_addr().unwrap()));
+    }
+}
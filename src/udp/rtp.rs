@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+use bytes::{Buf, Bytes};
+
+const RTP_VERSION: u8 = 2;
+const FIXED_HEADER_SIZE: usize = 12;
+const TS_SYNC_BYTE: u8 = 0x47;
+
+/// Packets further behind than this are taken as the sender having
+/// restarted rather than arriving late.
+const MAX_MISORDER: u64 = 100;
+
+/// The parts of an RTP packet (RFC 3550) needed to put the MPEG-TS payload
+/// (RFC 2250) back in order.
+pub struct RtpPacket {
+    pub sequence_number: u16,
+    pub payload: Bytes,
+}
+
+impl RtpPacket {
+    pub fn parse(mut data: Bytes) -> Option<Self> {
+        if data.len() < FIXED_HEADER_SIZE || data[0] >> 6 != RTP_VERSION {
+            return None;
+        }
+
+        let padding = data[0] & 0x20 != 0;
+        let extension = data[0] & 0x10 != 0;
+        let csrc_count = (data[0] & 0x0F) as usize;
+        let sequence_number = u16::from_be_bytes([data[2], data[3]]);
+
+        if padding {
+            let padding_size = *data.last()? as usize;
+            if padding_size > data.len() - FIXED_HEADER_SIZE {
+                return None;
+            }
+            data.truncate(data.len() - padding_size);
+        }
+
+        let mut header_size = FIXED_HEADER_SIZE + csrc_count * 4;
+
+        if extension {
+            if data.len() < header_size + 4 {
+                return None;
+            }
+
+            let extension_length = u16::from_be_bytes([data[header_size + 2], data[header_size + 3]]) as usize;
+            header_size += 4 + extension_length * 4;
+        }
+
+        if data.len() < header_size {
+            return None;
+        }
+
+        data.advance(header_size);
+
+        Some(Self { sequence_number, payload: data })
+    }
+}
+
+/// A datagram is either bare transport stream packets or an RTP packet
+/// carrying them. RTP version 2 headers start with 0b10, so they can't be
+/// mistaken for the TS sync byte.
+pub fn is_rtp(datagram: &[u8]) -> bool {
+    !datagram.is_empty() && datagram[0] != TS_SYNC_BYTE && datagram[0] >> 6 == RTP_VERSION
+}
+
+/// Puts RTP payloads back in sequence order. Packets are held until the gap
+/// in front of them is filled or more than `depth` packets are waiting, at
+/// which point the missing ones are given up on.
+pub struct JitterBuffer {
+    depth: usize,
+    next: Option<u64>,
+    highest: u64,
+    packets: BTreeMap<u64, Bytes>,
+}
+
+impl JitterBuffer {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            next: None,
+            highest: 0,
+            packets: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the payloads that are now in order.
+    pub fn push(&mut self, packet: RtpPacket) -> Vec<Bytes> {
+        let sequence_number = self.extend(packet.sequence_number);
+
+        match self.next {
+            Some(next) if sequence_number < next => {
+                if next - sequence_number <= MAX_MISORDER {
+                    log::debug!("Dropping late or duplicate RTP packet {}", packet.sequence_number);
+                    return Vec::new();
+                }
+
+                log::info!("RTP sequence restarted at {}", packet.sequence_number);
+                self.packets.clear();
+                self.next = Some(sequence_number);
+                self.highest = sequence_number;
+            },
+            None => self.next = Some(sequence_number),
+            _ => {},
+        }
+
+        self.highest = self.highest.max(sequence_number);
+        self.packets.insert(sequence_number, packet.payload);
+
+        self.release()
+    }
+
+    fn release(&mut self) -> Vec<Bytes> {
+        let mut payloads = Vec::new();
+
+        while let Some(next) = self.next {
+            if let Some(payload) = self.packets.remove(&next) {
+                payloads.push(payload);
+                self.next = Some(next + 1);
+                continue;
+            }
+
+            if self.packets.len() <= self.depth {
+                break;
+            }
+
+            // waited long enough, skip over the missing packets
+            let (&first, _) = self.packets.iter().next().unwrap();
+            log::debug!("Lost {} RTP packets", first - next);
+            self.next = Some(first);
+        }
+
+        payloads
+    }
+
+    /// Unwraps the 16 bit sequence number to the value closest to the highest seen.
+    fn extend(&self, sequence_number: u16) -> u64 {
+        if self.next.is_none() {
+            // leave room below the first packet for ones arriving late
+            return (1 << 16) + sequence_number as u64;
+        }
+
+        let base = self.highest & !0xFFFF;
+        [base.saturating_sub(1 << 16), base, base + (1 << 16)]
+            .into_iter()
+            .map(|cycle| cycle + sequence_number as u64)
+            .min_by_key(|candidate| candidate.abs_diff(self.highest))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, Bytes, BytesMut};
+    use super::{is_rtp, JitterBuffer, RtpPacket, MAX_MISORDER};
+
+    /// A parsed packet with its sequence number as the payload.
+    fn packet(sequence_number: u16) -> RtpPacket {
+        RtpPacket {
+            sequence_number,
+            payload: Bytes::copy_from_slice(&sequence_number.to_be_bytes()),
+        }
+    }
+
+    fn push_all(buffer: &mut JitterBuffer, sequence_numbers: &[u16]) -> Vec<u16> {
+        sequence_numbers.iter()
+            .flat_map(|sequence_number| buffer.push(packet(*sequence_number)))
+            .map(|payload| u16::from_be_bytes([payload[0], payload[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn reorders_packets() {
+        let mut buffer = JitterBuffer::new(4);
+
+        assert_eq!(push_all(&mut buffer, &[10]), vec![10]);
+        assert!(push_all(&mut buffer, &[12, 13]).is_empty());
+        assert_eq!(push_all(&mut buffer, &[11]), vec![11, 12, 13]);
+
+        // a duplicate of something already released is dropped
+        assert_eq!(push_all(&mut buffer, &[12, 14]), vec![14]);
+    }
+
+    #[test]
+    fn skips_packets_lost_beyond_depth() {
+        let mut buffer = JitterBuffer::new(2);
+
+        assert_eq!(push_all(&mut buffer, &[0]), vec![0]);
+        assert!(push_all(&mut buffer, &[2, 3]).is_empty());
+
+        // a third packet waiting on 1 gives up on it
+        assert_eq!(push_all(&mut buffer, &[4]), vec![2, 3, 4]);
+
+        // and it is too late when it does turn up
+        assert_eq!(push_all(&mut buffer, &[1, 5]), vec![5]);
+    }
+
+    #[test]
+    fn extends_sequence_numbers_across_wrap() {
+        let mut buffer = JitterBuffer::new(4);
+
+        assert_eq!(push_all(&mut buffer, &[65534]), vec![65534]);
+        assert!(push_all(&mut buffer, &[0]).is_empty());
+        assert_eq!(push_all(&mut buffer, &[65535]), vec![65535, 0]);
+        assert_eq!(push_all(&mut buffer, &[1, 2]), vec![1, 2]);
+
+        // one step back across the wrap is late, not a new cycle
+        assert!(buffer.extend(65535) < buffer.extend(2));
+    }
+
+    #[test]
+    fn restarts_when_sequence_jumps_back() {
+        let mut buffer = JitterBuffer::new(4);
+
+        assert_eq!(push_all(&mut buffer, &[1000, 1001]), vec![1000, 1001]);
+        assert!(push_all(&mut buffer, &[1003]).is_empty());
+
+        // up to MAX_MISORDER behind the next expected it is only late
+        assert!(push_all(&mut buffer, &[1002 - MAX_MISORDER as u16]).is_empty());
+
+        // further back the sender restarted, whatever was waiting is dropped
+        assert_eq!(push_all(&mut buffer, &[500, 501]), vec![500, 501]);
+        assert!(buffer.packets.is_empty());
+    }
+
+    /// A fixed header for MPEG-TS, payload type 33.
+    fn header(first_byte: u8, sequence_number: u16) -> BytesMut {
+        let mut data = BytesMut::new();
+        data.put_u8(first_byte);
+        data.put_u8(33);
+        data.put_u16(sequence_number);
+        data.put_u32(90_000);
+        data.put_u32(0x1234_5678);
+        data
+    }
+
+    #[test]
+    fn strips_header_csrcs_extension_and_padding() {
+        let payload = [0x47, 0x01, 0x02, 0x03];
+
+        // version 2 with padding, an extension and two CSRCs
+        let mut data = header(0x80 | 0x20 | 0x10 | 0x02, 7);
+        data.put_u32(1);
+        data.put_u32(2);
+        data.put_u16(0xBEDE);
+        data.put_u16(1);
+        data.put_u32(0xFFFF_FFFF);
+        data.put_slice(&payload);
+        data.put_slice(&[0, 0, 3]);
+
+        assert!(is_rtp(&data));
+
+        let packet = RtpPacket::parse(data.freeze()).unwrap();
+        assert_eq!(packet.sequence_number, 7);
+        assert_eq!(packet.payload, payload[..]);
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        // padding longer than the packet
+        let mut data = header(0xA0, 1);
+        data.put_u8(0xFF);
+        assert!(RtpPacket::parse(data.freeze()).is_none());
+
+        // extension header cut short
+        let data = header(0x90, 1);
+        assert!(RtpPacket::parse(data.freeze()).is_none());
+
+        // CSRCs cut short
+        let mut data = header(0x83, 1);
+        data.put_u32(1);
+        assert!(RtpPacket::parse(data.freeze()).is_none());
+
+        // bare transport stream isn't RTP
+        assert!(!is_rtp(&[0x47, 0x40, 0x00, 0x10]));
+    }
+}
@@ -9,7 +9,15 @@ pub type SRTSOCKET = int;
 pub enum SRT_SOCKOPT {
     SRTO_SNDSYN = 1,
     SRTO_RCVSYN = 2,
-    SRTO_STREAMID = 46
+    SRTO_SNDBUF = 5,
+    SRTO_RCVBUF = 6,
+    SRTO_RENDEZVOUS = 12,
+    SRTO_MAXBW = 16,
+    SRTO_LATENCY = 23,
+    SRTO_PASSPHRASE = 26,
+    SRTO_PBKEYLEN = 27,
+    SRTO_STREAMID = 46,
+    SRTO_PAYLOADSIZE = 49
 }
 
 #[repr(C)]
@@ -282,6 +290,12 @@ extern "C" {
 
     pub fn srt_accept(u: SRTSOCKET, addr: *mut sockaddr, addrlen: *mut int) -> SRTSOCKET;
 
+    pub fn srt_connect(u: SRTSOCKET, name: *const sockaddr, namelen: int) -> int;
+
+    pub fn srt_getsockstate(u: SRTSOCKET) -> SRT_SOCKSTATUS;
+
+    pub fn srt_getrejectreason(u: SRTSOCKET) -> int;
+
     pub fn srt_recv(souck: SRTSOCKET, buf: *mut char, len: int) -> int;
 
     pub fn srt_recvmsg2(u: SRTSOCKET, buf: *mut char, len: int, mctrl: *mut SRT_MSGCTRL) -> int;
@@ -69,6 +69,30 @@ pub enum SrtRejectReason {
     Timeout,    // connection timeout
 }
 
+impl From<i32> for SrtRejectReason {
+    fn from(reason: i32) -> Self {
+        match reason {
+            1 => SrtRejectReason::System,
+            2 => SrtRejectReason::Peer,
+            3 => SrtRejectReason::Resource,
+            4 => SrtRejectReason::Rogue,
+            5 => SrtRejectReason::Backlog,
+            6 => SrtRejectReason::IPE,
+            7 => SrtRejectReason::Close,
+            8 => SrtRejectReason::Version,
+            9 => SrtRejectReason::RdvCookie,
+            10 => SrtRejectReason::BadSecret,
+            11 => SrtRejectReason::Unsecure,
+            12 => SrtRejectReason::MessageAPI,
+            13 => SrtRejectReason::Congestion,
+            14 => SrtRejectReason::Filter,
+            15 => SrtRejectReason::Group,
+            16 => SrtRejectReason::Timeout,
+            _ => SrtRejectReason::Unknown,
+        }
+    }
+}
+
 fn error_msg(err: &SrtError) -> String {
     match err {
         SrtError::Unknown => "Internal error when setting the right error code".to_string(),
@@ -1,10 +1,10 @@
-use std::{net::{ToSocketAddrs, SocketAddr}, future::Future, task::{Context, Poll}, pin::Pin};
+use std::{net::{ToSocketAddrs, SocketAddr}, future::Future, task::{Context, Poll}, pin::Pin, thread, time::Duration};
 
 use anyhow::Result;
 use epoll::Epoll;
 use error::SrtError;
 use libsrt_sys;
-use socket::SrtSocket;
+use socket::{KeyLength, SrtSocket};
 use stream::SrtStream;
 
 pub mod log;
@@ -40,20 +40,132 @@ pub fn cleanup() -> Result<()> {
 }
 
 pub fn builder() -> SrtBuilder {
-    SrtBuilder {
-    }
+    SrtBuilder::default()
 }
 
+/// Socket options applied to every socket the builder creates, left at the
+/// libsrt defaults unless set.
+#[derive(Clone, Debug, Default)]
 pub struct SrtBuilder {
-
+    latency: Option<Duration>,
+    passphrase: Option<String>,
+    pbkeylen: Option<KeyLength>,
+    maxbw: Option<i64>,
+    rcvbuf: Option<i32>,
+    sndbuf: Option<i32>,
+    payload_size: Option<i32>,
+    stream_id: Option<String>,
 }
 
 impl SrtBuilder {
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    pub fn passphrase(mut self, passphrase: &str) -> Self {
+        self.passphrase = Some(passphrase.to_owned());
+        self
+    }
+
+    pub fn pbkeylen(mut self, key_length: KeyLength) -> Self {
+        self.pbkeylen = Some(key_length);
+        self
+    }
+
+    pub fn maxbw(mut self, bytes_per_second: i64) -> Self {
+        self.maxbw = Some(bytes_per_second);
+        self
+    }
+
+    pub fn rcvbuf(mut self, size: i32) -> Self {
+        self.rcvbuf = Some(size);
+        self
+    }
+
+    pub fn sndbuf(mut self, size: i32) -> Self {
+        self.sndbuf = Some(size);
+        self
+    }
+
+    pub fn payload_size(mut self, size: i32) -> Self {
+        self.payload_size = Some(size);
+        self
+    }
+
+    pub fn stream_id(mut self, stream_id: &str) -> Self {
+        self.stream_id = Some(stream_id.to_owned());
+        self
+    }
+
     pub fn listen<A: ToSocketAddrs>(self, addr: A, backlog: i32) -> Result<SrtListener> {
-        let socket = SrtSocket::new()?;
-        let socket = socket.bind(addr)?;
-        socket.listen(backlog)?; // Still synchronous
-        Ok(SrtListener { socket })
+        // owned from here on, so the socket is closed if anything below fails
+        let listener = SrtListener { socket: SrtSocket::new()? };
+        self.apply(&listener.socket)?;
+
+        listener.socket.bind(addr)?;
+        listener.socket.listen(backlog)?; // Still synchronous
+        Ok(listener)
+    }
+
+    /// Calls a remote listener, resolving once the connection is up.
+    pub fn connect<A: ToSocketAddrs>(self, addr: A) -> Result<ConnectFuture> {
+        let stream = SrtStream { socket: SrtSocket::new()? };
+        self.apply(&stream.socket)?;
+
+        Self::start_connect(stream, addr)
+    }
+
+    /// Binds `local` and connects to `remote`, which has to do the same
+    /// towards this side. Resolves once both have met.
+    pub fn rendezvous<A: ToSocketAddrs, B: ToSocketAddrs>(self, local: A, remote: B) -> Result<ConnectFuture> {
+        let stream = SrtStream { socket: SrtSocket::new()? };
+        self.apply(&stream.socket)?;
+
+        stream.socket.set_rendezvous(true)?;
+        stream.socket.bind(local)?;
+
+        Self::start_connect(stream, remote)
+    }
+
+    fn apply(&self, socket: &SrtSocket) -> Result<()> {
+        if let Some(latency) = self.latency {
+            socket.set_latency(latency)?;
+        }
+        if let Some(passphrase) = &self.passphrase {
+            socket.set_passphrase(passphrase)?;
+        }
+        if let Some(key_length) = self.pbkeylen {
+            socket.set_pbkeylen(key_length)?;
+        }
+        if let Some(maxbw) = self.maxbw {
+            socket.set_maxbw(maxbw)?;
+        }
+        if let Some(size) = self.rcvbuf {
+            socket.set_rcvbuf(size)?;
+        }
+        if let Some(size) = self.sndbuf {
+            socket.set_sndbuf(size)?;
+        }
+        if let Some(size) = self.payload_size {
+            socket.set_payload_size(size)?;
+        }
+        if let Some(stream_id) = &self.stream_id {
+            socket.set_stream_id(stream_id)?;
+        }
+        Ok(())
+    }
+
+    fn start_connect<A: ToSocketAddrs>(stream: SrtStream, addr: A) -> Result<ConnectFuture> {
+        let socket = stream.socket;
+        socket.set_receive_blocking(false)?;
+        socket.set_send_blocking(false)?;
+        let addr = socket.connect(addr)?;
+
+        Ok(ConnectFuture {
+            stream: Some(stream),
+            addr,
+        })
     }
 }
 
@@ -121,3 +233,46 @@ impl Future for AcceptFuture {
     }
 }
 
+/// How long a pending connect waits for the socket to become writable
+/// before its state is checked again, failed calls aren't always signalled.
+const CONNECT_RECHECK_MS: i64 = 100;
+
+pub struct ConnectFuture {
+    stream: Option<SrtStream>,
+    addr: SocketAddr,
+}
+
+impl Future for ConnectFuture {
+    type Output = Result<(SrtStream, SocketAddr)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let socket = self.stream.as_ref().expect("ConnectFuture polled after completion").socket;
+
+        match socket.state() {
+            libsrt_sys::SRT_SOCKSTATUS_SRTS_CONNECTED => {
+                let stream = self.stream.take().unwrap();
+                return Poll::Ready(Ok((stream, self.addr)));
+            },
+            libsrt_sys::SRT_SOCKSTATUS_SRTS_INIT
+            | libsrt_sys::SRT_SOCKSTATUS_SRTS_OPENED
+            | libsrt_sys::SRT_SOCKSTATUS_SRTS_CONNECTING => {},
+            _ => {
+                let reason = socket.reject_reason();
+                // dropping the stream closes the socket
+                self.stream = None;
+                return Poll::Ready(Err(SrtError::ConnRej(reason).into()));
+            },
+        }
+
+        let waker = cx.waker().clone();
+        let mut epoll = Epoll::new()?;
+        epoll.add(&socket, &libsrt_sys::SRT_EPOLL_OPT::SRT_EPOLL_OUT)?;
+        thread::spawn(move || {
+            let _ = epoll.wait(CONNECT_RECHECK_MS);
+            waker.wake();
+        });
+
+        Poll::Pending
+    }
+}
+
@@ -1,13 +1,14 @@
 use std::mem;
 use std::net::{ToSocketAddrs, SocketAddr};
 use std::num::NonZeroI64;
+use std::time::Duration;
 
 use anyhow::{Result, bail};
 use libc::{sockaddr, c_int, c_void, c_char};
 use os_socketaddr::{self, OsSocketAddr};
 use libsrt_sys;
 
-use super::error::SrtError;
+use super::error::{SrtError, SrtRejectReason};
 use super::error;
 
 #[derive(Copy, Clone, Debug)]
@@ -63,6 +64,32 @@ impl SrtSocket {
         }
     }
 
+    /// Starts connecting to the first of `addrs`. On a non-blocking socket
+    /// this returns straight away, `state` tells when the call has completed.
+    pub fn connect<A: ToSocketAddrs>(&self, addrs: A) -> Result<SocketAddr> {
+        if let Some(addr) = addrs.to_socket_addrs()?.next() {
+            let os_addr: OsSocketAddr = addr.into();
+            let result = unsafe {
+                libsrt_sys::srt_connect(
+                    self.id,
+                    os_addr.as_ptr() as *const sockaddr,
+                    os_addr.len() as i32,
+                )
+            };
+            return error::handle_result(addr, result).map_err(anyhow::Error::from);
+        }
+        bail!(SrtError::NoServer)
+    }
+
+    pub fn state(&self) -> libsrt_sys::SRT_SOCKSTATUS {
+        unsafe { libsrt_sys::srt_getsockstate(self.id) }
+    }
+
+    pub fn reject_reason(&self) -> SrtRejectReason {
+        let reason = unsafe { libsrt_sys::srt_getrejectreason(self.id) };
+        SrtRejectReason::from(reason)
+    }
+
     pub fn close(self) -> Result<()> {
         let result = unsafe { libsrt_sys::srt_close(self.id) };
         error::handle_result((), result).map_err(anyhow::Error::from)
@@ -168,6 +195,157 @@ impl SrtSocket {
 }
 
 
+/// Typed access to the socket options. Most of them have to be set before
+/// the socket is bound or connected, options set on a listener are
+/// inherited by the sockets it accepts.
+impl SrtSocket {
+    /// Receiver and sender latency, the time packets are held back to allow
+    /// for retransmission.
+    pub fn set_latency(&self, latency: Duration) -> Result<()> {
+        self.set_flag(libsrt_sys::SRT_SOCKOPT::SRTO_LATENCY, latency.as_millis() as i32)
+    }
+
+    pub fn latency(&self) -> Result<Duration> {
+        let latency: i32 = self.get_flag(libsrt_sys::SRT_SOCKOPT::SRTO_LATENCY)?;
+        Ok(Duration::from_millis(latency as u64))
+    }
+
+    /// Enables encryption, the passphrase must be 10 to 79 characters.
+    pub fn set_passphrase(&self, passphrase: &str) -> Result<()> {
+        self.set_string_flag(libsrt_sys::SRT_SOCKOPT::SRTO_PASSPHRASE, passphrase)
+    }
+
+    pub fn set_pbkeylen(&self, key_length: KeyLength) -> Result<()> {
+        self.set_flag(libsrt_sys::SRT_SOCKOPT::SRTO_PBKEYLEN, i32::from(key_length))
+    }
+
+    /// `None` until set, or agreed with the peer.
+    pub fn pbkeylen(&self) -> Result<Option<KeyLength>> {
+        let key_length: i32 = self.get_flag(libsrt_sys::SRT_SOCKOPT::SRTO_PBKEYLEN)?;
+        Ok(KeyLength::try_from(key_length).ok())
+    }
+
+    /// Maximum send bandwidth in bytes per second, -1 for no limit and 0 to
+    /// derive it from the input rate.
+    pub fn set_maxbw(&self, bytes_per_second: i64) -> Result<()> {
+        self.set_flag(libsrt_sys::SRT_SOCKOPT::SRTO_MAXBW, bytes_per_second)
+    }
+
+    pub fn maxbw(&self) -> Result<i64> {
+        self.get_flag(libsrt_sys::SRT_SOCKOPT::SRTO_MAXBW)
+    }
+
+    /// Receive buffer size in bytes.
+    pub fn set_rcvbuf(&self, size: i32) -> Result<()> {
+        self.set_flag(libsrt_sys::SRT_SOCKOPT::SRTO_RCVBUF, size)
+    }
+
+    pub fn rcvbuf(&self) -> Result<i32> {
+        self.get_flag(libsrt_sys::SRT_SOCKOPT::SRTO_RCVBUF)
+    }
+
+    /// Send buffer size in bytes.
+    pub fn set_sndbuf(&self, size: i32) -> Result<()> {
+        self.set_flag(libsrt_sys::SRT_SOCKOPT::SRTO_SNDBUF, size)
+    }
+
+    pub fn sndbuf(&self) -> Result<i32> {
+        self.get_flag(libsrt_sys::SRT_SOCKOPT::SRTO_SNDBUF)
+    }
+
+    /// Largest payload of a single message, 1316 for seven TS packets.
+    pub fn set_payload_size(&self, size: i32) -> Result<()> {
+        self.set_flag(libsrt_sys::SRT_SOCKOPT::SRTO_PAYLOADSIZE, size)
+    }
+
+    pub fn payload_size(&self) -> Result<i32> {
+        self.get_flag(libsrt_sys::SRT_SOCKOPT::SRTO_PAYLOADSIZE)
+    }
+
+    /// Both sides connect to each other instead of one listening.
+    pub fn set_rendezvous(&self, rendezvous: bool) -> Result<()> {
+        self.set_flag(libsrt_sys::SRT_SOCKOPT::SRTO_RENDEZVOUS, rendezvous)
+    }
+
+    pub fn rendezvous(&self) -> Result<bool> {
+        self.get_flag(libsrt_sys::SRT_SOCKOPT::SRTO_RENDEZVOUS)
+    }
+
+    /// The streamid sent to the listener when connecting.
+    pub fn set_stream_id(&self, stream_id: &str) -> Result<()> {
+        self.set_string_flag(libsrt_sys::SRT_SOCKOPT::SRTO_STREAMID, stream_id)
+    }
+
+    fn set_flag<T: Copy>(&self, opt: libsrt_sys::SRT_SOCKOPT, value: T) -> Result<()> {
+        let result = unsafe {
+            libsrt_sys::srt_setsockflag(
+                self.id,
+                opt,
+                &value as *const T as *const c_void,
+                mem::size_of::<T>() as c_int,
+            )
+        };
+        error::handle_result((), result).map_err(anyhow::Error::from)
+    }
+
+    fn set_string_flag(&self, opt: libsrt_sys::SRT_SOCKOPT, value: &str) -> Result<()> {
+        let result = unsafe {
+            libsrt_sys::srt_setsockflag(
+                self.id,
+                opt,
+                value.as_ptr() as *const c_void,
+                value.len() as c_int,
+            )
+        };
+        error::handle_result((), result).map_err(anyhow::Error::from)
+    }
+
+    fn get_flag<T: Copy + Default>(&self, opt: libsrt_sys::SRT_SOCKOPT) -> Result<T> {
+        let mut value = T::default();
+        let mut len = mem::size_of::<T>() as c_int;
+        let result = unsafe {
+            libsrt_sys::srt_getsockflag(
+                self.id,
+                opt,
+                &mut value as *mut T as *mut c_void,
+                &mut len as *mut c_int,
+            )
+        };
+        error::handle_result(value, result).map_err(anyhow::Error::from)
+    }
+}
+
+/// AES key length used for encryption
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyLength {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl From<KeyLength> for i32 {
+    fn from(key_length: KeyLength) -> i32 {
+        match key_length {
+            KeyLength::Aes128 => 16,
+            KeyLength::Aes192 => 24,
+            KeyLength::Aes256 => 32,
+        }
+    }
+}
+
+impl TryFrom<i32> for KeyLength {
+    type Error = SrtError;
+
+    fn try_from(bytes: i32) -> Result<Self, Self::Error> {
+        match bytes {
+            16 => Ok(KeyLength::Aes128),
+            24 => Ok(KeyLength::Aes192),
+            32 => Ok(KeyLength::Aes256),
+            _ => Err(SrtError::InvParam),
+        }
+    }
+}
+
 
 pub struct RecvMsgCtrl {
     pub src_time: Option<NonZeroI64>,